@@ -1,37 +1,602 @@
 extern crate clap;
 extern crate unjavac;
+extern crate zip;
 
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use unjavac::classfile;
 use unjavac::classfile::parser::*;
 use unjavac::decompiler::decompile::*;
+use unjavac::disassembler::error::Error;
+use unjavac::disassembler::javap::javap;
 use unjavac::disassembler::transform::*;
 
+/// Exit code if the input isn't a valid class file or can't be decompiled.
+const EXIT_INVALID_CLASS: i32 = 1;
+/// Exit code for invalid arguments and I/O errors.
+const EXIT_USAGE: i32 = 2;
+
+/// The stack size of the thread decompiling a class. The decompiler recurses over the nesting of
+/// the code, so this limits how deeply it may be nested, independently of the main thread.
+const DECOMPILER_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Why a command failed.
+enum Failure {
+    Class(Error),
+    /// The decompiler panicked, with this message.
+    Crash(String),
+    Io(io::Error),
+}
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Failure {
+        Failure::Class(err)
+    }
+}
+
+impl From<classfile::Error> for Failure {
+    fn from(err: classfile::Error) -> Failure {
+        Failure::Class(Error::from(err))
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Failure {
+        Failure::Io(err)
+    }
+}
+
 fn main() {
-    use std::fs::File;
-    let matches = clap::App::new("unjavac")
+    let input = || {
+        Arg::with_name("INPUT")
+            .help("The class file to process")
+            .required(true)
+    };
+    let output = |value_name, help| {
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name(value_name)
+            .help(help)
+    };
+    let output_file = || output("FILE", "Writes the output to FILE instead of stdout");
+    let lenient = || {
+        Arg::with_name("lenient")
+            .long("lenient")
+            .help("Shows instructions that can't be decoded as invalid instead of failing")
+    };
+    let mut passes = Pass::ALL
+        .iter()
+        .filter(|&&pass| pass != Pass::Structure)
+        .map(|pass| pass.name())
+        .collect::<Vec<_>>();
+    passes.push("all");
+    let app = App::new("unjavac")
         .about("Decompiles Java .class files")
-        .arg(
-            clap::Arg::with_name("INPUT")
-                .help("Sets the input class file to be decompiled")
-                .required(true),
+        .after_help(
+            "EXIT CODES:\n    0    Success\n    1    The input is not a valid class file or \
+             can't be decompiled\n    2    Invalid arguments or I/O error",
         )
-        .get_matches();
-    let input = matches.value_of("INPUT").unwrap();
-    let mut f = File::open(input).unwrap();
-    let class_file = parse_class_file(&mut f).unwrap();
-    let compilation_unit = transform(&class_file);
-    println!(
-        r#"
-DISASSEMBLY:
-============
-{:#?}"#,
-        compilation_unit
-    );
-    println!(
-        r#"
-DISASSEMBLY PRETTY-PRINTED:
-===========================
-{}"#,
-        compilation_unit
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(
+            SubCommand::with_name("decompile")
+                .about("Decompiles a class file, a .jar or .zip archive or a directory of classes")
+                .arg(input().help("The class file, archive or directory to decompile"))
+                .arg(output(
+                    "PATH",
+                    "Writes the output to PATH instead of stdout; for archives and \
+                     directories, PATH is the directory to write the Java sources to",
+                ))
+                .arg(lenient())
+                .arg(
+                    Arg::with_name("dump")
+                        .long("dump")
+                        .value_name("PASS")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .possible_values(&passes)
+                        .help("Also prints the result of the decompiler pass PASS"),
+                )
+                .arg(Arg::with_name("dot").long("dot").value_name("DIR").help(
+                    "Also writes the control flow graph of each method before \
+                             structuring it to DIR/CLASS.METHOD.dot, in the DOT language \
+                             of Graphviz",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Disassembles the bytecode of a class file")
+                .arg(input())
+                .arg(output_file())
+                .arg(lenient())
+                .arg(
+                    Arg::with_name("javap")
+                        .long("javap")
+                        .help("Prints the disassembly in the format of `javap -c -v -p`"),
+                )
+                .arg(
+                    Arg::with_name("debug")
+                        .long("debug")
+                        .conflicts_with("javap")
+                        .help("Prints the internal representation of the disassembly"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cfg")
+                .about("Prints the control flow graphs of the methods of a class file")
+                .arg(input())
+                .arg(output_file())
+                .arg(lenient()),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Prints an overview of a class file")
+                .arg(input())
+                .arg(output_file()),
+        );
+    let matches = app.get_matches_safe().unwrap_or_else(|err| {
+        if err.use_stderr() {
+            eprintln!("{}", err.message);
+            process::exit(EXIT_USAGE)
+        }
+        err.exit()
+    });
+    let (command, args) = matches.subcommand();
+    let args = args.unwrap();
+    let input = args.value_of("INPUT").unwrap();
+    if is_archive_or_directory(Path::new(input)) {
+        if command != "decompile" {
+            eprintln!(
+                "error: {}: {} only supports single class files",
+                input, command
+            );
+            process::exit(EXIT_USAGE)
+        }
+        if args.is_present("dot") {
+            eprintln!("error: {}: --dot only supports single class files", input);
+            process::exit(EXIT_USAGE)
+        }
+        let output = args.value_of("output").unwrap_or_else(|| {
+            eprintln!("error: decompiling an archive or directory requires --output");
+            process::exit(EXIT_USAGE)
+        });
+        let lenient = args.is_present("lenient");
+        match decompile_all(Path::new(input), Path::new(output), lenient) {
+            Ok(true) => return,
+            Ok(false) => process::exit(EXIT_INVALID_CLASS),
+            Err(err) => {
+                eprintln!("error: {}: {}", input, err);
+                process::exit(EXIT_USAGE)
+            }
+        }
+    }
+    let result = match args.value_of("output") {
+        Some(path) => {
+            let mut file = File::create(path).unwrap_or_else(|err| {
+                eprintln!("error: {}: {}", path, err);
+                process::exit(EXIT_USAGE)
+            });
+            run(command, args, input, &mut file)
+        }
+        None => run(command, args, input, &mut io::stdout()),
+    };
+    match result {
+        Ok(()) => {}
+        Err(Failure::Class(err)) => {
+            eprintln!("error: {}: {}", input, err);
+            process::exit(EXIT_INVALID_CLASS)
+        }
+        Err(Failure::Crash(message)) => {
+            eprintln!("error: {}: {}", input, message);
+            process::exit(EXIT_INVALID_CLASS)
+        }
+        Err(Failure::Io(err)) => {
+            eprintln!("error: {}: {}", input, err);
+            process::exit(EXIT_USAGE)
+        }
+    }
+}
+
+fn run(
+    command: &str,
+    args: &ArgMatches,
+    input: &str,
+    out: &mut (dyn Write + Send),
+) -> Result<(), Failure> {
+    let class_file = parse_class_file(&mut File::open(input)?)?;
+    let mut unit = transform(&class_file, args.is_present("lenient"))?;
+    match command {
+        "decompile" => {
+            let dumps = args
+                .values_of("dump")
+                .map(|names| names.collect::<Vec<_>>())
+                .unwrap_or_default();
+            // The result of the last pass is the decompiled code itself:
+            let is_dumped = |pass: Pass| {
+                pass != Pass::Structure && (dumps.contains(&pass.name()) || dumps.contains(&"all"))
+            };
+            let nested_classes = read_nested_classes(Path::new(input), args.is_present("lenient"));
+            let mut switch_maps = SwitchMaps::new();
+            for class in &nested_classes {
+                switch_maps.collect(class);
+            }
+            nest_member_classes(&mut unit, &mut member_classes(nested_classes));
+            let mut result = Ok(());
+            let mut graphs = GraphWriter::new(args.value_of("dot"), &unit);
+            let unit = catch_crash(|| {
+                decompile(
+                    unit,
+                    &switch_maps,
+                    |pass, dump| {
+                        if result.is_ok() && is_dumped(pass) {
+                            let underline = "=".repeat(pass.title().len() + 1);
+                            result = writeln!(out, "{}:\n{}\n{}", pass.title(), underline, dump);
+                        }
+                    },
+                    |warning| eprintln!("warning: {}: {}", input, warning),
+                    |method, cfg| graphs.write(method, cfg),
+                )
+            })
+            .map_err(Failure::Crash)?;
+            result?;
+            graphs.result?;
+            write!(out, "{}", unit)?;
+        }
+        "disasm" => {
+            if args.is_present("javap") {
+                write!(out, "{}", javap(&class_file, &unit)?)?;
+            } else if args.is_present("debug") {
+                writeln!(out, "{:#?}", unit)?;
+            } else {
+                write!(out, "{}", unit)?;
+            }
+        }
+        "cfg" => write!(out, "{}", unit.map(|code, _| build_cfg(code)))?,
+        "info" => info(&class_file, &unit, out)?,
+        _ => unreachable!("unknown subcommand {}", command),
+    }
+    Ok(())
+}
+
+/// Writes the control flow graphs of the methods of a class to `.dot` files, see `--dot`.
+struct GraphWriter {
+    directory: Option<PathBuf>,
+    class_name: String,
+    /// How many methods with each name were written, to number the overloaded ones.
+    counts: HashMap<String, usize>,
+    /// The first error, after which nothing is written anymore.
+    result: io::Result<()>,
+}
+
+impl GraphWriter {
+    fn new<C>(directory: Option<&str>, unit: &CompilationUnit<C>) -> GraphWriter {
+        GraphWriter {
+            directory: directory.map(PathBuf::from),
+            class_name: unit.simple_name().to_owned(),
+            counts: HashMap::new(),
+            result: Ok(()),
+        }
+    }
+
+    fn write(&mut self, method: &str, cfg: &Cfg<Statement, Expr>) {
+        let directory = match self.directory {
+            Some(ref directory) if self.result.is_ok() => directory,
+            _ => return,
+        };
+        // `<clinit>` isn't a convenient file name:
+        let method = method.trim_matches(|c| c == '<' || c == '>');
+        let count = self.counts.entry(method.to_owned()).or_insert(0);
+        *count += 1;
+        let file_name = if *count == 1 {
+            format!("{}.{}.dot", self.class_name, method)
+        } else {
+            format!("{}.{}_{}.dot", self.class_name, method, count)
+        };
+        let dot = cfg.to_dot(&(), &structure::find_loops(cfg));
+        self.result =
+            fs::create_dir_all(directory).and_then(|()| fs::write(directory.join(file_name), dot));
+    }
+}
+
+fn info(
+    class_file: &ClassFile,
+    unit: &CompilationUnit<Code>,
+    out: &mut dyn Write,
+) -> Result<(), Failure> {
+    let major = class_file.major_version;
+    let java_version = if major >= 49 {
+        format!("{}", major - 44)
+    } else {
+        format!("1.{}", major.saturating_sub(44))
+    };
+    writeln!(out, "name:          {}", unit.name)?;
+    writeln!(out, "kind:          {}", unit.typ)?;
+    let modifiers = unit
+        .modifiers
+        .iter()
+        .map(|modifier| modifier.to_string())
+        .collect::<Vec<_>>();
+    if !modifiers.is_empty() {
+        writeln!(out, "modifiers:     {}", modifiers.join(" "))?;
+    }
+    writeln!(
+        out,
+        "version:       {}.{} (Java {})",
+        major, class_file.minor_version, java_version
+    )?;
+    if class_file.super_class != 0 {
+        let super_class = lookup_class_name(&class_file.constant_pool, class_file.super_class)?;
+        writeln!(out, "super class:   {}", super_class)?;
+    }
+    let interfaces = unit
+        .implements
+        .iter()
+        .map(|class| class.0.as_str())
+        .collect::<Vec<_>>();
+    if !interfaces.is_empty() {
+        writeln!(out, "interfaces:    {}", interfaces.join(", "))?;
+    }
+    for attribute in &class_file.attributes {
+        if let Attribute::SourceFile(index) = *attribute {
+            let source_file = class_file.constant_pool.lookup_string(index)?;
+            writeln!(out, "source file:   {}", source_file)?;
+        }
+    }
+    writeln!(
+        out,
+        "constants:     {}",
+        class_file.constant_pool.constants.len()
+    )?;
+    writeln!(out, "fields:        {}", class_file.fields.len())?;
+    writeln!(out, "methods:       {}", class_file.methods.len())?;
+    Ok(())
+}
+
+fn is_archive_or_directory(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    path.is_dir() || extension == Some("jar") || extension == Some("zip")
+}
+
+/// Decompiles all classes in the archive or directory `input` into Java sources in `output`,
+/// mirroring the directory structure of the input. Member classes are decompiled into the source
+/// of their outer class, and classes generated by the compiler are skipped.
+/// A summary is printed to stderr, the result is whether all classes could be decompiled.
+fn decompile_all(input: &Path, output: &Path, lenient: bool) -> io::Result<bool> {
+    let classes = if input.is_dir() {
+        let mut classes = vec![];
+        read_classes_in_directory(input, Path::new(""), &mut classes)?;
+        classes
+    } else {
+        read_classes_in_archive(input)?
+    };
+    let mut failures = vec![];
+    let mut units = vec![];
+    for (path, bytes) in classes {
+        match bytes.and_then(|bytes| read_class(&bytes, lenient)) {
+            Ok(unit) => units.push((path, unit)),
+            Err(err) => failures.push((path, err)),
+        }
+    }
+    // Switches on enums refer to the synthetic classes generated for them:
+    let mut switch_maps = SwitchMaps::new();
+    for (_, unit) in &units {
+        switch_maps.collect(unit);
+    }
+    let names = units
+        .iter()
+        .map(|(_, unit)| unit.name.clone())
+        .collect::<HashSet<_>>();
+    let (members, top_level): (Vec<_>, Vec<_>) = units.into_iter().partition(|(_, unit)| {
+        unit.outer_class
+            .as_ref()
+            .is_some_and(|outer_class| names.contains(outer_class))
+    });
+    let mut members = member_classes(members.into_iter().map(|(_, unit)| unit));
+    let top_level = top_level
+        .into_iter()
+        .filter(|(_, unit)| !unit.synthetic)
+        .collect::<Vec<_>>();
+    let count = top_level.len() + failures.len();
+    for (path, mut unit) in top_level {
+        nest_member_classes(&mut unit, &mut members);
+        match catch_crash(|| decompile_class(&path, unit, &switch_maps)) {
+            Ok(source) => {
+                let path = output.join(path.with_extension("java"));
+                if let Some(directory) = path.parent() {
+                    fs::create_dir_all(directory)?;
+                }
+                fs::write(path, source)?;
+            }
+            Err(err) => failures.push((path, err)),
+        }
+    }
+    eprintln!("decompiled {} of {} classes", count - failures.len(), count);
+    for (path, err) in &failures {
+        eprintln!("failed: {}: {}", path.display(), err);
+    }
+    Ok(failures.is_empty())
+}
+
+/// The member classes by the names of their outer classes.
+fn member_classes<I>(classes: I) -> HashMap<String, Vec<CompilationUnit<Code>>>
+where
+    I: IntoIterator<Item = CompilationUnit<Code>>,
+{
+    let mut member_classes = HashMap::new();
+    for class in classes {
+        if let Some(outer_class) = class.outer_class.clone() {
+            member_classes
+                .entry(outer_class)
+                .or_insert_with(Vec::new)
+                .push(class);
+        }
+    }
+    member_classes
+}
+
+/// Moves the member classes of the unit, and theirs in turn, into it.
+fn nest_member_classes(
+    unit: &mut CompilationUnit<Code>,
+    member_classes: &mut HashMap<String, Vec<CompilationUnit<Code>>>,
+) {
+    for mut member_class in member_classes.remove(&unit.name).unwrap_or_default() {
+        nest_member_classes(&mut member_class, member_classes);
+        unit.nested.push(member_class);
+    }
+}
+
+/// Runs the decompiler in a thread of its own with a stack of `DECOMPILER_STACK_SIZE`, since it
+/// still panics on some unsupported bytecode. A panic is reported as an error with its message
+/// instead of aborting, and isn't printed.
+fn catch_crash<T, F>(decompile: F) -> Result<T, String>
+where
+    F: FnOnce() -> T + Send,
+    T: Send,
+{
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = thread::scope(|scope| {
+        let worker = thread::Builder::new()
+            .name("decompiler".to_owned())
+            .stack_size(DECOMPILER_STACK_SIZE)
+            .spawn_scoped(scope, decompile)
+            .map_err(|err| format!("the decompiler couldn't be started: {}", err))?;
+        worker.join().map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            format!("the decompiler crashed: {}", message)
+        })
+    });
+    panic::set_hook(default_hook);
+    result
+}
+
+/// Decompiles the class at `path` in the archive or directory. Warnings are printed to stderr.
+fn decompile_class(path: &Path, unit: CompilationUnit<Code>, switch_maps: &SwitchMaps) -> String {
+    let unit = decompile(
+        unit,
+        switch_maps,
+        |_, _| (),
+        |warning| eprintln!("warning: {}: {}", path.display(), warning),
+        |_, _| (),
     );
-    decompile(compilation_unit, true);
+    unit.to_string()
+}
+
+fn read_class(bytes: &[u8], lenient: bool) -> Result<CompilationUnit<Code>, String> {
+    let class_file = parse_class_file(&mut &bytes[..]).map_err(|err| err.to_string())?;
+    transform(&class_file, lenient).map_err(|err| err.to_string())
+}
+
+/// Reads the classes nested in the same top-level class as the class file, which are stored next
+/// to it as `Outer$....class`.
+/// Files that can't be read are ignored, their code is just missing then.
+fn read_nested_classes(class_file: &Path, lenient: bool) -> Vec<CompilationUnit<Code>> {
+    let mut classes = vec![];
+    let directory = match class_file.parent() {
+        Some(directory) if directory != Path::new("") => directory,
+        _ => Path::new("."),
+    };
+    let prefix = match class_file.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) => format!("{}$", stem.split('$').next().unwrap()),
+        None => return classes,
+    };
+    let mut entries = match fs::read_dir(directory) {
+        Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+        Err(_) => return classes,
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let is_nested = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(&prefix));
+        if !is_nested || !is_class_file(&path) {
+            continue;
+        }
+        if let Ok(unit) = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| read_class(&bytes, lenient))
+        {
+            classes.push(unit);
+        }
+    }
+    classes
+}
+
+/// The class files of an archive or directory with their paths, and their contents or why they
+/// couldn't be read.
+type ClassFiles = Vec<(PathBuf, Result<Vec<u8>, String>)>;
+
+/// Reads the class files in `directory` and its subdirectories, with their paths relative to the
+/// directory that was given on the command line.
+fn read_classes_in_directory(
+    directory: &Path,
+    relative: &Path,
+    classes: &mut ClassFiles,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            read_classes_in_directory(&path, &relative, classes)?;
+        } else if is_class_file(&path) {
+            classes.push((relative, fs::read(&path).map_err(|err| err.to_string())));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the class files in the archive. An entry that can't be read is reported with its index
+/// if its name isn't known.
+fn read_classes_in_archive(archive: &Path) -> io::Result<ClassFiles> {
+    let mut archive = zip::ZipArchive::new(File::open(archive)?)?;
+    let mut classes = vec![];
+    for i in 0..archive.len() {
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(err) => {
+                classes.push((PathBuf::from(format!("entry {}", i)), Err(err.to_string())));
+                continue;
+            }
+        };
+        // Entries whose names would escape the output directory are skipped:
+        let path = match entry.enclosed_name() {
+            Some(path) if entry.is_file() && is_class_file(path) => path.to_owned(),
+            _ => continue,
+        };
+        // The size in the header isn't trusted to preallocate the bytes, it may be made up:
+        let mut bytes = vec![];
+        let bytes = entry
+            .read_to_end(&mut bytes)
+            .map(|_| bytes)
+            .map_err(|err| err.to_string());
+        classes.push((path, bytes));
+    }
+    Ok(classes)
+}
+
+/// Checks whether the file holds a class. The declarations of modules and packages in
+/// `module-info.class` and `package-info.class` aren't classes.
+fn is_class_file(path: &Path) -> bool {
+    let is_declaration = path
+        .file_stem()
+        .is_some_and(|stem| stem == "module-info" || stem == "package-info");
+    !is_declaration
+        && path
+            .extension()
+            .is_some_and(|extension| extension == "class")
 }
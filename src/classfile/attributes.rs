@@ -0,0 +1,442 @@
+//! Decoding of the attributes defined in chapter 4.7 of the JVM spec.
+//! Attributes we don't know about are kept as raw bytes.
+
+use classfile::constant_pool::*;
+use classfile::error::*;
+use classfile::parser::*;
+use std::io::prelude::*;
+
+#[derive(Debug)]
+pub enum Attribute {
+    Code(CodeAttribute),
+    ConstantValue(u16),
+    Exceptions(Vec<u16>),
+    SourceFile(u16),
+    LineNumberTable(Vec<LineNumber>),
+    LocalVariableTable(Vec<LocalVariable>),
+    /// The `descriptor_index` of the entries points to a generic signature.
+    LocalVariableTypeTable(Vec<LocalVariable>),
+    Signature(u16),
+    InnerClasses(Vec<InnerClass>),
+    EnclosingMethod {
+        class_index: u16,
+        /// Zero if the class is not enclosed by a method or constructor.
+        method_index: u16,
+    },
+    BootstrapMethods(Vec<BootstrapMethod>),
+    StackMapTable(Vec<StackMapFrame>),
+    Synthetic,
+    Deprecated,
+    MethodParameters(Vec<MethodParameter>),
+    NestHost(u16),
+    NestMembers(Vec<u16>),
+    Record(Vec<RecordComponent>),
+    PermittedSubclasses(Vec<u16>),
+    Unknown(AttributeInfo),
+}
+
+#[derive(Debug)]
+pub struct AttributeInfo {
+    pub name_index: u16,
+    pub info: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct CodeAttribute {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionTableEntry>,
+    pub attributes: Vec<Attribute>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExceptionTableEntry {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// Zero for handlers catching everything (used for `finally`).
+    pub catch_type: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LineNumber {
+    pub start_pc: u16,
+    pub line_number: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LocalVariable {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub index: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct InnerClass {
+    pub inner_class_info_index: u16,
+    pub outer_class_info_index: u16,
+    pub inner_name_index: u16,
+    pub inner_class_access_flags: AccessFlags,
+}
+
+#[derive(Clone, Debug)]
+pub struct BootstrapMethod {
+    pub bootstrap_method_ref: u16,
+    pub bootstrap_arguments: Vec<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub enum StackMapFrame {
+    Same {
+        offset_delta: u16,
+    },
+    SameLocals1StackItem {
+        offset_delta: u16,
+        stack: VerificationType,
+    },
+    Chop {
+        offset_delta: u16,
+        /// Number of locals that are removed (1 to 3).
+        chopped: u8,
+    },
+    Append {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+    },
+    Full {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object {
+        class_index: u16,
+    },
+    /// The offset of the `new` instruction that created the object.
+    Uninitialized {
+        offset: u16,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MethodParameter {
+    /// Zero for parameters without a name.
+    pub name_index: u16,
+    pub access_flags: AccessFlags,
+}
+
+#[derive(Debug)]
+pub struct RecordComponent {
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Attribute {
+    /// The name of the attribute as it appears in the class file (if known).
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match *self {
+            Attribute::Code(..) => "Code",
+            Attribute::ConstantValue(..) => "ConstantValue",
+            Attribute::Exceptions(..) => "Exceptions",
+            Attribute::SourceFile(..) => "SourceFile",
+            Attribute::LineNumberTable(..) => "LineNumberTable",
+            Attribute::LocalVariableTable(..) => "LocalVariableTable",
+            Attribute::LocalVariableTypeTable(..) => "LocalVariableTypeTable",
+            Attribute::Signature(..) => "Signature",
+            Attribute::InnerClasses(..) => "InnerClasses",
+            Attribute::EnclosingMethod { .. } => "EnclosingMethod",
+            Attribute::BootstrapMethods(..) => "BootstrapMethods",
+            Attribute::StackMapTable(..) => "StackMapTable",
+            Attribute::Synthetic => "Synthetic",
+            Attribute::Deprecated => "Deprecated",
+            Attribute::MethodParameters(..) => "MethodParameters",
+            Attribute::NestHost(..) => "NestHost",
+            Attribute::NestMembers(..) => "NestMembers",
+            Attribute::Record(..) => "Record",
+            Attribute::PermittedSubclasses(..) => "PermittedSubclasses",
+            Attribute::Unknown(..) => return None,
+        })
+    }
+}
+
+pub fn parse_attributes<R: Read>(
+    input: &mut Reader<R>,
+    constant_pool: &ConstantPool,
+) -> Result<Vec<Attribute>> {
+    let count = input.u16()?;
+    let mut attributes = vec![];
+    for _ in 0..count {
+        let name_index = input.u16()?;
+        input.enter(Structure::Attribute { name_index });
+        let length = input.u32()?;
+        let offset = input.offset();
+        let info = input.bytes(length as usize)?;
+        let name = match *constant_pool.lookup(name_index)? {
+            ConstantInfo::Utf8(ref name) => name,
+            _ => {
+                return Err(input.error_at(
+                    offset,
+                    ErrorKind::UnexpectedConstant {
+                        index: name_index,
+                        expected: "a UTF-8 string",
+                    },
+                ))
+            }
+        };
+        let mut body = Reader::at(&info[..], offset, Structure::Attribute { name_index });
+        let attribute = parse_attribute_body(&mut body, name, constant_pool)?;
+        let attribute = match attribute {
+            Some(attribute) => {
+                if body.offset() != offset + u64::from(length) {
+                    return Err(body.error(ErrorKind::InvalidAttributeLength(length)));
+                }
+                attribute
+            }
+            None => Attribute::Unknown(AttributeInfo { name_index, info }),
+        };
+        attributes.push(attribute);
+    }
+    Ok(attributes)
+}
+
+/// Returns `None` for attributes we don't know.
+fn parse_attribute_body<R: Read>(
+    input: &mut Reader<R>,
+    name: &str,
+    constant_pool: &ConstantPool,
+) -> Result<Option<Attribute>> {
+    let attribute = match name {
+        "Code" => Attribute::Code(parse_code_attribute(input, constant_pool)?),
+        "ConstantValue" => Attribute::ConstantValue(input.u16()?),
+        "Exceptions" => Attribute::Exceptions(parse_indices(input)?),
+        "SourceFile" => Attribute::SourceFile(input.u16()?),
+        "LineNumberTable" => {
+            let count = input.u16()?;
+            let mut table = vec![];
+            for _ in 0..count {
+                table.push(LineNumber {
+                    start_pc: input.u16()?,
+                    line_number: input.u16()?,
+                });
+            }
+            Attribute::LineNumberTable(table)
+        }
+        "LocalVariableTable" => Attribute::LocalVariableTable(parse_local_variables(input)?),
+        "LocalVariableTypeTable" => {
+            Attribute::LocalVariableTypeTable(parse_local_variables(input)?)
+        }
+        "Signature" => Attribute::Signature(input.u16()?),
+        "InnerClasses" => {
+            let count = input.u16()?;
+            let mut classes = vec![];
+            for _ in 0..count {
+                classes.push(InnerClass {
+                    inner_class_info_index: input.u16()?,
+                    outer_class_info_index: input.u16()?,
+                    inner_name_index: input.u16()?,
+                    inner_class_access_flags: parse_access_flags(input)?,
+                });
+            }
+            Attribute::InnerClasses(classes)
+        }
+        "EnclosingMethod" => Attribute::EnclosingMethod {
+            class_index: input.u16()?,
+            method_index: input.u16()?,
+        },
+        "BootstrapMethods" => {
+            let count = input.u16()?;
+            let mut methods = vec![];
+            for _ in 0..count {
+                methods.push(BootstrapMethod {
+                    bootstrap_method_ref: input.u16()?,
+                    bootstrap_arguments: parse_indices(input)?,
+                });
+            }
+            Attribute::BootstrapMethods(methods)
+        }
+        "StackMapTable" => {
+            let count = input.u16()?;
+            let mut frames = vec![];
+            for _ in 0..count {
+                frames.push(parse_stack_map_frame(input)?);
+            }
+            Attribute::StackMapTable(frames)
+        }
+        "Synthetic" => Attribute::Synthetic,
+        "Deprecated" => Attribute::Deprecated,
+        "MethodParameters" => {
+            let count = input.u8()?;
+            let mut parameters = vec![];
+            for _ in 0..count {
+                parameters.push(MethodParameter {
+                    name_index: input.u16()?,
+                    access_flags: parse_access_flags(input)?,
+                });
+            }
+            Attribute::MethodParameters(parameters)
+        }
+        "NestHost" => Attribute::NestHost(input.u16()?),
+        "NestMembers" => Attribute::NestMembers(parse_indices(input)?),
+        "Record" => {
+            let count = input.u16()?;
+            let mut components = vec![];
+            for _ in 0..count {
+                components.push(RecordComponent {
+                    name_index: input.u16()?,
+                    descriptor_index: input.u16()?,
+                    attributes: parse_attributes(input, constant_pool)?,
+                });
+            }
+            Attribute::Record(components)
+        }
+        "PermittedSubclasses" => Attribute::PermittedSubclasses(parse_indices(input)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(attribute))
+}
+
+fn parse_code_attribute<R: Read>(
+    input: &mut Reader<R>,
+    constant_pool: &ConstantPool,
+) -> Result<CodeAttribute> {
+    let max_stack = input.u16()?;
+    let max_locals = input.u16()?;
+    let code_length = input.u32()?;
+    let code = input.bytes(code_length as usize)?;
+    let exception_table_length = input.u16()?;
+    let mut exception_table = vec![];
+    for _ in 0..exception_table_length {
+        exception_table.push(ExceptionTableEntry {
+            start_pc: input.u16()?,
+            end_pc: input.u16()?,
+            handler_pc: input.u16()?,
+            catch_type: input.u16()?,
+        });
+    }
+    let attributes = parse_attributes(input, constant_pool)?;
+    Ok(CodeAttribute {
+        max_stack,
+        max_locals,
+        code,
+        exception_table,
+        attributes,
+    })
+}
+
+/// Parses a list of (constant pool) indices preceded by its length.
+fn parse_indices<R: Read>(input: &mut Reader<R>) -> Result<Vec<u16>> {
+    let count = input.u16()?;
+    let mut indices = vec![];
+    for _ in 0..count {
+        indices.push(input.u16()?);
+    }
+    Ok(indices)
+}
+
+fn parse_local_variables<R: Read>(input: &mut Reader<R>) -> Result<Vec<LocalVariable>> {
+    let count = input.u16()?;
+    let mut variables = vec![];
+    for _ in 0..count {
+        variables.push(LocalVariable {
+            start_pc: input.u16()?,
+            length: input.u16()?,
+            name_index: input.u16()?,
+            descriptor_index: input.u16()?,
+            index: input.u16()?,
+        });
+    }
+    Ok(variables)
+}
+
+fn parse_stack_map_frame<R: Read>(input: &mut Reader<R>) -> Result<StackMapFrame> {
+    let offset = input.offset();
+    let frame_type = input.u8()?;
+    let frame = match frame_type {
+        0..=63 => StackMapFrame::Same {
+            offset_delta: u16::from(frame_type),
+        },
+        64..=127 => StackMapFrame::SameLocals1StackItem {
+            offset_delta: u16::from(frame_type - 64),
+            stack: parse_verification_type(input)?,
+        },
+        247 => StackMapFrame::SameLocals1StackItem {
+            offset_delta: input.u16()?,
+            stack: parse_verification_type(input)?,
+        },
+        248..=250 => StackMapFrame::Chop {
+            offset_delta: input.u16()?,
+            chopped: 251 - frame_type,
+        },
+        251 => StackMapFrame::Same {
+            offset_delta: input.u16()?,
+        },
+        252..=254 => {
+            let offset_delta = input.u16()?;
+            let mut locals = vec![];
+            for _ in 251..frame_type {
+                locals.push(parse_verification_type(input)?);
+            }
+            StackMapFrame::Append {
+                offset_delta,
+                locals,
+            }
+        }
+        255 => {
+            let offset_delta = input.u16()?;
+            let locals = parse_verification_types(input)?;
+            let stack = parse_verification_types(input)?;
+            StackMapFrame::Full {
+                offset_delta,
+                locals,
+                stack,
+            }
+        }
+        _ => return Err(input.error_at(offset, ErrorKind::InvalidStackMapFrame(frame_type))),
+    };
+    Ok(frame)
+}
+
+fn parse_verification_types<R: Read>(input: &mut Reader<R>) -> Result<Vec<VerificationType>> {
+    let count = input.u16()?;
+    let mut types = vec![];
+    for _ in 0..count {
+        types.push(parse_verification_type(input)?);
+    }
+    Ok(types)
+}
+
+fn parse_verification_type<R: Read>(input: &mut Reader<R>) -> Result<VerificationType> {
+    let offset = input.offset();
+    let tag = input.u8()?;
+    let typ = match tag {
+        0 => VerificationType::Top,
+        1 => VerificationType::Integer,
+        2 => VerificationType::Float,
+        3 => VerificationType::Double,
+        4 => VerificationType::Long,
+        5 => VerificationType::Null,
+        6 => VerificationType::UninitializedThis,
+        7 => VerificationType::Object {
+            class_index: input.u16()?,
+        },
+        8 => VerificationType::Uninitialized {
+            offset: input.u16()?,
+        },
+        _ => return Err(input.error_at(offset, ErrorKind::InvalidVerificationType(tag))),
+    };
+    Ok(typ)
+}
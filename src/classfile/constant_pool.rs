@@ -1,7 +1,6 @@
-use byteorder::{BigEndian, ReadBytesExt};
+use classfile::error::*;
 pub use classfile::parser::*;
 use std::io::prelude::*;
-use std::io::Result;
 
 #[derive(Debug)]
 pub struct ConstantPool {
@@ -12,6 +11,9 @@ pub struct ConstantPool {
 pub enum ConstantInfo {
     Utf8(String),
     Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
     Class {
         name_index: u16,
     },
@@ -26,78 +28,311 @@ pub enum ConstantInfo {
         class_index: u16,
         name_index: u16,
     },
+    InterfaceMethodRef {
+        class_index: u16,
+        name_index: u16,
+    },
     NameAndType {
         name_index: u16,
         descriptor_index: u16,
     },
+    MethodHandle {
+        reference_kind: ReferenceKind,
+        reference_index: u16,
+    },
+    MethodType {
+        descriptor_index: u16,
+    },
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    InvokeDynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    Module {
+        name_index: u16,
+    },
+    Package {
+        name_index: u16,
+    },
+    /// The unusable slot following a `Long` or `Double` constant.
+    /// (The JVM spec says: "In retrospect, making 8-byte constants take two constant pool entries
+    /// was a poor choice.")
+    Unusable,
+}
+
+impl ConstantInfo {
+    /// Long and double constants take up two slots in the constant pool.
+    pub fn slots(&self) -> u16 {
+        match *self {
+            ConstantInfo::Long(_) | ConstantInfo::Double(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReferenceKind {
+    GetField,
+    GetStatic,
+    PutField,
+    PutStatic,
+    InvokeVirtual,
+    InvokeStatic,
+    InvokeSpecial,
+    NewInvokeSpecial,
+    InvokeInterface,
+}
+
+impl ReferenceKind {
+    pub fn from_u8(kind: u8) -> Option<ReferenceKind> {
+        use self::ReferenceKind::*;
+        Some(match kind {
+            1 => GetField,
+            2 => GetStatic,
+            3 => PutField,
+            4 => PutStatic,
+            5 => InvokeVirtual,
+            6 => InvokeStatic,
+            7 => InvokeSpecial,
+            8 => NewInvokeSpecial,
+            9 => InvokeInterface,
+            _ => return None,
+        })
+    }
 }
 
-pub fn parse_constant_pool<R: Read>(input: &mut R) -> Result<Vec<ConstantInfo>> {
-    let count = input.read_u16::<BigEndian>()?;
+pub fn parse_constant_pool<R: Read>(input: &mut Reader<R>) -> Result<Vec<ConstantInfo>> {
+    let count = input.u16()?;
     let mut constant_pool = vec![];
-    for _ in 1..count {
-        let tag = input.read_u8()?;
+    let mut index = 1;
+    while index < count {
+        input.enter(Structure::Constant(index));
+        let tag_offset = input.offset();
+        let tag = input.u8()?;
         let constant_pool_info = match tag {
             1 => {
-                let length = input.read_u16::<BigEndian>()?;
-                let mut bytes = vec![0; length as usize];
-                input.read_exact(&mut bytes)?;
-                let utf8 = String::from_utf8(bytes).unwrap();
+                let length = input.u16()?;
+                let offset = input.offset();
+                let bytes = input.bytes(length as usize)?;
+                let utf8 = decode_modified_utf8(&bytes)
+                    .ok_or_else(|| input.error_at(offset, ErrorKind::InvalidUtf8))?;
                 ConstantInfo::Utf8(utf8)
             }
-            3 => {
-                let unsigned = input.read_u32::<BigEndian>()?;
-                ConstantInfo::Integer(unsigned as i32)
-            }
+            3 => ConstantInfo::Integer(input.i32()?),
+            4 => ConstantInfo::Float(input.f32()?),
+            5 => ConstantInfo::Long(input.i64()?),
+            6 => ConstantInfo::Double(input.f64()?),
             7 => ConstantInfo::Class {
-                name_index: input.read_u16::<BigEndian>()?,
+                name_index: input.u16()?,
             },
             8 => ConstantInfo::String {
-                string_index: input.read_u16::<BigEndian>()?,
+                string_index: input.u16()?,
             },
             9 => {
-                let class_index = input.read_u16::<BigEndian>()?;
-                let name_index = input.read_u16::<BigEndian>()?;
+                let class_index = input.u16()?;
+                let name_index = input.u16()?;
                 ConstantInfo::FieldRef {
-                    class_index: class_index,
-                    name_index: name_index,
+                    class_index,
+                    name_index,
                 }
             }
             10 => {
-                let class_index = input.read_u16::<BigEndian>()?;
-                let name_index = input.read_u16::<BigEndian>()?;
+                let class_index = input.u16()?;
+                let name_index = input.u16()?;
                 ConstantInfo::MethodRef {
-                    class_index: class_index,
-                    name_index: name_index,
+                    class_index,
+                    name_index,
+                }
+            }
+            11 => {
+                let class_index = input.u16()?;
+                let name_index = input.u16()?;
+                ConstantInfo::InterfaceMethodRef {
+                    class_index,
+                    name_index,
                 }
             }
             12 => {
-                let name_index = input.read_u16::<BigEndian>()?;
-                let descriptor_index = input.read_u16::<BigEndian>()?;
+                let name_index = input.u16()?;
+                let descriptor_index = input.u16()?;
                 ConstantInfo::NameAndType {
-                    name_index: name_index,
-                    descriptor_index: descriptor_index,
+                    name_index,
+                    descriptor_index,
+                }
+            }
+            15 => {
+                let kind = input.u8()?;
+                let reference_kind = ReferenceKind::from_u8(kind)
+                    .ok_or_else(|| input.error(ErrorKind::InvalidReferenceKind(kind)))?;
+                let reference_index = input.u16()?;
+                ConstantInfo::MethodHandle {
+                    reference_kind,
+                    reference_index,
+                }
+            }
+            16 => ConstantInfo::MethodType {
+                descriptor_index: input.u16()?,
+            },
+            17 => {
+                let bootstrap_method_attr_index = input.u16()?;
+                let name_and_type_index = input.u16()?;
+                ConstantInfo::Dynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                }
+            }
+            18 => {
+                let bootstrap_method_attr_index = input.u16()?;
+                let name_and_type_index = input.u16()?;
+                ConstantInfo::InvokeDynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
                 }
             }
-            _ => panic!("Unimplemented constant pool info tag: {}", tag),
+            19 => ConstantInfo::Module {
+                name_index: input.u16()?,
+            },
+            20 => ConstantInfo::Package {
+                name_index: input.u16()?,
+            },
+            _ => return Err(input.error_at(tag_offset, ErrorKind::InvalidConstantTag(tag))),
         };
+        let slots = constant_pool_info.slots();
         constant_pool.push(constant_pool_info);
+        if slots == 2 {
+            constant_pool.push(ConstantInfo::Unusable);
+        }
+        index += slots;
     }
     Ok(constant_pool)
 }
 
+/// Decodes the "modified UTF-8" encoding used by the JVM: the null character is encoded in two
+/// bytes and supplementary characters are encoded as surrogate pairs, each in three bytes.
+/// Unpaired surrogates, which Java strings may contain, are replaced by U+FFFD.
+fn decode_modified_utf8(bytes: &[u8]) -> Option<String> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().cloned();
+    while let Some(first) = iter.next() {
+        let unit = match first {
+            0x01..=0x7f => u16::from(first),
+            0xc0..=0xdf => {
+                let second = continuation(iter.next())?;
+                u16::from(first & 0x1f) << 6 | second
+            }
+            0xe0..=0xef => {
+                let second = continuation(iter.next())?;
+                let third = continuation(iter.next())?;
+                u16::from(first & 0x0f) << 12 | second << 6 | third
+            }
+            _ => return None,
+        };
+        units.push(unit);
+    }
+    Some(String::from_utf16_lossy(&units))
+}
+
+fn continuation(byte: Option<u8>) -> Option<u16> {
+    match byte {
+        Some(byte) if byte & 0xc0 == 0x80 => Some(u16::from(byte & 0x3f)),
+        _ => None,
+    }
+}
+
 impl ConstantPool {
-    pub fn lookup(&self, index: u16) -> &ConstantInfo {
-        &self.constants[index as usize - 1]
+    /// Looks up the constant with the given index.
+    /// Indexing starts at 1, and 8-byte constants occupy two indices.
+    pub fn lookup(&self, index: u16) -> Result<&ConstantInfo> {
+        match self.constants.get((index as usize).wrapping_sub(1)) {
+            None | Some(&ConstantInfo::Unusable) => Err(Error::new(
+                Structure::Constant(index),
+                ErrorKind::InvalidConstantIndex(index),
+            )),
+            Some(constant_info) => Ok(constant_info),
+        }
+    }
+
+    pub fn lookup_string(&self, index: u16) -> Result<&str> {
+        match *self.lookup(index)? {
+            ConstantInfo::Utf8(ref s) => Ok(s),
+            _ => Err(Error::new(
+                Structure::Constant(index),
+                ErrorKind::UnexpectedConstant {
+                    index,
+                    expected: "a UTF-8 string",
+                },
+            )),
+        }
     }
 
-    pub fn lookup_string(&self, index: u16) -> &str {
-        match *self.lookup(index) {
-            ConstantInfo::Utf8(ref s) => s,
-            ref constant_info => panic!(
-                "Error: Expected a UTF8 string looking up {} but found: {:?}",
-                index, constant_info
-            ),
+    pub fn lookup_name_and_type(&self, index: u16) -> Result<(u16, u16)> {
+        match *self.lookup(index)? {
+            ConstantInfo::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Ok((name_index, descriptor_index)),
+            _ => Err(Error::new(
+                Structure::Constant(index),
+                ErrorKind::UnexpectedConstant {
+                    index,
+                    expected: "a NameAndType",
+                },
+            )),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a constant pool with a long at #1, a string at #3 and a double at #4.
+    fn parse() -> ConstantPool {
+        let mut bytes = vec![0, 6];
+        bytes.push(5);
+        bytes.extend_from_slice(&7i64.to_be_bytes());
+        bytes.extend_from_slice(&[1, 0, 1, b'x']);
+        bytes.push(6);
+        bytes.extend_from_slice(&1.5f64.to_be_bytes());
+        let constants = parse_constant_pool(&mut Reader::new(&bytes[..])).unwrap();
+        ConstantPool { constants }
+    }
+
+    fn invalid_index(result: Result<&ConstantInfo>) -> Option<u16> {
+        match result {
+            Err(Error {
+                kind: ErrorKind::InvalidConstantIndex(index),
+                ..
+            }) => Some(index),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn eight_byte_constants_take_two_slots() {
+        let constant_pool = parse();
+        assert!(matches!(
+            constant_pool.lookup(1),
+            Ok(&ConstantInfo::Long(7))
+        ));
+        assert_eq!(constant_pool.lookup_string(3).unwrap(), "x");
+        assert!(matches!(constant_pool.lookup(4), Ok(&ConstantInfo::Double(d)) if d == 1.5));
+    }
+
+    #[test]
+    fn slot_after_eight_byte_constant_is_unusable() {
+        let constant_pool = parse();
+        assert_eq!(invalid_index(constant_pool.lookup(2)), Some(2));
+        assert_eq!(invalid_index(constant_pool.lookup(5)), Some(5));
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let constant_pool = parse();
+        assert_eq!(invalid_index(constant_pool.lookup(0)), Some(0));
+        assert_eq!(invalid_index(constant_pool.lookup(6)), Some(6));
+    }
+}
@@ -12,6 +12,7 @@ bitflags! {
         const SYNTHETIC = 0x1000;
         const ANNOTATION = 0x2000;
         const ENUM = 0x4000;
+        const MODULE = 0x8000;
 
         // Methods:
         const SYNCHRONIZED = 0x0020;
@@ -20,6 +21,11 @@ bitflags! {
         const NATIVE = 0x0100;
         const STRICT = 0x0800;
 
-        // TODO: Fields
+        // Fields:
+        const VOLATILE = 0x0040;
+        const TRANSIENT = 0x0080;
+
+        // Method parameters:
+        const MANDATED = 0x8000;
     }
 }
@@ -0,0 +1,244 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::result;
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// An error encountered while parsing a class file.
+#[derive(Debug)]
+pub struct Error {
+    /// Byte offset into the class file, if the error happened while reading it.
+    /// Errors found later, e.g. while resolving constant pool references, have no offset.
+    pub offset: Option<u64>,
+    pub structure: Structure,
+    pub kind: ErrorKind,
+}
+
+/// The part of the class file that was being parsed when an error occurred.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Structure {
+    Header,
+    Constant(u16),
+    ClassInfo,
+    Interfaces,
+    Field(u16),
+    Method(u16),
+    Attribute { name_index: u16 },
+    Descriptor,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// Includes running into the end of the file.
+    Io(io::Error),
+    InvalidMagic(u32),
+    InvalidConstantTag(u8),
+    InvalidReferenceKind(u8),
+    InvalidUtf8,
+    InvalidAccessFlags(u16),
+    InvalidConstantIndex(u16),
+    UnexpectedConstant {
+        index: u16,
+        expected: &'static str,
+    },
+    InvalidDescriptor(String),
+    /// The attribute's content doesn't match its declared length.
+    InvalidAttributeLength(u32),
+    InvalidStackMapFrame(u8),
+    InvalidVerificationType(u8),
+}
+
+impl Error {
+    pub fn new(structure: Structure, kind: ErrorKind) -> Error {
+        Error {
+            offset: None,
+            structure,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(offset) = self.offset {
+            write!(f, "at byte {:#x} ", offset)?;
+        }
+        write!(f, "in {}: {}", self.structure, self.kind)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Structure::Header => write!(f, "class file header"),
+            Structure::Constant(index) => write!(f, "constant #{}", index),
+            Structure::ClassInfo => write!(f, "class info"),
+            Structure::Interfaces => write!(f, "interfaces"),
+            Structure::Field(index) => write!(f, "field #{}", index),
+            Structure::Method(index) => write!(f, "method #{}", index),
+            Structure::Attribute { name_index } => write!(f, "attribute named by #{}", name_index),
+            Structure::Descriptor => write!(f, "descriptor"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Io(ref err) => write!(f, "{}", err),
+            ErrorKind::InvalidMagic(magic) => write!(f, "invalid magic number {:#x}", magic),
+            ErrorKind::InvalidConstantTag(tag) => write!(f, "invalid constant pool tag {}", tag),
+            ErrorKind::InvalidReferenceKind(kind) => {
+                write!(f, "invalid method handle reference kind {}", kind)
+            }
+            ErrorKind::InvalidUtf8 => write!(f, "invalid modified UTF-8 string"),
+            ErrorKind::InvalidAccessFlags(flags) => {
+                write!(f, "invalid access flags {:#06x}", flags)
+            }
+            ErrorKind::InvalidConstantIndex(index) => {
+                write!(f, "constant pool index #{} out of bounds", index)
+            }
+            ErrorKind::UnexpectedConstant { index, expected } => {
+                write!(f, "expected constant #{} to be {}", index, expected)
+            }
+            ErrorKind::InvalidDescriptor(ref descriptor) => {
+                write!(f, "invalid descriptor {:?}", descriptor)
+            }
+            ErrorKind::InvalidAttributeLength(length) => {
+                write!(f, "attribute content doesn't match its length {}", length)
+            }
+            ErrorKind::InvalidStackMapFrame(frame_type) => {
+                write!(f, "invalid stack map frame type {}", frame_type)
+            }
+            ErrorKind::InvalidVerificationType(tag) => {
+                write!(f, "invalid verification type tag {}", tag)
+            }
+        }
+    }
+}
+
+/// Wraps the input of the class file parser and keeps track of the byte offset and the
+/// structure currently being parsed, such that errors can point to the faulty location.
+pub struct Reader<R> {
+    input: R,
+    offset: u64,
+    structure: Structure,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(input: R) -> Reader<R> {
+        Reader {
+            input,
+            offset: 0,
+            structure: Structure::Header,
+        }
+    }
+
+    /// Creates a reader for a part of the class file starting at the given offset.
+    pub fn at(input: R, offset: u64, structure: Structure) -> Reader<R> {
+        Reader {
+            input,
+            offset,
+            structure,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Sets the structure that subsequent errors are attributed to.
+    pub fn enter(&mut self, structure: Structure) {
+        self.structure = structure;
+    }
+
+    /// Creates an error at the current offset.
+    pub fn error(&self, kind: ErrorKind) -> Error {
+        self.error_at(self.offset, kind)
+    }
+
+    pub fn error_at(&self, offset: u64, kind: ErrorKind) -> Error {
+        Error {
+            offset: Some(offset),
+            structure: self.structure,
+            kind,
+        }
+    }
+
+    fn advance<T>(&mut self, size: u64, result: io::Result<T>) -> Result<T> {
+        match result {
+            Ok(value) => {
+                self.offset += size;
+                Ok(value)
+            }
+            Err(err) => Err(self.error(ErrorKind::Io(err))),
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        let result = self.input.read_u8();
+        self.advance(1, result)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let result = self.input.read_u16::<BigEndian>();
+        self.advance(2, result)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let result = self.input.read_u32::<BigEndian>();
+        self.advance(4, result)
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        let result = self.input.read_i32::<BigEndian>();
+        self.advance(4, result)
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        let result = self.input.read_i64::<BigEndian>();
+        self.advance(8, result)
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        let result = self.input.read_f32::<BigEndian>();
+        self.advance(4, result)
+    }
+
+    pub fn f64(&mut self) -> Result<f64> {
+        let result = self.input.read_f64::<BigEndian>();
+        self.advance(8, result)
+    }
+
+    /// Reads `length` bytes, which are only allocated as they are read, since the length comes
+    /// from the file and may be made up.
+    pub fn bytes(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        let result = (&mut self.input)
+            .take(length as u64)
+            .read_to_end(&mut bytes)
+            .and_then(|read| {
+                if read < length {
+                    Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                } else {
+                    Ok(())
+                }
+            });
+        self.advance(length as u64, result).map(|()| bytes)
+    }
+}
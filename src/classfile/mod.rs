@@ -1,3 +1,7 @@
+pub mod attributes;
 pub mod constant_pool;
 pub mod constants;
+pub mod error;
 pub mod parser;
+
+pub use self::error::{Error, ErrorKind, Result, Structure};
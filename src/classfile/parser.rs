@@ -1,33 +1,42 @@
-use byteorder::{BigEndian, ReadBytesExt};
+pub use classfile::attributes::*;
 pub use classfile::constant_pool::*;
 pub use classfile::constants::*;
+use classfile::error::*;
 use std::io::prelude::*;
-use std::io::Result;
+
+pub const MAGIC: u32 = 0xCAFE_BABE;
 
 pub fn parse_class_file<R: Read>(input: &mut R) -> Result<ClassFile> {
-    let magic = input.read_u32::<BigEndian>()?;
-    let minor_version = input.read_u16::<BigEndian>()?;
-    let major_version = input.read_u16::<BigEndian>()?;
-    let constant_pool = parse_constant_pool(input)?;
-    let access_flags = AccessFlags::from_bits(input.read_u16::<BigEndian>()?).unwrap();
-    let this_class = input.read_u16::<BigEndian>()?;
-    let super_class = input.read_u16::<BigEndian>()?;
+    let input = &mut Reader::new(input);
+    let magic = input.u32()?;
+    if magic != MAGIC {
+        return Err(input.error_at(0, ErrorKind::InvalidMagic(magic)));
+    }
+    let minor_version = input.u16()?;
+    let major_version = input.u16()?;
+    let constant_pool = ConstantPool {
+        constants: parse_constant_pool(input)?,
+    };
+    input.enter(Structure::ClassInfo);
+    let access_flags = parse_access_flags(input)?;
+    let this_class = input.u16()?;
+    let super_class = input.u16()?;
     let interfaces = parse_interfaces(input)?;
-    let fields = parse_fields(input)?;
-    let methods = parse_methods(input)?;
+    let fields = parse_fields(input, &constant_pool)?;
+    let methods = parse_methods(input, &constant_pool)?;
+    let attributes = parse_attributes(input, &constant_pool)?;
     Ok(ClassFile {
-        magic: magic,
-        minor_version: minor_version,
-        major_version: major_version,
-        constant_pool: ConstantPool {
-            constants: constant_pool,
-        },
-        access_flags: access_flags,
-        this_class: this_class,
-        super_class: super_class,
-        interfaces: interfaces,
-        fields: fields,
-        methods: methods,
+        magic,
+        minor_version,
+        major_version,
+        constant_pool,
+        access_flags,
+        this_class,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        attributes,
     })
 }
 
@@ -43,47 +52,74 @@ pub struct ClassFile {
     pub interfaces: Vec<u16>,
     pub fields: Vec<FieldInfo>,
     pub methods: Vec<MethodInfo>,
+    pub attributes: Vec<Attribute>,
 }
 
-fn parse_interfaces<R: Read>(input: &mut R) -> Result<Vec<u16>> {
-    let count = input.read_u16::<BigEndian>()?;
-    let interfaces = vec![];
+pub fn parse_access_flags<R: Read>(input: &mut Reader<R>) -> Result<AccessFlags> {
+    let offset = input.offset();
+    let bits = input.u16()?;
+    AccessFlags::from_bits(bits)
+        .ok_or_else(|| input.error_at(offset, ErrorKind::InvalidAccessFlags(bits)))
+}
+
+fn parse_interfaces<R: Read>(input: &mut Reader<R>) -> Result<Vec<u16>> {
+    input.enter(Structure::Interfaces);
+    let count = input.u16()?;
+    let mut interfaces = vec![];
     for _ in 0..count {
-        unimplemented!()
+        interfaces.push(input.u16()?);
     }
     Ok(interfaces)
 }
 
-fn parse_fields<R: Read>(input: &mut R) -> Result<Vec<FieldInfo>> {
-    let count = input.read_u16::<BigEndian>()?;
-    let fields = vec![];
-    for _ in 0..count {
-        unimplemented!()
+fn parse_fields<R: Read>(
+    input: &mut Reader<R>,
+    constant_pool: &ConstantPool,
+) -> Result<Vec<FieldInfo>> {
+    let count = input.u16()?;
+    let mut fields = vec![];
+    for index in 0..count {
+        input.enter(Structure::Field(index));
+        let access_flags = parse_access_flags(input)?;
+        let name_index = input.u16()?;
+        let descriptor_index = input.u16()?;
+        let attributes = parse_attributes(input, constant_pool)?;
+        let field = FieldInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes,
+        };
+        fields.push(field);
     }
     Ok(fields)
 }
 
 #[derive(Debug)]
 pub struct FieldInfo {
-    access_flags: u16,
-    name_index: u16,
-    descriptor_index: u16,
-    attributes: Vec<AttributeInfo>,
+    pub access_flags: AccessFlags,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<Attribute>,
 }
 
-fn parse_methods<R: Read>(input: &mut R) -> Result<Vec<MethodInfo>> {
-    let count = input.read_u16::<BigEndian>()?;
+fn parse_methods<R: Read>(
+    input: &mut Reader<R>,
+    constant_pool: &ConstantPool,
+) -> Result<Vec<MethodInfo>> {
+    let count = input.u16()?;
     let mut methods = vec![];
-    for _ in 0..count {
-        let access_flags = AccessFlags::from_bits(input.read_u16::<BigEndian>()?).unwrap();
-        let name_index = input.read_u16::<BigEndian>()?;
-        let descriptor_index = input.read_u16::<BigEndian>()?;
-        let attributes = parse_attributes(input)?;
+    for index in 0..count {
+        input.enter(Structure::Method(index));
+        let access_flags = parse_access_flags(input)?;
+        let name_index = input.u16()?;
+        let descriptor_index = input.u16()?;
+        let attributes = parse_attributes(input, constant_pool)?;
         let method = MethodInfo {
-            access_flags: access_flags,
-            name_index: name_index,
-            descriptor_index: descriptor_index,
-            attributes: attributes,
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes,
         };
         methods.push(method);
     }
@@ -95,28 +131,5 @@ pub struct MethodInfo {
     pub access_flags: AccessFlags,
     pub name_index: u16,
     pub descriptor_index: u16,
-    pub attributes: Vec<AttributeInfo>,
-}
-
-pub fn parse_attributes<R: Read>(input: &mut R) -> Result<Vec<AttributeInfo>> {
-    let count = input.read_u16::<BigEndian>()?;
-    let mut attributes = vec![];
-    for _ in 0..count {
-        let attribute_name_index = input.read_u16::<BigEndian>()?;
-        let attribute_length = input.read_u32::<BigEndian>()?;
-        let mut info = vec![0; attribute_length as usize];
-        input.read_exact(&mut info)?;
-        let attribute = AttributeInfo {
-            name_index: attribute_name_index,
-            info: info,
-        };
-        attributes.push(attribute);
-    }
-    Ok(attributes)
-}
-
-#[derive(Debug)]
-pub struct AttributeInfo {
-    pub name_index: u16,
-    pub info: Vec<u8>,
+    pub attributes: Vec<Attribute>,
 }
@@ -1,17 +1,75 @@
+pub use decompiler::dominators::Dominators;
 use disassembler::instructions::*;
+use disassembler::pretty::PrettyBody;
 use disassembler::types::*;
 pub use petgraph::graph::*;
 pub use petgraph::visit::*;
 pub use petgraph::*;
 use pretty::*;
 
-use std::collections::BTreeMap;
+use std::fmt;
 
 pub type Label = NodeIndex<LabelIndex>;
 pub type LabelIndex = usize;
-pub type CfgGraph<Stmt, Cond> = Graph<BasicBlock<Stmt, Cond>, bool, Directed, LabelIndex>;
-pub type Edge = bool;
-type Map<K, T> = BTreeMap<K, T>;
+pub type CfgGraph<Stmt, Cond> = Graph<BasicBlock<Stmt, Cond>, Edge, Directed, LabelIndex>;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge {
+    /// Normal control flow: `true` if the block's condition holds or the block jumps
+    /// unconditionally, `false` if it falls through.
+    Branch(bool),
+    /// A case of a switch on the block's terminator, `None` for the default case.
+    Case(Option<i32>),
+    /// The block may throw an exception of the given class (or any exception if `None`),
+    /// which is caught by the target block.
+    Exception(Option<u16>),
+}
+
+impl Edge {
+    pub fn is_exception(&self) -> bool {
+        match *self {
+            Edge::Exception(_) => true,
+            Edge::Branch(_) | Edge::Case(_) => false,
+        }
+    }
+
+    pub fn is_case(&self) -> bool {
+        match *self {
+            Edge::Case(_) => true,
+            Edge::Branch(_) | Edge::Exception(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Edge::Branch(b) => write!(f, "{}", b),
+            Edge::Case(Some(value)) => write!(f, "case {}", value),
+            Edge::Case(None) => write!(f, "default"),
+            Edge::Exception(Some(class_ref)) => write!(f, "catch #{}", class_ref),
+            Edge::Exception(None) => write!(f, "catch any"),
+        }
+    }
+}
+
+/// What a block of instructions branches on at its end.
+#[derive(Copy, Clone, Debug)]
+pub enum Terminator {
+    /// A conditional jump, taking the `true` edge if the condition holds.
+    If(JumpCondition),
+    /// A switch on the int on top of the stack, taking the `Case` edge with its value.
+    Switch,
+}
+
+impl<T> PrettyWith<T> for Terminator {
+    fn pretty_with(&self, context: &T) -> Doc {
+        match *self {
+            Terminator::If(ref condition) => condition.pretty_with(context),
+            Terminator::Switch => doc("stack[-1]"),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Cfg<Stmt, Cond> {
@@ -31,6 +89,13 @@ impl<Stmt, Cond> Cfg<Stmt, Cond> {
     }
 }
 
+impl<Ctx, Stmt, Cond> PrettyBody<Ctx> for Cfg<Stmt, Cond>
+where
+    Stmt: PrettyWith<Ctx>,
+    Cond: PrettyWith<Ctx>,
+{
+}
+
 impl<Ctx, Stmt, Cond> PrettyWith<Ctx> for Cfg<Stmt, Cond>
 where
     Stmt: PrettyWith<Ctx>,
@@ -41,11 +106,15 @@ where
         let block_docs = self.graph.node_references().map(|node_ref| {
             let node_id = node_ref.id();
             let header = doc(format!("#{}:", node_id.index()));
-            let content = node_ref.weight().pretty_with(context);
-            let mut edge_refs = self.graph
+            let content = node_ref
+                .weight()
+                .pretty_as(context, self.terminator_keyword(node_id));
+            let (mut handler_refs, mut edge_refs): (Vec<_>, Vec<_>) = self
+                .graph
                 .edges_directed(node_id, Direction::Outgoing)
-                .collect::<Vec<_>>();
+                .partition(|e| e.weight().is_exception());
             edge_refs.sort_by_key(|&e| e.weight());
+            handler_refs.sort_by_key(|&e| e.weight());
             let gotos = if edge_refs.is_empty() {
                 empty()
             } else if edge_refs.len() == 1 {
@@ -62,127 +131,158 @@ where
                 let gotos = intersperse(gotos, newline());
                 nest(4, newline() + gotos)
             };
-            header + newline() + content + gotos
+            let handlers = handler_refs.iter().map(|edge_ref| {
+                newline()
+                    + format!(
+                        "{} => goto #{}",
+                        edge_ref.weight(),
+                        edge_ref.target().index()
+                    )
+            });
+            header + newline() + content + gotos + concat(handlers)
         });
         header + intersperse(block_docs, newline() + newline())
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Dominators {
-    root: Label,
-    map: Map<Label, Label>,
-    reversed: bool,
-}
-
-impl Dominators {
-    pub fn root(&self) -> Label {
-        self.root
-    }
-
-    pub fn is_for(&self, node: Label, mut check: Label) -> bool {
-        if check == node {
-            return true;
+impl<Stmt, Cond> Cfg<Stmt, Cond> {
+    /// Converts the graph to the DOT language of Graphviz. The blocks are labeled with their
+    /// statements and the edges like in the textual output. Each of the `loops` (given by their
+    /// blocks, see `structure::find_loops`) is drawn as a cluster named like its label, and the
+    /// jumps back to its entry point are bold.
+    pub fn to_dot<Ctx>(&self, context: &Ctx, loops: &[Vec<Label>]) -> String
+    where
+        Stmt: PrettyWith<Ctx>,
+        Cond: PrettyWith<Ctx>,
+    {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        // Loops are nested in the last loop before them that contains their entry point:
+        let innermost_loop = |node: Label, before: usize| {
+            loops[..before]
+                .iter()
+                .rposition(|nodes| nodes.contains(&node))
+        };
+        let mut nested = vec![vec![]; loops.len() + 1];
+        for (i, nodes) in loops.iter().enumerate() {
+            let parent = innermost_loop(nodes[0], i).unwrap_or(loops.len());
+            nested[parent].push(i);
         }
-        while let Some(imm) = self.get_immediate(check) {
-            if imm == node {
-                return true;
-            }
-            check = imm;
+        let mut blocks = vec![vec![]; loops.len() + 1];
+        for node in self.graph.node_indices() {
+            let parent = innermost_loop(node, loops.len()).unwrap_or(loops.len());
+            blocks[parent].push(node);
         }
-        false
-    }
-
-    pub fn get_immediate(&self, node: Label) -> Option<Label> {
-        self.map.get(&node).cloned()
-    }
-
-    pub fn get_all(&self, mut node: Label) -> Vec<Label> {
-        let mut path = vec![node];
-        while let Some(imm) = self.get_immediate(node) {
-            node = imm;
-            path.push(node);
+        self.clusters_to_dot(context, &nested, &blocks, loops.len(), 1, &mut dot);
+        for edge in self.graph.edge_references() {
+            let mut attributes = format!("label=\"{}\"", escape_dot(&edge.weight().to_string()));
+            if edge.weight().is_exception() {
+                attributes.push_str(", style=dashed, color=red");
+            }
+            let is_continue = loops
+                .iter()
+                .any(|nodes| nodes[0] == edge.target() && nodes.contains(&edge.source()));
+            if is_continue {
+                attributes.push_str(", style=bold");
+            }
+            dot.push_str(&format!(
+                "    n{} -> n{} [{}];\n",
+                edge.source().index(),
+                edge.target().index(),
+                attributes
+            ));
         }
-        path
+        dot.push_str("}\n");
+        dot
     }
 
-    // TODO use iterators?
-    pub fn get_common(&self, nodes: &[Label]) -> Option<Label> {
-        if nodes.is_empty() {
-            return None;
-        }
-        let mut paths = vec![];
-        for &node in nodes {
-            let mut path = self.get_all(node);
-            path.reverse();
-            paths.push(path);
-        }
-        let mut nearest = None;
-        for distance in 0..paths[0].len() {
-            let node = paths[0][distance];
-            for path in &paths {
-                if path.get(distance) != Some(&node) {
-                    return nearest;
-                }
+    /// Writes the blocks of the loop with the given index and the loops nested in it, or the
+    /// ones outside of all loops for `loops.len()`.
+    fn clusters_to_dot<Ctx>(
+        &self,
+        context: &Ctx,
+        nested: &[Vec<usize>],
+        blocks: &[Vec<Label>],
+        index: usize,
+        depth: usize,
+        dot: &mut String,
+    ) where
+        Stmt: PrettyWith<Ctx>,
+        Cond: PrettyWith<Ctx>,
+    {
+        let indent = "    ".repeat(depth);
+        for &node in &blocks[index] {
+            let header = if node == self.entry_point {
+                format!("#{}: entry", node.index())
+            } else if node == self.exit_point {
+                format!("#{}: exit", node.index())
+            } else {
+                format!("#{}:", node.index())
+            };
+            let content = self.graph[node]
+                .pretty_as(context, self.terminator_keyword(node))
+                .render_string(80);
+            // Each line is left-justified by ending it with `\l`:
+            let mut label = String::new();
+            for line in Some(header.as_str()).into_iter().chain(content.lines()) {
+                label.push_str(&escape_dot(line));
+                label.push_str("\\l");
             }
-            nearest = Some(node);
+            dot.push_str(&format!(
+                "{}n{} [label=\"{}\"];\n",
+                indent,
+                node.index(),
+                label
+            ));
+        }
+        for &lupe in &nested[index] {
+            dot.push_str(&format!("{}subgraph cluster_{} {{\n", indent, lupe));
+            dot.push_str(&format!("{}    label=\"loop_{}\";\n", indent, lupe));
+            self.clusters_to_dot(context, nested, blocks, lupe, depth + 1, dot);
+            dot.push_str(&format!("{}}}\n", indent));
         }
-        nearest
     }
 
-    fn pretty_from(&self, root: Label, reverse_map: &Map<Label, Vec<Label>>) -> Doc {
-        let children = if let Some(children) = reverse_map.get(&root) {
-            children
-        } else {
-            return newline() + doc(root.index());
-        };
-        let subtree = intersperse(
-            children.iter().map(|&l| self.pretty_from(l, reverse_map)),
-            empty(),
-        );
-        if self.reversed {
-            nest(2, subtree) + newline() + doc(root.index())
+    /// The keyword introducing the terminator of the block, `switch` or `if`.
+    fn terminator_keyword(&self, node: Label) -> &'static str {
+        let is_switch = self
+            .graph
+            .edges_directed(node, Direction::Outgoing)
+            .any(|e| e.weight().is_case());
+        if is_switch {
+            "switch"
         } else {
-            newline() + doc(root.index()) + nest(2, subtree)
+            "if"
         }
     }
-}
 
-impl<T> PrettyWith<T> for Dominators {
-    fn pretty_with(&self, _: &T) -> Doc {
-        let mut reverse_map = Map::new();
-        for (&node, &parent) in &self.map {
-            reverse_map
-                .entry(parent)
-                .or_insert_with(Vec::new)
-                .push(node);
-        }
-        doc("(Post)Dominators:") + self.pretty_from(self.root(), &reverse_map)
-    }
-}
-
-impl<Stmt, Cond> Cfg<Stmt, Cond> {
+    /// Computes the dominators of the blocks, or their postdominators if `post`.
     pub fn compute_dominators(&self, post: bool) -> Dominators {
-        let mut map = Map::new();
-        let dominators = if post {
-            algo::dominators::simple_fast(visit::Reversed(&self.graph), self.exit_point)
+        if post {
+            // Exceptional control flow is ignored because the blocks that may throw would be
+            // postdominated by nothing but the exit point otherwise.
+            // The function is left at the exit point, but blocks may have lost their successors
+            // in a pass, so they are exits as well.
+            let exits = self
+                .graph
+                .node_indices()
+                .filter(|&node| {
+                    self.graph
+                        .edges_directed(node, Direction::Outgoing)
+                        .all(|edge| edge.weight().is_exception())
+                })
+                .collect::<Vec<_>>();
+            Dominators::compute(&self.graph, &exits, true, |edge| !edge.is_exception())
         } else {
-            algo::dominators::simple_fast(&self.graph, self.entry_point)
-        };
-        for node in self.graph.node_indices() {
-            if let Some(dom) = dominators.immediate_dominator(node) {
-                map.insert(node, dom);
-            }
-        }
-        let root = dominators.root();
-        Dominators {
-            root: root,
-            map: map,
-            reversed: post,
+            Dominators::compute(&self.graph, &[self.entry_point], false, |_| true)
         }
     }
 }
 
+/// Escapes the text for a quoted string in the DOT language.
+pub fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
 #[derive(Clone, Debug)]
 pub struct BasicBlock<Stmt, Cond> {
     pub stmts: Vec<Stmt>,
@@ -198,15 +298,20 @@ impl<Stmt, Cond> Default for BasicBlock<Stmt, Cond> {
     }
 }
 
-impl<Ctx, Stmt, Cond> PrettyWith<Ctx> for BasicBlock<Stmt, Cond>
-where
-    Stmt: PrettyWith<Ctx>,
-    Cond: PrettyWith<Ctx>,
-{
-    fn pretty_with(&self, context: &Ctx) -> Doc {
+impl<Stmt, Cond> BasicBlock<Stmt, Cond> {
+    /// Prints the block with its terminator introduced by the given keyword (`if` or `switch`).
+    fn pretty_as<Ctx>(&self, context: &Ctx, keyword: &str) -> Doc
+    where
+        Stmt: PrettyWith<Ctx>,
+        Cond: PrettyWith<Ctx>,
+    {
         let stmts = self.stmts.iter().map(|stmt| stmt.pretty_with(context));
         let terminator = if let Some(ref condition) = self.terminator {
-            newline() + nest(4, doc("if (") + condition.pretty_with(context) + ")")
+            newline()
+                + nest(
+                    4,
+                    doc(keyword) + " (" + condition.pretty_with(context) + ")",
+                )
         } else {
             empty()
         };
@@ -214,10 +319,32 @@ where
     }
 }
 
-pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
+impl<Ctx, Stmt, Cond> PrettyWith<Ctx> for BasicBlock<Stmt, Cond>
+where
+    Stmt: PrettyWith<Ctx>,
+    Cond: PrettyWith<Ctx>,
+{
+    fn pretty_with(&self, context: &Ctx) -> Doc {
+        self.pretty_as(context, "if")
+    }
+}
+
+/// Splits the instructions into basic blocks. They keep their addresses.
+pub fn build_cfg(code: Code) -> Cfg<(u16, Instruction), Terminator> {
     use std::collections::HashMap;
     use std::collections::HashSet;
-    let instrs = code.instructions;
+    let mut instrs = code.instructions;
+    let mut handlers = code.exception_handlers;
+    // The instructions after an invalid one (see `--lenient`) may have been decoded from its
+    // operands, so only the invalid instruction is kept. The method fails to decompile then.
+    let invalid = instrs
+        .iter()
+        .position(|(_, instr)| matches!(*instr, Instruction::Invalid(_)));
+    if let Some(index) = invalid {
+        instrs = instrs.split_off(index);
+        instrs.truncate(1);
+        handlers.clear();
+    }
 
     let mut index_to_pc = HashMap::new();
     let mut pc_to_index = HashMap::new();
@@ -244,7 +371,18 @@ pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
                 // branch address starts a block:
                 bb_starts.insert(pc_to_index[&address]);
             }
-            Instruction::Return(_) => {
+            Instruction::Switch(ref switch) => {
+                // next instruction starts a block:
+                if let Some(next_pc) = next_pc {
+                    bb_starts.insert(next_pc);
+                }
+                // all the case addresses start blocks:
+                bb_starts.insert(pc_to_index[&switch.default]);
+                for &(_, address) in &switch.cases {
+                    bb_starts.insert(pc_to_index[&address]);
+                }
+            }
+            Instruction::Return(_) | Instruction::Throw | Instruction::Invalid(_) => {
                 // next instruction starts a block:
                 if let Some(next_pc) = next_pc {
                     bb_starts.insert(next_pc);
@@ -253,6 +391,14 @@ pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
             _ => (),
         }
     }
+    for handler in &handlers {
+        // the handler and the boundaries of the protected range start blocks:
+        for pc in &[handler.handler, handler.start, handler.end] {
+            if let Some(&index) = pc_to_index.get(pc) {
+                bb_starts.insert(index);
+            }
+        }
+    }
     let mut bb_starts = bb_starts.iter().cloned().collect::<Vec<_>>();
     bb_starts.sort();
 
@@ -267,9 +413,11 @@ pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
     bb_starts.reverse();
 
     let mut pc_to_bb_id = HashMap::new();
+    let mut bb_start_pcs = vec![];
     for (i, &start_index) in bb_starts.iter().enumerate() {
         let start_pc = index_to_pc[&start_index];
         pc_to_bb_id.insert(start_pc, i);
+        bb_start_pcs.push(start_pc);
     }
 
     let mut bbs = vec![];
@@ -280,21 +428,28 @@ pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
         match block.last().unwrap().1 {
             Instruction::Jump(Jump { condition, address }) => {
                 if condition.is_some() {
-                    edges.push((block_id, block_id + 1, false));
-                    terminator = condition;
+                    edges.push((block_id, block_id + 1, Edge::Branch(false)));
+                    terminator = condition.map(Terminator::If);
+                }
+                edges.push((block_id, pc_to_bb_id[&address], Edge::Branch(true)));
+                delete_last = true;
+            }
+            Instruction::Switch(ref switch) => {
+                for &(value, address) in &switch.cases {
+                    edges.push((block_id, pc_to_bb_id[&address], Edge::Case(Some(value))));
                 }
-                edges.push((block_id, pc_to_bb_id[&address], true));
+                edges.push((block_id, pc_to_bb_id[&switch.default], Edge::Case(None)));
+                terminator = Some(Terminator::Switch);
                 delete_last = true;
             }
-            Instruction::Return(_) => {}
+            Instruction::Return(_) | Instruction::Throw | Instruction::Invalid(_) => {}
             _ => {
-                edges.push((block_id, block_id + 1, false));
+                edges.push((block_id, block_id + 1, Edge::Branch(false)));
             }
         }
         if delete_last {
             block.pop().unwrap();
         }
-        let block = block.drain(..).map(|(_, instr)| instr).collect::<Vec<_>>();
         let bb = BasicBlock {
             stmts: block,
             terminator: terminator,
@@ -302,10 +457,24 @@ pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
         bbs.push(bb);
     }
 
+    for handler in &handlers {
+        // javac protects parts of some handlers by the handler itself, e.g. for `synchronized`.
+        // Those edges aren't needed to structure the code.
+        if handler.start <= handler.handler && handler.handler < handler.end {
+            continue;
+        }
+        let handler_id = pc_to_bb_id[&handler.handler];
+        for (block_id, &start_pc) in bb_start_pcs.iter().enumerate() {
+            if handler.start <= start_pc && start_pc < handler.end {
+                edges.push((block_id, handler_id, Edge::Exception(handler.catch_type)));
+            }
+        }
+    }
+
     // Create empty function entry block:
     bbs.push(BasicBlock::default());
     let entry_point = bbs.len() - 1;
-    edges.push((entry_point, 0, false));
+    edges.push((entry_point, 0, Edge::Branch(false)));
     let entry_point = entry_point.into();
 
     // Create empty function exit block:
@@ -326,15 +495,15 @@ pub fn build_cfg(code: Code) -> Cfg<Instruction, JumpCondition> {
     let mut outdegree_zero_nodes = vec![];
     for node in cfg.graph.node_indices() {
         let outdegree_zero = {
-            let mut outgoing = cfg.graph.neighbors_directed(node, Direction::Outgoing);
-            outgoing.next().is_none()
+            let mut outgoing = cfg.graph.edges_directed(node, Direction::Outgoing);
+            outgoing.all(|edge| edge.weight().is_exception())
         };
         if outdegree_zero && node != exit_point {
             outdegree_zero_nodes.push(node);
         }
     }
     for node in outdegree_zero_nodes {
-        cfg.graph.add_edge(node, exit_point, false);
+        cfg.graph.add_edge(node, exit_point, Edge::Branch(false));
     }
     cfg
 }
@@ -1,49 +1,260 @@
 pub use decompiler::cfg::*;
+pub use decompiler::passes::switches::SwitchMaps;
 pub use decompiler::passes::*;
 pub use decompiler::types::*;
 pub use disassembler::types::*;
 
-pub fn decompile(unit: CompilationUnit<Code>, verbose: bool) -> CompilationUnit<Block> {
+use std::{fmt, mem};
+
+/// The passes of the decompiler, in the order in which they are run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Cfg,
+    StackToVar,
+    VarProp,
+    Structure,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 4] = [Pass::Cfg, Pass::StackToVar, Pass::VarProp, Pass::Structure];
+
+    /// The name used to select the pass on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Cfg => "cfg",
+            Pass::StackToVar => "stack-to-var",
+            Pass::VarProp => "var-prop",
+            Pass::Structure => "structure",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.iter().cloned().find(|pass| pass.name() == name)
+    }
+
+    /// The heading of the pass's output in dumps.
+    pub fn title(self) -> &'static str {
+        match self {
+            Pass::Cfg => "PASS 1: CONTROL FLOW GRAPH",
+            Pass::StackToVar => "PASS 2: STACK TO VARIABLES",
+            Pass::VarProp => "PASS 3: VARIABLE PROPAGATION",
+            Pass::Structure => "PASS 4: STRUCTURE THE CONTROL FLOW GRAPH",
+        }
+    }
+}
+
+/// Runs all passes on the disassembled unit and its member classes. The methods generated by the
+/// compiler are dropped, since they aren't part of the source code.
+/// `switch_maps` are used to recover switches on enums, see `SwitchMaps`.
+/// `dump` is called with the result of each pass, e.g. to print it for debugging.
+/// `warn` is called with a message about each method whose code can't be decompiled or can't be
+/// reproduced faithfully.
+/// `graph` is called with the name and the control flow graph of each method before structuring.
+pub fn decompile<F, W, G>(
+    unit: CompilationUnit<Code>,
+    switch_maps: &SwitchMaps,
+    mut dump: F,
+    mut warn: W,
+    mut graph: G,
+) -> CompilationUnit<Block>
+where
+    F: FnMut(Pass, &dyn fmt::Display),
+    W: FnMut(String),
+    G: FnMut(&str, &Cfg<Statement, Expr>),
+{
+    let mut unit = decompile_class(unit, switch_maps, &mut dump, &mut warn, &mut graph);
+    // The member classes share the imports of the top-level class:
+    imports::shorten_names(&mut unit);
+    dump(Pass::Structure, &unit);
+    unit
+}
+
+/// Runs the passes except for shortening the names on the class and its member classes.
+fn decompile_class(
+    mut unit: CompilationUnit<Code>,
+    switch_maps: &SwitchMaps,
+    dump: &mut dyn FnMut(Pass, &dyn fmt::Display),
+    warn: &mut dyn FnMut(String),
+    graph: &mut dyn FnMut(&str, &Cfg<Statement, Expr>),
+) -> CompilationUnit<Block> {
+    let nested = mem::take(&mut unit.nested);
+    let needed_bridges = needed_bridges(&unit);
+    unit.declarations.retain(|declaration| match *declaration {
+        Declaration::Method(ref method) => {
+            let key = (method.name.clone(), parameter_types(&method.signature));
+            !method.synthetic || needed_bridges.contains(&key)
+        }
+        _ => true,
+    });
     let unit = unit.map(|c, _| build_cfg(c));
-    if verbose {
-        println!(
-            r#"
-PASS 1: CONTROL FLOW GRAPH:
-===========================
-{}"#,
-            unit
-        );
+    dump(Pass::Cfg, &unit);
+    let unit = stack_to_var::stack_to_vars(unit, &mut *warn);
+    dump(Pass::StackToVar, &unit);
+    let unit = variables::split_variables(unit);
+    let unit = var_prop::var_prop(unit);
+    // The objects created with `new` are only propagated once their constructor call is merged:
+    let unit = constructors::merge_object_creations(unit);
+    let unit = var_prop::var_prop(unit);
+    // Merging the conditions makes it possible to propagate more variables:
+    let mut unit = conditions::merge_conditions(unit);
+    type_inference::infer_types(&mut unit);
+    constructors::handle_constructors(&mut unit, &mut *warn);
+    let unit = var_prop::var_prop(unit);
+    // Likewise the arrays, once their elements are merged into an array initializer:
+    let unit = arrays::recover_array_initializers(unit);
+    let unit = var_prop::var_prop(unit);
+    dump(Pass::VarProp, &unit);
+    let mut unit = structure::structure(unit, &mut *warn, &mut *graph);
+    synchronized::recover_synchronized(&mut unit);
+    switches::recover_switches(&mut unit, switch_maps);
+    loops::recover_for_loops(&mut unit);
+    variables::declare_variables(&mut unit);
+    enums::recover_enum_constants(&mut unit);
+    if let Err(err) = initializers::recover_static_initializer(&mut unit) {
+        warn(format!("`<clinit>` can't be decompiled: {}", err));
     }
-    let unit = stack_to_var::stack_to_vars(unit);
-    if verbose {
-        println!(
-            r#"
-PASS 2: STACK TO VARIABLES:
-===========================
-{}"#,
-            unit
-        );
+    member_classes::recover_outer_instance(&mut unit);
+    unit.nested = nested
+        .into_iter()
+        .map(|nested| decompile_class(nested, switch_maps, dump, warn, graph))
+        .collect();
+    unit
+}
+
+/// The names and parameter types of the bridge methods that are kept. Since the types of the
+/// decompiled code are raw, a class implementing `Comparable<T>` needs the bridge method
+/// `compareTo(Object)`. A bridge method with the same parameter types as another method only
+/// differs in its return type, which overriding with a covariant return type doesn't need.
+fn needed_bridges<C>(unit: &CompilationUnit<C>) -> Vec<(String, Vec<Type>)> {
+    let methods = unit
+        .declarations
+        .iter()
+        .filter_map(|declaration| match *declaration {
+            Declaration::Method(ref method) => Some(method),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    methods
+        .iter()
+        .filter(|bridge| bridge.bridge)
+        .filter(|bridge| {
+            !methods.iter().any(|method| {
+                !method.bridge
+                    && method.name == bridge.name
+                    && parameter_types(&method.signature) == parameter_types(&bridge.signature)
+            })
+        })
+        .map(|bridge| (bridge.name.clone(), parameter_types(&bridge.signature)))
+        .collect()
+}
+
+fn parameter_types(signature: &Signature) -> Vec<Type> {
+    signature
+        .parameters
+        .iter()
+        .map(|parameter| parameter.1.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use classfile::parser::parse_class_file;
+    use disassembler::transform::transform;
+    use std::io::ErrorKind;
+    use std::path::Path;
+    use std::process::{self, Command};
+    use std::{env, fs};
+
+    fn read_class(bytes: &[u8]) -> CompilationUnit<Code> {
+        transform(&parse_class_file(&mut &bytes[..]).unwrap(), false).unwrap()
     }
-    let mut unit = var_prop::var_prop(unit);
-    constructors::handle_constructors(&mut unit);
-    if verbose {
-        println!(
-            r#"
-PASS 3: VARIABLE PROPAGATION:
-===========================
-{}"#,
-            unit
+
+    /// Decompiles the class, compiles the result with `javac` and runs `check` in the directory
+    /// of the class files, unless `javac` isn't installed.
+    fn round_trip<F>(unit: CompilationUnit<Code>, check: F)
+    where
+        F: FnOnce(&Path) -> Result<(), String>,
+    {
+        let name = unit.name.clone();
+        let unit = decompile(
+            unit,
+            &SwitchMaps::new(),
+            |_, _| (),
+            |warning| panic!("{}", warning),
+            |_, _| (),
         );
+        let directory = env::temp_dir().join(format!("unjavac-{}-{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let source = directory.join(format!("{}.java", name));
+        fs::write(&source, unit.to_string()).unwrap();
+        let output = Command::new("javac")
+            .arg("-d")
+            .arg(&directory)
+            .arg(&source)
+            .output();
+        let result = match output {
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                eprintln!("javac isn't installed, the decompiled code isn't compiled");
+                Ok(())
+            }
+            Err(err) => Err(err.to_string()),
+            Ok(ref output) if !output.status.success() => Err(format!(
+                "javac rejects the decompiled code:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )),
+            Ok(_) => check(&directory),
+        };
+        fs::remove_dir_all(&directory).unwrap();
+        if let Err(err) = result {
+            panic!("{}\n{}", err, unit);
+        }
     }
-    let unit = structure::structure(unit);
-    if verbose {
-        println!(
-            r#"
-PASS 4: STRUCTURE THE CONRTOL FLOW GRAPH:
-=========================================
-{}"#,
-            unit
-        );
+
+    /// Decompiles `testdata/RoundTrip.class` with its member classes and compiles the result.
+    #[test]
+    fn round_trip_compiles() {
+        let mut unit = read_class(include_bytes!("../../testdata/RoundTrip.class"));
+        unit.nested = vec![
+            read_class(include_bytes!("../../testdata/RoundTrip$Counter.class")),
+            read_class(include_bytes!("../../testdata/RoundTrip$Limits.class")),
+        ];
+        round_trip(unit, |_| Ok(()));
+    }
+
+    /// Runs the class in the directory and checks what it prints.
+    fn run(directory: &Path, class: &str, expected: &str) -> Result<(), String> {
+        let output = Command::new("java")
+            .arg("-cp")
+            .arg(directory)
+            .arg(class)
+            .output()
+            .map_err(|err| err.to_string())?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout == expected {
+            Ok(())
+        } else {
+            Err(format!("the decompiled code prints\n{}", stdout))
+        }
+    }
+
+    /// Decompiles `testdata/Evaluation.class` and checks that the result prints the same as the
+    /// original class.
+    #[test]
+    fn round_trip_evaluates_in_order() {
+        let unit = read_class(include_bytes!("../../testdata/Evaluation.class"));
+        round_trip(unit, |directory| {
+            run(directory, "Evaluation", "15\n27\n19\n53\n305\n5\n")
+        });
+    }
+
+    /// Decompiles `testdata/Variables.class`, whose variables are split and named again, and
+    /// checks that the result prints the same as the original class.
+    #[test]
+    fn round_trip_keeps_variables_apart() {
+        let unit = read_class(include_bytes!("../../testdata/Variables.class"));
+        round_trip(unit, |directory| {
+            run(directory, "Variables", "307\n366\n3\n")
+        });
     }
-    unit
 }
@@ -0,0 +1,499 @@
+//! Dominator trees of control flow graphs, computed with the algorithm of Cooper, Harvey and
+//! Kennedy ("A Simple, Fast Dominance Algorithm").
+//!
+//! The graph may have several roots, e.g. the exits of a function for postdominators. They are
+//! the children of a virtual root, so the nodes reachable from several roots don't have an
+//! immediate dominator. The tree is numbered along an Euler tour with a sparse table of the
+//! minimum depths, which answers the queries for the nearest common dominator in constant time.
+
+use decompiler::cfg::*;
+use pretty::*;
+
+/// The virtual root is the node after the last node of the graph.
+fn virtual_root(node_count: usize) -> usize {
+    node_count
+}
+
+#[derive(Clone, Debug)]
+pub struct Dominators {
+    roots: Vec<Label>,
+    /// The immediate dominator of each node, `None` for the roots and the unreachable nodes.
+    immediate: Vec<Option<Label>>,
+    /// The nodes immediately dominated by each node, and by the virtual root at the end.
+    children: Vec<Vec<Label>>,
+    frontiers: Vec<Vec<Label>>,
+    /// The nodes of the tree (including the virtual root) along an Euler tour, with their depths.
+    tour: Vec<(usize, usize)>,
+    /// The index of the first occurrence of each node in the tour, `None` if it's unreachable.
+    first: Vec<Option<usize>>,
+    /// `sparse[k][i]` is the index of the node with the minimum depth in `tour[i..i + 2^k]`.
+    sparse: Vec<Vec<usize>>,
+    reversed: bool,
+}
+
+impl Dominators {
+    /// Computes the dominators of the nodes reachable from `roots`, or their postdominators if
+    /// `reversed` (the edges are followed backwards then). Only the edges that satisfy `follow`
+    /// are considered.
+    pub fn compute<Stmt, Cond, F>(
+        graph: &CfgGraph<Stmt, Cond>,
+        roots: &[Label],
+        reversed: bool,
+        follow: F,
+    ) -> Dominators
+    where
+        F: Fn(&Edge) -> bool,
+    {
+        let count = graph.node_count();
+        let root = virtual_root(count);
+        let mut successors = vec![vec![]; count + 1];
+        let mut predecessors = vec![vec![]; count + 1];
+        for edge in graph.edge_references() {
+            if !follow(edge.weight()) {
+                continue;
+            }
+            let (from, to) = if reversed {
+                (edge.target().index(), edge.source().index())
+            } else {
+                (edge.source().index(), edge.target().index())
+            };
+            // Switches may have several cases with the same target:
+            if !successors[from].contains(&to) {
+                successors[from].push(to);
+                predecessors[to].push(from);
+            }
+        }
+        for &node in roots {
+            successors[root].push(node.index());
+            predecessors[node.index()].push(root);
+        }
+        let postorder = postorder(&successors, root);
+        let mut number = vec![None; count + 1];
+        for (i, &node) in postorder.iter().enumerate() {
+            number[node] = Some(i);
+        }
+        let mut idom = vec![None; count + 1];
+        idom[root] = Some(root);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in postorder.iter().rev().skip(1) {
+                let mut new_idom = None;
+                for &pred in &predecessors[node] {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, &number, pred, other),
+                    });
+                }
+                if new_idom != idom[node] {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        let mut frontiers = vec![vec![]; count];
+        for (node, preds) in predecessors[..count].iter().enumerate() {
+            let node_idom = match idom[node] {
+                Some(node_idom) if preds.len() >= 2 => node_idom,
+                _ => continue,
+            };
+            for &pred in preds {
+                let mut runner = pred;
+                while runner != node_idom && idom[runner].is_some() {
+                    if !frontiers[runner].contains(&node) {
+                        frontiers[runner].push(node);
+                    }
+                    runner = idom[runner].unwrap();
+                }
+            }
+        }
+        let mut children = vec![vec![]; count + 1];
+        for (node, &parent) in idom[..count].iter().enumerate() {
+            if let Some(parent) = parent {
+                children[parent].push(node);
+            }
+        }
+        let (tour, first, sparse) = number_tree(&children, root);
+        let label = |node: usize| Label::new(node);
+        Dominators {
+            roots: roots.to_vec(),
+            immediate: idom[..count]
+                .iter()
+                .map(|&parent| parent.filter(|&parent| parent != root).map(label))
+                .collect(),
+            children: children
+                .into_iter()
+                .map(|nodes| nodes.into_iter().map(label).collect())
+                .collect(),
+            frontiers: frontiers
+                .into_iter()
+                .map(|nodes| nodes.into_iter().map(label).collect())
+                .collect(),
+            tour,
+            first,
+            sparse,
+            reversed,
+        }
+    }
+
+    pub fn roots(&self) -> &[Label] {
+        &self.roots
+    }
+
+    /// Whether `node` dominates `check`. Every node dominates itself.
+    pub fn is_for(&self, node: Label, check: Label) -> bool {
+        node == check || self.common_ancestor(node, check) == Some(node)
+    }
+
+    pub fn get_immediate(&self, node: Label) -> Option<Label> {
+        self.immediate.get(node.index()).cloned().unwrap_or(None)
+    }
+
+    /// The dominators of the node, starting with itself.
+    pub fn get_all(&self, mut node: Label) -> Vec<Label> {
+        let mut path = vec![node];
+        while let Some(imm) = self.get_immediate(node) {
+            node = imm;
+            path.push(node);
+        }
+        path
+    }
+
+    /// The nearest node that dominates all of the given nodes, if any.
+    pub fn get_common<I>(&self, nodes: I) -> Option<Label>
+    where
+        I: IntoIterator<Item = Label>,
+    {
+        let mut nodes = nodes.into_iter();
+        let first = nodes.next()?;
+        nodes.try_fold(first, |common, node| {
+            if common == node {
+                Some(common)
+            } else {
+                self.common_ancestor(common, node)
+            }
+        })
+    }
+
+    /// The dominance frontier of the node: the nodes that it doesn't strictly dominate but that
+    /// have a predecessor that it dominates. For postdominators, these are the nodes that
+    /// decide whether the node is executed.
+    pub fn frontier(&self, node: Label) -> &[Label] {
+        self.frontiers
+            .get(node.index())
+            .map_or(&[], |nodes| nodes.as_slice())
+    }
+
+    /// Converts the tree to the DOT language of Graphviz, with an edge from each node to the
+    /// nodes it immediately dominates. The nodes are named like the blocks in `Cfg::to_dot`.
+    pub fn to_dot(&self) -> String {
+        let name = if self.reversed {
+            "postdominators"
+        } else {
+            "dominators"
+        };
+        let mut dot = format!(
+            "digraph {} {{\n    node [shape=box, fontname=monospace];\n",
+            name
+        );
+        for (node, &parent) in self.immediate.iter().enumerate() {
+            if parent.is_some() || self.roots.contains(&Label::new(node)) {
+                dot.push_str(&format!("    n{} [label=\"#{}\"];\n", node, node));
+            }
+        }
+        for (node, &parent) in self.immediate.iter().enumerate() {
+            if let Some(parent) = parent {
+                dot.push_str(&format!("    n{} -> n{};\n", parent.index(), node));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Finds the nearest common ancestor of two distinct nodes in the tree, `None` if it's the
+    /// virtual root or one of them is unreachable.
+    fn common_ancestor(&self, a: Label, b: Label) -> Option<Label> {
+        let a = (*self.first.get(a.index())?)?;
+        let b = (*self.first.get(b.index())?)?;
+        let (start, end) = if a <= b { (a, b) } else { (b, a) };
+        let level = log2(end - start + 1);
+        let left = self.sparse[level][start];
+        let right = self.sparse[level][end + 1 - (1 << level)];
+        let (node, _) = if self.tour[left].1 <= self.tour[right].1 {
+            self.tour[left]
+        } else {
+            self.tour[right]
+        };
+        if node == virtual_root(self.immediate.len()) {
+            None
+        } else {
+            Some(Label::new(node))
+        }
+    }
+
+    fn pretty_from(&self, root: Label) -> Doc {
+        let children = &self.children[root.index()];
+        if children.is_empty() {
+            return newline() + doc(root.index());
+        }
+        let subtree = concat(children.iter().map(|&l| self.pretty_from(l)));
+        if self.reversed {
+            nest(2, subtree) + newline() + doc(root.index())
+        } else {
+            newline() + doc(root.index()) + nest(2, subtree)
+        }
+    }
+}
+
+impl<T> PrettyWith<T> for Dominators {
+    fn pretty_with(&self, _: &T) -> Doc {
+        let roots = &self.children[virtual_root(self.immediate.len())];
+        let trees = roots.iter().map(|&root| self.pretty_from(root));
+        doc("(Post)Dominators:") + concat(trees)
+    }
+}
+
+/// The nodes reachable from `root` in postorder.
+fn postorder(successors: &[Vec<usize>], root: usize) -> Vec<usize> {
+    let mut visited = vec![false; successors.len()];
+    let mut order = vec![];
+    // Each entry is a node and the index of its next successor to visit:
+    let mut stack = vec![(root, 0)];
+    visited[root] = true;
+    while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+        if let Some(&successor) = successors[node].get(*next) {
+            *next += 1;
+            if !visited[successor] {
+                visited[successor] = true;
+                stack.push((successor, 0));
+            }
+        } else {
+            order.push(node);
+            stack.pop();
+        }
+    }
+    order
+}
+
+/// Finds the nearest common dominator of two nodes while the dominators are computed.
+fn intersect(
+    idom: &[Option<usize>],
+    number: &[Option<usize>],
+    mut a: usize,
+    mut b: usize,
+) -> usize {
+    while a != b {
+        while number[a] < number[b] {
+            a = idom[a].unwrap();
+        }
+        while number[b] < number[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
+
+type Numbering = (Vec<(usize, usize)>, Vec<Option<usize>>, Vec<Vec<usize>>);
+
+/// Walks the tree along an Euler tour, see `Dominators`.
+fn number_tree(children: &[Vec<usize>], root: usize) -> Numbering {
+    let mut tour = vec![];
+    let mut first = vec![None; children.len() - 1];
+    let mut stack = vec![(root, 0)];
+    while !stack.is_empty() {
+        let depth = stack.len() - 1;
+        let (node, ref mut next) = stack[depth];
+        if *next == 0 && node != root {
+            first[node] = Some(tour.len());
+        }
+        tour.push((node, depth));
+        if let Some(&child) = children[node].get(*next) {
+            *next += 1;
+            stack.push((child, 0));
+        } else {
+            stack.pop();
+        }
+    }
+    let mut sparse = vec![(0..tour.len()).collect::<Vec<_>>()];
+    let mut width = 1;
+    while 2 * width <= tour.len() {
+        let row = {
+            let previous = &sparse[sparse.len() - 1];
+            (0..tour.len() + 1 - 2 * width)
+                .map(|i| {
+                    let (left, right) = (previous[i], previous[i + width]);
+                    if tour[left].1 <= tour[right].1 {
+                        left
+                    } else {
+                        right
+                    }
+                })
+                .collect()
+        };
+        sparse.push(row);
+        width *= 2;
+    }
+    (tour, first, sparse)
+}
+
+fn log2(n: usize) -> usize {
+    (usize::BITS - 1 - n.leading_zeros()) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a graph with the given number of nodes and unconditional edges between them.
+    fn build(count: usize, edges: &[(usize, usize)]) -> CfgGraph<(), ()> {
+        let mut graph = CfgGraph::default();
+        for _ in 0..count {
+            graph.add_node(BasicBlock::default());
+        }
+        for &(from, to) in edges {
+            graph.add_edge(Label::new(from), Label::new(to), Edge::Branch(true));
+        }
+        graph
+    }
+
+    fn labels(nodes: &[usize]) -> Vec<Label> {
+        nodes.iter().map(|&node| Label::new(node)).collect()
+    }
+
+    fn frontier(dominators: &Dominators, node: usize) -> Vec<Label> {
+        let mut frontier = dominators.frontier(Label::new(node)).to_vec();
+        frontier.sort();
+        frontier
+    }
+
+    /// Checks the constant time queries against walking up the tree from the nodes.
+    fn check_queries(dominators: &Dominators, count: usize) {
+        for a in (0..count).map(Label::new) {
+            let path = dominators.get_all(a);
+            for b in (0..count).map(Label::new) {
+                let other = dominators.get_all(b);
+                assert_eq!(
+                    dominators.is_for(a, b),
+                    other.contains(&a),
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+                let common = other.into_iter().find(|node| path.contains(node));
+                assert_eq!(dominators.get_common(vec![a, b]), common, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn diamond() {
+        let graph = build(4, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+        let dominators = Dominators::compute(&graph, &labels(&[0]), false, |_| true);
+        assert_eq!(dominators.get_immediate(Label::new(0)), None);
+        for node in 1..4 {
+            assert_eq!(
+                dominators.get_immediate(Label::new(node)),
+                Some(Label::new(0))
+            );
+        }
+        assert_eq!(dominators.get_common(labels(&[1, 2])), Some(Label::new(0)));
+        assert_eq!(dominators.get_common(labels(&[3, 3])), Some(Label::new(3)));
+        assert!(!dominators.is_for(Label::new(1), Label::new(3)));
+        assert_eq!(frontier(&dominators, 0), vec![]);
+        assert_eq!(frontier(&dominators, 1), labels(&[3]));
+        assert_eq!(frontier(&dominators, 2), labels(&[3]));
+        assert_eq!(frontier(&dominators, 3), vec![]);
+        check_queries(&dominators, 4);
+    }
+
+    #[test]
+    fn loop_with_exit() {
+        // 1 is the loop header, 2 the body and 3 the exit:
+        let graph = build(4, &[(0, 1), (1, 2), (2, 1), (1, 3)]);
+        let dominators = Dominators::compute(&graph, &labels(&[0]), false, |_| true);
+        assert_eq!(dominators.get_all(Label::new(2)), labels(&[2, 1, 0]));
+        assert_eq!(dominators.get_all(Label::new(3)), labels(&[3, 1, 0]));
+        assert_eq!(dominators.get_common(labels(&[2, 3])), Some(Label::new(1)));
+        assert_eq!(frontier(&dominators, 1), labels(&[1]));
+        assert_eq!(frontier(&dominators, 2), labels(&[1]));
+        assert_eq!(frontier(&dominators, 3), vec![]);
+        check_queries(&dominators, 4);
+    }
+
+    #[test]
+    fn postdominators_of_several_exits() {
+        // 3 and 4 are exits, and 2 may go on to either of them:
+        let graph = build(5, &[(0, 1), (0, 2), (1, 3), (2, 3), (2, 4)]);
+        let postdominators = Dominators::compute(&graph, &labels(&[3, 4]), true, |_| true);
+        assert_eq!(postdominators.roots(), &labels(&[3, 4])[..]);
+        assert_eq!(
+            postdominators.get_immediate(Label::new(1)),
+            Some(Label::new(3))
+        );
+        assert_eq!(postdominators.get_immediate(Label::new(2)), None);
+        assert_eq!(postdominators.get_immediate(Label::new(0)), None);
+        assert!(postdominators.is_for(Label::new(3), Label::new(1)));
+        assert!(!postdominators.is_for(Label::new(3), Label::new(0)));
+        assert!(!postdominators.is_for(Label::new(4), Label::new(2)));
+        assert_eq!(postdominators.get_common(labels(&[1, 2])), None);
+        assert_eq!(postdominators.get_common(labels(&[3, 4])), None);
+        // The nodes deciding whether each node is executed:
+        assert_eq!(frontier(&postdominators, 1), labels(&[0]));
+        assert_eq!(frontier(&postdominators, 2), labels(&[0]));
+        assert_eq!(frontier(&postdominators, 3), labels(&[0, 2]));
+        assert_eq!(frontier(&postdominators, 4), labels(&[2]));
+        check_queries(&postdominators, 5);
+    }
+
+    #[test]
+    fn frontiers_by_definition() {
+        // Nested loops with branches, a side entry into the join of the inner branch, and an
+        // unreachable node:
+        let edges = [
+            (0, 1),
+            (1, 2),
+            (1, 3),
+            (2, 4),
+            (3, 4),
+            (4, 5),
+            (5, 1),
+            (5, 6),
+            (6, 7),
+            (6, 8),
+            (3, 8),
+            (7, 9),
+            (8, 9),
+            (9, 6),
+            (10, 9),
+        ];
+        let count = 11;
+        let graph = build(count, &edges);
+        let dominators = Dominators::compute(&graph, &labels(&[0]), false, |_| true);
+        assert_eq!(dominators.get_immediate(Label::new(10)), None);
+        assert_eq!(dominators.get_immediate(Label::new(8)), Some(Label::new(1)));
+        check_queries(&dominators, count);
+        // A node is in the frontier of the dominators of its predecessors that don't strictly
+        // dominate it:
+        let mut expected = vec![vec![]; count];
+        for &(pred, node) in &edges {
+            if pred == 10 {
+                continue;
+            }
+            for dominator in dominators.get_all(Label::new(pred)) {
+                let frontier = &mut expected[dominator.index()];
+                let is_strict =
+                    dominator != Label::new(node) && dominators.is_for(dominator, Label::new(node));
+                if !is_strict && !frontier.contains(&Label::new(node)) {
+                    frontier.push(Label::new(node));
+                }
+            }
+        }
+        for (node, mut expected) in expected.into_iter().enumerate() {
+            expected.sort();
+            assert_eq!(frontier(&dominators, node), expected, "{}", node);
+        }
+    }
+}
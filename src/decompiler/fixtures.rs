@@ -0,0 +1,91 @@
+//! Builds the code that the tests of the decompiler passes run on, and prints the results.
+
+use decompiler::cfg::*;
+use decompiler::types::*;
+use disassembler::types::*;
+use pretty::*;
+
+pub fn var(name: &str) -> Expr {
+    mk_variable(name.to_owned())
+}
+
+pub fn int(value: i32) -> Expr {
+    Expr::Literal(Literal::Integer(value))
+}
+
+pub fn assign(name: &str, value: Expr) -> Statement {
+    stmt_expr(Expr::Assign {
+        to: Box::new(Assignable::Variable(name.to_owned(), 0)),
+        op: None,
+        from: Box::new(value),
+    })
+}
+
+/// The static field `T.name` of type `int`.
+pub fn field(name: &str) -> Assignable {
+    Assignable::Field {
+        this: None,
+        class: ClassRef("T".to_owned()),
+        field: FieldRef {
+            class_ref: 0,
+            name: name.to_owned(),
+            typ: Type::Int,
+        },
+    }
+}
+
+/// Invokes the static method `T.name` with the given parameter types.
+pub fn invoke(name: &str, parameters: &[Type], return_type: Type, args: Vec<Expr>) -> Expr {
+    let method = MethodRef {
+        class_ref: 0,
+        name: name.to_owned(),
+        signature: Signature {
+            parameters: parameters
+                .iter()
+                .map(|typ| (String::new(), typ.clone()))
+                .collect(),
+            return_type,
+        },
+    };
+    Expr::Invoke(None, method, ClassRef("T".to_owned()), args)
+}
+
+/// Calls the static method `T.name` without parameters or a result.
+pub fn call(name: &str, args: Vec<Expr>) -> Statement {
+    stmt_expr(invoke(name, &[], Type::Void, args))
+}
+
+/// Builds a control flow graph from its blocks, followed by the entry block, which goes on to
+/// the first one, and the exit block.
+pub fn build(
+    blocks: Vec<(Vec<Statement>, Option<&str>)>,
+    edges: &[(usize, usize, Edge)],
+) -> Cfg<Statement, Expr> {
+    let mut graph = CfgGraph::default();
+    for (stmts, cond) in blocks {
+        graph.add_node(BasicBlock {
+            stmts,
+            terminator: cond.map(var),
+        });
+    }
+    let entry_point = graph.add_node(BasicBlock::default());
+    let exit_point = graph.add_node(BasicBlock::default());
+    graph.add_edge(entry_point, Label::new(0), Edge::Branch(false));
+    for &(from, to, edge) in edges {
+        graph.add_edge(Label::new(from), Label::new(to), edge);
+    }
+    Cfg {
+        graph,
+        entry_point,
+        exit_point,
+    }
+}
+
+/// Prints the statements on separate lines, leaving out the `nop`s.
+pub fn to_string(stmts: &[Statement]) -> String {
+    let stmts = stmts
+        .iter()
+        .filter(|stmt| **stmt != Statement::Nop)
+        .map(|stmt| stmt.pretty_with(&()));
+    intersperse(stmts, newline()).render_string(100)
+}
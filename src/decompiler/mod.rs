@@ -1,5 +1,8 @@
 pub mod cfg;
 pub mod decompile;
+pub mod dominators;
+#[cfg(test)]
+pub mod fixtures;
 pub mod passes;
 pub mod pretty;
 pub mod types;
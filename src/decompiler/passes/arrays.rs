@@ -0,0 +1,114 @@
+//! Recovers array initializers, e.g. `new int[]{1, 2}`.
+//!
+//! javac creates the array with the number of elements as its length and then stores each
+//! element in order. After propagating the variables, this is:
+//!
+//! ```ignore
+//! stack_0 = new int[2];
+//! stack_0[0] = 1;
+//! stack_0[1] = 2;
+//! ```
+//!
+//! An element that is an array initializer itself is still assigned to a stack variable before
+//! it's stored, because the variable was used for each of its own elements.
+
+use decompiler::cfg::*;
+use decompiler::passes::stack_to_var::is_stack_variable;
+use decompiler::types::*;
+use disassembler::types::*;
+
+pub fn recover_array_initializers(
+    unit: CompilationUnit<Cfg<Statement, Expr>>,
+) -> CompilationUnit<Cfg<Statement, Expr>> {
+    unit.map(|mut cfg, _| {
+        cfg.map(recover_in_stmts);
+        cfg
+    })
+}
+
+fn recover_in_stmts(stmts: &mut Vec<Statement>) {
+    // Backwards, so that nested initializers are recovered before the ones containing them:
+    for i in (0..stmts.len()).rev() {
+        if let Some((end, init)) = match_array_initializer(stmts, i) {
+            stmts.splice(i..end + 1, Some(init));
+        }
+    }
+}
+
+/// Matches the creation of an array at `i` and the statements storing its elements, returning
+/// the index of the last one and the assignment of the array initializer.
+fn match_array_initializer(stmts: &[Statement], i: usize) -> Option<(usize, Statement)> {
+    let (array, creation) = match_assignment(&stmts[i])?;
+    let (typ, length) = match *creation {
+        Expr::NewArray {
+            ref typ,
+            ref lengths,
+        } if is_stack_variable(array) => match *lengths.as_slice() {
+            // An empty initializer can't be distinguished from `new T[0]`:
+            [Expr::Literal(Literal::Integer(length))] if length > 0 => (typ, length as usize),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let mut elements = vec![];
+    let mut end = i;
+    while elements.len() < length {
+        let (element, store) = match_element(stmts, end, array, elements.len())?;
+        elements.push(element);
+        end = store;
+    }
+    let init = Expr::ArrayInit {
+        typ: typ.clone(),
+        elements,
+    };
+    let stmt = stmt_expr(Expr::Assign {
+        to: Box::new(Assignable::Variable(array.clone(), 0)),
+        op: None,
+        from: Box::new(init),
+    });
+    Some((end, stmt))
+}
+
+/// Matches the element with the given index being stored after statement `i`, possibly assigned
+/// to a stack variable right before. Returns the element and the index of the store.
+fn match_element(
+    stmts: &[Statement],
+    i: usize,
+    array: &str,
+    index: usize,
+) -> Option<(Expr, usize)> {
+    let j = next_stmt(stmts, i)?;
+    if let Some(element) = match_store(&stmts[j], array, index) {
+        return Some((element.clone(), j));
+    }
+    let (var, element) = match_assignment(&stmts[j])?;
+    let store = next_stmt(stmts, j)?;
+    match match_store(&stmts[store], array, index) {
+        Some(stored) if is_stack_variable(var) && as_variable(stored) == Some(var) => {
+            Some((element.clone(), store))
+        }
+        _ => None,
+    }
+}
+
+/// Matches `array[index] = element;`, returning the element.
+fn match_store<'a>(stmt: &'a Statement, array: &str, index: usize) -> Option<&'a Expr> {
+    match *stmt {
+        Statement::Expr(Expr::Assign {
+            ref to,
+            op: None,
+            ref from,
+        }) => match **to {
+            Assignable::ArrayAccess {
+                array: ref stored_array,
+                index: ref stored_index,
+            } if as_variable(stored_array).is_some_and(|var| var == array)
+                && **stored_index == Expr::Literal(Literal::Integer(index as i32)) =>
+            {
+                Some(from)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
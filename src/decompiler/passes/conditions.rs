@@ -0,0 +1,291 @@
+//! Merges the blocks that javac generates for `&&`, `||` and `?:` back into expressions.
+//!
+//! Each operand of `a && b` is a conditional jump of its own, so the second one is a block that
+//! contains nothing but its condition and is only reached from the first one.
+//! If both jump to the same target when the whole condition is false, they are merged into one
+//! block with the condition `a && b`, similarly for `||`.
+//!
+//! `c ? x : y` becomes a conditional jump to two blocks that each push a value onto the stack and
+//! continue at the same block. After propagating the variables, each of them only assigns the
+//! value to the same stack variable, so they are replaced by assigning `c ? x : y` before the
+//! jump. Nested `?:` expressions are handled the same way.
+//!
+//! The blocks that are merged into others are left without statements and edges.
+
+use decompiler::cfg::*;
+use decompiler::passes::stack_to_var::is_stack_variable;
+use decompiler::types::*;
+use disassembler::types::*;
+use std::mem;
+
+pub fn merge_conditions(
+    unit: CompilationUnit<Cfg<Statement, Expr>>,
+) -> CompilationUnit<Cfg<Statement, Expr>> {
+    unit.map(|mut cfg, _| {
+        while merge_chain(&mut cfg) || merge_ternary(&mut cfg) {}
+        cfg
+    })
+}
+
+/// Merges two conditional blocks into one with the condition `a && b` or `a || b`.
+fn merge_chain(cfg: &mut Cfg<Statement, Expr>) -> bool {
+    for first in cfg.graph.node_indices() {
+        let (t, f) = match branches(cfg, first) {
+            Some(branches) => branches,
+            None => continue,
+        };
+        for &(second, is_true_branch) in &[(f, false), (t, true)] {
+            if !is_mergeable(cfg, first, second) || !is_empty(cfg, second) {
+                continue;
+            }
+            let (second_t, second_f) = match branches(cfg, second) {
+                Some(branches) => branches,
+                None => continue,
+            };
+            let a = cfg.graph[first].terminator.clone().unwrap();
+            let b = cfg.graph[second].terminator.clone().unwrap();
+            let merged = if !is_true_branch && second_t == t {
+                (BinOp::LogOr, b, t, second_f)
+            } else if !is_true_branch && second_f == t {
+                (BinOp::LogOr, negate(b), t, second_t)
+            } else if is_true_branch && second_f == f {
+                (BinOp::LogAnd, b, second_t, f)
+            } else if is_true_branch && second_t == f {
+                (BinOp::LogAnd, negate(b), second_f, f)
+            } else {
+                continue;
+            };
+            let (op, b, t, f) = merged;
+            remove_block(cfg, second);
+            cfg.graph[first].terminator = Some(Expr::BinaryOp(op, Box::new(a), Box::new(b)));
+            set_successors(
+                cfg,
+                first,
+                &[(Edge::Branch(true), t), (Edge::Branch(false), f)],
+            );
+            return true;
+        }
+    }
+    false
+}
+
+/// Merges a conditional block and the blocks computing the values of both branches into an
+/// assignment of a `?:` expression.
+fn merge_ternary(cfg: &mut Cfg<Statement, Expr>) -> bool {
+    for cond_block in cfg.graph.node_indices() {
+        let (t, f) = match branches(cfg, cond_block) {
+            Some(branches) => branches,
+            None => continue,
+        };
+        let mut merged = vec![];
+        let then = branch_value(cfg, cond_block, t, &mut merged);
+        let els = branch_value(cfg, cond_block, f, &mut merged);
+        let (var, then, join, els) = match (then, els) {
+            (Some((var, then, join)), Some((other_var, els, other_join)))
+                if var == other_var && join == other_join =>
+            {
+                (var, then, join, els)
+            }
+            _ => continue,
+        };
+        for block in merged {
+            remove_block(cfg, block);
+        }
+        let cond = cfg.graph[cond_block].terminator.take().unwrap();
+        let value = Expr::IfThenElse {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            els: Box::new(els),
+        };
+        cfg.graph[cond_block].stmts.push(stmt_expr(Expr::Assign {
+            to: Box::new(Assignable::Variable(var, 0)),
+            op: None,
+            from: Box::new(value),
+        }));
+        set_successors(cfg, cond_block, &[(Edge::Branch(true), join)]);
+        return true;
+    }
+    false
+}
+
+/// Matches a block reached from `parent` that only assigns a value to a stack variable, or
+/// chooses between two such values by its condition.
+/// Returns the variable, the value and the block where the control flow continues, and adds the
+/// blocks to `merged`.
+fn branch_value(
+    cfg: &Cfg<Statement, Expr>,
+    parent: Label,
+    block: Label,
+    merged: &mut Vec<Label>,
+) -> Option<(Ident, Expr, Label)> {
+    if !is_mergeable(cfg, parent, block) {
+        return None;
+    }
+    if let Some((t, f)) = branches(cfg, block) {
+        if !is_empty(cfg, block) {
+            return None;
+        }
+        let (var, then, join) = branch_value(cfg, block, t, merged)?;
+        let (other_var, els, other_join) = branch_value(cfg, block, f, merged)?;
+        if var != other_var || join != other_join {
+            return None;
+        }
+        merged.push(block);
+        let cond = cfg.graph[block].terminator.clone().unwrap();
+        let value = Expr::IfThenElse {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            els: Box::new(els),
+        };
+        return Some((var, value, join));
+    }
+    let mut stmts = cfg.graph[block]
+        .stmts
+        .iter()
+        .filter(|stmt| **stmt != Statement::Nop);
+    let (var, value) = match (stmts.next(), stmts.next()) {
+        (
+            Some(&Statement::Expr(Expr::Assign {
+                ref to,
+                op: None,
+                ref from,
+            })),
+            None,
+        ) => match **to {
+            Assignable::Variable(ref var, _) if is_stack_variable(var) => {
+                (var.clone(), (**from).clone())
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let mut successors = cfg
+        .graph
+        .edges_directed(block, Direction::Outgoing)
+        .filter(|edge| !edge.weight().is_exception());
+    let join = match (successors.next(), successors.next()) {
+        (Some(edge), None) if cfg.graph[block].terminator.is_none() => edge.target(),
+        _ => return None,
+    };
+    merged.push(block);
+    Some((var, value, join))
+}
+
+/// The targets of the `true` and `false` edges of a block ending with a condition.
+fn branches(cfg: &Cfg<Statement, Expr>, block: Label) -> Option<(Label, Label)> {
+    cfg.graph[block].terminator.as_ref()?;
+    let mut t = None;
+    let mut f = None;
+    for edge in cfg.graph.edges_directed(block, Direction::Outgoing) {
+        match *edge.weight() {
+            Edge::Branch(true) => t = Some(edge.target()),
+            Edge::Branch(false) => f = Some(edge.target()),
+            Edge::Case(_) => return None,
+            Edge::Exception(_) => {}
+        }
+    }
+    Some((t?, f?))
+}
+
+/// Checks whether `block` can be merged into `parent`: it must only be reached from there and be
+/// protected by the same exception handlers.
+fn is_mergeable(cfg: &Cfg<Statement, Expr>, parent: Label, block: Label) -> bool {
+    let mut predecessors = cfg.graph.edges_directed(block, Direction::Incoming);
+    let is_only_predecessor = match (predecessors.next(), predecessors.next()) {
+        (Some(edge), None) => edge.source() == parent && !edge.weight().is_exception(),
+        _ => false,
+    };
+    is_only_predecessor
+        && block != parent
+        && block != cfg.exit_point
+        && handlers(cfg, block) == handlers(cfg, parent)
+}
+
+fn handlers(cfg: &Cfg<Statement, Expr>, block: Label) -> Vec<(Label, Edge)> {
+    let mut handlers = cfg
+        .graph
+        .edges_directed(block, Direction::Outgoing)
+        .filter(|edge| edge.weight().is_exception())
+        .map(|edge| (edge.target(), *edge.weight()))
+        .collect::<Vec<_>>();
+    handlers.sort();
+    handlers
+}
+
+fn is_empty(cfg: &Cfg<Statement, Expr>, block: Label) -> bool {
+    cfg.graph[block]
+        .stmts
+        .iter()
+        .all(|stmt| *stmt == Statement::Nop)
+}
+
+/// Replaces the outgoing edges of the block, except for the exception edges.
+fn set_successors(cfg: &mut Cfg<Statement, Expr>, block: Label, successors: &[(Edge, Label)]) {
+    while let Some(edge) = cfg
+        .graph
+        .edges_directed(block, Direction::Outgoing)
+        .find(|edge| !edge.weight().is_exception())
+        .map(|edge| edge.id())
+    {
+        cfg.graph.remove_edge(edge);
+    }
+    for &(edge, target) in successors {
+        cfg.graph.add_edge(block, target, edge);
+    }
+}
+
+/// Removes the contents and the edges of the block.
+/// The block itself stays because removing it would change the labels of other blocks.
+fn remove_block(cfg: &mut Cfg<Statement, Expr>, block: Label) {
+    while let Some(edge) = cfg
+        .graph
+        .edges_directed(block, Direction::Outgoing)
+        .map(|edge| edge.id())
+        .next()
+    {
+        cfg.graph.remove_edge(edge);
+    }
+    cfg.graph[block] = BasicBlock::default();
+}
+
+/// Turns `if (cond) {} else { ... }` into `if (!cond) { ... }`.
+pub fn invert_empty_then(stmt: &mut Statement) {
+    if let Statement::If {
+        ref mut cond,
+        ref mut then,
+        els: Some(ref mut els),
+    } = *stmt
+    {
+        let is_empty = |stmts: &[Statement]| stmts.iter().all(|stmt| *stmt == Statement::Nop);
+        if is_empty(&then.1) && !is_empty(&els.1) {
+            *cond = negate(cond.clone());
+            mem::swap(then, els);
+        }
+    }
+}
+
+/// The negation of the condition, without `!` where the operators can be inverted instead.
+pub fn negate(cond: Expr) -> Expr {
+    match cond {
+        Expr::BinaryOp(BinOp::Cmp(ordering), left, right) => {
+            Expr::BinaryOp(BinOp::Cmp(ordering.negate()), left, right)
+        }
+        // `!(a < b)` isn't `a >= b` if `a` or `b` is NaN, only (in)equality can be inverted:
+        Expr::BinaryOp(BinOp::FloatCmp(ordering @ Ordering::EQ), left, right)
+        | Expr::BinaryOp(BinOp::FloatCmp(ordering @ Ordering::NE), left, right) => {
+            Expr::BinaryOp(BinOp::FloatCmp(ordering.negate()), left, right)
+        }
+        Expr::BinaryOp(BinOp::LogAnd, left, right) => Expr::BinaryOp(
+            BinOp::LogOr,
+            Box::new(negate(*left)),
+            Box::new(negate(*right)),
+        ),
+        Expr::BinaryOp(BinOp::LogOr, left, right) => Expr::BinaryOp(
+            BinOp::LogAnd,
+            Box::new(negate(*left)),
+            Box::new(negate(*right)),
+        ),
+        Expr::UnaryOp(UnOp::LogNot, cond) => *cond,
+        cond => Expr::UnaryOp(UnOp::LogNot, Box::new(cond)),
+    }
+}
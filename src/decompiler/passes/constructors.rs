@@ -1,31 +1,85 @@
+//! Distinguishes the two kinds of constructor calls: the objects created with `new` are
+//! initialized by calling a constructor on them, and constructors call another constructor of
+//! the same object, `this(...)` or `super(...)`.
+//!
+//! The creation of an object with `new` is merged with its constructor call twice: before the
+//! conditions are merged, so that the branches of a `?:` that create objects can become
+//! expressions, and afterwards, for the objects whose constructor arguments contain a `?:`.
+
 use decompiler::cfg::*;
+use decompiler::passes::stack_to_var::{is_stack_variable, stub};
 use decompiler::types::*;
 use disassembler::instructions::*;
+use std::{mem, slice};
+
+/// Merges the creation of objects with the calls of their constructors where they're in the same
+/// block or only separated by blocks without conditions.
+pub fn merge_object_creations(
+    unit: CompilationUnit<Cfg<Statement, Expr>>,
+) -> CompilationUnit<Cfg<Statement, Expr>> {
+    unit.map(|mut cfg, _| {
+        fold_object_creations(&mut cfg);
+        cfg
+    })
+}
 
-pub fn handle_constructors(unit: &mut CompilationUnit<Cfg<Statement, Expr>>) {
+/// Turns the `<init>` methods into constructors. `warn` is called with a message for each method
+/// where an object creation can't be merged with its constructor call, which is replaced by a
+/// stub that throws an exception.
+pub fn handle_constructors<W>(unit: &mut CompilationUnit<Cfg<Statement, Expr>>, mut warn: W)
+where
+    W: FnMut(String),
+{
     let class_name = unit.name.to_owned();
     unit.declarations.iter_mut().for_each(|declaration| {
+        if let Declaration::Method(Method {
+            ref name,
+            code: Some(ref mut code),
+            ..
+        }) = *declaration
+        {
+            fold_object_creations(code);
+            let is_unmerged = code.graph.node_indices().any(|block| {
+                code.graph[block]
+                    .stmts
+                    .iter()
+                    .any(|stmt| match_constructor_call(stmt).is_some())
+            });
+            if is_unmerged {
+                let reason = "an object creation can't be merged with its constructor call";
+                warn(format!("`{}` can't be decompiled: {}", name, reason));
+                *code = stub(reason);
+            }
+        }
         let replacement = match *declaration {
-            Declaration::Method(ref mut m) => if m.name == "<init>" {
-                let modifiers = m.modifiers.clone();
-                let parameters = m.signature.parameters.clone();
-                let mut code = m.code.clone().expect("abstract contstructor");
-                let mut visitor = ConstructorVisitor {
-                    class_name: &class_name,
-                };
-                code.map(|stmts| {
-                    stmts
-                        .iter_mut()
-                        .for_each(|stmt| visitor.visit_statement(stmt))
-                });
-                Some(Declaration::Constructor(Constructor {
-                    modifiers,
-                    parameters,
-                    code,
-                }))
-            } else {
-                None
-            },
+            Declaration::Method(ref mut m) => {
+                if m.name == "<init>" {
+                    let modifiers = m.modifiers.clone();
+                    let parameters = m.signature.parameters.clone();
+                    let throws = m.throws.clone();
+                    let variables = m.variables.clone();
+                    let locals = m.locals.clone();
+                    let mut code = m.code.clone().expect("abstract contstructor");
+                    let mut visitor = ConstructorVisitor {
+                        class_name: &class_name,
+                    };
+                    code.map(|stmts| {
+                        stmts
+                            .iter_mut()
+                            .for_each(|stmt| visitor.visit_statement(stmt))
+                    });
+                    Some(Declaration::Constructor(Constructor {
+                        modifiers,
+                        parameters,
+                        throws,
+                        variables,
+                        locals,
+                        code,
+                    }))
+                } else {
+                    None
+                }
+            }
             _ => None,
         };
         // FIXME: Fix this once non-lexical lifetimes are available
@@ -51,14 +105,10 @@ impl<'a> Visitor for ConstructorVisitor<'a> {
             ref args,
         )) = *stmt
         {
-            assert!(
-                match **expr {
-                    Expr::This => true,
-                    _ => false,
-                },
-                "constructor call not on `this`!"
-            );
-            if method_name == "<init>" {
+            // The calls left on stack variables are for objects created with `new`, whose
+            // methods were replaced by stubs. Otherwise it's on `this`, possibly still `local_0`:
+            let on_this = !as_variable(expr).is_some_and(|var| is_stack_variable(var));
+            if method_name == "<init>" && on_this {
                 if self.class_name == class_name {
                     Some(Statement::ThisCall(args.clone()))
                 } else {
@@ -75,3 +125,81 @@ impl<'a> Visitor for ConstructorVisitor<'a> {
         }
     }
 }
+
+/// Merges the creation of objects with the calls of their constructors into `new C(args)`.
+///
+/// `new C` is assigned to a stack variable first, and the constructor is called on the variable
+/// once the arguments are computed. This may be in a later block if they contain conditions.
+fn fold_object_creations(cfg: &mut Cfg<Statement, Expr>) {
+    for block in cfg.graph.node_indices() {
+        let mut i = 0;
+        while i < cfg.graph[block].stmts.len() {
+            let creation = match_constructor_call(&cfg.graph[block].stmts[i])
+                .and_then(|var| find_creation(cfg, block, i, var));
+            if let Some((creation_block, j)) = creation {
+                let mut creation = cfg.graph[creation_block].stmts.remove(j);
+                if creation_block == block {
+                    i -= 1;
+                }
+                if let Statement::Expr(Expr::Invoke(_, _, _, ref mut args)) =
+                    cfg.graph[block].stmts[i]
+                {
+                    if let Statement::Expr(Expr::Assign { ref mut from, .. }) = creation {
+                        if let Expr::New {
+                            args: ref mut new_args,
+                            ..
+                        } = **from
+                        {
+                            *new_args = mem::take(args);
+                        }
+                    }
+                }
+                cfg.graph[block].stmts[i] = creation;
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Matches a constructor call on a stack variable, returning the variable.
+fn match_constructor_call(stmt: &Statement) -> Option<&Ident> {
+    match *stmt {
+        Statement::Expr(Expr::Invoke(Some(ref object), ref method, ..))
+            if method.name == "<init>" =>
+        {
+            as_variable(object).filter(|var| is_stack_variable(var))
+        }
+        _ => None,
+    }
+}
+
+/// Finds the assignment of the created object to `var` that precedes statement `i` of `block`.
+/// Between the two, the variable is only on the stack, so it's the closest statement using it.
+fn find_creation(
+    cfg: &Cfg<Statement, Expr>,
+    mut block: Label,
+    mut i: usize,
+    var: &str,
+) -> Option<(Label, usize)> {
+    for _ in 0..cfg.graph.node_count() {
+        let stmts = &cfg.graph[block].stmts;
+        let last_use = stmts[..i]
+            .iter()
+            .rposition(|stmt| uses_variable(slice::from_ref(stmt), var));
+        if let Some(j) = last_use {
+            let is_creation = match match_assignment(&stmts[j]) {
+                Some((assigned, Expr::New { args, .. })) if assigned == var => args.is_empty(),
+                _ => false,
+            };
+            return if is_creation { Some((block, j)) } else { None };
+        }
+        // Otherwise it's assigned in the preceding block, unless there are several:
+        let mut predecessors = cfg.graph.neighbors_directed(block, Direction::Incoming);
+        block = match (predecessors.next(), predecessors.next()) {
+            (Some(predecessor), None) => predecessor,
+            _ => return None,
+        };
+        i = cfg.graph[block].stmts.len();
+    }
+    None
+}
@@ -0,0 +1,170 @@
+//! Turns the code that javac generates for an enum back into its constants.
+//!
+//! Each constant is a static final field of the enum that the static initializer assigns a new
+//! instance to, passing the name and the ordinal of the constant before the arguments of the
+//! constructor in the source code:
+//!
+//! ```ignore
+//! RED = new Color("RED", 0, 255);
+//! $VALUES = $values();
+//! ```
+//!
+//! The constructors pass the name and the ordinal on to `super(name, ordinal)`. The `$VALUES`
+//! array with all constants, the methods `values()` and `valueOf(String)` and, since Java 15,
+//! `$values()` are generated as well. All of this can't be written in the source code, so it's
+//! removed. Constants with a body are instances of an anonymous subclass and aren't recovered.
+
+use decompiler::types::*;
+use disassembler::types::*;
+
+pub fn recover_enum_constants(unit: &mut CompilationUnit<Block>) {
+    if unit.typ != UnitType::Enum {
+        return;
+    }
+    let this_type = Type::Reference(unit.name.clone());
+    let mut constants = vec![];
+    for declaration in &mut unit.declarations {
+        match *declaration {
+            Declaration::Method(Method {
+                ref name,
+                code: Some(ref mut code),
+                ..
+            }) if name == "<clinit>" => {
+                for stmt in &mut code.1 {
+                    if let Some(constant) = match_constant_creation(stmt, &unit.name) {
+                        constants.push(constant);
+                        *stmt = Statement::Nop;
+                    } else if is_values_assignment(stmt, &unit.name) {
+                        *stmt = Statement::Nop;
+                    }
+                }
+            }
+            Declaration::Constructor(ref mut constructor) => {
+                remove_name_and_ordinal(constructor);
+            }
+            _ => {}
+        }
+    }
+    constants.sort_by_key(|&(ordinal, _)| ordinal);
+    unit.declarations.retain(|declaration| match *declaration {
+        Declaration::Field(ref field) => {
+            field.name != "$VALUES"
+                && !constants
+                    .iter()
+                    .any(|(_, constant)| constant.name == field.name)
+        }
+        Declaration::Method(ref method) => !is_generated_method(method, &this_type),
+        Declaration::Constructor(ref constructor) => !is_implicit(constructor),
+        Declaration::EnumConstant(_) => true,
+    });
+    let constants = constants
+        .into_iter()
+        .map(|(_, constant)| Declaration::EnumConstant(constant));
+    unit.declarations.splice(0..0, constants);
+}
+
+/// Matches `CONSTANT = new Enum("CONSTANT", ordinal, args)`, returning the ordinal and the
+/// constant, unless an argument isn't a literal.
+fn match_constant_creation(stmt: &Statement, enum_name: &str) -> Option<(i32, EnumConstant)> {
+    let (to, from) = match *stmt {
+        Statement::Expr(Expr::Assign {
+            ref to,
+            op: None,
+            ref from,
+        }) => (to, from),
+        _ => return None,
+    };
+    let name = match **to {
+        Assignable::Field {
+            this: None,
+            ref class,
+            ref field,
+        } if class.0 == enum_name => &field.name,
+        _ => return None,
+    };
+    let args = match **from {
+        Expr::New {
+            class: Type::Reference(ref class),
+            ref args,
+        } if class == enum_name => args,
+        _ => return None,
+    };
+    let ordinal = match args.get(..2) {
+        Some(
+            &[Expr::Literal(Literal::String(ref string)), Expr::Literal(Literal::Integer(ordinal))],
+        ) if string == name => ordinal,
+        _ => return None,
+    };
+    let arguments = args[2..]
+        .iter()
+        .map(|arg| match *arg {
+            Expr::Literal(ref literal) => Some(literal.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((
+        ordinal,
+        EnumConstant {
+            name: name.clone(),
+            arguments,
+        },
+    ))
+}
+
+/// Matches the assignment of the array of all constants, `$VALUES = ...`.
+fn is_values_assignment(stmt: &Statement, enum_name: &str) -> bool {
+    match *stmt {
+        Statement::Expr(Expr::Assign { ref to, .. }) => match **to {
+            Assignable::Field {
+                this: None,
+                ref class,
+                ref field,
+            } => class.0 == enum_name && field.name == "$VALUES",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether the method is one of those that javac generates for every enum.
+fn is_generated_method(method: &Method<Block>, this_type: &Type) -> bool {
+    let signature = &method.signature;
+    let is_static = method.modifiers.contains(&Modifier::Static);
+    match (method.name.as_str(), signature.parameters.as_slice()) {
+        ("values", &[]) | ("$values", &[]) => {
+            is_static && signature.return_type == Type::Array(Box::new(this_type.clone()))
+        }
+        ("valueOf", &[(_, Type::Reference(ref parameter))]) => {
+            is_static && parameter == "java.lang.String" && signature.return_type == *this_type
+        }
+        _ => false,
+    }
+}
+
+/// Removes the name and the ordinal of the constant from the parameters of the constructor,
+/// and the call of the constructor of `java.lang.Enum` they are passed to.
+fn remove_name_and_ordinal(constructor: &mut Constructor<Block>) {
+    if constructor.parameters.len() < 2 {
+        return;
+    }
+    constructor.parameters.drain(..2);
+    for stmt in &mut constructor.code.1 {
+        match *stmt {
+            Statement::SuperCall(_) => *stmt = Statement::Nop,
+            Statement::ThisCall(ref mut args) if args.len() >= 2 => {
+                args.drain(..2);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether the constructor has no parameters and does nothing, so it needn't be declared.
+fn is_implicit(constructor: &Constructor<Block>) -> bool {
+    let Block(ref declarations, ref stmts) = constructor.code;
+    constructor.parameters.is_empty()
+        && declarations.is_empty()
+        && stmts
+            .iter()
+            .all(|stmt| *stmt == Statement::Nop || *stmt == Statement::Return(None))
+}
@@ -0,0 +1,222 @@
+//! Shortens the names of the referenced classes where this is unambiguous, and collects the
+//! imports that are necessary for that.
+//!
+//! A class is referred to by its simple name if no other referenced class has the same simple
+//! name. It's imported unless it's in `java.lang` or in the package of the unit.
+//! Nested classes are referred to through their top-level class, e.g. `Map.Entry` for the binary
+//! name `java.util.Map$Entry`, which is shortened and imported like that. Local and anonymous
+//! classes keep their binary names because they can't be referred to in the source code.
+//! The member classes of the unit are printed in it, so they share its imports.
+
+use decompiler::types::*;
+use disassembler::types::*;
+use std::collections::{HashMap, HashSet};
+
+pub fn shorten_names(unit: &mut CompilationUnit<Block>) {
+    let mut names = HashSet::new();
+    visit_class_names(unit, &mut |name| {
+        names.insert(name.clone());
+    });
+    let mut classes_by_simple_name = HashMap::new();
+    for name in &names {
+        if let Some((top_level, _)) = split_nested(name) {
+            let (_, simple_name) = split_name(top_level);
+            classes_by_simple_name
+                .entry(simple_name)
+                .or_insert_with(HashSet::new)
+                .insert(top_level);
+        }
+    }
+    let (unit_top_level, _) = split_nested(&unit.name).unwrap_or((&unit.name, String::new()));
+    let (_, unit_simple_name) = split_name(unit_top_level);
+    let mut imports = vec![];
+    let mut short_names = HashMap::new();
+    for name in &names {
+        let (top_level, nested) = match split_nested(name) {
+            Some(split) => split,
+            None => continue,
+        };
+        let (package, simple_name) = split_name(top_level);
+        let is_unambiguous = classes_by_simple_name[simple_name].len() == 1
+            && (simple_name != unit_simple_name || top_level == unit_top_level);
+        if package.is_empty() || !is_unambiguous {
+            if !nested.is_empty() {
+                short_names.insert(name.clone(), top_level.to_owned() + &nested);
+            }
+            continue;
+        }
+        if package != "java.lang" && package != unit.package() {
+            imports.push(top_level.to_owned());
+        }
+        short_names.insert(name.clone(), simple_name.to_owned() + &nested);
+    }
+    imports.sort();
+    imports.dedup();
+    unit.imports = imports;
+    visit_class_names(unit, &mut |name| {
+        if let Some(short_name) = short_names.get(name) {
+            *name = short_name.clone();
+        }
+    });
+}
+
+fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => ("", name),
+    }
+}
+
+/// Splits the binary name of a class into the name of its top-level class and the names of the
+/// classes nested in it, as they are written in the source code: `a.B$C$D` into `a.B` and `.C.D`.
+/// `None` for local and anonymous classes, e.g. `a.B$1`.
+fn split_nested(name: &str) -> Option<(&str, String)> {
+    let mut parts = name.split('$');
+    let top_level = parts.next().unwrap();
+    if top_level.is_empty() {
+        return None;
+    }
+    let mut nested = String::new();
+    for part in parts {
+        if !part.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return None;
+        }
+        nested.push('.');
+        nested.push_str(part);
+    }
+    Some((top_level, nested))
+}
+
+/// Calls `f` on the names of all classes that are referenced in the printed code of the unit,
+/// including its member classes.
+fn visit_class_names(unit: &mut CompilationUnit<Block>, f: &mut dyn FnMut(&mut String)) {
+    if let Some(ref mut class) = unit.extends {
+        f(&mut class.0);
+    }
+    for class in &mut unit.implements {
+        f(&mut class.0);
+    }
+    for declaration in &mut unit.declarations {
+        match *declaration {
+            Declaration::Field(ref mut field) => {
+                visit_type(&mut field.typ, f);
+                if let Some(ref mut initializer) = field.initializer {
+                    ClassNameVisitor { f: &mut *f }.visit_expr(initializer);
+                }
+            }
+            Declaration::EnumConstant(ref mut constant) => {
+                for argument in &mut constant.arguments {
+                    if let Literal::Class(ref mut typ) = *argument {
+                        visit_type(typ, f);
+                    }
+                }
+            }
+            Declaration::Method(ref mut method) => {
+                visit_type(&mut method.signature.return_type, f);
+                for &mut (_, ref mut typ) in &mut method.signature.parameters {
+                    visit_type(typ, f);
+                }
+                for class in &mut method.throws {
+                    f(&mut class.0);
+                }
+                if let Some(ref mut code) = method.code {
+                    ClassNameVisitor { f: &mut *f }.visit_block(code);
+                }
+            }
+            Declaration::Constructor(ref mut constructor) => {
+                for &mut (_, ref mut typ) in &mut constructor.parameters {
+                    visit_type(typ, f);
+                }
+                for class in &mut constructor.throws {
+                    f(&mut class.0);
+                }
+                ClassNameVisitor { f: &mut *f }.visit_block(&mut constructor.code);
+            }
+        }
+    }
+    for nested in &mut unit.nested {
+        visit_class_names(nested, f);
+    }
+}
+
+fn visit_type(typ: &mut Type, f: &mut dyn FnMut(&mut String)) {
+    match *typ {
+        Type::Array(ref mut element) => visit_type(element, f),
+        Type::Reference(ref mut name) => f(name),
+        _ => {}
+    }
+}
+
+struct ClassNameVisitor<'a> {
+    f: &'a mut dyn FnMut(&mut String),
+}
+
+impl<'a> Visitor for ClassNameVisitor<'a> {
+    fn visit_block(&mut self, block: &mut Block) {
+        for declaration in &mut block.0 {
+            visit_type(&mut declaration.typ, self.f);
+        }
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        match *stmt {
+            Statement::Decl(ref mut decl) => visit_type(&mut decl.typ, self.f),
+            Statement::Try {
+                ref mut resources,
+                ref mut catches,
+                ..
+            } => {
+                for resource in resources {
+                    visit_type(&mut resource.typ, self.f);
+                }
+                for catch in catches {
+                    for typ in &mut catch.types {
+                        visit_type(typ, self.f);
+                    }
+                }
+            }
+            Statement::For(_, ref mut control, _) => match **control {
+                ForControl::Iteration { ref mut elem, .. }
+                | ForControl::General {
+                    init: ForInit::Decl(ref mut elem),
+                    ..
+                } => visit_type(&mut elem.typ, self.f),
+                ForControl::General { .. } => {}
+            },
+            _ => {}
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        match *expr {
+            Expr::Literal(Literal::Class(ref mut typ))
+            | Expr::New {
+                class: ref mut typ, ..
+            }
+            | Expr::NewArray { ref mut typ, .. }
+            | Expr::ArrayInit { ref mut typ, .. }
+            | Expr::Cast(ref mut typ, _)
+            | Expr::InstanceOf(_, ref mut typ) => visit_type(typ, self.f),
+            // The class is only printed for static methods:
+            Expr::Invoke(None, _, ref mut class, _) | Expr::QualifiedThis(ref mut class) => {
+                (self.f)(&mut class.0)
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+
+    fn visit_assignable(&mut self, assignable: &mut Assignable) {
+        if let Assignable::Field {
+            this: None,
+            ref mut class,
+            ..
+        } = *assignable
+        {
+            (self.f)(&mut class.0);
+        }
+        walk_assignable(self, assignable);
+    }
+}
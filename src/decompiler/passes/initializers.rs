@@ -0,0 +1,140 @@
+//! Turns the code of the static initializer (`<clinit>`) into a valid initializer block.
+//!
+//! The static final fields of the class that have no initializer are assigned in it, which Java
+//! only allows by their simple names, e.g. `lock = new Object();` rather than `T.lock = ...`.
+//! An initializer that is empty then, e.g. one that only created the constants of an enum, is
+//! removed.
+//!
+//! An interface can't have a static initializer, so its assignments are moved into the
+//! initializers of the fields instead, which fails if it does anything else.
+
+use decompiler::types::*;
+use disassembler::types::*;
+
+pub fn recover_static_initializer(unit: &mut CompilationUnit<Block>) -> Result<(), String> {
+    let blank_finals = unit
+        .declarations
+        .iter()
+        .filter_map(|declaration| match *declaration {
+            Declaration::Field(ref field)
+                if field.modifiers.contains(&Modifier::Static)
+                    && field.modifiers.contains(&Modifier::Final)
+                    && field.value.is_none() =>
+            {
+                Some(field.name.clone())
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for declaration in &mut unit.declarations {
+        let code = match *declaration {
+            Declaration::Method(Method {
+                ref name,
+                code: Some(ref mut code),
+                ..
+            }) if name == "<clinit>" => code,
+            _ => continue,
+        };
+        BlankFinalVisitor {
+            class_name: &unit.name,
+            blank_finals: &blank_finals,
+        }
+        .visit_block(code);
+    }
+    if unit.typ == UnitType::Interface {
+        initialize_fields(unit)?;
+    }
+    unit.declarations.retain(|declaration| match *declaration {
+        Declaration::Method(Method {
+            ref name,
+            code: Some(Block(ref declarations, ref stmts)),
+            ..
+        }) if name == "<clinit>" => {
+            !declarations.is_empty() || stmts.iter().any(|stmt| *stmt != Statement::Nop)
+        }
+        _ => true,
+    });
+    Ok(())
+}
+
+/// Moves the assignments of the static initializer of an interface into the initializers of the
+/// fields.
+fn initialize_fields(unit: &mut CompilationUnit<Block>) -> Result<(), String> {
+    let code = unit
+        .declarations
+        .iter_mut()
+        .filter_map(|declaration| match *declaration {
+            Declaration::Method(Method {
+                ref name,
+                code: Some(ref mut code),
+                ..
+            }) if name == "<clinit>" => Some(code),
+            _ => None,
+        })
+        .next();
+    let Block(ref declarations, ref mut stmts) = match code {
+        Some(code) => code,
+        None => return Ok(()),
+    };
+    if !declarations.is_empty() {
+        return Err("the static initializer of an interface can't have local variables".to_owned());
+    }
+    let initializers = stmts
+        .iter()
+        .filter(|stmt| **stmt != Statement::Nop)
+        .map(|stmt| match *stmt {
+            // The blank finals are assigned as variables already:
+            Statement::Expr(Expr::Assign {
+                ref to,
+                op: None,
+                ref from,
+            }) => match **to {
+                Assignable::Variable(ref field, _) => Ok((field.clone(), (**from).clone())),
+                _ => Err(()),
+            },
+            _ => Err(()),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| {
+            "the static initializer of an interface can only assign its fields".to_owned()
+        })?;
+    stmts.clear();
+    for (name, initializer) in initializers {
+        for declaration in &mut unit.declarations {
+            if let Declaration::Field(ref mut field) = *declaration {
+                if field.name == name {
+                    field.initializer = Some(initializer.clone());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the assignments of the blank finals of the class with assignments of variables with
+/// their names, which are printed as simple names. The variables have been declared already.
+struct BlankFinalVisitor<'a> {
+    class_name: &'a str,
+    blank_finals: &'a [String],
+}
+
+impl<'a> Visitor for BlankFinalVisitor<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if let Expr::Assign { ref mut to, .. } = *expr {
+            let simple_name = match **to {
+                Assignable::Field {
+                    this: None,
+                    ref class,
+                    ref field,
+                } if class.0 == self.class_name && self.blank_finals.contains(&field.name) => {
+                    Some(field.name.clone())
+                }
+                _ => None,
+            };
+            if let Some(simple_name) = simple_name {
+                **to = Assignable::Variable(simple_name, 0);
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
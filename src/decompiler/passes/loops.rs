@@ -0,0 +1,709 @@
+//! Rewrites loops into `for` loops where they have the shape of one.
+//!
+//! `for (init; cond; update) { ... }` is compiled like `init; while (cond) { ...; update; }`,
+//! where `continue` jumps to the update. So a loop that tests a variable assigned right before
+//! it and updates the variable at the end of its body and before each `continue` becomes a
+//! `for` loop.
+//!
+//! An enhanced `for` loop over an array is compiled to a loop over the indices of a copy of the
+//! array:
+//!
+//! ```ignore
+//! tmp = array;
+//! length = tmp.length;
+//! i = 0;
+//! while (i < length) {
+//!     elem = tmp[i];
+//!     ...
+//!     i = i + 1;
+//! }
+//! ```
+//!
+//! and one over an `Iterable` to a loop using its iterator:
+//!
+//! ```ignore
+//! it = iterable.iterator();
+//! while (it.hasNext()) {
+//!     elem = (Type) it.next();
+//!     ...
+//! }
+//! ```
+//!
+//! If the element was only used once and the class file doesn't contain its name, it has been
+//! propagated into its use, so it has no variable anymore. It's given a new name then. A loop
+//! over the indices of an array that only uses the index to access the array is turned into an
+//! enhanced `for` loop the same way.
+//!
+//! The types of the class file don't have type arguments, so the `Iterable` is raw and its
+//! elements are `Object`s. The cast of the element to its type in the source code is kept in
+//! each use of it instead.
+
+use decompiler::passes::conditions::invert_empty_then;
+use decompiler::types::*;
+use disassembler::types::*;
+
+pub fn recover_for_loops(unit: &mut CompilationUnit<Block>) {
+    for declaration in &mut unit.declarations {
+        let (parameters, locals, code) = match *declaration {
+            Declaration::Method(Method {
+                ref signature,
+                ref locals,
+                code: Some(ref mut code),
+                ..
+            }) => (&signature.parameters, locals, code),
+            Declaration::Constructor(ref mut constructor) => (
+                &constructor.parameters,
+                &constructor.locals,
+                &mut constructor.code,
+            ),
+            _ => continue,
+        };
+        let mut types = parameters.clone();
+        types.extend(locals.iter().cloned());
+        LoopVisitor { types }.visit_block(code);
+    }
+}
+
+/// Recovers the `for` loops of each block before those nested in it, so that the types of the
+/// elements of the enclosing loops are known.
+struct LoopVisitor {
+    /// The types of the parameters, the local variables and the elements of the enclosing
+    /// loops.
+    types: Vec<(Ident, Type)>,
+}
+
+impl Visitor for LoopVisitor {
+    fn visit_block(&mut self, block: &mut Block) {
+        self.recover_for_loops(&mut block.1);
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        match *stmt {
+            Statement::Switch { ref mut cases, .. } => {
+                for case in cases {
+                    self.recover_for_loops(&mut case.stmts);
+                }
+            }
+            Statement::For(_, ref control, _) => {
+                if let ForControl::Iteration { ref elem, .. } = **control {
+                    self.types.push((elem.ident.clone(), elem.typ.clone()));
+                    walk_statement(self, stmt);
+                    self.types.pop();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        walk_statement(self, stmt);
+    }
+}
+
+impl LoopVisitor {
+    /// Replaces the loops in the statements by `for` loops, starting with the last one so that
+    /// the loops after a loop are already known when checking whether its variables are needed
+    /// after it.
+    fn recover_for_loops(&self, stmts: &mut Vec<Statement>) {
+        let mut i = stmts.len();
+        while i > 0 {
+            i -= 1;
+            let replacement = match_array_loop(stmts, i, &self.types)
+                .or_else(|| match_iterator_loop(stmts, i))
+                .or_else(|| match_for_loop(stmts, i));
+            if let Some((start, for_loop)) = replacement {
+                stmts.splice(start..i + 1, Some(for_loop));
+                i = start;
+            }
+        }
+    }
+}
+
+/// A loop that tests its condition before each iteration.
+struct WhileLoop {
+    label: Option<Ident>,
+    cond: Expr,
+    body: Vec<Statement>,
+}
+
+/// Matches a `while` loop that tests its condition before each iteration.
+fn match_while(stmt: &Statement) -> Option<WhileLoop> {
+    match *stmt {
+        Statement::While {
+            ref label,
+            ref cond,
+            ref body,
+            do_while: false,
+        } => Some(WhileLoop {
+            label: label.clone(),
+            cond: cond.clone(),
+            body: body.1.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Checks whether a `break` or `continue` to `target` refers to the loop with the given label.
+/// `nested` tells whether it's inside another loop within that loop.
+fn targets(target: &Option<Ident>, label: &Option<Ident>, nested: bool) -> bool {
+    match *target {
+        Some(ref target) => label.as_ref() == Some(target),
+        None => !nested,
+    }
+}
+
+/// Matches `init; while (cond) { ...; update; }` where `init` assigns a variable that `cond`
+/// tests and `update` assigns.
+fn match_for_loop(stmts: &[Statement], i: usize) -> Option<(usize, Statement)> {
+    let mut while_loop = match_while(&stmts[i])?;
+    let start = previous_stmt(stmts, i)?;
+    let (var, _) = match_assignment(&stmts[start])?;
+    if !uses_variable(&[stmt_expr(while_loop.cond.clone())], var) {
+        return None;
+    }
+    let update = remove_update(&mut while_loop.body, &while_loop.label, var)?;
+    let init = match stmts[start] {
+        Statement::Expr(ref init) => init.clone(),
+        _ => return None,
+    };
+    let control = ForControl::General {
+        init: ForInit::Expr(init),
+        cond: while_loop.cond,
+        update: compound_assignment(update),
+    };
+    Some((start, for_loop(while_loop.label, control, while_loop.body)))
+}
+
+/// Matches the loop that javac generates for an enhanced `for` loop over an array.
+fn match_array_loop(
+    stmts: &[Statement],
+    i: usize,
+    types: &[(Ident, Type)],
+) -> Option<(usize, Statement)> {
+    let mut while_loop = match_while(&stmts[i])?;
+    let index_init = previous_stmt(stmts, i)?;
+    let index = match match_assignment(&stmts[index_init]) {
+        Some((index, &Expr::Literal(Literal::Integer(0)))) => index,
+        _ => return None,
+    };
+    let length = match while_loop.cond {
+        Expr::BinaryOp(BinOp::Cmp(Ordering::LT), ref left, ref right)
+            if as_variable(left) == Some(index) =>
+        {
+            right
+        }
+        _ => return None,
+    };
+    // The length is stored in a variable unless it was propagated into the condition:
+    let (length, tmp, start) = match as_variable(length) {
+        Some(length) => {
+            let length_init = previous_stmt(stmts, index_init)?;
+            let tmp = match match_assignment(&stmts[length_init]) {
+                Some((var, value)) if var == length => as_array_length(value)?,
+                _ => return None,
+            };
+            (Some(length), tmp, length_init)
+        }
+        None => (None, as_array_length(length)?, index_init),
+    };
+    // The array is copied to `tmp`, unless it was propagated into the assignment of `tmp`
+    // (e.g. if it's the element of an enclosing loop), so that `tmp` holds the array itself:
+    let copy =
+        previous_stmt(stmts, start).and_then(|tmp_init| match match_assignment(&stmts[tmp_init]) {
+            Some((var, array)) if var == tmp => Some((tmp_init, array.clone())),
+            _ => None,
+        });
+    let is_copied = copy.is_some();
+    let (start, array) = copy.unwrap_or_else(|| (start, mk_variable(tmp.clone())));
+    let increment = stmt_expr(Expr::Assign {
+        to: Box::new(Assignable::Variable(index.clone(), 0)),
+        op: None,
+        from: Box::new(Expr::BinaryOp(
+            BinOp::Add,
+            Box::new(mk_variable(index.clone())),
+            Box::new(Expr::Literal(Literal::Integer(1))),
+        )),
+    });
+    if remove_update(&mut while_loop.body, &while_loop.label, index)? != increment {
+        return None;
+    }
+    let element = Expr::Assignable(Box::new(Assignable::ArrayAccess {
+        array: Box::new(mk_variable(tmp.clone())),
+        index: Box::new(mk_variable(index.clone())),
+    }));
+    let (elem, _) = take_element(&mut while_loop.body, index, |expr| {
+        if *expr == element {
+            Some(None)
+        } else {
+            None
+        }
+    })?;
+    let is_renamed = elem.starts_with(ELEMENT);
+    let mut locals = vec![tmp, index];
+    locals.extend(length);
+    if locals
+        .iter()
+        .any(|&var| uses_variable(&while_loop.body, var))
+    {
+        return None;
+    }
+    let rest = &stmts[i + 1..];
+    if !is_copied {
+        locals.retain(|&var| var != tmp);
+    }
+    if !is_dead(rest, &elem) || locals.iter().any(|&var| !is_dead(rest, var)) {
+        return None;
+    }
+    let declared = if is_renamed {
+        None
+    } else {
+        declared_type(&elem, types)
+    };
+    let typ = declared.unwrap_or_else(|| element_type(&array, types));
+    let control = ForControl::Iteration {
+        elem: LocalDecl {
+            ident: elem,
+            typ,
+            init: None,
+        },
+        container: array,
+    };
+    Some((start, for_loop(while_loop.label, control, while_loop.body)))
+}
+
+/// Matches `array.length`, returning the variable of the array.
+fn as_array_length(expr: &Expr) -> Option<&Ident> {
+    match *expr {
+        Expr::ArrayLength(ref array) => as_variable(array),
+        _ => None,
+    }
+}
+
+/// Matches the loop that javac generates for an enhanced `for` loop over an `Iterable`.
+fn match_iterator_loop(stmts: &[Statement], i: usize) -> Option<(usize, Statement)> {
+    let mut while_loop = match_while(&stmts[i])?;
+    let start = previous_stmt(stmts, i)?;
+    let (it, iterable) = match match_assignment(&stmts[start]) {
+        Some((it, &Expr::Invoke(Some(ref iterable), ref method, _, ref args)))
+            if method.name == "iterator" && args.is_empty() =>
+        {
+            (it, iterable)
+        }
+        _ => return None,
+    };
+    let has_next = match while_loop.cond {
+        Expr::BinaryOp(BinOp::Cmp(Ordering::NE), ref call, ref zero)
+            if **zero == Expr::Literal(Literal::Integer(0)) =>
+        {
+            call
+        }
+        ref call => call,
+    };
+    // `next()` is called once per iteration, so the element can't have been propagated into
+    // more than one use:
+    if !is_call(has_next, it, "hasNext") || count_uses(&while_loop.body, it) > 1 {
+        return None;
+    }
+    let (elem, typ) = take_element(&mut while_loop.body, it, |expr| match *expr {
+        Expr::Cast(ref typ, ref call) if is_call(call, it, "next") => Some(Some(typ.clone())),
+        ref call if is_call(call, it, "next") => Some(None),
+        _ => None,
+    })?;
+    let rest = &stmts[i + 1..];
+    if uses_variable(&while_loop.body, it) || !is_dead(rest, it) || !is_dead(rest, &elem) {
+        return None;
+    }
+    if let Some(typ) = typ.filter(|typ| *typ != object()) {
+        cast_reads(&mut while_loop.body, &elem, &typ)?;
+    }
+    let control = ForControl::Iteration {
+        elem: LocalDecl {
+            ident: elem,
+            typ: object(),
+            init: None,
+        },
+        container: (**iterable).clone(),
+    };
+    Some((start, for_loop(while_loop.label, control, while_loop.body)))
+}
+
+/// Casts each read of the element of a loop over an `Iterable` to its type in the source code.
+/// Fails if the element is assigned.
+fn cast_reads(body: &mut [Statement], elem: &str, typ: &Type) -> Option<()> {
+    let uses = count_uses(body, elem);
+    let mut visitor = ReplaceVisitor {
+        f: |expr: &Expr| {
+            if as_variable(expr)? != elem {
+                return None;
+            }
+            Some(Expr::Cast(typ.clone(), Box::new(expr.clone())))
+        },
+        count: 0,
+    };
+    for stmt in body.iter_mut() {
+        visitor.visit_statement(stmt);
+    }
+    if visitor.count != uses {
+        return None;
+    }
+    Some(())
+}
+
+/// Checks whether the expression calls the method without arguments on the variable.
+fn is_call(expr: &Expr, var: &str, name: &str) -> bool {
+    match *expr {
+        Expr::Invoke(Some(ref this), ref method, _, ref args) => {
+            method.name == name
+                && args.is_empty()
+                && as_variable(this).is_some_and(|this| this == var)
+        }
+        _ => false,
+    }
+}
+
+fn for_loop(label: Option<Ident>, control: ForControl, body: Vec<Statement>) -> Statement {
+    let label = label.filter(|label| is_label_used(&body, label));
+    Statement::For(label, Box::new(control), Block(vec![], body))
+}
+
+/// Removes the update of a `for` loop, which assigns `var`, from each end of the body where it
+/// completes normally and before each `continue` of the loop, and returns it.
+fn remove_update(body: &mut Vec<Statement>, label: &Option<Ident>, var: &str) -> Option<Statement> {
+    let update = match last_stmt(body) {
+        Some(update) => update,
+        None => find_update(body, label, false)?,
+    };
+    match match_assignment(&update) {
+        Some((assigned, _)) if assigned == var => {}
+        _ => return None,
+    }
+    remove_at_ends(body, &update)?;
+    remove_before_continues(body, label, &update, false)?;
+    Some(update)
+}
+
+/// The last statement executed when the statements complete normally. If they end with an
+/// `if`-`else`, it's the last one of a branch that completes normally.
+fn last_stmt(stmts: &[Statement]) -> Option<Statement> {
+    let last = previous_stmt(stmts, stmts.len())?;
+    match stmts[last] {
+        Statement::If {
+            ref then,
+            els: Some(ref els),
+            ..
+        } => Some(then)
+            .into_iter()
+            .chain(Some(els))
+            .filter(|branch| completes_normally(&branch.1))
+            .filter_map(|branch| last_stmt(&branch.1))
+            .next(),
+        Statement::Break(_)
+        | Statement::Continue(_)
+        | Statement::Return(_)
+        | Statement::Throw(_) => None,
+        ref stmt => Some(stmt.clone()),
+    }
+}
+
+/// Removes `update` from each end of the statements where they complete normally, see
+/// `last_stmt`. Fails if one of them isn't `update`.
+fn remove_at_ends(stmts: &mut Vec<Statement>, update: &Statement) -> Option<()> {
+    if !completes_normally(stmts) {
+        return Some(());
+    }
+    let last = previous_stmt(stmts, stmts.len())?;
+    if let Statement::If {
+        ref mut then,
+        els: Some(ref mut els),
+        ..
+    } = stmts[last]
+    {
+        remove_at_ends(&mut then.1, update)?;
+        remove_at_ends(&mut els.1, update)?;
+    } else if stmts[last] == *update {
+        stmts.remove(last);
+        return Some(());
+    } else {
+        return None;
+    }
+    invert_empty_then(&mut stmts[last]);
+    Some(())
+}
+
+/// The statement before the first `continue` of the loop.
+fn find_update(stmts: &mut [Statement], label: &Option<Ident>, nested: bool) -> Option<Statement> {
+    for i in 0..stmts.len() {
+        if let Statement::Continue(ref target) = stmts[i] {
+            if targets(target, label, nested) {
+                return previous_stmt(stmts, i).map(|update| stmts[update].clone());
+            }
+        }
+        let nested = nested || is_loop(&stmts[i]);
+        for inner in nested_stmts(&mut stmts[i]) {
+            if let Some(update) = find_update(inner, label, nested) {
+                return Some(update);
+            }
+        }
+    }
+    None
+}
+
+/// Removes `update` before each `continue` of the loop. Fails if a `continue` isn't preceded by
+/// it.
+fn remove_before_continues(
+    stmts: &mut Vec<Statement>,
+    label: &Option<Ident>,
+    update: &Statement,
+    nested: bool,
+) -> Option<()> {
+    let mut i = 0;
+    while i < stmts.len() {
+        if let Statement::Continue(ref target) = stmts[i] {
+            if targets(target, label, nested) {
+                let previous = previous_stmt(stmts, i)?;
+                if stmts[previous] != *update {
+                    return None;
+                }
+                stmts.remove(previous);
+                continue;
+            }
+        }
+        let nested = nested || is_loop(&stmts[i]);
+        for inner in nested_stmts(&mut stmts[i]) {
+            remove_before_continues(inner, label, update, nested)?;
+        }
+        i += 1;
+    }
+    Some(())
+}
+
+/// Checks whether the end of the statements can be reached, erring on the side of `true`.
+fn completes_normally(stmts: &[Statement]) -> bool {
+    let last = match previous_stmt(stmts, stmts.len()) {
+        Some(last) => last,
+        None => return true,
+    };
+    match stmts[last] {
+        Statement::Break(_)
+        | Statement::Continue(_)
+        | Statement::Return(_)
+        | Statement::Throw(_) => false,
+        Statement::If {
+            ref then,
+            els: Some(ref els),
+            ..
+        } => completes_normally(&then.1) || completes_normally(&els.1),
+        Statement::Block(ref block) => completes_normally(&block.1),
+        _ => true,
+    }
+}
+
+fn is_loop(stmt: &Statement) -> bool {
+    matches!(*stmt, Statement::While { .. } | Statement::For(..))
+}
+
+/// The lists of statements directly nested in the statement.
+fn nested_stmts(stmt: &mut Statement) -> Vec<&mut Vec<Statement>> {
+    match *stmt {
+        Statement::Block(ref mut block)
+        | Statement::While {
+            body: ref mut block,
+            ..
+        }
+        | Statement::For(_, _, ref mut block)
+        | Statement::Synchronized(_, ref mut block) => vec![&mut block.1],
+        Statement::If {
+            ref mut then,
+            ref mut els,
+            ..
+        } => Some(&mut then.1)
+            .into_iter()
+            .chain(els.as_mut().map(|els| &mut els.1))
+            .collect(),
+        Statement::Try {
+            ref mut block,
+            ref mut catches,
+            ref mut finally,
+            ..
+        } => Some(&mut block.1)
+            .into_iter()
+            .chain(catches.iter_mut().map(|catch| &mut catch.block.1))
+            .chain(Some(&mut finally.1))
+            .collect(),
+        Statement::Switch { ref mut cases, .. } => {
+            cases.iter_mut().map(|case| &mut case.stmts).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The name of the elements that were propagated into their uses, see `take_element`. The
+/// elements are split from it like the variables of a slot, so that they get their final names
+/// when they are declared.
+const ELEMENT: &str = "elem#";
+
+/// Removes the assignment of the element to its variable at the start of the body of an
+/// enhanced `for` loop. `is_element` matches the element, returning the type it's cast to.
+/// Otherwise, the element was propagated into its uses, each of which must be the only use of the
+/// variable `var` that the loop uses to get the element. They are replaced by a new variable.
+/// Returns the variable of the element and its type, if known.
+fn take_element<F>(
+    body: &mut Vec<Statement>,
+    var: &str,
+    is_element: F,
+) -> Option<(Ident, Option<Type>)>
+where
+    F: Fn(&Expr) -> Option<Option<Type>>,
+{
+    if let Some(first) = body.iter().position(|stmt| *stmt != Statement::Nop) {
+        let elem = match_assignment(&body[first])
+            .and_then(|(var, value)| Some((var.clone(), is_element(value)?)));
+        if elem.is_some() {
+            body.remove(first);
+            return elem;
+        }
+    }
+    let uses = count_uses(body, var);
+    let elem = format!("{}{}", ELEMENT, var);
+    let mut typ = None;
+    let mut visitor = ReplaceVisitor {
+        f: |expr: &Expr| {
+            typ = is_element(expr)?;
+            Some(mk_variable(elem.clone()))
+        },
+        count: 0,
+    };
+    for stmt in body.iter_mut() {
+        visitor.visit_statement(stmt);
+    }
+    if visitor.count == 0 || visitor.count != uses {
+        return None;
+    }
+    Some((elem, typ))
+}
+
+/// Replaces the expressions for which `f` returns a replacement.
+struct ReplaceVisitor<F> {
+    f: F,
+    count: usize,
+}
+
+impl<F: FnMut(&Expr) -> Option<Expr>> Visitor for ReplaceVisitor<F> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        match (self.f)(expr) {
+            Some(replacement) => {
+                *expr = replacement;
+                self.count += 1;
+            }
+            None => walk_expr(self, expr),
+        }
+    }
+}
+
+/// Turns `var = var op value` into `var op= value`.
+fn compound_assignment(update: Statement) -> Expr {
+    let update = match update {
+        Statement::Expr(update) => update,
+        _ => unreachable!(),
+    };
+    if let Expr::Assign {
+        ref to,
+        op: None,
+        ref from,
+    } = update
+    {
+        if let Expr::BinaryOp(op, ref left, ref right) = **from {
+            let is_compound = match op {
+                BinOp::Cmp(_) | BinOp::FloatCmp(_) | BinOp::LogAnd | BinOp::LogOr => false,
+                _ => **left == Expr::Assignable(to.clone()),
+            };
+            if is_compound {
+                // `iinc` adds negative numbers to decrement:
+                let (op, right) = match (op, &**right) {
+                    (BinOp::Add, &Expr::Literal(Literal::Integer(value))) if value < 0 => {
+                        (BinOp::Sub, Expr::Literal(Literal::Integer(-value)))
+                    }
+                    _ => (op, (**right).clone()),
+                };
+                return Expr::Assign {
+                    to: to.clone(),
+                    op: Some(op),
+                    from: Box::new(right),
+                };
+            }
+        }
+    }
+    update
+}
+
+/// The type of the elements of the array, as far as it's known without inferring types.
+/// `types` are the known types of variables.
+fn element_type(array: &Expr, types: &[(Ident, Type)]) -> Type {
+    match array_type(array, types) {
+        Some(Type::Array(element)) => *element,
+        _ => object(),
+    }
+}
+
+/// The type of the expression, if it's known without inferring types.
+fn array_type(array: &Expr, types: &[(Ident, Type)]) -> Option<Type> {
+    match *array {
+        Expr::Assignable(ref assignable) => match **assignable {
+            Assignable::Variable(ref var, _) => declared_type(var, types),
+            Assignable::Field { ref field, .. } => Some(field.typ.clone()),
+            Assignable::ArrayAccess { ref array, .. } => match array_type(array, types) {
+                Some(Type::Array(element)) => Some(*element),
+                _ => None,
+            },
+        },
+        Expr::Invoke(_, ref method, ..) => Some(method.signature.return_type.clone()),
+        Expr::Cast(ref typ, _)
+        | Expr::NewArray { ref typ, .. }
+        | Expr::ArrayInit { ref typ, .. } => Some(typ.clone()),
+        _ => None,
+    }
+}
+
+/// The type of the variable in `types`, preferring the innermost one.
+fn declared_type(var: &str, types: &[(Ident, Type)]) -> Option<Type> {
+    types
+        .iter()
+        .rev()
+        .find(|entry| entry.0 == var)
+        .map(|entry| entry.1.clone())
+}
+
+fn object() -> Type {
+    Type::Reference("java.lang.Object".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompiler::fixtures::*;
+
+    fn array(typ: Type) -> Type {
+        Type::Array(Box::new(typ))
+    }
+
+    #[test]
+    fn element_types() {
+        let strings = Type::Reference("java.lang.String".to_owned());
+        let created = Expr::ArrayInit {
+            typ: array(strings.clone()),
+            elements: vec![],
+        };
+        assert_eq!(element_type(&created, &[]), strings);
+        let created = Expr::NewArray {
+            typ: array(strings.clone()),
+            lengths: vec![int(3)],
+        };
+        assert_eq!(element_type(&created, &[]), strings);
+        let row = Expr::Assignable(Box::new(Assignable::ArrayAccess {
+            array: Box::new(var("local_0")),
+            index: Box::new(int(0)),
+        }));
+        let types = [("local_0".to_owned(), array(array(Type::Int)))];
+        assert_eq!(element_type(&row, &types), Type::Int);
+        assert_eq!(element_type(&row, &[]), object());
+    }
+}
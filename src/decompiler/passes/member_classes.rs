@@ -0,0 +1,113 @@
+//! Refers to the outer instance of a member class as `Outer.this`.
+//!
+//! javac passes the outer instance to the constructors of a (non-static) member class, which
+//! store it in the synthetic field `this$0` before calling the super constructor, and accesses
+//! the members of the outer class through that field. Neither the field nor the assignment can be
+//! written in the source code, so they are removed and `this.this$0` becomes `Outer.this`. The
+//! number in the name is the depth of nesting, e.g. a member class of a member class accesses the
+//! outermost instance as `this.this$1.this$0`.
+
+use decompiler::types::*;
+use disassembler::types::*;
+
+pub fn recover_outer_instance(unit: &mut CompilationUnit<Block>) {
+    let outer_type = match unit.outer_class {
+        Some(ref outer_class) => Type::Reference(outer_class.clone()),
+        None => return,
+    };
+    let outer_field = unit
+        .declarations
+        .iter()
+        .filter_map(|declaration| match *declaration {
+            Declaration::Field(ref field)
+                if field.synthetic
+                    && field.name.starts_with("this$")
+                    && field.typ == outer_type =>
+            {
+                Some(field.name.clone())
+            }
+            _ => None,
+        })
+        .next();
+    let outer_field = match outer_field {
+        Some(outer_field) => outer_field,
+        None => return,
+    };
+    unit.declarations.retain(|declaration| match *declaration {
+        Declaration::Field(ref field) => field.name != outer_field,
+        _ => true,
+    });
+    for declaration in &mut unit.declarations {
+        let code = match *declaration {
+            Declaration::Method(ref mut method) => method.code.as_mut(),
+            Declaration::Constructor(ref mut constructor) => {
+                for stmt in &mut constructor.code.1 {
+                    if is_outer_assignment(stmt, &outer_field) {
+                        *stmt = Statement::Nop;
+                    }
+                }
+                Some(&mut constructor.code)
+            }
+            Declaration::Field(_) | Declaration::EnumConstant(_) => None,
+        };
+        if let Some(code) = code {
+            OuterInstanceVisitor {
+                outer_field: &outer_field,
+            }
+            .visit_block(code);
+        }
+    }
+}
+
+/// Matches `this.this$0 = ...`.
+fn is_outer_assignment(stmt: &Statement, outer_field: &str) -> bool {
+    match *stmt {
+        Statement::Expr(Expr::Assign {
+            ref to, op: None, ..
+        }) => match **to {
+            Assignable::Field {
+                this: Some(ref this),
+                ref field,
+                ..
+            } => **this == Expr::This && field.name == outer_field,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Replaces the reads of the outer instance with `Outer.this`, also through the outer instances
+/// of the outer classes.
+struct OuterInstanceVisitor<'a> {
+    outer_field: &'a str,
+}
+
+impl<'a> Visitor for OuterInstanceVisitor<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        walk_expr(self, expr);
+        let outer_class = match *expr {
+            Expr::Assignable(ref assignable) => match **assignable {
+                Assignable::Field {
+                    this: Some(ref this),
+                    ref field,
+                    ..
+                } => {
+                    let is_outer_instance = match **this {
+                        Expr::This => field.name == self.outer_field,
+                        Expr::QualifiedThis(_) => field.name.starts_with("this$"),
+                        _ => false,
+                    };
+                    match field.typ {
+                        Type::Reference(ref class) if is_outer_instance => Some(class.clone()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(outer_class) = outer_class {
+            *expr = Expr::QualifiedThis(ClassRef(outer_class));
+        }
+    }
+}
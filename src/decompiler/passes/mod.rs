@@ -1,4 +1,15 @@
+pub mod arrays;
+pub mod conditions;
 pub mod constructors;
+pub mod enums;
+pub mod imports;
+pub mod initializers;
+pub mod loops;
+pub mod member_classes;
 pub mod stack_to_var;
 pub mod structure;
+pub mod switches;
+pub mod synchronized;
+pub mod type_inference;
 pub mod var_prop;
+pub mod variables;
@@ -1,6 +1,7 @@
 use decompiler::cfg::*;
 use decompiler::types::*;
 use disassembler::instructions::*;
+use std::mem;
 
 pub fn convert_un_op(op: UnaryOp) -> UnOp {
     match op {
@@ -24,35 +25,57 @@ pub fn convert_bin_op(op: BinaryOp) -> BinOp {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, PartialEq)]
-pub struct StackLayout(pub StackVarId);
+/// The values on the stack, each held by the stack variable of its position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StackLayout {
+    /// Whether each value is a long or double, which count as two slots for the `2` variants of
+    /// `StackManage`.
+    wide: Vec<bool>,
+}
 
 impl StackLayout {
     pub fn new() -> Self {
-        StackLayout(0)
+        StackLayout::default()
+    }
+
+    pub fn depth(&self) -> StackVarId {
+        self.wide.len() as StackVarId
     }
 
     pub fn get(&self, i: isize) -> StackVarId {
-        self.0 - i
+        self.depth() + i
     }
 
-    pub fn push(&mut self) -> StackVarId {
-        self.0 += 1;
-        self.0 - 1
+    pub fn push(&mut self, wide: bool) -> StackVarId {
+        self.wide.push(wide);
+        self.depth() - 1
     }
 
     pub fn pop(&mut self) -> StackVarId {
-        self.0 -= 1;
-        assert!(self.0 >= 0);
-        self.0
+        self.wide.pop().expect("pop from an empty stack");
+        self.depth()
     }
 
-    pub fn execute(&mut self, instruction: &Instruction, metadata: &Metadata) -> Vec<Statement> {
-        match *instruction {
+    /// Translates the instruction into statements on the variables holding the stack.
+    /// Fails with a message if the instruction isn't supported.
+    pub fn execute(
+        &mut self,
+        instruction: &Instruction,
+        metadata: &Metadata,
+    ) -> Result<Vec<Statement>, String> {
+        let unsupported = || Err(format!("the instruction `{}` isn't supported", instruction));
+        Ok(match *instruction {
             Instruction::Nop => vec![],
+            // e.g. method handles, which have no literal syntax:
+            Instruction::Load(RValue::ConstantRef { const_ref })
+                if !metadata.literals.contains_key(&const_ref) =>
+            {
+                return unsupported()
+            }
             Instruction::Load(ref rvalue) => {
+                let wide = self.is_wide(rvalue, metadata);
                 let expr = self.make_stack_vars_rvalue(rvalue, metadata);
-                let top = self.push();
+                let top = self.push(wide);
                 vec![stmt_expr(Expr::Assign {
                     to: Box::new(Assignable::Variable(stack(top), 0)),
                     op: None,
@@ -60,8 +83,10 @@ impl StackLayout {
                 })]
             }
             Instruction::Store(ref to) => {
+                // The stack indices of `to` are relative to the stack including the value:
+                let top = self.get(-1);
                 let assignable = self.make_stack_vars_lvalue(to, metadata);
-                let top = self.pop();
+                self.pop();
                 vec![stmt_expr(Expr::Assign {
                     to: Box::new(assignable),
                     op: None,
@@ -69,9 +94,9 @@ impl StackLayout {
                 })]
             }
             Instruction::Arithm(ref arithm) => match *arithm {
-                Arithm::UnaryOp(op) => {
+                Arithm::UnaryOp(kind, op) => {
                     let v = self.pop();
-                    let res = self.push();
+                    let res = self.push(kind.is_wide());
                     let to = Box::new(Assignable::Variable(stack(res), 0));
                     let from = Box::new(Expr::UnaryOp(
                         convert_un_op(op),
@@ -83,10 +108,10 @@ impl StackLayout {
                         from: from,
                     })]
                 }
-                Arithm::BinaryOp(op) => {
+                Arithm::BinaryOp(kind, op) => {
                     let w = self.pop();
                     let v = self.pop();
-                    let res = self.push();
+                    let res = self.push(kind.is_wide());
                     let to = Box::new(Assignable::Variable(stack(res), 0));
                     let from = Box::new(Expr::BinaryOp(
                         convert_bin_op(op),
@@ -116,37 +141,147 @@ impl StackLayout {
                         from: from,
                     })]
                 }
+                Arithm::Compare { kind, .. } => {
+                    let w = self.pop();
+                    let v = self.pop();
+                    let res = self.push(false);
+                    let from = compare(kind, mk_variable(stack(v)), mk_variable(stack(w)));
+                    vec![stmt_expr(Expr::Assign {
+                        to: Box::new(Assignable::Variable(stack(res), 0)),
+                        op: None,
+                        from: Box::new(from),
+                    })]
+                }
             },
-            Instruction::TypeConv(_) => unimplemented!(),
-            Instruction::ObjManip(_) => unimplemented!(),
-            Instruction::StackManage(_) => unimplemented!(),
+            Instruction::TypeConv(TypeConv { to, .. }) => {
+                let v = self.pop();
+                let res = self.push(to.is_wide());
+                vec![stmt_expr(Expr::Assign {
+                    to: Box::new(Assignable::Variable(stack(res), 0)),
+                    op: None,
+                    from: Box::new(Expr::Cast(
+                        primitive_type(to),
+                        Box::new(mk_variable(stack(v))),
+                    )),
+                })]
+            }
+            Instruction::ObjManip(ref obj_manip) => {
+                let class = |class_ref| match metadata.literals[&class_ref] {
+                    Literal::Class(ref typ) => typ.clone(),
+                    _ => unreachable!(),
+                };
+                let from = match *obj_manip {
+                    // The object is initialized by the constructor call that follows, which is
+                    // merged into this expression later, see `constructors`.
+                    ObjManip::New { class_ref } => Expr::New {
+                        class: class(class_ref),
+                        args: vec![],
+                    },
+                    ObjManip::NewArray(ref typ) => Expr::NewArray {
+                        typ: Type::Array(Box::new(typ.clone())),
+                        lengths: self.pop_values(1),
+                    },
+                    ObjManip::ANewArray { class_ref } => Expr::NewArray {
+                        typ: Type::Array(Box::new(class(class_ref))),
+                        lengths: self.pop_values(1),
+                    },
+                    ObjManip::MultiANewArray {
+                        class_ref,
+                        dimensions,
+                    } => Expr::NewArray {
+                        typ: class(class_ref),
+                        lengths: self.pop_values(dimensions as isize),
+                    },
+                    ObjManip::ArrayLength => {
+                        let v = self.pop();
+                        Expr::ArrayLength(Box::new(mk_variable(stack(v))))
+                    }
+                    ObjManip::CheckCast { class_ref } => {
+                        let v = self.pop();
+                        Expr::Cast(class(class_ref), Box::new(mk_variable(stack(v))))
+                    }
+                    ObjManip::InstanceOf { class_ref } => {
+                        let v = self.pop();
+                        Expr::InstanceOf(Box::new(mk_variable(stack(v))), class(class_ref))
+                    }
+                };
+                let res = self.push(false);
+                vec![stmt_expr(Expr::Assign {
+                    to: Box::new(Assignable::Variable(stack(res), 0)),
+                    op: None,
+                    from: Box::new(from),
+                })]
+            }
+            Instruction::StackManage(op) => {
+                let stmts = match op {
+                    StackManage::Pop => self.discard(1),
+                    StackManage::Pop2 => self.discard(2),
+                    StackManage::Dup => self.dup(1, 0),
+                    StackManage::DupX1 => self.dup(1, 1),
+                    StackManage::DupX2 => self.dup(1, 2),
+                    StackManage::Dup2 => self.dup(2, 0),
+                    StackManage::Dup2X1 => self.dup(2, 1),
+                    StackManage::Dup2X2 => self.dup(2, 2),
+                    StackManage::Swap => {
+                        let stmts = self.dup(1, 1);
+                        // The copy on top only serves as a temporary variable:
+                        self.wide.pop();
+                        stmts
+                    }
+                };
+                match stmts {
+                    Some(stmts) => stmts,
+                    // A long or double would be split:
+                    None => return unsupported(),
+                }
+            }
             Instruction::Jump(_) => unreachable!(),
+            Instruction::Switch(_) => unreachable!(),
+            Instruction::Subroutine(_) => return unsupported(),
+            Instruction::Invoke(Invoke {
+                method_index,
+                kind: InvokeKind::Dynamic,
+            }) => {
+                let dynamic_ref = &metadata.dynamic_refs[&method_index];
+                let signature = match dynamic_ref.typ {
+                    Descriptor::Signature(ref signature) => signature.clone(),
+                    Descriptor::Type(_) => return unsupported(),
+                };
+                // The call site is linked by a bootstrap method, so there's no method to call in
+                // the source code. Only string concatenations are translated (lambdas aren't):
+                let args = self.pop_values(signature.parameters.len() as isize);
+                let concatenation = metadata
+                    .bootstrap_methods
+                    .get(dynamic_ref.bootstrap_method as usize)
+                    .and_then(|bootstrap| concatenation(bootstrap, args, &signature, metadata));
+                let concatenation = match concatenation {
+                    Some(concatenation) => concatenation,
+                    None => return unsupported(),
+                };
+                let result = self.push(false);
+                vec![stmt_expr(Expr::Assign {
+                    from: Box::new(concatenation),
+                    op: None,
+                    to: Box::new(Assignable::Variable(stack(result), 0)),
+                })]
+            }
             Instruction::Invoke(Invoke { method_index, kind }) => {
                 let method_ref = &metadata.method_refs[&method_index];
                 let class_ref = &metadata.class_refs[&method_ref.class_ref];
-                let args_count = method_ref.signature.parameters.len() as isize;
-                let args_range = self.0 - args_count..self.0;
-                self.0 -= args_count;
+                let args = self.pop_values(method_ref.signature.parameters.len() as isize);
                 let this_object = match kind {
-                    InvokeKind::Special | InvokeKind::Virtual => {
+                    InvokeKind::Special | InvokeKind::Virtual | InvokeKind::Interface => {
                         let top = self.pop();
                         Some(Box::new(mk_variable(stack(top))))
                     }
                     _ => None,
                 };
-                let method_call = Expr::Invoke(
-                    this_object,
-                    method_ref.clone(),
-                    class_ref.clone(),
-                    args_range
-                        .into_iter()
-                        .map(|i| mk_variable(stack(i)))
-                        .collect::<Vec<_>>(),
-                );
+                let method_call =
+                    Expr::Invoke(this_object, method_ref.clone(), class_ref.clone(), args);
                 if method_ref.signature.return_type == Type::Void {
                     vec![stmt_expr(method_call)]
                 } else {
-                    let result = self.push();
+                    let result = self.push(method_ref.signature.return_type.is_wide());
                     vec![stmt_expr(Expr::Assign {
                         from: Box::new(method_call),
                         op: None,
@@ -154,7 +289,10 @@ impl StackLayout {
                     })]
                 }
             }
-            Instruction::Throw => unimplemented!(),
+            Instruction::Throw => {
+                let top = self.pop();
+                vec![Statement::Throw(mk_variable(stack(top)))]
+            }
             Instruction::Return(value) => {
                 let value = value.map(|_| {
                     let top = self.pop();
@@ -162,7 +300,107 @@ impl StackLayout {
                 });
                 vec![Statement::Return(value)]
             }
-            Instruction::Synchronized(_) => unimplemented!(),
+            Instruction::Synchronized(op) => {
+                let top = self.pop();
+                let object = mk_variable(stack(top));
+                vec![match op {
+                    Synchronized::MonitorEnter => Statement::MonitorEnter(object),
+                    Synchronized::MonitorExit => Statement::MonitorExit(object),
+                }]
+            }
+            Instruction::Invalid(opcode) => return Err(format!("invalid opcode {:#x}", opcode)),
+        })
+    }
+
+    /// Pops the topmost `count` values, returning them in the order they were pushed.
+    fn pop_values(&mut self, count: isize) -> Vec<Expr> {
+        let start = self.depth() - count;
+        assert!(start >= 0);
+        self.wide.truncate(start as usize);
+        (start..start + count)
+            .map(|i| mk_variable(stack(i)))
+            .collect()
+    }
+
+    /// The number of values on top of the stack, below the topmost `skip` values, that take
+    /// `slots` slots, or `None` if a long or double would be split.
+    fn values_in_slots(&self, slots: usize, skip: usize) -> Option<usize> {
+        let mut taken = 0;
+        let mut count = 0;
+        for &wide in self.wide.iter().rev().skip(skip) {
+            if taken == slots {
+                break;
+            }
+            taken += if wide { 2 } else { 1 };
+            count += 1;
+        }
+        if taken == slots {
+            Some(count)
+        } else {
+            None
+        }
+    }
+
+    /// Discards the values in the topmost `slots` slots (`pop` and `pop2`).
+    fn discard(&mut self, slots: usize) -> Option<Vec<Statement>> {
+        let count = self.values_in_slots(slots, 0)?;
+        // The values are discarded, e.g. the result of a method call. The ones without side
+        // effects are dropped once they're propagated, see `var_prop`:
+        Some(
+            self.pop_values(count as isize)
+                .into_iter()
+                .map(stmt_expr)
+                .collect(),
+        )
+    }
+
+    /// Copies the values in the topmost `slots` slots below the `skipped_slots` slots under them
+    /// (the `dup` variants), e.g. `dup_x1` turns the stack `a, b` into `b, a, b`.
+    fn dup(&mut self, slots: usize, skipped_slots: usize) -> Option<Vec<Statement>> {
+        let copied = self.values_in_slots(slots, 0)?;
+        let skipped = self.values_in_slots(skipped_slots, copied)?;
+        let depth = self.wide.len();
+        let assign = |to: usize, from: usize| {
+            stmt_expr(Expr::Assign {
+                to: Box::new(Assignable::Variable(stack(to as StackVarId), 0)),
+                op: None,
+                from: Box::new(mk_variable(stack(from as StackVarId))),
+            })
+        };
+        // The copies are pushed first, then the skipped values are moved up, from the topmost
+        // one, and finally the copied values are moved below them:
+        let mut stmts: Vec<_> = (0..copied)
+            .map(|i| assign(depth + i, depth - copied + i))
+            .collect();
+        if skipped > 0 {
+            let bottom = depth - copied - skipped;
+            stmts.extend(
+                (0..skipped)
+                    .rev()
+                    .map(|i| assign(bottom + copied + i, bottom + i)),
+            );
+            stmts.extend((0..copied).map(|i| assign(bottom + i, depth + i)));
+        }
+        let copies = self.wide.split_off(depth - copied);
+        let moved = self.wide.split_off(depth - copied - skipped);
+        self.wide.extend(copies.iter().chain(&moved).chain(&copies));
+        Some(stmts)
+    }
+
+    /// Whether the value loaded by `rvalue` is a long or double.
+    fn is_wide(&self, rvalue: &RValue, metadata: &Metadata) -> bool {
+        let is_wide_literal =
+            |literal: &Literal| matches!(*literal, Literal::Long(_) | Literal::Double(_));
+        match *rvalue {
+            RValue::Constant(ref literal) => is_wide_literal(literal),
+            RValue::ConstantRef { const_ref } => is_wide_literal(&metadata.literals[&const_ref]),
+            RValue::LValue(LValue::Local(kind, _))
+            | RValue::LValue(LValue::ArrayElement { kind, .. }) => kind.is_wide(),
+            RValue::LValue(LValue::Stack(index)) => self.wide[self.get(index) as usize],
+            RValue::LValue(LValue::StaticField { field_ref })
+            | RValue::LValue(LValue::InstanceField { field_ref, .. }) => {
+                metadata.field_refs[&field_ref].typ.is_wide()
+            }
         }
     }
 
@@ -181,7 +419,7 @@ impl StackLayout {
     fn make_stack_vars_lvalue(&mut self, expr: &LValue, metadata: &Metadata) -> Assignable {
         let mut remove = 0;
         let result = match *expr {
-            LValue::Local(index) => Assignable::Variable(local(index), 0),
+            LValue::Local(_, index) => Assignable::Variable(local(index), 0),
             LValue::Stack(index) => {
                 let real_index = self.get(index);
                 remove += 1;
@@ -210,11 +448,36 @@ impl StackLayout {
                     field: field.clone(),
                 }
             }
+            LValue::ArrayElement {
+                array_stack_index,
+                index_stack_index,
+                ..
+            } => {
+                let array = self.get(array_stack_index);
+                let index = self.get(index_stack_index);
+                remove += 2;
+                Assignable::ArrayAccess {
+                    array: Box::new(mk_variable(stack(array))),
+                    index: Box::new(mk_variable(stack(index))),
+                }
+            }
         };
-        self.0 -= remove;
+        for _ in 0..remove {
+            self.pop();
+        }
         result
     }
 
+    pub fn terminator_to_expr(&mut self, terminator: &Terminator) -> Expr {
+        match *terminator {
+            Terminator::If(ref cond) => self.cond_to_expr(cond),
+            Terminator::Switch => {
+                let v = self.pop();
+                mk_variable(stack(v))
+            }
+        }
+    }
+
     pub fn cond_to_expr(&mut self, cond: &JumpCondition) -> Expr {
         match *cond {
             JumpCondition::CmpZero(ord) => {
@@ -225,6 +488,14 @@ impl StackLayout {
                     Box::new(Expr::Literal(Literal::Integer(0))),
                 )
             }
+            JumpCondition::CmpNull(ord) => {
+                let v = self.pop();
+                Expr::BinaryOp(
+                    BinOp::Cmp(ord),
+                    Box::new(mk_variable(stack(v))),
+                    Box::new(Expr::Literal(Literal::NullReference)),
+                )
+            }
             JumpCondition::Cmp(ord) | JumpCondition::CmpRef(ord) => {
                 let w = self.pop();
                 let v = self.pop();
@@ -238,34 +509,322 @@ impl StackLayout {
     }
 }
 
+/// Rebuilds the string concatenation of the arguments that a call site linked by the
+/// `StringConcatFactory` computes, e.g. `"x = " + x` from the recipe `x = \u{1}` and `x`. The
+/// recipe contains the text, with `\u{1}` for each argument and `\u{2}` for each further constant
+/// argument of the bootstrap method.
+fn concatenation(
+    bootstrap: &BootstrapRef,
+    args: Vec<Expr>,
+    signature: &Signature,
+    metadata: &Metadata,
+) -> Option<Expr> {
+    let method = metadata.method_refs.get(&bootstrap.method_ref)?;
+    let class = metadata.class_refs.get(&method.class_ref)?;
+    if class.0 != "java.lang.invoke.StringConcatFactory" {
+        return None;
+    }
+    let (recipe, constants) = match &*method.name {
+        "makeConcatWithConstants" => match metadata.literals.get(bootstrap.arguments.first()?) {
+            Some(Literal::String(recipe)) => (recipe.clone(), &bootstrap.arguments[1..]),
+            _ => return None,
+        },
+        "makeConcat" => ("\u{1}".repeat(args.len()), &[][..]),
+        _ => return None,
+    };
+    let string = Type::Reference("java.lang.String".to_owned());
+    let mut args = args.into_iter().zip(&signature.parameters);
+    let mut constants = constants.iter();
+    // The operands, and whether they are strings:
+    let mut operands = vec![];
+    let mut text = String::new();
+    for c in recipe.chars() {
+        let operand = match c {
+            '\u{1}' => {
+                let (arg, (_, typ)) = args.next()?;
+                (arg, *typ == string)
+            }
+            '\u{2}' => match *metadata.literals.get(constants.next()?)? {
+                Literal::String(ref constant) => {
+                    text.push_str(constant);
+                    continue;
+                }
+                ref literal => (Expr::Literal(literal.clone()), false),
+            },
+            c => {
+                text.push(c);
+                continue;
+            }
+        };
+        if !text.is_empty() {
+            operands.push((Expr::Literal(Literal::String(mem::take(&mut text))), true));
+        }
+        operands.push(operand);
+    }
+    if !text.is_empty() {
+        operands.push((Expr::Literal(Literal::String(text)), true));
+    }
+    if args.next().is_some() {
+        return None;
+    }
+    // `+` only concatenates if the first or the second operand is a string:
+    if !operands.iter().take(2).any(|&(_, is_string)| is_string) {
+        operands.insert(0, (Expr::Literal(Literal::String(String::new())), true));
+    }
+    let mut operands = operands.into_iter().map(|(operand, _)| operand);
+    let first = operands.next()?;
+    Some(operands.fold(first, |left, right| {
+        Expr::BinaryOp(BinOp::Add, Box::new(left), Box::new(right))
+    }))
+}
+
+/// The type of values of the primitive `kind`, e.g. the target type of a conversion.
+fn primitive_type(kind: Kind) -> Type {
+    match kind {
+        Kind::B => Type::Byte,
+        Kind::S => Type::Short,
+        Kind::C => Type::Char,
+        Kind::I => Type::Int,
+        Kind::L => Type::Long,
+        Kind::F => Type::Float,
+        Kind::D => Type::Double,
+        Kind::A => unreachable!(),
+    }
+}
+
+/// The call of `Long.compare`, `Float.compare` or `Double.compare` that computes the result of a
+/// comparison instruction. For floating point numbers, it only matches `fcmpg` and `dcmpg` if an
+/// operand is NaN, but the comparisons in conditions are merged into them, see `merge_comparison`.
+fn compare(kind: Kind, v: Expr, w: Expr) -> Expr {
+    let (class, typ) = match kind {
+        Kind::L => ("java.lang.Long", Type::Long),
+        Kind::F => ("java.lang.Float", Type::Float),
+        _ => ("java.lang.Double", Type::Double),
+    };
+    let method = MethodRef {
+        class_ref: 0,
+        name: "compare".to_owned(),
+        signature: Signature {
+            parameters: vec![(String::new(), typ.clone()), (String::new(), typ)],
+            return_type: Type::Int,
+        },
+    };
+    Expr::Invoke(None, method, ClassRef(class.to_owned()), vec![v, w])
+}
+
+/// Merges the comparison at the end of the block into its condition, which tests the result of
+/// the comparison, e.g. `stack_0 = Long.compare(stack_0, stack_1)` and `stack_0 < 0` into
+/// `stack_0 < stack_1`.
+///
+/// A comparison of floating point numbers in Java is false if an operand is NaN (except for
+/// `!=`), while the condition holds for some orderings depending on the result that the
+/// comparison instruction gives for NaN, e.g. `stack_0 >= 0` after `dcmpg`. Such a condition
+/// becomes the negation of the opposite comparison, `!(stack_0 < stack_1)`.
+fn merge_comparison(bb: &mut BasicBlock<Statement, Expr>, kind: Kind, nan_greater: bool) {
+    let operands = match (bb.stmts.last(), &bb.terminator) {
+        (
+            Some(&Statement::Expr(Expr::Assign {
+                ref to, ref from, ..
+            })),
+            &Some(Expr::BinaryOp(BinOp::Cmp(_), ref result, ref zero)),
+        ) if **result == Expr::Assignable(to.clone())
+            && **zero == Expr::Literal(Literal::Integer(0)) =>
+        {
+            match **from {
+                Expr::Invoke(_, _, _, ref args) if args.len() == 2 => args.clone(),
+                _ => return,
+            }
+        }
+        _ => return,
+    };
+    let ordering = match bb.terminator {
+        Some(Expr::BinaryOp(BinOp::Cmp(ordering), _, _)) => ordering,
+        _ => return,
+    };
+    let mut operands = operands.into_iter();
+    let (left, right) = (operands.next().unwrap(), operands.next().unwrap());
+    // Whether the condition holds if an operand is NaN, although the comparison doesn't:
+    let differs_for_nan = match ordering {
+        Ordering::GT | Ordering::GE => nan_greater,
+        Ordering::LT | Ordering::LE => !nan_greater,
+        Ordering::EQ | Ordering::NE => false,
+    };
+    let (left, right) = (Box::new(left), Box::new(right));
+    bb.terminator = Some(if kind == Kind::L {
+        Expr::BinaryOp(BinOp::Cmp(ordering), left, right)
+    } else if differs_for_nan {
+        let opposite = Expr::BinaryOp(BinOp::FloatCmp(ordering.negate()), left, right);
+        Expr::UnaryOp(UnOp::LogNot, Box::new(opposite))
+    } else {
+        Expr::BinaryOp(BinOp::FloatCmp(ordering), left, right)
+    });
+    bb.stmts.pop();
+}
+
 fn stack(i: isize) -> String {
     format!("stack_{}", i)
 }
 
+/// Checks whether the variable holds a value on the stack (rather than a local variable).
+pub fn is_stack_variable(ident: &str) -> bool {
+    ident.starts_with("stack_")
+}
+
 fn local(i: usize) -> String {
     format!("local_{}", i)
 }
 
-pub fn stack_to_vars(
-    unit: CompilationUnit<Cfg<Instruction, JumpCondition>>,
-) -> CompilationUnit<Cfg<Statement, Expr>> {
-    let mut unit = unit.map(transform);
+/// Checks whether the variable is named after its slot, because the class file doesn't contain
+/// the names of the local variables.
+pub fn is_unnamed_local(ident: &str) -> bool {
+    ident.starts_with("local_")
+}
+
+/// The variable holding the exception caught by the handler starting at the given block.
+pub fn caught_exception(handler: Label) -> Ident {
+    format!("exception_{}", handler.index())
+}
+
+/// The names of the local variables of a method, where the class file contains them.
+/// Otherwise the variables are named after their slots, see `local`.
+struct LocalNames {
+    variables: Vec<LocalVariableScope>,
+    parameters: Vec<(u16, Ident)>,
+}
+
+impl LocalNames {
+    fn new<C>(method: &Method<C>) -> LocalNames {
+        let is_static = method.modifiers.contains(&Modifier::Static);
+        let slots = method.signature.parameter_slots(is_static);
+        let parameters = slots
+            .into_iter()
+            .zip(&method.signature.parameters)
+            .filter(|(_, parameter)| !parameter.0.is_empty())
+            .map(|(slot, parameter)| (slot, parameter.0.clone()))
+            .collect();
+        // The variable in slot 0 is `this`, see `handle_parameters`:
+        let variables = method
+            .variables
+            .iter()
+            .filter(|variable| is_static || variable.index != 0)
+            .cloned()
+            .collect();
+        LocalNames {
+            variables,
+            parameters,
+        }
+    }
+
+    /// The name of the variable in slot `index` that is accessed by the instruction at `pc`.
+    fn get(&self, index: u16, pc: u16, is_store: bool) -> Option<&Ident> {
+        let in_scope = self
+            .variables
+            .iter()
+            .find(|variable| variable.index == index && variable.start <= pc && pc < variable.end);
+        // A variable is only in scope after the instruction storing its initial value, which is
+        // at most 4 bytes long (a `wide` store):
+        let starting = || {
+            self.variables.iter().find(|variable| {
+                is_store
+                    && variable.index == index
+                    && pc < variable.start
+                    && variable.start - pc <= 4
+            })
+        };
+        let parameter = || {
+            self.parameters
+                .iter()
+                .find(|parameter| parameter.0 == index)
+                .map(|parameter| &parameter.1)
+        };
+        in_scope
+            .or_else(starting)
+            .map(|variable| &variable.name)
+            .or_else(parameter)
+    }
+
+    /// Renames the local variable that is accessed by the instruction at `pc` in the statements
+    /// generated for it.
+    fn rename(&self, stmts: &mut [Statement], instruction: &Instruction, pc: u16) {
+        let (index, is_store) = match *instruction {
+            Instruction::Load(RValue::LValue(LValue::Local(_, index))) => (index as u16, false),
+            Instruction::Store(LValue::Local(_, index)) => (index as u16, true),
+            Instruction::Arithm(Arithm::IncreaseLocal { local_index, .. }) => (local_index, false),
+            _ => return,
+        };
+        if let Some(name) = self.get(index, pc, is_store) {
+            let mut visitor = RenameVisitor {
+                from: local(index as usize),
+                to: name,
+            };
+            for stmt in stmts {
+                visitor.visit_statement(stmt);
+            }
+        }
+    }
+}
+
+struct RenameVisitor<'a> {
+    from: Ident,
+    to: &'a str,
+}
+
+impl<'a> Visitor for RenameVisitor<'a> {
+    fn visit_assignable(&mut self, assignable: &mut Assignable) {
+        if let Assignable::Variable(ref mut var, _) = *assignable {
+            if *var == self.from {
+                *var = self.to.to_owned();
+            }
+        }
+        walk_assignable(self, assignable);
+    }
+}
+
+/// Replaces the stack by variables. `warn` is called with a message for each method whose code
+/// can't be decompiled, which is replaced by a stub that throws an exception.
+pub fn stack_to_vars<W>(
+    unit: CompilationUnit<Cfg<(u16, Instruction), Terminator>>,
+    mut warn: W,
+) -> CompilationUnit<Cfg<Statement, Expr>>
+where
+    W: FnMut(String),
+{
+    // `map` transforms the code of the methods in the order of their declarations:
+    let mut names = unit
+        .declarations
+        .iter()
+        .filter_map(|declaration| match *declaration {
+            Declaration::Method(ref method) if method.code.is_some() => {
+                Some((method.name.clone(), LocalNames::new(method)))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let mut unit = unit.map(|cfg, metadata| {
+        let (name, names) = names.next().unwrap();
+        transform(cfg, metadata, &names).unwrap_or_else(|reason| {
+            warn(format!("`{}` can't be decompiled: {}", name, reason));
+            stub(&reason)
+        })
+    });
     for declaration in &mut unit.declarations {
         match *declaration {
             Declaration::Method(ref mut method) => {
                 handle_parameters(method);
             }
             Declaration::Constructor(..) => unreachable!("no constructors at this point"),
-            Declaration::Field(..) => {}
+            Declaration::Field(..) | Declaration::EnumConstant(..) => {}
         }
     }
     unit
 }
 
 fn transform(
-    mut cfg: Cfg<Instruction, JumpCondition>,
+    mut cfg: Cfg<(u16, Instruction), Terminator>,
     metadata: &Metadata,
-) -> Cfg<Statement, Expr> {
+    names: &LocalNames,
+) -> Result<Cfg<Statement, Expr>, String> {
     use petgraph::visit::Dfs;
     let mut stack_at_bb = vec![None; cfg.graph.node_count()];
     stack_at_bb[0] = Some(StackLayout::new());
@@ -273,23 +832,50 @@ fn transform(
     let mut dfs = Dfs::new(&cfg.graph, NodeIndex::new(0));
     while let Some(v) = dfs.next(&cfg.graph) {
         let index = v.index();
-        let mut stack = stack_at_bb[index].unwrap();
+        let mut stack = stack_at_bb[index].clone().unwrap();
+        let is_handler = cfg
+            .graph
+            .edges_directed(v, Direction::Incoming)
+            .any(|edge| edge.weight().is_exception());
         new_bbs[index] = {
             let bb = &mut cfg.graph[v];
             let mut new_bb = BasicBlock::default();
-            for inst in &mut bb.stmts {
-                new_bb.stmts.append(&mut stack.execute(inst, metadata));
+            if is_handler {
+                // The caught exception is the only value on the stack:
+                let top = stack.push(false);
+                new_bb.stmts.push(stmt_expr(Expr::Assign {
+                    to: Box::new(Assignable::Variable(self::stack(top), 0)),
+                    op: None,
+                    from: Box::new(mk_variable(caught_exception(v))),
+                }));
+            }
+            for &(pc, ref inst) in &bb.stmts {
+                let mut stmts = stack.execute(inst, metadata)?;
+                names.rename(&mut stmts, inst, pc);
+                new_bb.stmts.append(&mut stmts);
+            }
+            new_bb.terminator = bb.terminator.map(|t| stack.terminator_to_expr(&t));
+            if let Some(&(_, Instruction::Arithm(Arithm::Compare { kind, nan_greater }))) =
+                bb.stmts.last()
+            {
+                merge_comparison(&mut new_bb, kind, nan_greater);
             }
-            new_bb.terminator = bb.terminator.map(|t| stack.cond_to_expr(&t));
             new_bb
         };
-        for w in cfg.graph.neighbors_directed(v, Direction::Outgoing) {
+        for edge in cfg.graph.edges_directed(v, Direction::Outgoing) {
+            let w = edge.target();
+            // The stack is cleared when an exception is thrown:
+            let stack = if edge.weight().is_exception() {
+                StackLayout::new()
+            } else {
+                stack.clone()
+            };
             let stack_at_w = &mut stack_at_bb[w.index()];
-            if let Some(stack_at_w) = *stack_at_w {
-                // Assert that all paths to w result in the same stack size:
+            if let Some(ref stack_at_w) = *stack_at_w {
+                // Assert that all paths to w result in the same stack layout:
                 assert_eq!(
                     stack,
-                    stack_at_w,
+                    *stack_at_w,
                     "expected stack {:?} at beginning of node #{} but found {:?}",
                     stack,
                     w.index(),
@@ -300,36 +886,321 @@ fn transform(
             }
         }
     }
-    use std::mem;
-    Cfg {
+    Ok(Cfg {
         graph: cfg.graph.map(
             |nx, _| mem::replace(&mut new_bbs[nx.index()], BasicBlock::default()),
             |_, e| *e,
         ),
         entry_point: cfg.entry_point,
         exit_point: cfg.exit_point,
+    })
+}
+
+/// Throws an exception saying that the code can't be decompiled for the reason.
+pub fn throw_unsupported(reason: &str) -> Statement {
+    Statement::Throw(Expr::New {
+        class: Type::Reference("java.lang.UnsupportedOperationException".to_owned()),
+        args: vec![Expr::Literal(Literal::String(format!(
+            "can't be decompiled: {}",
+            reason
+        )))],
+    })
+}
+
+/// The code of a method that can't be decompiled: it throws an exception with the reason.
+pub fn stub(reason: &str) -> Cfg<Statement, Expr> {
+    let mut graph = CfgGraph::default();
+    let body = graph.add_node(BasicBlock {
+        stmts: vec![throw_unsupported(reason)],
+        terminator: None,
+    });
+    let entry_point = graph.add_node(BasicBlock::default());
+    let exit_point = graph.add_node(BasicBlock::default());
+    graph.add_edge(entry_point, body, Edge::Branch(false));
+    graph.add_edge(body, exit_point, Edge::Branch(false));
+    Cfg {
+        graph,
+        entry_point,
+        exit_point,
     }
 }
 
 fn handle_parameters(method: &mut Method<Cfg<Statement, Expr>>) {
-    let mut local_index = 0;
-    let mut assignments = vec![];
-    if !method.modifiers.contains(&Modifier::Static) {
-        let to = Box::new(Assignable::Variable(local(local_index), 0));
-        let from = Box::new(Expr::This);
-        assignments.push(Statement::Expr(Expr::Assign {
-            from: from,
+    let is_static = method.modifiers.contains(&Modifier::Static);
+    let slots = method.signature.parameter_slots(is_static);
+    for (parameter, slot) in method.signature.parameters.iter_mut().zip(slots) {
+        if parameter.0.is_empty() {
+            parameter.0 = local(slot as usize);
+        }
+    }
+    let cfg = match method.code {
+        Some(ref mut cfg) if !is_static => cfg,
+        _ => return,
+    };
+    let mut visitor = ThisVisitor {
+        assigned: false,
+        replace: false,
+    };
+    for bb in cfg.graph.node_weights_mut() {
+        visitor.visit_block_stmts(bb);
+    }
+    if visitor.assigned {
+        // Slot 0 is reused for another variable, so it only holds `this` at the start:
+        let assignment = stmt_expr(Expr::Assign {
+            to: Box::new(Assignable::Variable(local(0), 0)),
             op: None,
-            to: to,
-        }));
-        local_index += 1;
+            from: Box::new(Expr::This),
+        });
+        cfg.graph[cfg.entry_point].stmts.push(assignment);
+    } else {
+        visitor.replace = true;
+        for bb in cfg.graph.node_weights_mut() {
+            visitor.visit_block_stmts(bb);
+        }
+    }
+}
+
+/// Finds out whether the variable in slot 0 is assigned, otherwise replaces it by `this`.
+struct ThisVisitor {
+    assigned: bool,
+    replace: bool,
+}
+
+impl ThisVisitor {
+    fn visit_block_stmts(&mut self, bb: &mut BasicBlock<Statement, Expr>) {
+        for stmt in &mut bb.stmts {
+            self.visit_statement(stmt);
+        }
+        if let Some(ref mut cond) = bb.terminator {
+            self.visit_expr(cond);
+        }
+    }
+}
+
+impl Visitor for ThisVisitor {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if self.replace && as_variable(expr).is_some_and(|var| *var == local(0)) {
+            *expr = Expr::This;
+            return;
+        }
+        if let Expr::Assign { ref to, .. } = *expr {
+            if let Assignable::Variable(ref var, _) = **to {
+                self.assigned |= *var == local(0);
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompiler::passes::conditions::negate;
+    use std::collections::HashMap;
+
+    const ORDERINGS: [Ordering; 6] = [
+        Ordering::EQ,
+        Ordering::NE,
+        Ordering::LT,
+        Ordering::GE,
+        Ordering::GT,
+        Ordering::LE,
+    ];
+
+    fn holds(ordering: Ordering, a: f64, b: f64) -> bool {
+        match ordering {
+            Ordering::EQ => a == b,
+            Ordering::NE => a != b,
+            Ordering::LT => a < b,
+            Ordering::GE => a >= b,
+            Ordering::GT => a > b,
+            Ordering::LE => a <= b,
+        }
+    }
+
+    /// Evaluates a condition on the variables `a` and `b` like Java does.
+    fn evaluate(cond: &Expr, a: f64, b: f64) -> bool {
+        let value = |expr: &Expr| match as_variable(expr).map(String::as_str) {
+            Some("a") => a,
+            Some("b") => b,
+            _ => panic!("unexpected operand {:?}", expr),
+        };
+        match *cond {
+            Expr::BinaryOp(BinOp::FloatCmp(ordering), ref left, ref right) => {
+                holds(ordering, value(left), value(right))
+            }
+            Expr::UnaryOp(UnOp::LogNot, ref cond) => !evaluate(cond, a, b),
+            _ => panic!("unexpected condition {:?}", cond),
+        }
     }
-    for parameter in method.signature.parameters.iter_mut() {
-        parameter.0 = local(local_index);
-        local_index += 1;
+
+    /// Merges the comparison of `a` and `b` into the condition `result <ordering> 0`.
+    fn merged(kind: Kind, nan_greater: bool, ordering: Ordering) -> Expr {
+        let result = mk_variable("stack_0".to_owned());
+        let compare = compare(
+            kind,
+            mk_variable("a".to_owned()),
+            mk_variable("b".to_owned()),
+        );
+        let mut bb = BasicBlock {
+            stmts: vec![stmt_expr(Expr::Assign {
+                to: Box::new(Assignable::Variable("stack_0".to_owned(), 0)),
+                op: None,
+                from: Box::new(compare),
+            })],
+            terminator: Some(Expr::BinaryOp(
+                BinOp::Cmp(ordering),
+                Box::new(result),
+                Box::new(Expr::Literal(Literal::Integer(0))),
+            )),
+        };
+        merge_comparison(&mut bb, kind, nan_greater);
+        assert_eq!(bb.stmts, vec![]);
+        bb.terminator.unwrap()
     }
-    if let Some(ref mut cfg) = method.code {
-        let entry_block = &mut cfg.graph.node_weight_mut(cfg.entry_point).unwrap();
-        entry_block.stmts.append(&mut assignments);
+
+    #[test]
+    fn merged_floating_point_comparisons() {
+        for &nan_greater in &[false, true] {
+            for &ordering in &ORDERINGS {
+                let cond = merged(Kind::D, nan_greater, ordering);
+                for &(a, b) in &[(1.0, 2.0), (2.0, 2.0), (3.0, 2.0), (f64::NAN, 2.0)] {
+                    // The result of `dcmpg` or `dcmpl`, which the jump compares with 0:
+                    let result = match a.partial_cmp(&b) {
+                        Some(order) => order as i32,
+                        None if nan_greater => 1,
+                        None => -1,
+                    };
+                    let expected = holds(ordering, result.into(), 0.0);
+                    let message = format!(
+                        "{:?} with nan_greater = {} for {} and {}",
+                        ordering, nan_greater, a, b
+                    );
+                    assert_eq!(evaluate(&cond, a, b), expected, "{}", message);
+                    assert_eq!(
+                        evaluate(&negate(cond.clone()), a, b),
+                        !expected,
+                        "{}",
+                        message
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merged_long_comparisons() {
+        for &ordering in &ORDERINGS {
+            let cond = merged(Kind::L, false, ordering);
+            let expected = Expr::BinaryOp(
+                BinOp::Cmp(ordering),
+                Box::new(mk_variable("a".to_owned())),
+                Box::new(mk_variable("b".to_owned())),
+            );
+            assert_eq!(cond, expected);
+        }
+    }
+
+    /// Executes `op` on a stack of values, of which those marked in `wide` are longs or doubles,
+    /// returning the values afterwards (numbered from the bottom of the initial stack).
+    fn manipulate(op: StackManage, wide: &[bool]) -> Vec<usize> {
+        let mut layout = StackLayout::new();
+        for &wide in wide {
+            layout.push(wide);
+        }
+        let stmts = layout
+            .execute(&Instruction::StackManage(op), &Metadata::new())
+            .unwrap();
+        let mut values: HashMap<String, usize> =
+            (0..wide.len()).map(|i| (stack(i as isize), i)).collect();
+        for stmt in stmts {
+            match stmt {
+                Statement::Expr(Expr::Assign { to, op: None, from }) => {
+                    let from = as_variable(&from).unwrap();
+                    let value = values[from];
+                    match *to {
+                        Assignable::Variable(to, 0) => values.insert(to, value),
+                        _ => panic!("unexpected assignment to {:?}", to),
+                    };
+                }
+                Statement::Expr(Expr::Assignable(_)) => {}
+                _ => panic!("unexpected statement {:?}", stmt),
+            }
+        }
+        (0..layout.depth()).map(|i| values[&stack(i)]).collect()
+    }
+
+    #[test]
+    fn stack_manipulation() {
+        use disassembler::instructions::StackManage::*;
+        let cases: &[(StackManage, &[bool], &[usize])] = &[
+            (Pop, &[false, false], &[0]),
+            (Pop2, &[false, false, false], &[0]),
+            (Pop2, &[false, true], &[0]),
+            (Dup, &[false], &[0, 0]),
+            (DupX1, &[false, false, false], &[0, 2, 1, 2]),
+            (DupX2, &[false, false, false], &[2, 0, 1, 2]),
+            (DupX2, &[true, false], &[1, 0, 1]),
+            (Dup2, &[false, false], &[0, 1, 0, 1]),
+            (Dup2, &[false, true], &[0, 1, 1]),
+            (Dup2X1, &[false, false, false], &[1, 2, 0, 1, 2]),
+            (Dup2X1, &[false, true], &[1, 0, 1]),
+            (Dup2X2, &[false, false, false, false], &[2, 3, 0, 1, 2, 3]),
+            (Dup2X2, &[false, false, true], &[2, 0, 1, 2]),
+            (Dup2X2, &[true, false, false], &[1, 2, 0, 1, 2]),
+            (Dup2X2, &[true, true], &[1, 0, 1]),
+            (Swap, &[false, false, false], &[0, 2, 1]),
+        ];
+        for &(op, wide, expected) in cases {
+            assert_eq!(manipulate(op, wide), expected, "{:?} on {:?}", op, wide);
+        }
+    }
+
+    fn variable(index: u16, start: u16, end: u16, name: &str) -> LocalVariableScope {
+        LocalVariableScope {
+            index,
+            start,
+            end,
+            name: name.to_owned(),
+            typ: Type::Int,
+        }
+    }
+
+    #[test]
+    fn local_variable_names() {
+        // `static int sum(int[] a) { int s = 0; for (int x : a) s += x; int y = s; return y; }`
+        // compiled with `-g`, which doesn't list the variables of the enhanced `for` loop:
+        let method = Method::<()> {
+            modifiers: vec![Modifier::Static],
+            name: "sum".to_owned(),
+            signature: Signature {
+                parameters: vec![("".to_owned(), Type::Array(Box::new(Type::Int)))],
+                return_type: Type::Int,
+            },
+            throws: vec![],
+            variables: vec![
+                variable(0, 0, 42, "a"),
+                variable(1, 2, 42, "s"),
+                variable(5, 24, 31, "x"),
+                variable(5, 38, 42, "y"),
+            ],
+            locals: vec![],
+            code: None,
+            synthetic: false,
+            bridge: false,
+        };
+        let names = LocalNames::new(&method);
+        let name = |index, pc, is_store| names.get(index, pc, is_store).map(String::as_str);
+        assert_eq!(name(0, 0, false), Some("a"));
+        assert_eq!(name(1, 1, true), Some("s"));
+        // The element of the loop is stored right before its scope starts:
+        assert_eq!(name(5, 22, true), Some("x"));
+        assert_eq!(name(5, 26, false), Some("x"));
+        assert_eq!(name(5, 37, true), Some("y"));
+        // The copy of the array, its length and the index:
+        assert_eq!(name(2, 5, true), None);
+        assert_eq!(name(4, 15, false), None);
+        // A load outside the scope of the variable doesn't read it:
+        assert_eq!(name(5, 33, false), None);
     }
 }
@@ -1,10 +1,11 @@
-//! Convert a control flow to structured control flow statements (if, while, break, continue).
+//! Convert a control flow to structured control flow statements (if, switch, while, break,
+//! continue, try).
 //!
 //! It uses the following strategy:
 //!
 //! 1. Find strongly connected components, i.e. loops
-//! 2. Check each loop has at most one entry point and
-//!    find the exit point (postdominator of the exit points)
+//! 2. Make each loop have a single entry point by duplicating code (node splitting) where it
+//!    has several and find the exit point (postdominator of the exit points)
 //! 3. For each loop: replace all edges to the entry point from within the loop
 //!    by `continue $current_loop_label;`
 //! 4. For each loop replace all edges to the exit point from within the loop
@@ -32,10 +33,33 @@
 //!
 //! The actual implementation is less recursive in order not to run the risk of blowing the stack.
 //! (If only Rust had guaranteed tail call optimization...)
+//!
+//! A switch is structured like an if with more branches: each case is structured up to the
+//! join point (the postdominator), a jump to the join point becomes `break` and reaching the
+//! next case falls through.
+//!
+//! Exception handlers are grouped into try statements by the blocks they protect.
+//! When the entry of such a group of blocks is reached, the try statement is output:
+//! its body is structured up to the point where the control flow of the body and the handlers
+//! joins again and each handler becomes a catch clause.
+//! javac compiles a finally clause to a handler catching everything, which executes the finally
+//! code and rethrows the exception, and copies the finally code to every other exit of the try
+//! statement. Such handlers are recognized and the copies are removed afterwards.
+//!
+//! Finally, a loop that starts with a conditional `break` out of it becomes a `while` loop with
+//! that condition, and one that ends with a conditional `continue` a `do`-`while` loop.
+//! Labels are only kept where a `break` or `continue` doesn't refer to the innermost loop.
+//!
+//! If the control flow of a method can't be structured like this, it throws an exception instead
+//! and its unstructured code is kept in a comment.
 
 use decompiler::cfg::*;
+use decompiler::passes::conditions::{invert_empty_then, negate};
+use decompiler::passes::stack_to_var::{caught_exception, throw_unsupported};
+use decompiler::passes::variables::is_copy;
 use decompiler::types::*;
 use disassembler::types::*;
+use pretty::*;
 use std::collections::{BTreeMap, BTreeSet};
 
 type Set<T> = BTreeSet<T>;
@@ -44,8 +68,56 @@ type Map<K, T> = BTreeMap<K, T>;
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 struct Jump(Label, Label);
 
-pub fn structure(unit: CompilationUnit<Cfg<Statement, Expr>>) -> CompilationUnit<Block> {
-    unit.map(structure_cfg)
+/// Structures the control flow of each method. `warn` is called with a message for each method
+/// whose control flow is irreducible, so that code had to be duplicated, and for each method whose
+/// control flow can't be structured, see `structure_cfg`.
+/// `graph` is called with the name and the control flow graph of each method right before it's
+/// structured, after the transforms that precede structuring, e.g. to export it for debugging.
+pub fn structure<W, G>(
+    mut unit: CompilationUnit<Cfg<Statement, Expr>>,
+    mut warn: W,
+    mut graph: G,
+) -> CompilationUnit<Block>
+where
+    W: FnMut(String),
+    G: FnMut(&str, &Cfg<Statement, Expr>),
+{
+    let class_name = unit.simple_name().to_owned();
+    let mut names = vec![];
+    for declaration in &mut unit.declarations {
+        let (name, cfg) = match *declaration {
+            Declaration::Method(Method {
+                ref name,
+                code: Some(ref mut cfg),
+                ..
+            }) => (name, cfg),
+            Declaration::Constructor(ref mut constructor) => (&class_name, &mut constructor.code),
+            _ => continue,
+        };
+        if split_irreducible_loops(cfg) {
+            warn(format!(
+                "the control flow of `{}` is irreducible, some of its code was duplicated",
+                name
+            ));
+        }
+        names.push(name.clone());
+    }
+    // `map` visits the code of the methods in the same order as the loop above.
+    let mut names = names.into_iter();
+    unit.map(|cfg, metadata| {
+        let name = names.next().expect("a method without name");
+        structure_cfg(
+            cfg,
+            metadata,
+            |cfg| graph(&name, cfg),
+            |reason| {
+                warn(format!(
+                    "the control flow of `{}` can't be structured: {}",
+                    name, reason
+                ))
+            },
+        )
+    })
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -62,12 +134,38 @@ fn loop_label(index: usize) -> String {
     format!("loop_{}", index)
 }
 
+fn switch_label(index: usize) -> String {
+    format!("switch_{}", index)
+}
+
+/// A switch whose cases are being structured.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct ActiveSwitch {
+    id: usize,
+    join: Label,
+}
+
+/// The blocks protected by the same exception handlers, which become a try statement.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct TryRegion {
+    nodes: Set<Label>,
+    entry: Label,
+    /// The handlers in the order of the catch clauses and the exception classes they catch
+    /// (`None` means all exceptions).
+    handlers: Vec<(Label, Vec<Option<u16>>)>,
+}
+
 #[derive(Debug)]
 struct Context<'a, S: 'a, C: 'a> {
     cfg: &'a Cfg<S, C>,
     loops: Vec<Loop>,
     entry_to_loop_index: Map<Label, usize>,
     loop_breaks: Map<Jump, usize>, // jump -> loop index
+    tries: Vec<TryRegion>,
+    opened_tries: Set<usize>,
+    try_depth: usize,
+    switch_count: usize,
+    active_switches: Vec<ActiveSwitch>,
     dominators: Dominators,
     postdominators: Dominators,
 }
@@ -80,6 +178,11 @@ fn create_context<S, C>(cfg: &Cfg<S, C>) -> Context<S, C> {
         loops: vec![],
         entry_to_loop_index: Map::new(),
         loop_breaks: Map::new(),
+        tries: vec![],
+        opened_tries: Set::new(),
+        try_depth: 0,
+        switch_count: 0,
+        active_switches: vec![],
         dominators: dominators,
         postdominators: postdominators,
     }
@@ -92,13 +195,27 @@ enum Structured {
     Loop { id: usize, body: Vec<Structured> },
     Break(usize),
     Continue(usize),
+    Switch {
+        id: usize,
+        block: Label,
+        cases: Vec<(Vec<Option<i32>>, Vec<Structured>)>,
+    },
+    BreakSwitch(usize),
+    Try {
+        body: Vec<Structured>,
+        handlers: Vec<(Label, Vec<Option<u16>>, Vec<Structured>)>,
+    },
 }
 
 fn structured_to_statement(
     cfg: &Cfg<Statement, Expr>,
+    metadata: &Metadata,
+    stripped: &Set<Label>,
     structured: Vec<Structured>,
 ) -> Vec<Statement> {
     let mut result = vec![];
+    // The finally code copied to the statements following a try statement:
+    let mut copied_finally = vec![];
     for stmt in structured {
         match stmt {
             Structured::BasicBlock(label) => {
@@ -106,50 +223,862 @@ fn structured_to_statement(
             }
             Structured::If(cond, then, els) => result.push(Statement::If {
                 cond: cfg.graph[cond].terminator.clone().unwrap(),
-                then: Block(vec![], structured_to_statement(cfg, then)),
-                els: Some(Block(vec![], structured_to_statement(cfg, els))),
-            }),
-            Structured::Loop { id, body } => result.push(Statement::While {
-                label: Some(loop_label(id)),
-                cond: Expr::Literal(Literal::Boolean(true)),
-                body: Block(vec![], structured_to_statement(cfg, body)),
-                do_while: false,
+                then: Block(
+                    vec![],
+                    structured_to_statement(cfg, metadata, stripped, then),
+                ),
+                els: Some(Block(
+                    vec![],
+                    structured_to_statement(cfg, metadata, stripped, els),
+                )),
             }),
+            Structured::Loop { id, body } => {
+                let body = structured_to_statement(cfg, metadata, stripped, body);
+                make_loop(loop_label(id), body, &mut result);
+            }
             Structured::Break(id) => {
                 result.push(Statement::Break(Some(loop_label(id))));
             }
             Structured::Continue(id) => {
                 result.push(Statement::Continue(Some(loop_label(id))));
             }
+            Structured::Switch { id, block, cases } => result.push(Statement::Switch {
+                label: Some(switch_label(id)),
+                expr: cfg.graph[block].terminator.clone().unwrap(),
+                cases: cases
+                    .into_iter()
+                    .map(|(values, body)| SwitchCase {
+                        values: values
+                            .into_iter()
+                            .map(|value| value.map(|v| Expr::Literal(Literal::Integer(v))))
+                            .collect(),
+                        stmts: structured_to_statement(cfg, metadata, stripped, body),
+                    })
+                    .collect(),
+            }),
+            Structured::BreakSwitch(id) => {
+                result.push(Statement::Break(Some(switch_label(id))));
+            }
+            Structured::Try { body, handlers } => {
+                let copies_removed = handlers
+                    .last()
+                    .is_some_and(|&(handler, _, _)| stripped.contains(&handler));
+                let block = Block(
+                    vec![],
+                    structured_to_statement(cfg, metadata, stripped, body),
+                );
+                let catches = handlers
+                    .into_iter()
+                    .map(|(handler, types, handler_body)| {
+                        let stmts = structured_to_statement(cfg, metadata, stripped, handler_body);
+                        make_catch(handler, &types, stmts, metadata)
+                    })
+                    .collect();
+                let (stmt, finally_copied) = make_try(block, catches, copies_removed);
+                if let Some(finally) = finally_copied {
+                    copied_finally.push((result.len() + 1, finally));
+                }
+                result.push(stmt);
+            }
+        }
+    }
+    for (start, finally) in copied_finally.into_iter().rev() {
+        if let Some(end) = match_start(&result[start..], &finally) {
+            result.drain(start..start + end);
         }
     }
     result
 }
 
-fn structure_cfg(cfg: Cfg<Statement, Expr>, _: &Metadata) -> Block {
-    let structured = cfg_to_structured(&cfg);
-    Block(vec![], structured_to_statement(&cfg, structured))
+/// Creates the loop statement and adds it to `result`. If the body starts with breaking out of
+/// the loop under some condition, it becomes a `while` loop with that condition. This also works
+/// if the loop is left some other way under that condition, when there's no `break` out of it
+/// otherwise: those statements then follow the loop. If the body ends with continuing the loop
+/// under some condition, it becomes a `do`-`while` loop. Otherwise it loops `while (true)`.
+fn make_loop(label: Ident, mut body: Vec<Statement>, result: &mut Vec<Statement>) {
+    let label = Some(label);
+    let break_stmt = Statement::Break(label.clone());
+    if let Some(first) = body.iter().position(|stmt| *stmt != Statement::Nop) {
+        let head = match body[first] {
+            Statement::If {
+                ref cond,
+                ref then,
+                ref els,
+            } => {
+                let els = els.as_ref().map_or(&[][..], |els| &els.1);
+                let is_left = |stmts: &[Statement], other: &[Statement]| {
+                    leaves_loop(stmts, &label)
+                        && !any_stmt(other, |stmt| *stmt == break_stmt)
+                        && !any_stmt(&body[first + 1..], |stmt| *stmt == break_stmt)
+                };
+                if is_jump(&then.1, &break_stmt) {
+                    Some((negate(cond.clone()), els.to_vec(), vec![]))
+                } else if is_jump(els, &break_stmt) {
+                    Some((cond.clone(), then.1.clone(), vec![]))
+                } else if is_left(&then.1, els) {
+                    Some((negate(cond.clone()), els.to_vec(), then.1.clone()))
+                } else if is_left(els, &then.1) {
+                    Some((cond.clone(), then.1.clone(), els.to_vec()))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some((cond, mut stmts, exit)) = head {
+            stmts.extend(body.drain(first + 1..));
+            if !exit.is_empty() {
+                break_before_exit(&mut stmts, &exit, &break_stmt);
+            }
+            remove_trailing_continue(&mut stmts, &label);
+            result.push(Statement::While {
+                label,
+                cond,
+                body: Block(vec![], stmts),
+                do_while: false,
+            });
+            result.extend(exit);
+            return;
+        }
+    }
+    if let Some(last) = previous_stmt(&body, body.len()) {
+        let continue_stmt = Statement::Continue(label.clone());
+        let tail = match body[last] {
+            Statement::If {
+                ref cond,
+                ref then,
+                els: Some(ref els),
+            } => {
+                if is_jump(&then.1, &continue_stmt) && is_jump(&els.1, &break_stmt) {
+                    Some(cond.clone())
+                } else if is_jump(&then.1, &break_stmt) && is_jump(&els.1, &continue_stmt) {
+                    Some(negate(cond.clone()))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        // `continue` in a `do`-`while` loop jumps to the condition, not to the start of the body:
+        let is_continued = any_stmt(&body[..last], |stmt| *stmt == continue_stmt);
+        if let (Some(cond), false) = (tail, is_continued) {
+            body.truncate(last);
+            result.push(Statement::While {
+                label,
+                cond,
+                body: Block(vec![], body),
+                do_while: true,
+            });
+            return;
+        }
+    }
+    remove_trailing_continue(&mut body, &label);
+    result.push(Statement::While {
+        label,
+        cond: Expr::Literal(Literal::Boolean(true)),
+        body: Block(vec![], body),
+        do_while: false,
+    });
+}
+
+/// Replaces the copies of `exit`, the statements following the loop, at the end of branches in
+/// its body with `break`ing out of it, so that they're only written once. `exit` ends with leaving
+/// the loop some other way, so these copies don't fall through to anything else.
+fn break_before_exit(stmts: &mut Vec<Statement>, exit: &[Statement], break_stmt: &Statement) {
+    if let Some(start) = match_end_exactly(stmts, exit) {
+        stmts.truncate(start);
+        stmts.push(break_stmt.clone());
+        return;
+    }
+    for stmt in stmts {
+        match *stmt {
+            Statement::Block(ref mut block) | Statement::Synchronized(_, ref mut block) => {
+                break_before_exit(&mut block.1, exit, break_stmt)
+            }
+            Statement::If {
+                ref mut then,
+                ref mut els,
+                ..
+            } => {
+                break_before_exit(&mut then.1, exit, break_stmt);
+                if let Some(ref mut els) = *els {
+                    break_before_exit(&mut els.1, exit, break_stmt);
+                }
+            }
+            Statement::While { ref mut body, .. } | Statement::For(_, _, ref mut body) => {
+                break_before_exit(&mut body.1, exit, break_stmt);
+            }
+            Statement::Try {
+                ref mut block,
+                ref mut catches,
+                ..
+            } => {
+                break_before_exit(&mut block.1, exit, break_stmt);
+                for catch in catches {
+                    break_before_exit(&mut catch.block.1, exit, break_stmt);
+                }
+            }
+            Statement::Switch { ref mut cases, .. } => {
+                for case in cases {
+                    break_before_exit(&mut case.stmts, exit, break_stmt);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks whether the statements end with `expected`, ignoring `nop`s, and returns the index where
+/// the match starts.
+fn match_end_exactly(stmts: &[Statement], expected: &[Statement]) -> Option<usize> {
+    let mut start = stmts.len();
+    let expected = expected.iter().filter(|stmt| **stmt != Statement::Nop);
+    for stmt in expected.rev() {
+        while start > 0 && stmts[start - 1] == Statement::Nop {
+            start -= 1;
+        }
+        if start == 0 || stmts[start - 1] != *stmt {
+            return None;
+        }
+        start -= 1;
+    }
+    Some(start)
+}
+
+/// Checks whether the statements are just the jump, ignoring `nop`s.
+fn is_jump(stmts: &[Statement], jump: &Statement) -> bool {
+    let mut stmts = stmts.iter().filter(|stmt| **stmt != Statement::Nop);
+    stmts.next() == Some(jump) && stmts.next().is_none()
+}
+
+/// Checks whether the statements end with leaving the loop other than by `break`ing out of it,
+/// and don't refer to it otherwise.
+fn leaves_loop(stmts: &[Statement], label: &Option<Ident>) -> bool {
+    let is_left = match previous_stmt(stmts, stmts.len()).map(|last| &stmts[last]) {
+        Some(&Statement::Return(_)) | Some(&Statement::Throw(_)) => true,
+        Some(&Statement::Break(ref target)) | Some(&Statement::Continue(ref target)) => {
+            target.is_some() && target != label
+        }
+        _ => false,
+    };
+    is_left
+        && label
+            .as_ref()
+            .is_some_and(|label| !is_label_used(stmts, label))
+}
+
+/// Removes the `continue` statements of the loop that are the last statement of the body anyway.
+fn remove_trailing_continue(stmts: &mut Vec<Statement>, label: &Option<Ident>) {
+    let last = match previous_stmt(stmts, stmts.len()) {
+        Some(last) => last,
+        None => return,
+    };
+    match stmts[last] {
+        Statement::Continue(ref target) if target == label => {}
+        Statement::If {
+            ref mut then,
+            ref mut els,
+            ..
+        } => {
+            remove_trailing_continue(&mut then.1, label);
+            if let Some(ref mut els) = *els {
+                remove_trailing_continue(&mut els.1, label);
+            }
+            return;
+        }
+        Statement::Try {
+            ref mut block,
+            ref mut catches,
+            ..
+        } => {
+            remove_trailing_continue(&mut block.1, label);
+            for catch in catches {
+                remove_trailing_continue(&mut catch.block.1, label);
+            }
+            return;
+        }
+        _ => return,
+    }
+    stmts.truncate(last);
+}
+
+/// Removes the labels from `break` and `continue` statements where they refer to the innermost
+/// loop (or switch, for `break`), then the labels of loops and switches that aren't used.
+/// `breakable` is the label of the innermost loop or switch, `lupe` the one of the innermost loop.
+fn remove_labels(stmts: &mut [Statement], breakable: &Option<Ident>, lupe: &Option<Ident>) {
+    for stmt in stmts {
+        match *stmt {
+            Statement::Break(ref mut target) if target == breakable => *target = None,
+            Statement::Continue(ref mut target) if target == lupe => *target = None,
+            Statement::Block(ref mut block) | Statement::Synchronized(_, ref mut block) => {
+                remove_labels(&mut block.1, breakable, lupe)
+            }
+            Statement::If {
+                ref mut then,
+                ref mut els,
+                ..
+            } => {
+                remove_labels(&mut then.1, breakable, lupe);
+                if let Some(ref mut els) = *els {
+                    remove_labels(&mut els.1, breakable, lupe);
+                }
+            }
+            Statement::While {
+                ref mut label,
+                ref mut body,
+                ..
+            }
+            | Statement::For(ref mut label, _, ref mut body) => {
+                remove_labels(&mut body.1, label, label);
+                if label.as_ref().is_some_and(|l| !is_label_used(&body.1, l)) {
+                    *label = None;
+                }
+            }
+            Statement::Try {
+                ref mut block,
+                ref mut catches,
+                ref mut finally,
+                ..
+            } => {
+                remove_labels(&mut block.1, breakable, lupe);
+                for catch in catches {
+                    remove_labels(&mut catch.block.1, breakable, lupe);
+                }
+                remove_labels(&mut finally.1, breakable, lupe);
+            }
+            Statement::Switch {
+                ref mut label,
+                ref mut cases,
+                ..
+            } => {
+                for case in cases.iter_mut() {
+                    remove_labels(&mut case.stmts, label, lupe);
+                }
+                let is_used = label
+                    .as_ref()
+                    .is_some_and(|l| cases.iter().any(|case| is_label_used(&case.stmts, l)));
+                if !is_used {
+                    *label = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Creates the catch clause for the handler.
+/// The caught exception is named like the variable it's stored in at the beginning.
+fn make_catch(
+    handler: Label,
+    types: &[Option<u16>],
+    mut stmts: Vec<Statement>,
+    metadata: &Metadata,
+) -> Catch {
+    let types = types
+        .iter()
+        .map(|typ| match *typ {
+            Some(class_ref) => Type::Reference(metadata.class_refs[&class_ref].0.clone()),
+            None => Type::Reference("java.lang.Throwable".to_owned()),
+        })
+        .collect();
+    let exception = mk_variable(caught_exception(handler));
+    let mut ident = caught_exception(handler);
+    if let Some(i) = stmts.iter().position(|stmt| *stmt != Statement::Nop) {
+        let var = match stmts[i] {
+            Statement::Expr(Expr::Assign {
+                ref to, ref from, ..
+            }) if **from == exception => match **to {
+                Assignable::Variable(ref var, _) => Some(var.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(var) = var {
+            ident = var;
+            stmts.remove(i);
+        }
+    }
+    Catch {
+        types,
+        ident,
+        block: Block(vec![], stmts),
+    }
+}
+
+/// Creates the try statement, recognizing a finally clause (see the module documentation).
+/// If the copy of the finally code on the normal path through the try statement wasn't found
+/// inside it, it's expected after it and returned. If `copies_removed`, the copies were already
+/// removed from the control flow graph, see `strip_finally_copies`.
+fn make_try(
+    block: Block,
+    mut catches: Vec<Catch>,
+    copies_removed: bool,
+) -> (Statement, Option<Vec<Statement>>) {
+    let finally = catches.last().and_then(|catch| {
+        if catch.types != [Type::Reference("java.lang.Throwable".to_owned())] {
+            return None;
+        }
+        let stmts = catch
+            .block
+            .1
+            .iter()
+            .filter(|stmt| **stmt != Statement::Nop)
+            .cloned()
+            .collect::<Vec<_>>();
+        let exception = mk_variable(catch.ident.clone());
+        match stmts.split_last() {
+            Some((Statement::Throw(e), finally))
+                if *e == exception && !uses_variable(finally, &catch.ident) =>
+            {
+                Some(finally.to_vec())
+            }
+            _ => None,
+        }
+    });
+    let finally = match finally {
+        Some(finally) => finally,
+        None => {
+            let stmt = Statement::Try {
+                resources: vec![],
+                block,
+                catches,
+                finally: Block::default(),
+            };
+            return (stmt, None);
+        }
+    };
+    catches.pop();
+    let mut block = block;
+    let mut found = copies_removed;
+    if !copies_removed {
+        found |= strip_finally_at_end(&mut block.1, &finally);
+        strip_finally_before_jumps(&mut block.1, &finally);
+        for catch in &mut catches {
+            found |= strip_finally_at_end(&mut catch.block.1, &finally);
+            strip_finally_before_jumps(&mut catch.block.1, &finally);
+        }
+    }
+    let copied = if found || finally.is_empty() {
+        None
+    } else {
+        Some(finally.clone())
+    };
+    // javac compiles `try {} catch {} finally {}` like `try { try {} catch {} } finally {}`:
+    if catches.is_empty() {
+        let mut stmts = block.1.iter().filter(|stmt| **stmt != Statement::Nop);
+        if let (
+            Some(Statement::Try {
+                resources,
+                block,
+                catches,
+                finally: inner_finally,
+            }),
+            None,
+        ) = (stmts.next(), stmts.next())
+        {
+            if inner_finally.1.is_empty() && !catches.is_empty() {
+                let stmt = Statement::Try {
+                    resources: resources.clone(),
+                    block: block.clone(),
+                    catches: catches.clone(),
+                    finally: Block(vec![], finally),
+                };
+                return (stmt, copied);
+            }
+        }
+    }
+    let stmt = Statement::Try {
+        resources: vec![],
+        block,
+        catches,
+        finally: Block(vec![], finally),
+    };
+    (stmt, copied)
+}
+
+/// Removes the finally code at the end of the statements, i.e. the copy for the normal exit.
+/// Returns whether it was found.
+fn strip_finally_at_end(stmts: &mut Vec<Statement>, finally: &[Statement]) -> bool {
+    if let Some(start) = match_end(stmts, stmts.len(), finally) {
+        stmts.truncate(start);
+        return true;
+    }
+    match stmts.iter_mut().rev().find(|stmt| **stmt != Statement::Nop) {
+        Some(&mut Statement::If {
+            ref mut then,
+            ref mut els,
+            ..
+        }) => {
+            let found_then = strip_finally_at_end(&mut then.1, finally);
+            let found_else = els
+                .as_mut()
+                .is_some_and(|els| strip_finally_at_end(&mut els.1, finally));
+            found_then || found_else
+        }
+        Some(&mut Statement::Try {
+            ref mut block,
+            ref mut catches,
+            ..
+        }) => {
+            let mut found = strip_finally_at_end(&mut block.1, finally);
+            for catch in catches {
+                found |= strip_finally_at_end(&mut catch.block.1, finally);
+            }
+            found
+        }
+        _ => false,
+    }
+}
+
+/// Removes the finally code before statements leaving the try statement.
+fn strip_finally_before_jumps(stmts: &mut Vec<Statement>, finally: &[Statement]) {
+    let mut i = 0;
+    while i < stmts.len() {
+        match stmts[i] {
+            Statement::Return(_) | Statement::Break(_) | Statement::Continue(_) => {
+                if let Some(start) = match_end(stmts, i, finally) {
+                    stmts.drain(start..i);
+                    i = start;
+                }
+            }
+            Statement::Block(ref mut block) => strip_finally_before_jumps(&mut block.1, finally),
+            Statement::If {
+                ref mut then,
+                ref mut els,
+                ..
+            } => {
+                strip_finally_before_jumps(&mut then.1, finally);
+                if let Some(ref mut els) = *els {
+                    strip_finally_before_jumps(&mut els.1, finally);
+                }
+            }
+            Statement::While { ref mut body, .. } | Statement::For(_, _, ref mut body) => {
+                strip_finally_before_jumps(&mut body.1, finally);
+            }
+            Statement::Try {
+                ref mut block,
+                ref mut catches,
+                ..
+            } => {
+                strip_finally_before_jumps(&mut block.1, finally);
+                for catch in catches {
+                    strip_finally_before_jumps(&mut catch.block.1, finally);
+                }
+            }
+            Statement::Switch { ref mut cases, .. } => {
+                for case in cases {
+                    strip_finally_before_jumps(&mut case.stmts, finally);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Checks whether the statements before `end` end with a copy of `expected` (see `is_copy`),
+/// ignoring `nop`s, and returns the index where the match starts.
+fn match_end(stmts: &[Statement], end: usize, expected: &[Statement]) -> Option<usize> {
+    let mut start = end;
+    for stmt in expected.iter().rev() {
+        while start > 0 && stmts[start - 1] == Statement::Nop {
+            start -= 1;
+        }
+        if start == 0 || !is_copy(&stmts[start - 1], stmt) {
+            return None;
+        }
+        start -= 1;
+    }
+    Some(start)
+}
+
+/// Checks whether the statements start with a copy of `expected` (see `is_copy`), ignoring
+/// `nop`s, and returns the index where the match ends.
+fn match_start(stmts: &[Statement], expected: &[Statement]) -> Option<usize> {
+    let mut end = 0;
+    for stmt in expected {
+        while end < stmts.len() && stmts[end] == Statement::Nop {
+            end += 1;
+        }
+        if end == stmts.len() || !is_copy(&stmts[end], stmt) {
+            return None;
+        }
+        end += 1;
+    }
+    Some(end)
 }
 
-fn cfg_to_structured<S: Clone, C: Clone>(cfg: &Cfg<S, C>) -> Vec<Structured> {
-    let mut ctx = create_context(&cfg);
+/// Removes the copies of the code of finally clauses that javac places on the exits of the blocks
+/// they protect (see the module documentation) from the control flow graph, before it's
+/// structured. This covers every exit, also those that continue a loop or fall through to its
+/// update, and not just the ones that become `return`, `break` or `continue` statements.
+/// Only straight-line finally code is handled like this, along with the handlers whose copies are
+/// all found at the beginning of blocks that are only reached from the protected blocks.
+/// Returns those handlers, the copies of the others are removed by `make_try`.
+fn strip_finally_copies(cfg: &mut Cfg<Statement, Expr>) -> Set<Label> {
+    let mut protected: Map<Label, Set<Label>> = Map::new();
+    for edge in cfg.graph.edge_references() {
+        if let Edge::Exception(None) = *edge.weight() {
+            protected
+                .entry(edge.target())
+                .or_default()
+                .insert(edge.source());
+        }
+    }
+    let mut stripped = Set::new();
+    for (handler, nodes) in protected {
+        let finally = match finally_of_handler(&straight_line_code(cfg, handler), handler) {
+            Some(ref finally) if finally.is_empty() => continue,
+            Some(finally) => finally,
+            None => continue,
+        };
+        let mut exits = Set::new();
+        for &node in &nodes {
+            for edge in cfg.graph.edges_directed(node, Direction::Outgoing) {
+                let target = edge.target();
+                if !edge.weight().is_exception()
+                    && !nodes.contains(&target)
+                    && target != cfg.exit_point
+                {
+                    exits.insert(target);
+                }
+            }
+        }
+        let copies = exits
+            .into_iter()
+            .map(|exit| {
+                let is_own_copy = cfg
+                    .graph
+                    .edges_directed(exit, Direction::Incoming)
+                    .all(|edge| nodes.contains(&edge.source()));
+                match match_start(&cfg.graph[exit].stmts, &finally) {
+                    Some(end) if is_own_copy => Some((exit, end)),
+                    _ => None,
+                }
+            })
+            .collect::<Option<Vec<_>>>();
+        if let Some(copies) = copies {
+            for (exit, end) in copies {
+                cfg.graph[exit].stmts.drain(..end);
+            }
+            stripped.insert(handler);
+        }
+    }
+    stripped
+}
+
+/// The statements of the block and of the blocks following it, as long as the control flow just
+/// goes on to a block that isn't reached from anywhere else.
+fn straight_line_code(cfg: &Cfg<Statement, Expr>, start: Label) -> Vec<Statement> {
+    let normal_edges = |node, direction| {
+        cfg.graph
+            .edges_directed(node, direction)
+            .filter(|edge| !edge.weight().is_exception())
+    };
+    let mut stmts = vec![];
+    let mut node = start;
+    loop {
+        stmts.extend(cfg.graph[node].stmts.iter().cloned());
+        if cfg.graph[node].terminator.is_some() {
+            return stmts;
+        }
+        let mut successors = normal_edges(node, Direction::Outgoing).map(|edge| edge.target());
+        match (successors.next(), successors.next()) {
+            (Some(next), None)
+                if next != start && normal_edges(next, Direction::Incoming).count() == 1 =>
+            {
+                node = next
+            }
+            _ => return stmts,
+        }
+    }
+}
+
+/// The finally code of a handler catching all exceptions that executes the finally code
+/// (`stmts`, without control flow) and rethrows the exception.
+fn finally_of_handler(stmts: &[Statement], handler: Label) -> Option<Vec<Statement>> {
+    let mut stmts = stmts
+        .iter()
+        .filter(|stmt| **stmt != Statement::Nop)
+        .collect::<Vec<_>>();
+    let mut exception = caught_exception(handler);
+    // The exception may be stored in a variable first:
+    let stored = match stmts.first() {
+        Some(&&Statement::Expr(Expr::Assign {
+            ref to,
+            op: None,
+            ref from,
+        })) if **from == mk_variable(exception.clone()) => match **to {
+            Assignable::Variable(ref var, _) => Some(var.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(var) = stored {
+        exception = var;
+        stmts.remove(0);
+    }
+    let (last, finally) = stmts.split_last()?;
+    match **last {
+        Statement::Throw(ref e) if *e == mk_variable(exception.clone()) => {
+            let finally = finally.iter().map(|&stmt| stmt.clone()).collect::<Vec<_>>();
+            if uses_variable(&finally, &exception) {
+                None
+            } else {
+                Some(finally)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Inserts an empty block in front of each loop that is entered from several blocks, which all
+/// jump to it instead. Loops are only entered by jumps, so otherwise the branches of a statement
+/// before the loop would join at its entry, e.g. for a do-while loop after an if statement or a
+/// loop that was split by `split_irreducible_loops`.
+fn add_loop_preheaders<S, C>(cfg: &mut Cfg<S, C>) {
+    let dominators = cfg.compute_dominators(false);
+    let entries = cfg.graph.node_indices().collect::<Vec<_>>();
+    for entry in entries {
+        let (back, outside): (Vec<_>, Vec<_>) = cfg
+            .graph
+            .edges_directed(entry, Direction::Incoming)
+            .filter(|edge| !edge.weight().is_exception())
+            .map(|edge| (edge.id(), edge.source(), *edge.weight()))
+            .partition(|&(_, source, _)| dominators.is_for(entry, source));
+        if back.is_empty() || outside.len() < 2 {
+            continue;
+        }
+        let preheader = cfg.graph.add_node(BasicBlock::default());
+        cfg.graph.add_edge(preheader, entry, Edge::Branch(true));
+        // Removing an edge changes the id of the last one, so they are removed from the last:
+        let mut ids = outside.iter().map(|&(id, _, _)| id).collect::<Vec<_>>();
+        ids.sort_by(|a, b| b.cmp(a));
+        for id in ids {
+            cfg.graph.remove_edge(id);
+        }
+        for (_, source, weight) in outside {
+            cfg.graph.add_edge(source, preheader, weight);
+        }
+    }
+}
+
+/// Structures the control flow graph of a method. If that fails, `warn` is called with the reason
+/// and the method throws an exception instead, with its unstructured code in a comment.
+fn structure_cfg<G, W>(cfg: Cfg<Statement, Expr>, metadata: &Metadata, graph: G, warn: W) -> Block
+where
+    G: FnOnce(&Cfg<Statement, Expr>),
+    W: FnOnce(String),
+{
+    // The unstructured code is shown as it was before the copies of finally code were removed:
+    let mut transformed = cfg.clone();
+    let stripped = strip_finally_copies(&mut transformed);
+    add_loop_preheaders(&mut transformed);
+    graph(&transformed);
+    let structured = match cfg_to_structured(&transformed) {
+        Ok(structured) => structured,
+        Err(reason) => {
+            let code = cfg.pretty_with(&()).render_string(100);
+            let stmts = vec![Statement::Comment(code), throw_unsupported(&reason)];
+            warn(reason);
+            return Block(vec![], stmts);
+        }
+    };
+    let mut stmts = structured_to_statement(&transformed, metadata, &stripped, structured);
+    remove_labels(&mut stmts, &None, &None);
+    if let Some(last) = previous_stmt(&stmts, stmts.len()) {
+        if stmts[last] == Statement::Return(None) {
+            stmts.truncate(last);
+        }
+    }
+    let mut block = Block(vec![], stmts);
+    EmptyThenVisitor.visit_block(&mut block);
+    block
+}
+
+/// Turns the `if` statements with an empty `then` branch around, see `invert_empty_then`.
+struct EmptyThenVisitor;
+
+impl Visitor for EmptyThenVisitor {
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement(self, stmt);
+        invert_empty_then(stmt);
+    }
+}
+
+fn cfg_to_structured<S: Clone, C: Clone>(cfg: &Cfg<S, C>) -> Result<Vec<Structured>, String> {
+    let mut ctx = create_context(cfg);
     let all_nodes = ctx.cfg.graph.node_indices().collect::<Set<_>>();
-    collect_loops(&mut ctx, &all_nodes);
+    collect_tries(&mut ctx);
+    collect_loops(&mut ctx, &all_nodes)?;
     let entry = ctx.cfg.entry_point;
     let exit = ctx.cfg.exit_point;
     structure_from_to(&mut ctx, entry, exit)
 }
 
+/// Finds the loops like the structuring does. Each loop is given by its blocks, starting with
+/// its entry point, and the loops are in the order of their labels (`loop_0`, ...), so a loop
+/// comes before the loops nested in it. If the loops can't be structured, only those found up to
+/// that point are returned.
+pub fn find_loops<S, C>(cfg: &Cfg<S, C>) -> Vec<Vec<Label>> {
+    let mut ctx = create_context(cfg);
+    let all_nodes = ctx.cfg.graph.node_indices().collect::<Set<_>>();
+    collect_tries(&mut ctx);
+    let _ = collect_loops(&mut ctx, &all_nodes);
+    ctx.loops
+        .into_iter()
+        .map(|lupe| {
+            let entry = lupe.entry;
+            let mut nodes = vec![entry];
+            nodes.extend(lupe.nodes.into_iter().filter(|&node| node != entry));
+            nodes
+        })
+        .collect()
+}
+
 fn structure_from_to<S: Clone, C: Clone>(
     ctx: &mut Context<S, C>,
     mut cur: Label,
     stop: Label,
-) -> Vec<Structured> {
+) -> Result<Vec<Structured>, String> {
     let mut result = vec![];
     while cur != stop && cur != ctx.cfg.exit_point {
-        cur = translate_block(ctx, &mut result, cur, stop);
+        cur = translate_block(ctx, &mut result, cur, stop)?;
     }
-    result
+    Ok(result)
+}
+
+/// Checks whether the control flow from `node` always passes `stop`.
+/// Inside try statements, it may also leave the statement before, e.g. by returning.
+fn passes<S, C>(ctx: &Context<S, C>, node: Label, stop: Label) -> bool {
+    ctx.try_depth > 0 || is_postdominated(ctx, stop, node)
+}
+
+/// Fails unless the control flow from `node` always passes `stop`, see `passes`. Otherwise the
+/// code from `node` on can't be structured up to `stop`.
+fn check_passes<S, C>(
+    ctx: &Context<S, C>,
+    node: Label,
+    stop: Label,
+    what: &str,
+) -> Result<(), String> {
+    if passes(ctx, node, stop) {
+        Ok(())
+    } else {
+        Err(format!(
+            "stop point {} doesn't postdominate {} {}",
+            stop.index(),
+            what,
+            node.index()
+        ))
+    }
+}
+
+/// Like `Dominators::is_for` for postdominators, but also true for nodes that don't have any
+/// postdominators because they are in a loop that is only left by exceptions.
+fn is_postdominated<S, C>(ctx: &Context<S, C>, postdominator: Label, node: Label) -> bool {
+    ctx.postdominators.is_for(postdominator, node)
+        || (node != ctx.cfg.exit_point && ctx.postdominators.get_immediate(node).is_none())
 }
 
 fn handle_jump<S: Clone, C: Clone>(
@@ -157,42 +1086,71 @@ fn handle_jump<S: Clone, C: Clone>(
     result: &mut Vec<Structured>,
     jump: Jump,
     stop: Label,
-) -> Label {
+) -> Result<Label, String> {
     let Jump(_cur, next) = jump;
-    assert!(
-        ctx.postdominators.is_for(stop, next),
-        "stop point {} doesn't postdominate next {}",
-        stop.index(),
-        next.index()
-    );
-
-    if let Some(&loop_index) = ctx.entry_to_loop_index.get(&next) {
-        // It's a jump to a loop entry
+    check_passes(ctx, next, stop, "next")?;
+
+    let loop_entry = ctx.entry_to_loop_index.get(&next).cloned();
+    if let Some(loop_index) = loop_entry {
         if ctx.loops[loop_index].continue_edges.contains(&jump) {
             result.push(Structured::Continue(loop_index));
-            return stop;
-        } else {
-            let brk = ctx.loops[loop_index].break_point;
-            assert!(
-                ctx.postdominators.is_for(stop, brk),
-                "stop point {} doesn't postdominate brk {}",
-                stop.index(),
-                brk.index()
-            );
-            let body = structure_from_to(ctx, next, brk);
-            result.push(Structured::Loop {
-                id: loop_index,
-                body: body,
-            });
-            return brk;
+            return Ok(stop);
         }
     }
+    if next == ctx.cfg.exit_point {
+        // The block returns or throws, so it doesn't need to break out of a loop.
+        return Ok(next);
+    }
+    // Leaving a loop or switch is checked before entering a loop, since the code following it
+    // may be another loop:
+    let switch = ctx
+        .active_switches
+        .iter()
+        .rev()
+        .find(|switch| switch.join == next);
+    if let Some(switch) = switch {
+        // It's a jump out of a switch (break)
+        result.push(Structured::BreakSwitch(switch.id));
+        return Ok(next);
+    }
     if let Some(&loop_index) = ctx.loop_breaks.get(&jump) {
         // It's a jump out of the loop (break)
         result.push(Structured::Break(loop_index));
-        return ctx.loops[loop_index].break_point;
+        return Ok(stop);
+    }
+    if let Some(loop_index) = loop_entry {
+        // It's a jump to a loop entry
+        if !try_encloses_loop(ctx, next, loop_index) {
+            return translate_loops(ctx, result, loop_index, stop);
+        }
+    }
+    Ok(next)
+}
+
+/// Outputs the loop and the loops directly following it, returning where the control flow
+/// continues after them.
+fn translate_loops<S: Clone, C: Clone>(
+    ctx: &mut Context<S, C>,
+    result: &mut Vec<Structured>,
+    mut loop_index: usize,
+    stop: Label,
+) -> Result<Label, String> {
+    loop {
+        let entry = ctx.loops[loop_index].entry;
+        let brk = ctx.loops[loop_index].break_point;
+        check_passes(ctx, brk, stop, "brk")?;
+        let body = structure_from_to(ctx, entry, brk)?;
+        result.push(Structured::Loop {
+            id: loop_index,
+            body,
+        });
+        loop_index = match ctx.entry_to_loop_index.get(&brk) {
+            // The loop is only left by continuing an enclosing loop, which its body does:
+            Some(&i) if ctx.loops[i].nodes.contains(&entry) => return Ok(stop),
+            Some(&i) if brk != stop && !try_encloses_loop(ctx, brk, i) => i,
+            _ => return Ok(brk),
+        };
     }
-    next
 }
 
 fn translate_block<S: Clone, C: Clone>(
@@ -200,65 +1158,451 @@ fn translate_block<S: Clone, C: Clone>(
     result: &mut Vec<Structured>,
     cur: Label,
     stop: Label,
-) -> Label {
+) -> Result<Label, String> {
     let mut outgoing: Map<Edge, Label> = Map::new();
     for edge in ctx.cfg.graph.edges_directed(cur, Direction::Outgoing) {
+        if edge.weight().is_exception() {
+            continue;
+        }
         outgoing.insert(*edge.weight(), edge.target());
     }
+    if let Some(try_index) = unopened_try_at(ctx, cur) {
+        return translate_try(ctx, result, try_index, stop);
+    }
     let bb = ctx.cfg.graph[cur].clone();
     result.push(Structured::BasicBlock(cur));
     let cond = bb.terminator;
-    if cond.is_some() {
-        assert_eq!(
-            2,
-            outgoing.len(),
-            "basic block #{} (with condition) should have 2 successors",
-            cur.index()
-        );
-        let join = ctx.postdominators.get_immediate(cur).unwrap();
-        assert!(
-            ctx.postdominators.is_for(stop, join),
-            "stop point {} doesn't postdominate the join point {}",
-            stop.index(),
-            join.index()
-        );
+    if outgoing.keys().any(Edge::is_case) {
+        translate_switch(ctx, result, cur, &outgoing, stop)
+    } else if cond.is_some() {
+        let (then_target, else_target) = match (
+            outgoing.get(&Edge::Branch(true)),
+            outgoing.get(&Edge::Branch(false)),
+        ) {
+            (Some(&then_target), Some(&else_target)) if outgoing.len() == 2 => {
+                (then_target, else_target)
+            }
+            _ => {
+                return Err(format!(
+                    "basic block #{} (with condition) should have 2 successors",
+                    cur.index()
+                ))
+            }
+        };
+        let join = find_join(ctx, cur, stop)?;
+        check_passes(ctx, join, stop, "the join point")?;
         let mut then_stmts = vec![];
-        let then_block = handle_jump(ctx, &mut then_stmts, Jump(cur, outgoing[&true]), stop);
-        then_stmts.append(&mut structure_from_to(ctx, then_block, join));
+        let then_block = handle_jump(ctx, &mut then_stmts, Jump(cur, then_target), stop)?;
+        then_stmts.append(&mut structure_from_to(ctx, then_block, join)?);
         let mut else_stmts = vec![];
-        let else_block = handle_jump(ctx, &mut else_stmts, Jump(cur, outgoing[&false]), stop);
-        else_stmts.append(&mut structure_from_to(ctx, else_block, join));
+        let else_block = handle_jump(ctx, &mut else_stmts, Jump(cur, else_target), stop)?;
+        else_stmts.append(&mut structure_from_to(ctx, else_block, join)?);
         result.push(Structured::If(cur, then_stmts, else_stmts));
-        join
+        Ok(join)
     } else {
-        assert!(
-            outgoing.len() <= 1,
-            "basic block #{} has too many successors",
-            cur.index()
-        );
-        let next = *outgoing.values().next().expect(&format!(
-            "basic block {} has no outgoing edges",
-            cur.index()
-        ));
-        handle_jump(ctx, result, Jump(cur, next), stop)
+        let mut successors = outgoing.values();
+        match (successors.next(), successors.next()) {
+            (Some(&next), None) => handle_jump(ctx, result, Jump(cur, next), stop),
+            (None, _) => Err(format!(
+                "basic block #{} has no outgoing edges",
+                cur.index()
+            )),
+            (Some(_), Some(_)) => Err(format!(
+                "basic block #{} has too many successors",
+                cur.index()
+            )),
+        }
+    }
+}
+
+/// Finds the point where the branches of the block join again.
+fn find_join<S, C>(ctx: &Context<S, C>, cur: Label, stop: Label) -> Result<Label, String> {
+    let join = ctx
+        .postdominators
+        .get_immediate(cur)
+        .ok_or_else(|| format!("basic block #{} has no postdominator", cur.index()))?;
+    let after_stop = join != stop && is_postdominated(ctx, join, stop);
+    if (join == ctx.cfg.exit_point && stop != ctx.cfg.exit_point) || after_stop {
+        // Inside a try statement, the branches may not join before returning or leaving the
+        // statement otherwise:
+        return Ok(stop);
+    }
+    let in_loop = ctx
+        .entry_to_loop_index
+        .get(&join)
+        .is_some_and(|&i| ctx.loops[i].nodes.contains(&cur));
+    if in_loop {
+        // All branches continue the loop, so the join point is the end of the loop body:
+        return Ok(stop);
+    }
+    Ok(join)
+}
+
+fn translate_switch<S: Clone, C: Clone>(
+    ctx: &mut Context<S, C>,
+    result: &mut Vec<Structured>,
+    cur: Label,
+    outgoing: &Map<Edge, Label>,
+    stop: Label,
+) -> Result<Label, String> {
+    let join = find_join(ctx, cur, stop)?;
+    check_passes(ctx, join, stop, "the join point")?;
+    // The cases are output in the order of their code, so they can fall through:
+    let mut cases: Map<Label, Vec<Option<i32>>> = Map::new();
+    for (edge, &target) in outgoing {
+        if let Edge::Case(value) = *edge {
+            cases.entry(target).or_default().push(value);
+        }
+    }
+    for values in cases.values_mut() {
+        // The gaps of a tableswitch jump to the default case, so their labels are redundant:
+        if values.contains(&None) {
+            values.retain(Option::is_none);
+        }
+        // `default` comes last among the labels of a case:
+        values.sort_by_key(Option::is_none);
+    }
+    // Cases that jump to the join point directly are only needed if there's a default case:
+    if cases
+        .get(&join)
+        .is_some_and(|values| values.contains(&None))
+    {
+        cases.remove(&join);
+    }
+    let id = ctx.switch_count;
+    ctx.switch_count += 1;
+    let targets = cases.keys().cloned().collect::<Vec<_>>();
+    ctx.active_switches.push(ActiveSwitch { id, join });
+    let mut structured_cases = vec![];
+    for (i, (target, values)) in cases.into_iter().enumerate() {
+        let mut body = vec![];
+        let mut next = handle_jump(ctx, &mut body, Jump(cur, target), join)?;
+        if next == target {
+            next = translate_block(ctx, &mut body, target, join)?;
+        }
+        // The case ends where it falls through to the next one, other cases are duplicated:
+        let next_case = targets.get(i + 1).cloned();
+        while next != join && next != ctx.cfg.exit_point && Some(next) != next_case {
+            next = translate_block(ctx, &mut body, next, join)?;
+        }
+        structured_cases.push((values, body));
+    }
+    ctx.active_switches.pop();
+    // The last case doesn't need to break out of the switch:
+    if let Some(&mut (_, ref mut body)) = structured_cases.last_mut() {
+        if body.last() == Some(&Structured::BreakSwitch(id)) {
+            body.pop();
+        }
+    }
+    result.push(Structured::Switch {
+        id,
+        block: cur,
+        cases: structured_cases,
+    });
+    Ok(join)
+}
+
+/// Returns the try statement starting at the block that hasn't been output yet.
+/// If there are several, the outermost one is returned.
+fn unopened_try_at<S, C>(ctx: &Context<S, C>, node: Label) -> Option<usize> {
+    (0..ctx.tries.len())
+        .filter(|i| ctx.tries[*i].entry == node && !ctx.opened_tries.contains(i))
+        .max_by_key(|&i| ctx.tries[i].nodes.len())
+}
+
+/// Checks whether a try statement that hasn't been output yet starts at the loop entry and
+/// contains the whole loop, so the loop must be output inside the try statement.
+fn try_encloses_loop<S, C>(ctx: &Context<S, C>, entry: Label, loop_index: usize) -> bool {
+    unopened_try_at(ctx, entry)
+        .is_some_and(|i| ctx.loops[loop_index].nodes.is_subset(&ctx.tries[i].nodes))
+}
+
+fn translate_try<S: Clone, C: Clone>(
+    ctx: &mut Context<S, C>,
+    result: &mut Vec<Structured>,
+    try_index: usize,
+    stop: Label,
+) -> Result<Label, String> {
+    ctx.opened_tries.insert(try_index);
+    let region = ctx.tries[try_index].clone();
+    let join = find_try_join(ctx, &region, stop);
+    ctx.try_depth += 1;
+    let mut body = vec![];
+    let encloses_loop = ctx
+        .entry_to_loop_index
+        .get(&region.entry)
+        .is_some_and(|&i| ctx.loops[i].nodes.is_subset(&region.nodes));
+    let next = if encloses_loop {
+        // The loop was skipped by `handle_jump` because it belongs inside the try statement.
+        // The function entry point is never part of a loop, so this isn't a `continue`:
+        handle_jump(
+            ctx,
+            &mut body,
+            Jump(ctx.cfg.entry_point, region.entry),
+            join,
+        )?
+    } else {
+        region.entry
+    };
+    body.append(&mut structure_from_to(ctx, next, join)?);
+    let mut handlers = vec![];
+    for (handler, types) in region.handlers {
+        let handler_body = structure_from_to(ctx, handler, join)?;
+        handlers.push((handler, types, handler_body));
+    }
+    ctx.try_depth -= 1;
+    result.push(Structured::Try { body, handlers });
+    Ok(join)
+}
+
+/// Finds the point where the control flow continues after the try statement.
+///
+/// Exits of the body that only lead to the end of the function (`return`) are ignored, so such
+/// code stays in the body. So are the exits out of the innermost loop around the try statement,
+/// which become `break`s, and the ones that jump straight back to its entry, which become
+/// `continue`s.
+fn find_try_join<S, C>(ctx: &Context<S, C>, region: &TryRegion, stop: Label) -> Label {
+    let exit = ctx.cfg.exit_point;
+    let lupe =
+        ctx.loops.iter().rev().find(|lupe| {
+            !region.nodes.contains(&lupe.entry) && lupe.nodes.is_superset(&region.nodes)
+        });
+    let mut exits = Set::new();
+    for &node in &region.nodes {
+        for edge in ctx.cfg.graph.edges_directed(node, Direction::Outgoing) {
+            let target = edge.target();
+            if !edge.weight().is_exception()
+                && !region.nodes.contains(&target)
+                && target != exit
+                && ctx.postdominators.get_immediate(target) != Some(exit)
+                && lupe.is_none_or(|lupe| {
+                    lupe.nodes.contains(&target) && !continues_loop(ctx, lupe, target)
+                })
+            {
+                exits.insert(target);
+            }
+        }
+    }
+    let mut join = ctx.postdominators.get_common(exits).unwrap_or(exit);
+    if join != exit {
+        for &(handler, _) in &region.handlers {
+            match ctx.postdominators.get_common(vec![join, handler]) {
+                Some(common) if common != exit => join = common,
+                _ => {}
+            }
+        }
+    }
+    // All exits continue the loop, so the join point is the end of the loop body:
+    let is_loop_entry = lupe.is_some_and(|lupe| lupe.entry == join);
+    if join == exit || is_loop_entry || !is_postdominated(ctx, stop, join) {
+        stop
+    } else {
+        join
     }
 }
 
-fn collect_loops<S, C>(ctx: &mut Context<S, C>, filter: &Set<Label>) {
+/// Checks whether the control flow goes from `node` to the entry of the loop through empty
+/// blocks only, i.e. it's a `continue` of the loop.
+fn continues_loop<S, C>(ctx: &Context<S, C>, lupe: &Loop, mut node: Label) -> bool {
+    let mut visited = Set::new();
+    while node != lupe.entry {
+        if !ctx.cfg.graph[node].stmts.is_empty() || !visited.insert(node) {
+            return false;
+        }
+        let mut successors = ctx
+            .cfg
+            .graph
+            .edges_directed(node, Direction::Outgoing)
+            .filter(|edge| !edge.weight().is_exception());
+        node = match (successors.next(), successors.next()) {
+            (Some(edge), None) if ctx.cfg.graph[node].terminator.is_none() => edge.target(),
+            _ => return false,
+        };
+    }
+    true
+}
+
+fn collect_tries<S, C>(ctx: &mut Context<S, C>) {
+    let graph = &ctx.cfg.graph;
+    // handler -> (protected blocks, caught exception classes)
+    let mut handlers = Map::new();
+    for edge in graph.edge_references() {
+        if let Edge::Exception(catch_type) = *edge.weight() {
+            let handler = handlers
+                .entry(edge.target())
+                .or_insert_with(|| (Set::new(), vec![]));
+            handler.0.insert(edge.source());
+            if !handler.1.contains(&catch_type) {
+                handler.1.push(catch_type);
+            }
+        }
+    }
+    // Handlers protecting the same blocks belong to the same try statement:
+    let mut regions: Vec<TryRegion> = vec![];
+    for (handler, (nodes, types)) in handlers {
+        if let Some(region) = regions.iter_mut().find(|region| region.nodes == nodes) {
+            region.handlers.push((handler, types));
+            continue;
+        }
+        let entry = nodes
+            .iter()
+            .cloned()
+            .find(|&node| {
+                graph
+                    .edges_directed(node, Direction::Incoming)
+                    .any(|edge| !edge.weight().is_exception() && !nodes.contains(&edge.source()))
+            })
+            .unwrap_or_else(|| *nodes.iter().next().unwrap());
+        regions.push(TryRegion {
+            nodes,
+            entry,
+            handlers: vec![(handler, types)],
+        });
+    }
+    ctx.tries = regions;
+}
+
+fn collect_loops<S, C>(ctx: &mut Context<S, C>, filter: &Set<Label>) -> Result<(), String> {
     if filter.is_empty() {
-        return;
+        return Ok(());
     }
     let sccs = compute_strongly_connected_components(&ctx.cfg.graph, filter);
     for mut nodes in sccs {
-        if !is_scc_loop(ctx, &nodes) {
+        if !is_scc_loop(&ctx.cfg.graph, &nodes) {
             continue;
         }
-        let lupe = find_entries_and_exits(ctx, nodes.clone());
+        add_try_blocks(ctx, &mut nodes);
+        let lupe = find_entries_and_exits(ctx, nodes.clone())?;
         let entry = lupe.entry;
         store_loop_in_context(ctx, lupe);
         // recursively collect the nested loops inside this loop:
         nodes.remove(&entry);
-        collect_loops(ctx, &nodes);
+        collect_loops(ctx, &nodes)?;
+    }
+    Ok(())
+}
+
+/// Adds the blocks of the try statements that start inside the loop (and don't contain all of it)
+/// to it, if they are only
+/// reached from inside the loop. They leave the loop, e.g. to return, but the try statement must
+/// still be nested in the loop.
+fn add_try_blocks<S, C>(ctx: &Context<S, C>, nodes: &mut Set<Label>) {
+    loop {
+        let added = ctx
+            .tries
+            .iter()
+            .filter(|region| nodes.contains(&region.entry) && !region.nodes.is_superset(nodes))
+            .flat_map(|region| region.nodes.difference(nodes))
+            .cloned()
+            .find(|&node| {
+                // Handlers are entered by exceptions, they belong to the try statement anyway:
+                let mut incoming = ctx.cfg.graph.edges_directed(node, Direction::Incoming);
+                incoming.clone().any(|edge| !edge.weight().is_exception())
+                    && incoming.all(|edge| nodes.contains(&edge.source()))
+            });
+        match added {
+            Some(node) => nodes.insert(node),
+            None => return,
+        };
+    }
+}
+
+/// Makes every loop have a single entry point by node splitting: javac never generates loops
+/// with several entry points, but other compilers and obfuscators may.
+/// One entry point of such a loop is kept, for each other one the blocks of the loop reachable
+/// from it without passing the kept one are duplicated and the jumps into the loop at that entry
+/// point go to the copies instead. The copies then lead back into the loop through the kept
+/// entry point only. This is repeated until all loops, including nested ones, are reducible.
+/// Returns whether anything was split.
+fn split_irreducible_loops<S: Clone, C: Clone>(cfg: &mut Cfg<S, C>) -> bool {
+    let mut is_split = false;
+    loop {
+        let all_nodes = cfg.graph.node_indices().collect::<Set<_>>();
+        match find_irreducible_loop(&cfg.graph, &all_nodes) {
+            Some((nodes, entries)) => split_loop(cfg, &nodes, &entries),
+            None => return is_split,
+        }
+        is_split = true;
+    }
+}
+
+/// Finds a loop with several entry points like `collect_loops` finds the loops, returning its
+/// blocks and entry points.
+fn find_irreducible_loop<S, C>(
+    graph: &CfgGraph<S, C>,
+    filter: &Set<Label>,
+) -> Option<(Set<Label>, Set<Label>)> {
+    for mut nodes in compute_strongly_connected_components(graph, filter) {
+        if !is_scc_loop(graph, &nodes) {
+            continue;
+        }
+        let entries = nodes
+            .iter()
+            .cloned()
+            .filter(|&node| {
+                graph
+                    .neighbors_directed(node, Direction::Incoming)
+                    .any(|incoming| !nodes.contains(&incoming))
+            })
+            .collect::<Set<_>>();
+        if entries.len() > 1 {
+            return Some((nodes, entries));
+        }
+        // A loop without entry points is dead code, it's left to the structuring to reject it:
+        match entries.into_iter().next() {
+            Some(entry) => nodes.remove(&entry),
+            None => continue,
+        };
+        if let Some(irreducible) = find_irreducible_loop(graph, &nodes) {
+            return Some(irreducible);
+        }
+    }
+    None
+}
+
+/// Splits the loop consisting of `nodes`, see `split_irreducible_loops`. The entry point that
+/// comes first in the code is kept.
+fn split_loop<S: Clone, C: Clone>(cfg: &mut Cfg<S, C>, nodes: &Set<Label>, entries: &Set<Label>) {
+    let mut entries = entries.iter().cloned();
+    let kept = entries.next().unwrap();
+    for entry in entries {
+        // The blocks reachable from the entry point within the loop without passing `kept`:
+        let mut region = Set::new();
+        let mut todo = vec![entry];
+        while let Some(node) = todo.pop() {
+            if node == kept || !nodes.contains(&node) || !region.insert(node) {
+                continue;
+            }
+            todo.extend(cfg.graph.neighbors_directed(node, Direction::Outgoing));
+        }
+        let copies = region
+            .iter()
+            .map(|&node| {
+                let block = cfg.graph[node].clone();
+                (node, cfg.graph.add_node(block))
+            })
+            .collect::<Map<_, _>>();
+        for (&node, &copy) in &copies {
+            let edges = cfg
+                .graph
+                .edges_directed(node, Direction::Outgoing)
+                .map(|edge| (*edge.weight(), edge.target()))
+                .collect::<Vec<_>>();
+            for (weight, target) in edges {
+                let target = copies.get(&target).cloned().unwrap_or(target);
+                cfg.graph.add_edge(copy, target, weight);
+            }
+        }
+        // The jumps from outside the loop enter the copy instead:
+        while let Some((id, source, weight)) = cfg
+            .graph
+            .edges_directed(entry, Direction::Incoming)
+            .find(|edge| !nodes.contains(&edge.source()))
+            .map(|edge| (edge.id(), edge.source(), *edge.weight()))
+        {
+            cfg.graph.remove_edge(id);
+            cfg.graph.add_edge(source, copies[&entry], weight);
+        }
     }
 }
 
@@ -268,25 +1612,25 @@ fn compute_strongly_connected_components<S, C>(
 ) -> Vec<Set<Label>> {
     let filtered = NodeFiltered(graph, |n| filter.contains(&n));
     algo::kosaraju_scc(&filtered)
-    .iter_mut()
-    .rev() // we want a topological sort
-    .map(|v| v.drain(..).collect::<Set<_>>())
-    .collect::<Vec<_>>()
+        .iter_mut()
+        .rev() // we want a topological sort
+        .map(|v| v.drain(..).collect::<Set<_>>())
+        .collect::<Vec<_>>()
 }
 
-fn is_scc_loop<S, C>(ctx: &Context<S, C>, nodes: &Set<Label>) -> bool {
+fn is_scc_loop<S, C>(graph: &CfgGraph<S, C>, nodes: &Set<Label>) -> bool {
     if nodes.is_empty() {
         return false;
     }
     if nodes.len() == 1 {
         let node = *nodes.iter().next().unwrap();
-        ctx.cfg.graph.find_edge(node, node).is_some()
+        graph.find_edge(node, node).is_some()
     } else {
         true
     }
 }
 
-fn find_entries_and_exits<S, C>(ctx: &Context<S, C>, nodes: Set<Label>) -> Loop {
+fn find_entries_and_exits<S, C>(ctx: &Context<S, C>, nodes: Set<Label>) -> Result<Loop, String> {
     let mut entry_points = Set::new();
     let mut exit_points = Set::new();
     for &node in &nodes {
@@ -298,22 +1642,31 @@ fn find_entries_and_exits<S, C>(ctx: &Context<S, C>, nodes: Set<Label>) -> Loop
                 entry_points.insert(node);
             }
         }
-        let outgoing_neighbors = graph.neighbors_directed(node, Direction::Outgoing);
-        for outgoing in outgoing_neighbors {
-            if !nodes.contains(&outgoing) {
+        for edge in graph.edges_directed(node, Direction::Outgoing) {
+            // Handlers outside the loop are left via the try statement, not `break`. The blocks
+            // of try statements that were added to the loop may return or throw, leaving it for
+            // the exit point like the blocks outside of it:
+            if !nodes.contains(&edge.target()) && !edge.weight().is_exception() {
                 // not an intra-loop edge
-                exit_points.insert(outgoing);
+                exit_points.insert(edge.target());
             }
         }
     }
     // extract the entry point:
-    assert!(
-        entry_points.len() <= 1,
-        "The loop consisting of the basic blocks {:?} has multiple entry points {:?}",
-        nodes,
-        entry_points
-    );
-    let entry_point = *entry_points.iter().next().unwrap();
+    let entry_point = match (entry_points.iter().next(), entry_points.len()) {
+        (Some(&entry_point), 1) => entry_point,
+        _ => {
+            let blocks = |labels: &Set<Label>| {
+                let labels = labels.iter().map(|label| format!("#{}", label.index()));
+                labels.collect::<Vec<_>>().join(", ")
+            };
+            return Err(format!(
+                "the loop consisting of the basic blocks {} has the entry points [{}]",
+                blocks(&nodes),
+                blocks(&entry_points)
+            ));
+        }
+    };
     let mut continue_edges = Set::new();
     {
         let graph = &ctx.cfg.graph;
@@ -330,38 +1683,452 @@ fn find_entries_and_exits<S, C>(ctx: &Context<S, C>, nodes: Set<Label>) -> Loop
         let graph = &ctx.cfg.graph;
         let incoming_neighbors = graph.neighbors_directed(break_point, Direction::Incoming);
         for incoming in incoming_neighbors {
-            if ctx.dominators.is_for(entry_point, break_point) {
+            if ctx.dominators.is_for(entry_point, incoming) {
                 break_edges.insert(Jump(incoming, break_point));
             }
         }
     }
-    Loop {
-        nodes: nodes,
+    Ok(Loop {
+        nodes,
         entry: entry_point,
-        continue_edges: continue_edges,
+        continue_edges,
         exits: exit_points,
-        break_point: break_point,
-        break_edges: break_edges,
-    }
+        break_point,
+        break_edges,
+    })
 }
 
 fn find_best_break_block<S, C>(ctx: &Context<S, C>, exits: &Set<Label>) -> Label {
-    let exits = exits.clone();
     // TODO: This can be improved if the CFG looks like this:
     // A ----------------> exit
     // B --> D ----> E -==-^
     // C ----^
     // Here, we should pick D to be the best beak_block, not exit.
+    // Loops that are only left by exceptions don't have any exits:
     ctx.postdominators
-        .get_common(&exits.iter().cloned().collect::<Vec<_>>())
-        .unwrap()
+        .get_common(exits.iter().cloned())
+        .unwrap_or(ctx.cfg.exit_point)
 }
 
 fn store_loop_in_context<S, C>(ctx: &mut Context<S, C>, lupe: Loop) {
     let loop_index = ctx.loops.len();
     ctx.entry_to_loop_index.insert(lupe.entry, loop_index);
+    // Loops are stored before the loops nested in them. If a nested loop is left at the same
+    // point, the jump leaves the enclosing loop as well:
     for edge in lupe.break_edges.clone() {
-        ctx.loop_breaks.insert(edge, loop_index);
+        ctx.loop_breaks.entry(edge).or_insert(loop_index);
     }
     ctx.loops.push(lupe);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompiler::fixtures::*;
+
+    fn throw(handler: usize) -> Statement {
+        Statement::Throw(mk_variable(caught_exception(Label::new(handler))))
+    }
+
+    fn structure_to_string(cfg: Cfg<Statement, Expr>) -> String {
+        let Block(_, stmts) = structure_cfg(
+            cfg,
+            &Metadata::new(),
+            |_| {},
+            |reason| panic!("the control flow can't be structured: {}", reason),
+        );
+        to_string(&stmts)
+    }
+
+    #[test]
+    fn finally_in_loop_before_update() {
+        // The copy of the finally code falls through to the rest of the loop body:
+        let cfg = build(
+            vec![
+                (vec![], Some("done")),
+                (vec![call("g", vec![])], None),
+                (vec![call("fin", vec![])], None),
+                (vec![call("fin", vec![]), throw(3)], None),
+                (vec![call("update", vec![])], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 5, Edge::Branch(true)),
+                (1, 2, Edge::Branch(false)),
+                (1, 3, Edge::Exception(None)),
+                (2, 4, Edge::Branch(true)),
+                (3, 7, Edge::Branch(false)),
+                (4, 0, Edge::Branch(true)),
+                (5, 7, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "while (!done) {",
+            "    try {",
+            "        T.g();",
+            "    } finally {",
+            "        T.fin();",
+            "    }",
+            "    T.update();",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn finally_in_loop_with_break_and_continue() {
+        // Each of the three exits of the try statement has its own copy of the finally code, the
+        // normal one and the one of `continue` both go on to the update of the loop:
+        let cfg = build(
+            vec![
+                (vec![], Some("end")),
+                (vec![], Some("next")),
+                (vec![call("fin", vec![])], None),
+                (vec![], Some("skip")),
+                (vec![call("fin", vec![])], None),
+                (vec![call("g", vec![])], None),
+                (vec![call("fin", vec![])], None),
+                (vec![call("update", vec![])], None),
+                (vec![call("fin", vec![]), throw(8)], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 9, Edge::Branch(true)),
+                (1, 2, Edge::Branch(false)),
+                (1, 3, Edge::Branch(true)),
+                (2, 9, Edge::Branch(true)),
+                (3, 4, Edge::Branch(false)),
+                (3, 5, Edge::Branch(true)),
+                (4, 7, Edge::Branch(true)),
+                (5, 6, Edge::Branch(false)),
+                (6, 7, Edge::Branch(true)),
+                (7, 0, Edge::Branch(true)),
+                (1, 8, Edge::Exception(None)),
+                (3, 8, Edge::Exception(None)),
+                (5, 8, Edge::Exception(None)),
+                (8, 11, Edge::Branch(false)),
+                (9, 11, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "while (!end) {",
+            "    try {",
+            "        if (next) {",
+            "            if (skip) {",
+            "                T.g();",
+            "            }",
+            "        } else {",
+            "            break;",
+            "        }",
+            "    } finally {",
+            "        T.fin();",
+            "    }",
+            "    T.update();",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn consecutive_loops() {
+        // The first loop is left by jumping to the entry of the second one:
+        //   int l(int j, int k) { while (j < 10) j++; while (k < 10) k++; return j + k; }
+        let cfg = build(
+            vec![
+                (vec![], Some("a")),
+                (vec![call("f", vec![])], None),
+                (vec![], Some("b")),
+                (vec![call("g", vec![])], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 2, Edge::Branch(true)),
+                (1, 0, Edge::Branch(true)),
+                (2, 3, Edge::Branch(false)),
+                (2, 4, Edge::Branch(true)),
+                (3, 2, Edge::Branch(true)),
+                (4, 6, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "while (!a) {",
+            "    T.f();",
+            "}",
+            "while (!b) {",
+            "    T.g();",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn continue_outer_loop_from_inner_loop() {
+        // The inner loop is only left by continuing the outer one, like in
+        //   write: while (length > 0) {
+        //       for (int i = offset; i < bufs.length; i++) {
+        //           if (bufs[i] != 0) { length--; continue write; }
+        //       }
+        //       length = 0;
+        //   }
+        let cfg = build(
+            vec![
+                (vec![], Some("a")),
+                (vec![call("f", vec![])], None),
+                (vec![], Some("b")),
+                (vec![], Some("c")),
+                (vec![call("g", vec![])], None),
+                (vec![call("update", vec![])], None),
+                (vec![call("h", vec![])], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 7, Edge::Branch(true)),
+                (1, 2, Edge::Branch(true)),
+                (2, 3, Edge::Branch(false)),
+                (2, 6, Edge::Branch(true)),
+                (3, 4, Edge::Branch(false)),
+                (3, 5, Edge::Branch(true)),
+                (4, 0, Edge::Branch(true)),
+                (5, 2, Edge::Branch(true)),
+                (6, 0, Edge::Branch(true)),
+                (7, 9, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "loop_0: while (!a) {",
+            "    T.f();",
+            "    while (!b) {",
+            "        if (c) {",
+            "            T.update();",
+            "        } else {",
+            "            T.g();",
+            "            continue loop_0;",
+            "        }",
+            "    }",
+            "    T.h();",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn unstructured_fallback() {
+        // The dead loop isn't entered from anywhere, so it can't be structured:
+        let mut cfg = build(
+            vec![
+                (vec![Statement::Return(None)], None),
+                (vec![call("f", vec![])], None),
+                (vec![call("g", vec![])], None),
+            ],
+            &[
+                (0, 4, Edge::Branch(false)),
+                (1, 2, Edge::Branch(true)),
+                (2, 1, Edge::Branch(true)),
+            ],
+        );
+        assert!(!split_irreducible_loops(&mut cfg));
+        let mut warning = None;
+        let Block(_, stmts) = structure_cfg(
+            cfg,
+            &Metadata::new(),
+            |_| {},
+            |reason| warning = Some(reason),
+        );
+        let reason = "the loop consisting of the basic blocks #1, #2 has the entry points []";
+        assert_eq!(warning.as_deref(), Some(reason));
+        match stmts[..] {
+            [Statement::Comment(ref code), ref throw] => {
+                assert!(code.contains("T.f();"));
+                assert_eq!(*throw, throw_unsupported(reason));
+            }
+            _ => panic!("unexpected statements {:?}", stmts),
+        }
+    }
+
+    #[test]
+    fn irreducible_loop() {
+        // The loop has two entry points, `f()` stands for the first `iinc` and `g()` for the
+        // second one:
+        //    0: iload_0
+        //    1: ifeq 11
+        //    4: iinc 0, -1
+        //    7: iload_0
+        //    8: ifle 17
+        //   11: iinc 0, -2
+        //   14: goto 4
+        //   17: return
+        let mut cfg = build(
+            vec![
+                (vec![], Some("a")),
+                (vec![call("f", vec![])], Some("b")),
+                (vec![call("g", vec![])], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 2, Edge::Branch(true)),
+                (1, 2, Edge::Branch(false)),
+                (1, 3, Edge::Branch(true)),
+                (2, 1, Edge::Branch(true)),
+                (3, 5, Edge::Branch(false)),
+            ],
+        );
+        assert!(split_irreducible_loops(&mut cfg));
+        let expected = [
+            "if (a) {",
+            "    T.g();",
+            "}",
+            "while (true) {",
+            "    T.f();",
+            "    if (b) {",
+            "        break;",
+            "    } else {",
+            "        T.g();",
+            "    }",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn code_after_loop_left_twice() {
+        // `while (!a) { while (!b) { if (c) break loop_0; if (d) break; } T.row(); } T.after();`,
+        // where leaving the inner loop either way continues with `T.row()`:
+        let cfg = build(
+            vec![
+                (vec![], Some("a")),
+                (vec![], Some("b")),
+                (vec![], Some("c")),
+                (vec![], Some("d")),
+                (vec![call("row", vec![])], None),
+                (vec![call("after", vec![]), Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 5, Edge::Branch(true)),
+                (1, 2, Edge::Branch(false)),
+                (1, 4, Edge::Branch(true)),
+                (2, 3, Edge::Branch(false)),
+                (2, 5, Edge::Branch(true)),
+                (3, 1, Edge::Branch(false)),
+                (3, 4, Edge::Branch(true)),
+                (4, 0, Edge::Branch(false)),
+                (5, 7, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "loop_0: while (!a) {",
+            "    while (!b) {",
+            "        if (c) {",
+            "            break loop_0;",
+            "        } else {",
+            "            if (d) {",
+            "                break;",
+            "            }",
+            "        }",
+            "    }",
+            "    T.row();",
+            "}",
+            "T.after();",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn throw_in_finally_in_loop() {
+        // `while (!done) { try { if (a) throw e; } finally { T.fin(); } T.update(); }`, the
+        // throw leaves the loop through the handler:
+        let cfg = build(
+            vec![
+                (vec![], Some("done")),
+                (vec![], Some("a")),
+                (vec![Statement::Throw(var("e"))], None),
+                (vec![call("fin", vec![])], None),
+                (vec![call("fin", vec![]), throw(4)], None),
+                (vec![call("update", vec![])], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 6, Edge::Branch(true)),
+                (1, 2, Edge::Branch(true)),
+                (1, 3, Edge::Branch(false)),
+                (1, 4, Edge::Exception(None)),
+                (2, 8, Edge::Branch(false)),
+                (2, 4, Edge::Exception(None)),
+                (3, 5, Edge::Branch(false)),
+                (4, 8, Edge::Branch(false)),
+                (5, 0, Edge::Branch(false)),
+                (6, 8, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "while (!done) {",
+            "    try {",
+            "        if (a) {",
+            "            throw e;",
+            "        }",
+            "    } finally {",
+            "        T.fin();",
+            "    }",
+            "    T.update();",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn continue_and_throw_in_finally_in_loop() {
+        // `while (!done) { try { if (c) continue; if (a) throw e; } finally { T.fin(); }
+        // T.after(); }`:
+        let cfg = build(
+            vec![
+                (vec![], Some("done")),
+                (vec![], Some("c")),
+                (vec![call("fin", vec![])], None),
+                (vec![], Some("a")),
+                (vec![Statement::Throw(var("e"))], None),
+                (vec![call("fin", vec![]), throw(5)], None),
+                (vec![call("fin", vec![]), call("after", vec![])], None),
+                (vec![Statement::Return(None)], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 7, Edge::Branch(true)),
+                (1, 2, Edge::Branch(true)),
+                (1, 3, Edge::Branch(false)),
+                (1, 5, Edge::Exception(None)),
+                (2, 0, Edge::Branch(false)),
+                (3, 4, Edge::Branch(true)),
+                (3, 6, Edge::Branch(false)),
+                (3, 5, Edge::Exception(None)),
+                (4, 9, Edge::Branch(false)),
+                (4, 5, Edge::Exception(None)),
+                (5, 9, Edge::Branch(false)),
+                (6, 0, Edge::Branch(false)),
+                (7, 9, Edge::Branch(false)),
+            ],
+        );
+        let expected = [
+            "while (!done) {",
+            "    try {",
+            "        if (c) {",
+            "            continue;",
+            "        } else {",
+            "            if (a) {",
+            "                throw e;",
+            "            }",
+            "        }",
+            "    } finally {",
+            "        T.fin();",
+            "    }",
+            "    T.after();",
+            "}",
+        ];
+        assert_eq!(structure_to_string(cfg), expected.join("\n"));
+    }
+}
@@ -0,0 +1,352 @@
+//! Rewrites the code that javac generates for switches on strings and enums back into switches
+//! on the strings and enum constants.
+//!
+//! A switch on a string is compiled to a switch on the hash code of the string. Its cases compare
+//! the string with the strings of that hash code and store the number of the matching case in a
+//! temporary variable, which the actual switch is on:
+//!
+//! ```ignore
+//! tmp = s;
+//! index = -1;
+//! switch (tmp.hashCode()) {
+//!     case 97:
+//!         if (tmp.equals("a")) {
+//!             index = 0;
+//!         }
+//!         break;
+//!     ...
+//! }
+//! switch (index) {
+//!     case 0:
+//!     ...
+//! }
+//! ```
+//!
+//! A switch on an enum is compiled to a switch on an element of an array in a synthetic class,
+//! e.g. `Outer$1.$SwitchMap$Color[c.ordinal()]`, which maps the ordinals of the enum constants to
+//! the numbers of the cases. The arrays are filled by the static initializer of that class, so
+//! they have to be collected from it before (see `SwitchMaps`).
+
+use decompiler::types::*;
+use disassembler::types::*;
+use std::collections::HashMap;
+
+/// The `$SwitchMap$...` arrays of the synthetic classes for enum switches, by class and field
+/// name. Each array maps the numbers of the cases to the names of the enum constants.
+#[derive(Debug, Default)]
+pub struct SwitchMaps {
+    maps: HashMap<(String, String), HashMap<i32, String>>,
+}
+
+impl SwitchMaps {
+    pub fn new() -> SwitchMaps {
+        SwitchMaps::default()
+    }
+
+    /// Collects the switch maps that are filled in the static initializer of the class.
+    pub fn collect(&mut self, unit: &CompilationUnit<Code>) {
+        for declaration in &unit.declarations {
+            let code = match *declaration {
+                Declaration::Method(Method {
+                    ref name,
+                    code: Some(ref code),
+                    ..
+                }) if name == "<clinit>" => code,
+                _ => continue,
+            };
+            let instructions = code
+                .instructions
+                .iter()
+                .map(|(_, instruction)| instruction)
+                .collect::<Vec<_>>();
+            // `map[Enum.CONSTANT.ordinal()] = case;`
+            for window in instructions.windows(5) {
+                let (map, constant, method_index, case) =
+                    match (window[0], window[1], window[2], window[3], window[4]) {
+                        (
+                            &Instruction::Load(RValue::LValue(LValue::StaticField {
+                                field_ref: map,
+                            })),
+                            &Instruction::Load(RValue::LValue(LValue::StaticField {
+                                field_ref: constant,
+                            })),
+                            &Instruction::Invoke(Invoke {
+                                method_index,
+                                kind: InvokeKind::Virtual,
+                            }),
+                            &Instruction::Load(RValue::Constant(Literal::Integer(case))),
+                            &Instruction::Store(LValue::ArrayElement { kind: Kind::I, .. }),
+                        ) => (map, constant, method_index, case),
+                        _ => continue,
+                    };
+                let map = &unit.metadata.field_refs[&map];
+                if !map.name.starts_with("$SwitchMap$")
+                    || unit.metadata.method_refs[&method_index].name != "ordinal"
+                {
+                    continue;
+                }
+                let class = &unit.metadata.class_refs[&map.class_ref];
+                let constant = &unit.metadata.field_refs[&constant];
+                self.maps
+                    .entry((class.0.clone(), map.name.clone()))
+                    .or_default()
+                    .insert(case, constant.name.clone());
+            }
+        }
+    }
+
+    /// Matches the expression of a switch on an enum, returning the switch map and the enum value.
+    fn match_switch<'e>(&self, expr: &'e Expr) -> Option<(&HashMap<i32, String>, &'e Expr)> {
+        let (array, index) = match *expr {
+            Expr::Assignable(ref assignable) => match **assignable {
+                Assignable::ArrayAccess {
+                    ref array,
+                    ref index,
+                } => (array, index),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let map = match **array {
+            Expr::Assignable(ref assignable) => match **assignable {
+                Assignable::Field {
+                    this: None,
+                    ref class,
+                    ref field,
+                } => self.maps.get(&(class.0.clone(), field.name.clone()))?,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match **index {
+            Expr::Invoke(Some(ref value), ref method, _, ref args)
+                if method.name == "ordinal" && args.is_empty() =>
+            {
+                Some((map, value))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn recover_switches(unit: &mut CompilationUnit<Block>, switch_maps: &SwitchMaps) {
+    for declaration in &mut unit.declarations {
+        let code = match *declaration {
+            Declaration::Method(ref mut method) => method.code.as_mut(),
+            Declaration::Constructor(ref mut constructor) => Some(&mut constructor.code),
+            Declaration::Field(_) | Declaration::EnumConstant(_) => None,
+        };
+        if let Some(code) = code {
+            SwitchVisitor { switch_maps }.visit_block(code);
+        }
+    }
+}
+
+struct SwitchVisitor<'a> {
+    switch_maps: &'a SwitchMaps,
+}
+
+impl<'a> Visitor for SwitchVisitor<'a> {
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block(self, block);
+        recover_string_switches(&mut block.1);
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement(self, stmt);
+        if let Statement::Switch {
+            ref mut expr,
+            ref mut cases,
+            ..
+        } = *stmt
+        {
+            for case in cases.iter_mut() {
+                recover_string_switches(&mut case.stmts);
+            }
+            let replacement = self
+                .switch_maps
+                .match_switch(expr)
+                .and_then(|(map, value)| {
+                    let values = map_case_values(cases, |case| {
+                        map.get(&case).map(|constant| mk_variable(constant.clone()))
+                    })?;
+                    Some((value.clone(), values))
+                });
+            if let Some((value, values)) = replacement {
+                *expr = value;
+                for (case, values) in cases.iter_mut().zip(values) {
+                    case.values = values;
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the numbers of the cases by the expressions returned by `f`.
+/// Fails if `f` fails for any of them.
+fn map_case_values<F>(cases: &[SwitchCase], f: F) -> Option<Vec<Vec<Option<Expr>>>>
+where
+    F: Fn(i32) -> Option<Expr>,
+{
+    cases
+        .iter()
+        .map(|case| {
+            case.values
+                .iter()
+                .map(|value| match *value {
+                    Some(Expr::Literal(Literal::Integer(case))) => f(case).map(Some),
+                    Some(_) => None,
+                    None => Some(None),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn recover_string_switches(stmts: &mut Vec<Statement>) {
+    let mut i = 0;
+    while i < stmts.len() {
+        if let Some((start, end, switch)) = match_string_switch(stmts, i) {
+            stmts.splice(start..end, Some(switch));
+            i = start;
+        }
+        i += 1;
+    }
+}
+
+/// Matches the code of a switch on a string whose switch on the hash code is at `i`.
+/// Returns the range of the statements and the switch statement to replace them with.
+fn match_string_switch(stmts: &[Statement], i: usize) -> Option<(usize, usize, Statement)> {
+    let (tmp, hash_label, hash_cases) = match stmts[i] {
+        Statement::Switch {
+            ref label,
+            expr: Expr::Invoke(Some(ref tmp), ref method, _, ref args),
+            ref cases,
+        } if method.name == "hashCode" && args.is_empty() => (as_variable(tmp)?, label, cases),
+        _ => return None,
+    };
+    let index_init = previous_stmt(stmts, i)?;
+    let index = match match_assignment(&stmts[index_init]) {
+        Some((index, &Expr::Literal(Literal::Integer(-1)))) => index,
+        _ => return None,
+    };
+    // The string is usually stored in a temporary variable first:
+    let (start, string) = match previous_stmt(stmts, index_init) {
+        Some(tmp_init) => match match_assignment(&stmts[tmp_init]) {
+            Some((var, string)) if var == tmp => (tmp_init, string.clone()),
+            _ => (index_init, mk_variable(tmp.clone())),
+        },
+        None => (index_init, mk_variable(tmp.clone())),
+    };
+    let mut strings = HashMap::new();
+    for case in hash_cases {
+        collect_string_cases(&case.stmts, tmp, index, hash_label, &mut strings)?;
+    }
+    let end = next_stmt(stmts, i)?;
+    let (label, cases) = match stmts[end] {
+        Statement::Switch {
+            ref label,
+            ref expr,
+            ref cases,
+        } if as_variable(expr) == Some(index) => (label, cases),
+        _ => return None,
+    };
+    let rest = &stmts[end + 1..];
+    let is_used = |var: &str| {
+        uses_variable(rest, var) || cases.iter().any(|case| uses_variable(&case.stmts, var))
+    };
+    if is_used(index) || (start != index_init && is_used(tmp)) {
+        return None;
+    }
+    let values = map_case_values(cases, |case| {
+        strings
+            .get(&case)
+            .map(|string| Expr::Literal(Literal::String(string.clone())))
+    })?;
+    let cases = cases
+        .iter()
+        .zip(values)
+        .map(|(case, values)| SwitchCase {
+            values,
+            stmts: case.stmts.clone(),
+        })
+        .collect();
+    let switch = Statement::Switch {
+        label: label.clone(),
+        expr: string,
+        cases,
+    };
+    Some((start, end + 1, switch))
+}
+
+/// Collects the numbers of the cases assigned to `index` in a case of the switch on the hash
+/// code, which compares `tmp` with each string of that hash code.
+fn collect_string_cases(
+    stmts: &[Statement],
+    tmp: &str,
+    index: &str,
+    label: &Option<Ident>,
+    strings: &mut HashMap<i32, String>,
+) -> Option<()> {
+    for stmt in stmts {
+        match *stmt {
+            Statement::Nop => {}
+            Statement::Break(ref target) if target == label => {}
+            Statement::If {
+                ref cond,
+                ref then,
+                ref els,
+            } => {
+                let (string, is_equal) = match_equals(cond, tmp)?;
+                let no_stmts = vec![];
+                let els = els.as_ref().map_or(&no_stmts, |els| &els.1);
+                let (matched, other) = if is_equal {
+                    (&then.1, els)
+                } else {
+                    (els, &then.1)
+                };
+                let mut case = None;
+                for stmt in matched {
+                    match *stmt {
+                        Statement::Nop => {}
+                        Statement::Break(ref target) if target == label => {}
+                        _ => match match_assignment(stmt) {
+                            Some((var, &Expr::Literal(Literal::Integer(number))))
+                                if var == index && case.is_none() =>
+                            {
+                                case = Some(number)
+                            }
+                            _ => return None,
+                        },
+                    }
+                }
+                strings.insert(case?, string);
+                collect_string_cases(other, tmp, index, label, strings)?;
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+/// Matches a condition comparing `tmp` with a string, returning the string and whether the
+/// condition holds if they are equal.
+fn match_equals(cond: &Expr, tmp: &str) -> Option<(String, bool)> {
+    let (call, is_equal) = match *cond {
+        Expr::UnaryOp(UnOp::LogNot, ref call) => (&**call, false),
+        ref call => (call, true),
+    };
+    match *call {
+        Expr::Invoke(Some(ref this), ref method, _, ref args)
+            if method.name == "equals"
+                && as_variable(this).is_some_and(|var| var == tmp)
+                && args.len() == 1 =>
+        {
+            match args[0] {
+                Expr::Literal(Literal::String(ref string)) => Some((string.clone(), is_equal)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,122 @@
+//! Rewrites the code that javac generates for `synchronized` statements back into them.
+//!
+//! The object is stored in a temporary variable, so that the same object is released again.
+//! Releasing it is the finally code of the statement:
+//!
+//! ```ignore
+//! tmp = lock;
+//! monitorenter(lock);
+//! try {
+//!     ...
+//! } finally {
+//!     monitorexit(tmp);
+//! }
+//! ```
+//!
+//! The finally clause has been recognized by the structurer already, but `lock` is usually
+//! still a stack variable that was assigned before, because it's used twice.
+
+use decompiler::passes::stack_to_var::is_stack_variable;
+use decompiler::types::*;
+use disassembler::types::*;
+
+pub fn recover_synchronized(unit: &mut CompilationUnit<Block>) {
+    for declaration in &mut unit.declarations {
+        let code = match *declaration {
+            Declaration::Method(ref mut method) => method.code.as_mut(),
+            Declaration::Constructor(ref mut constructor) => Some(&mut constructor.code),
+            Declaration::Field(_) | Declaration::EnumConstant(_) => None,
+        };
+        if let Some(code) = code {
+            SynchronizedVisitor.visit_block(code);
+        }
+    }
+}
+
+struct SynchronizedVisitor;
+
+impl Visitor for SynchronizedVisitor {
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block(self, block);
+        recover_synchronized_stmts(&mut block.1);
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement(self, stmt);
+        if let Statement::Switch { ref mut cases, .. } = *stmt {
+            for case in cases {
+                recover_synchronized_stmts(&mut case.stmts);
+            }
+        }
+    }
+}
+
+fn recover_synchronized_stmts(stmts: &mut Vec<Statement>) {
+    let mut i = 0;
+    while i < stmts.len() {
+        if let Some((start, synchronized)) = match_synchronized(stmts, i) {
+            stmts.splice(start..i + 1, Some(synchronized));
+            i = start;
+        }
+        i += 1;
+    }
+}
+
+/// Matches the try statement of a `synchronized` statement at `i` and the statements before it,
+/// returning the index of the first one and the `synchronized` statement.
+fn match_synchronized(stmts: &[Statement], i: usize) -> Option<(usize, Statement)> {
+    let (block, catches, tmp) = match stmts[i] {
+        Statement::Try {
+            ref resources,
+            ref block,
+            ref catches,
+            ref finally,
+        } if resources.is_empty() => match *finally.1.as_slice() {
+            [Statement::MonitorExit(ref tmp)] => (block, catches, as_variable(tmp)?),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let enter = previous_stmt(stmts, i)?;
+    let lock = match stmts[enter] {
+        Statement::MonitorEnter(ref lock) => lock,
+        _ => return None,
+    };
+    let tmp_init = previous_stmt(stmts, enter)?;
+    match match_assignment(&stmts[tmp_init]) {
+        Some((var, value)) if var == tmp && value == lock => {}
+        _ => return None,
+    }
+    // The temporary variable is only used to release the object:
+    if count_uses(&stmts[i..i + 1], tmp) != 1 {
+        return None;
+    }
+    // Take the object from the stack variable if it isn't needed anymore:
+    let mut start = tmp_init;
+    let mut lock = lock.clone();
+    if let Some(lock_init) = previous_stmt(stmts, tmp_init) {
+        if let Some((var, value)) = match_assignment(&stmts[lock_init]) {
+            let is_lock = as_variable(&lock).is_some_and(|lock| lock == var);
+            let is_needed = !is_dead(&block.1, var)
+                || catches.iter().any(|catch| !is_dead(&catch.block.1, var))
+                || !is_dead(&stmts[i + 1..], var);
+            if is_lock && is_stack_variable(var) && !is_needed {
+                lock = value.clone();
+                start = lock_init;
+            }
+        }
+    }
+    let block = if catches.is_empty() {
+        block.clone()
+    } else {
+        // A try statement that is the whole body has been merged with the finally clause:
+        let stmt = Statement::Try {
+            resources: vec![],
+            block: block.clone(),
+            catches: catches.clone(),
+            finally: Block::default(),
+        };
+        Block(vec![], vec![stmt])
+    };
+    Some((start, Statement::Synchronized(lock, block)))
+}
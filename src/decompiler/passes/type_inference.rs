@@ -0,0 +1,1007 @@
+//! Infers the types of the local variables and of the stack variables that are left.
+//!
+//! The JVM only distinguishes the kinds of values: `boolean`, `byte`, `char`, `short` and `int`
+//! values are all ints. A variable has the common type of the values assigned to it, which are
+//! typed by the method descriptors, the field types and the operators. Integer literals fit any
+//! of the int types, so a variable that is only assigned literals gets its type from its uses:
+//! it's a `boolean` if it's only assigned `0` and `1` and used like a boolean, i.e. where one is
+//! expected or compared with `0`.
+//!
+//! Once the types are known, the literals are rewritten where a `boolean` or a `char` is
+//! expected, and comparisons of booleans with `0` become `b` and `!b`. This runs before the
+//! constructor calls are merged into the objects they initialize, so that their parameter types
+//! are still known.
+//!
+//! The types are stored in the methods, so that the variables can be declared after structuring
+//! their code, see `variables`.
+
+use decompiler::cfg::*;
+use decompiler::passes::conditions::negate;
+use decompiler::passes::stack_to_var::caught_exception;
+use decompiler::passes::variables::original_name;
+use decompiler::types::*;
+use disassembler::types::*;
+use std::collections::{HashMap, HashSet};
+
+pub fn infer_types(unit: &mut CompilationUnit<Cfg<Statement, Expr>>) {
+    let this = Type::Reference(unit.name.clone());
+    for declaration in &mut unit.declarations {
+        if let Declaration::Method(ref mut method) = *declaration {
+            if let Some(ref mut cfg) = method.code {
+                let mut inference = Inference {
+                    this: &this,
+                    return_type: &method.signature.return_type,
+                    metadata: &unit.metadata,
+                    types: HashMap::new(),
+                };
+                let locals = inference.infer(cfg, &method.signature.parameters, &method.variables);
+                inference.rewrite(cfg);
+                method.locals = locals;
+            }
+        }
+    }
+}
+
+/// How a variable is used by the expression containing it.
+#[derive(Clone, Debug, PartialEq)]
+enum Usage {
+    /// Where a value of the type is expected, e.g. as an argument.
+    Expected(Type),
+    /// As a number, e.g. as an operand of an arithmetic operator.
+    Numeric,
+    /// In a way that fits any type, e.g. compared with `0`.
+    Any,
+}
+
+struct Inference<'a> {
+    this: &'a Type,
+    return_type: &'a Type,
+    metadata: &'a Metadata,
+    types: HashMap<Ident, Type>,
+}
+
+impl<'a> Inference<'a> {
+    /// Infers the types of the variables of the method, returning those of the local variables
+    /// in the order in which they are assigned first.
+    fn infer(
+        &mut self,
+        cfg: &Cfg<Statement, Expr>,
+        parameters: &[(Ident, Type)],
+        variables: &[LocalVariableScope],
+    ) -> Vec<(Ident, Type)> {
+        let mut definitions = Definitions {
+            order: vec![],
+            values: HashMap::new(),
+        };
+        for block in cfg.graph.node_indices() {
+            for stmt in &cfg.graph[block].stmts {
+                definitions.visit_statement(&mut stmt.clone());
+            }
+        }
+        // The types that are known already: those of the parameters, the caught exceptions and
+        // the variables declared in the local variable table, unless they are ambiguous.
+        let mut known = HashMap::new();
+        for block in cfg.graph.node_indices() {
+            let exceptions = cfg
+                .graph
+                .edges_directed(block, Direction::Incoming)
+                .filter_map(|edge| match *edge.weight() {
+                    Edge::Exception(Some(class_ref)) => Some(Type::Reference(
+                        self.metadata.class_refs[&class_ref].0.clone(),
+                    )),
+                    Edge::Exception(None) => Some(throwable()),
+                    _ => None,
+                })
+                .fold(None, |typ, exception| {
+                    Some(typ.map_or(exception.clone(), |typ| join(typ, exception)))
+                });
+            if let Some(typ) = exceptions {
+                known.insert(caught_exception(block), typ);
+            }
+        }
+        let mut ambiguous = HashSet::new();
+        for variable in variables {
+            if known
+                .get(&variable.name)
+                .is_some_and(|typ| *typ != variable.typ)
+            {
+                ambiguous.insert(variable.name.clone());
+            }
+            known.insert(variable.name.clone(), variable.typ.clone());
+        }
+        for name in ambiguous {
+            known.remove(&name);
+        }
+        for parameter in parameters {
+            known.insert(parameter.0.clone(), parameter.1.clone());
+        }
+        // The variables that were split from another one have its type:
+        for var in &definitions.order {
+            if let Some(typ) = known.get(original_name(var)).cloned() {
+                known.entry(var.clone()).or_insert(typ);
+            }
+        }
+        self.types = known.clone();
+        let undecided = definitions
+            .order
+            .iter()
+            .filter(|var| !known.contains_key(*var))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Each round types the variables by their values, then decides the types of those that
+        // are only assigned literals, which may type other variables in turn:
+        for _ in 0..=undecided.len() {
+            self.type_by_values(&undecided, &definitions);
+            let usages = self.collect_usages(cfg);
+            let no_usages = vec![];
+            let decidable = undecided
+                .iter()
+                .filter(|var| !self.types.contains_key(*var))
+                .filter(|var| {
+                    definitions.values[*var]
+                        .iter()
+                        .all(|value| is_constant(value) || self.type_of(value).is_some())
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            // Otherwise the variables only depend on each other:
+            let decidable = if decidable.is_empty() {
+                undecided
+                    .iter()
+                    .filter(|var| !self.types.contains_key(*var))
+                    .cloned()
+                    .collect()
+            } else {
+                decidable
+            };
+            if decidable.is_empty() {
+                break;
+            }
+            for var in decidable {
+                let usages = usages.get(&var).unwrap_or(&no_usages);
+                let typ = type_by_usages(&definitions.values[&var], usages);
+                self.types.insert(var, typ);
+            }
+        }
+        definitions
+            .order
+            .iter()
+            .filter(|var| !parameters.iter().any(|parameter| parameter.0 == **var))
+            .filter(|var| !var.starts_with("exception_"))
+            .map(|var| {
+                (
+                    var.clone(),
+                    self.types.get(var).cloned().unwrap_or_else(object),
+                )
+            })
+            .collect()
+    }
+
+    /// Types the variables by the common type of the values assigned to them, ignoring the
+    /// literals, until the types don't change anymore.
+    fn type_by_values(&mut self, vars: &[Ident], definitions: &Definitions) {
+        for _ in 0..=vars.len() {
+            let mut changed = false;
+            for var in vars {
+                let values = &definitions.values[var];
+                let typ = values
+                    .iter()
+                    .filter(|value| !is_constant(value))
+                    .filter_map(|value| self.type_of(value))
+                    .fold(None, |typ, value| {
+                        Some(typ.map_or(value.clone(), |typ| join(typ, value)))
+                    });
+                let typ = match typ {
+                    // A boolean can only be assigned `0` and `1`:
+                    Some(Type::Boolean)
+                        if !values
+                            .iter()
+                            .filter(|value| is_constant(value))
+                            .all(is_boolean_constant) =>
+                    {
+                        Some(Type::Int)
+                    }
+                    typ => typ,
+                };
+                if let Some(typ) = typ {
+                    if self.types.get(var) != Some(&typ) {
+                        self.types.insert(var.clone(), typ);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// The type of the expression, as far as it's known.
+    fn type_of(&self, expr: &Expr) -> Option<Type> {
+        match *expr {
+            Expr::Literal(ref literal) => match *literal {
+                Literal::NullReference => None,
+                Literal::Boolean(_) => Some(Type::Boolean),
+                Literal::Byte(_) => Some(Type::Byte),
+                Literal::Short(_) => Some(Type::Short),
+                Literal::Char(_) => Some(Type::Char),
+                Literal::Integer(_) => Some(Type::Int),
+                Literal::Long(_) => Some(Type::Long),
+                Literal::Float(_) => Some(Type::Float),
+                Literal::Double(_) => Some(Type::Double),
+                Literal::String(_) => Some(string()),
+                Literal::Class(_) => Some(Type::Reference("java.lang.Class".to_owned())),
+            },
+            Expr::Assignable(ref assignable) => self.type_of_assignable(assignable),
+            Expr::UnaryOp(UnOp::LogNot, _) => Some(Type::Boolean),
+            Expr::UnaryOp(_, ref operand) => promote(self.type_of(operand), self.type_of(operand)),
+            Expr::BinaryOp(op, ref left, ref right) => match op {
+                BinOp::Cmp(_) | BinOp::FloatCmp(_) | BinOp::LogAnd | BinOp::LogOr => {
+                    Some(Type::Boolean)
+                }
+                BinOp::Shl | BinOp::Shr | BinOp::Ushr => {
+                    promote(self.type_of(left), Some(Type::Int))
+                }
+                BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => {
+                    let (left, right) = (self.type_of(left), self.type_of(right));
+                    if left == Some(Type::Boolean) || right == Some(Type::Boolean) {
+                        Some(Type::Boolean)
+                    } else {
+                        promote(left, right)
+                    }
+                }
+                BinOp::Add => {
+                    let (left, right) = (self.type_of(left), self.type_of(right));
+                    if left == Some(string()) || right == Some(string()) {
+                        Some(string())
+                    } else {
+                        promote(left, right)
+                    }
+                }
+                BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => {
+                    promote(self.type_of(left), self.type_of(right))
+                }
+            },
+            Expr::IfThenElse {
+                ref then, ref els, ..
+            } => {
+                // That's how javac computes the value of a boolean expression:
+                if is_boolean_constant(then) && is_boolean_constant(els) {
+                    return Some(Type::Boolean);
+                }
+                // An integer literal takes the type of the other value, e.g. `c ? x : 98` is a
+                // char if `x` is:
+                let (then, els) = if is_constant(then) && is_constant(els) {
+                    (self.type_of(then), self.type_of(els))
+                } else {
+                    (self.operand_type(then), self.operand_type(els))
+                };
+                match (then, els) {
+                    (Some(then), Some(els)) => Some(join(then, els)),
+                    (then, els) => then.or(els),
+                }
+            }
+            Expr::Invoke(_, ref method, ..) => Some(method.signature.return_type.clone()),
+            Expr::Assign { ref to, .. } => self.type_of_assignable(to),
+            Expr::New { ref class, .. } => Some(class.clone()),
+            Expr::NewArray { ref typ, .. } | Expr::ArrayInit { ref typ, .. } => Some(typ.clone()),
+            Expr::Cast(ref typ, _) => Some(typ.clone()),
+            Expr::InstanceOf(..) => Some(Type::Boolean),
+            Expr::ArrayLength(_) => Some(Type::Int),
+            Expr::This => Some(self.this.clone()),
+            Expr::QualifiedThis(ref class) => Some(Type::Reference(class.0.clone())),
+            Expr::Super => None,
+        }
+    }
+
+    fn type_of_assignable(&self, assignable: &Assignable) -> Option<Type> {
+        match *assignable {
+            Assignable::Variable(ref var, _) => self.types.get(var).cloned(),
+            Assignable::Field { ref field, .. } => Some(field.typ.clone()),
+            Assignable::ArrayAccess { ref array, .. } => match self.type_of(array) {
+                Some(Type::Array(element)) => Some(*element),
+                _ => None,
+            },
+        }
+    }
+
+    /// Collects how the variables are used by the code.
+    fn collect_usages(&self, cfg: &Cfg<Statement, Expr>) -> HashMap<Ident, Vec<Usage>> {
+        let mut usages = HashMap::new();
+        for block in cfg.graph.node_indices() {
+            for stmt in &cfg.graph[block].stmts {
+                match *stmt {
+                    Statement::Expr(ref expr)
+                    | Statement::Throw(ref expr)
+                    | Statement::MonitorEnter(ref expr)
+                    | Statement::MonitorExit(ref expr) => {
+                        self.collect_expr_usages(expr, Usage::Any, &mut usages)
+                    }
+                    Statement::Return(Some(ref expr)) => {
+                        let usage = Usage::Expected(self.return_type.clone());
+                        self.collect_expr_usages(expr, usage, &mut usages)
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(ref cond) = cfg.graph[block].terminator {
+                let usage = if is_switch(cfg, block) {
+                    Usage::Expected(Type::Int)
+                } else {
+                    Usage::Expected(Type::Boolean)
+                };
+                self.collect_expr_usages(cond, usage, &mut usages);
+            }
+        }
+        usages
+    }
+
+    /// Collects how the variables are used by the expression, which is used as `usage`.
+    fn collect_expr_usages(
+        &self,
+        expr: &Expr,
+        usage: Usage,
+        usages: &mut HashMap<Ident, Vec<Usage>>,
+    ) {
+        let boolean = || Usage::Expected(Type::Boolean);
+        match *expr {
+            Expr::Literal(_) | Expr::This | Expr::QualifiedThis(_) | Expr::Super => {}
+            Expr::Assignable(ref assignable) => {
+                if let Assignable::Variable(ref var, _) = **assignable {
+                    usages.entry(var.clone()).or_default().push(usage);
+                }
+                self.collect_assignable_usages(assignable, usages);
+            }
+            Expr::UnaryOp(UnOp::LogNot, ref operand) => {
+                self.collect_expr_usages(operand, boolean(), usages)
+            }
+            Expr::UnaryOp(_, ref operand) => {
+                self.collect_expr_usages(operand, Usage::Numeric, usages)
+            }
+            Expr::BinaryOp(op, ref left, ref right) => {
+                let usage = match op {
+                    BinOp::LogAnd | BinOp::LogOr => boolean(),
+                    BinOp::Cmp(Ordering::EQ)
+                    | BinOp::Cmp(Ordering::NE)
+                    | BinOp::BitAnd
+                    | BinOp::BitOr
+                    | BinOp::BitXor => {
+                        let types = [self.operand_type(left), self.operand_type(right)];
+                        if types.contains(&Some(Type::Boolean)) {
+                            boolean()
+                        } else if types.iter().flatten().any(is_numeric) {
+                            Usage::Numeric
+                        } else {
+                            Usage::Any
+                        }
+                    }
+                    BinOp::Add
+                        if self.type_of(left) == Some(string())
+                            || self.type_of(right) == Some(string()) =>
+                    {
+                        Usage::Any
+                    }
+                    _ => Usage::Numeric,
+                };
+                self.collect_expr_usages(left, usage.clone(), usages);
+                self.collect_expr_usages(right, usage, usages);
+            }
+            Expr::IfThenElse {
+                ref cond,
+                ref then,
+                ref els,
+            } => {
+                self.collect_expr_usages(cond, boolean(), usages);
+                self.collect_expr_usages(then, usage.clone(), usages);
+                self.collect_expr_usages(els, usage, usages);
+            }
+            Expr::Invoke(ref this, ref method, _, ref args) => {
+                if let Some(ref this) = *this {
+                    self.collect_expr_usages(this, Usage::Any, usages);
+                }
+                for (arg, parameter) in args.iter().zip(&method.signature.parameters) {
+                    let usage = Usage::Expected(parameter.1.clone());
+                    self.collect_expr_usages(arg, usage, usages);
+                }
+            }
+            Expr::Assign {
+                ref to, ref from, ..
+            } => {
+                self.collect_assignable_usages(to, usages);
+                let usage = self
+                    .type_of_assignable(to)
+                    .map_or(Usage::Any, Usage::Expected);
+                self.collect_expr_usages(from, usage, usages);
+            }
+            Expr::New { ref args, .. } => {
+                for arg in args {
+                    self.collect_expr_usages(arg, Usage::Any, usages);
+                }
+            }
+            Expr::NewArray { ref lengths, .. } => {
+                for length in lengths {
+                    self.collect_expr_usages(length, Usage::Expected(Type::Int), usages);
+                }
+            }
+            Expr::ArrayInit {
+                ref typ,
+                ref elements,
+            } => {
+                for element in elements {
+                    let usage = element_type(typ).map_or(Usage::Any, Usage::Expected);
+                    self.collect_expr_usages(element, usage, usages);
+                }
+            }
+            Expr::Cast(_, ref expr)
+            | Expr::InstanceOf(ref expr, _)
+            | Expr::ArrayLength(ref expr) => self.collect_expr_usages(expr, Usage::Any, usages),
+        }
+    }
+
+    fn collect_assignable_usages(
+        &self,
+        assignable: &Assignable,
+        usages: &mut HashMap<Ident, Vec<Usage>>,
+    ) {
+        match *assignable {
+            Assignable::Variable(..) => {}
+            Assignable::Field { ref this, .. } => {
+                if let Some(ref this) = *this {
+                    self.collect_expr_usages(this, Usage::Any, usages);
+                }
+            }
+            Assignable::ArrayAccess {
+                ref array,
+                ref index,
+            } => {
+                self.collect_expr_usages(array, Usage::Any, usages);
+                self.collect_expr_usages(index, Usage::Expected(Type::Int), usages);
+            }
+        }
+    }
+
+    /// The type of an operand of `==` or a bitwise operator, where integer literals fit both
+    /// booleans and numbers.
+    fn operand_type(&self, expr: &Expr) -> Option<Type> {
+        if is_constant(expr) {
+            None
+        } else {
+            self.type_of(expr)
+        }
+    }
+
+    /// Rewrites the literals and comparisons of the code according to the inferred types.
+    fn rewrite(&self, cfg: &mut Cfg<Statement, Expr>) {
+        for block in cfg.graph.node_indices() {
+            for stmt in &mut cfg.graph[block].stmts {
+                match *stmt {
+                    Statement::Expr(ref mut expr)
+                    | Statement::Throw(ref mut expr)
+                    | Statement::MonitorEnter(ref mut expr)
+                    | Statement::MonitorExit(ref mut expr) => self.rewrite_expr(expr, None),
+                    Statement::Return(Some(ref mut expr)) => {
+                        self.rewrite_expr(expr, Some(self.return_type))
+                    }
+                    _ => {}
+                }
+            }
+            let expected = if is_switch(cfg, block) {
+                Type::Int
+            } else {
+                Type::Boolean
+            };
+            if let Some(ref mut cond) = cfg.graph[block].terminator {
+                self.rewrite_expr(cond, Some(&expected));
+            }
+        }
+    }
+
+    /// Rewrites the expression, where a value of type `expected` is expected.
+    fn rewrite_expr(&self, expr: &mut Expr, expected: Option<&Type>) {
+        let replacement = match *expr {
+            Expr::Literal(ref mut literal) => {
+                if let Literal::Integer(value) = *literal {
+                    match expected {
+                        Some(&Type::Boolean) if value == 0 || value == 1 => {
+                            *literal = Literal::Boolean(value == 1);
+                        }
+                        Some(&Type::Char) if (0..=0xffff).contains(&value) => {
+                            *literal = Literal::Char(value as u16);
+                        }
+                        _ => {}
+                    }
+                }
+                None
+            }
+            Expr::Assignable(ref mut assignable) => {
+                self.rewrite_assignable(assignable);
+                None
+            }
+            Expr::UnaryOp(op, ref mut operand) => {
+                let expected = if op == UnOp::LogNot {
+                    Some(&Type::Boolean)
+                } else {
+                    None
+                };
+                self.rewrite_expr(operand, expected);
+                None
+            }
+            Expr::BinaryOp(op, ref mut left, ref mut right) => {
+                let types = [self.operand_type(left), self.operand_type(right)];
+                let expected = match op {
+                    BinOp::LogAnd | BinOp::LogOr => Some(Type::Boolean),
+                    BinOp::Cmp(Ordering::EQ)
+                    | BinOp::Cmp(Ordering::NE)
+                    | BinOp::BitAnd
+                    | BinOp::BitOr
+                    | BinOp::BitXor
+                        if types.contains(&Some(Type::Boolean)) =>
+                    {
+                        Some(Type::Boolean)
+                    }
+                    BinOp::Cmp(_) if types.contains(&Some(Type::Char)) => Some(Type::Char),
+                    _ => None,
+                };
+                self.rewrite_expr(left, expected.as_ref());
+                self.rewrite_expr(right, expected.as_ref());
+                match op {
+                    BinOp::Cmp(ordering) if expected == Some(Type::Boolean) => {
+                        compare_boolean(ordering, left, right)
+                    }
+                    _ => None,
+                }
+            }
+            Expr::IfThenElse {
+                ref mut cond,
+                ref mut then,
+                ref mut els,
+            } => {
+                self.rewrite_expr(cond, Some(&Type::Boolean));
+                // The value is a boolean if the expression computes a boolean, and a char if one
+                // of the values is a char:
+                let types = [self.operand_type(then), self.operand_type(els)];
+                let expected = expected.cloned().or_else(|| {
+                    if is_boolean_constant(then) && is_boolean_constant(els) {
+                        Some(Type::Boolean)
+                    } else if types.contains(&Some(Type::Char)) {
+                        Some(Type::Char)
+                    } else {
+                        None
+                    }
+                });
+                self.rewrite_expr(then, expected.as_ref());
+                self.rewrite_expr(els, expected.as_ref());
+                simplify_choice(cond, then, els).or_else(|| match **cond {
+                    // javac jumps to the second value if the condition holds:
+                    Expr::UnaryOp(UnOp::LogNot, ref cond) => Some(Expr::IfThenElse {
+                        cond: cond.clone(),
+                        then: els.clone(),
+                        els: then.clone(),
+                    }),
+                    _ => None,
+                })
+            }
+            Expr::Invoke(ref mut this, ref method, _, ref mut args) => {
+                if let Some(ref mut this) = *this {
+                    self.rewrite_expr(this, None);
+                }
+                for (arg, parameter) in args.iter_mut().zip(&method.signature.parameters) {
+                    self.rewrite_expr(arg, Some(&parameter.1));
+                }
+                None
+            }
+            Expr::Assign {
+                ref mut to,
+                ref mut from,
+                ..
+            } => {
+                self.rewrite_assignable(to);
+                let expected = self.type_of_assignable(to);
+                self.rewrite_expr(from, expected.as_ref());
+                None
+            }
+            Expr::New { ref mut args, .. } => {
+                for arg in args {
+                    self.rewrite_expr(arg, None);
+                }
+                None
+            }
+            Expr::NewArray {
+                ref mut lengths, ..
+            } => {
+                for length in lengths {
+                    self.rewrite_expr(length, Some(&Type::Int));
+                }
+                None
+            }
+            Expr::ArrayInit {
+                ref typ,
+                ref mut elements,
+            } => {
+                let expected = element_type(typ);
+                for element in elements {
+                    self.rewrite_expr(element, expected.as_ref());
+                }
+                None
+            }
+            Expr::Cast(_, ref mut expr)
+            | Expr::InstanceOf(ref mut expr, _)
+            | Expr::ArrayLength(ref mut expr) => {
+                self.rewrite_expr(expr, None);
+                None
+            }
+            Expr::This | Expr::QualifiedThis(_) | Expr::Super => None,
+        };
+        if let Some(replacement) = replacement {
+            *expr = replacement;
+        }
+    }
+
+    fn rewrite_assignable(&self, assignable: &mut Assignable) {
+        match *assignable {
+            Assignable::Variable(..) => {}
+            Assignable::Field { ref mut this, .. } => {
+                if let Some(ref mut this) = *this {
+                    self.rewrite_expr(this, None);
+                }
+            }
+            Assignable::ArrayAccess {
+                ref mut array,
+                ref mut index,
+            } => {
+                self.rewrite_expr(array, None);
+                self.rewrite_expr(index, Some(&Type::Int));
+            }
+        }
+    }
+}
+
+/// The values assigned to each variable.
+struct Definitions {
+    /// The variables in the order in which they are assigned first.
+    order: Vec<Ident>,
+    values: HashMap<Ident, Vec<Expr>>,
+}
+
+impl Visitor for Definitions {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if let Expr::Assign {
+            ref to,
+            op: None,
+            ref from,
+        } = *expr
+        {
+            if let Assignable::Variable(ref var, _) = **to {
+                if !self.values.contains_key(var) {
+                    self.order.push(var.clone());
+                }
+                let values = self.values.entry(var.clone()).or_default();
+                values.push((**from).clone());
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Decides the type of a variable that is only assigned literals, and values of unknown types,
+/// by its usages.
+fn type_by_usages(values: &[Expr], usages: &[Usage]) -> Type {
+    if values.iter().all(|value| !is_constant(value)) {
+        // Usually a reference that is only assigned `null`:
+        return usages
+            .iter()
+            .filter_map(|usage| match *usage {
+                Usage::Expected(ref typ) => Some(typ.clone()),
+                Usage::Numeric => Some(Type::Int),
+                Usage::Any => None,
+            })
+            .next()
+            .unwrap_or_else(object);
+    }
+    let mut constants = values.iter().filter(|value| is_constant(value));
+    let is_boolean = constants.all(is_boolean_constant)
+        && (!usages.is_empty() || values.iter().any(|value| !is_literal(value)))
+        && usages
+            .iter()
+            .all(|usage| *usage == Usage::Any || *usage == Usage::Expected(Type::Boolean));
+    if is_boolean {
+        return Type::Boolean;
+    }
+    // A narrower type if all usages expect it, e.g. a `char` that is passed to methods:
+    let mut expected = usages.iter().filter(|usage| **usage != Usage::Any);
+    match expected.next() {
+        Some(&Usage::Expected(ref typ @ Type::Byte))
+        | Some(&Usage::Expected(ref typ @ Type::Short))
+        | Some(&Usage::Expected(ref typ @ Type::Char))
+            if expected.all(|usage| *usage == Usage::Expected(typ.clone())) =>
+        {
+            typ.clone()
+        }
+        _ => Type::Int,
+    }
+}
+
+/// Checks whether the expression is an integer literal, or chooses between such literals.
+fn is_constant(expr: &Expr) -> bool {
+    match *expr {
+        Expr::Literal(Literal::Integer(_)) => true,
+        Expr::IfThenElse {
+            ref then, ref els, ..
+        } => is_constant(then) && is_constant(els),
+        _ => false,
+    }
+}
+
+/// Checks whether the expression is a literal that is a boolean or `0` or `1`, or chooses
+/// between such literals.
+fn is_boolean_constant(expr: &Expr) -> bool {
+    match *expr {
+        Expr::Literal(Literal::Integer(0))
+        | Expr::Literal(Literal::Integer(1))
+        | Expr::Literal(Literal::Boolean(_)) => true,
+        Expr::IfThenElse {
+            ref then, ref els, ..
+        } => is_boolean_constant(then) && is_boolean_constant(els),
+        _ => false,
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(*expr, Expr::Literal(_))
+}
+
+/// Simplifies the comparison of two booleans if one of them is a literal, e.g. `b == false` to
+/// `!b`.
+fn compare_boolean(ordering: Ordering, left: &Expr, right: &Expr) -> Option<Expr> {
+    let (value, literal) = match (left, right) {
+        (value, &Expr::Literal(Literal::Boolean(literal)))
+        | (&Expr::Literal(Literal::Boolean(literal)), value) => (value, literal),
+        _ => return None,
+    };
+    match ordering {
+        Ordering::EQ if literal => Some(value.clone()),
+        Ordering::NE if !literal => Some(value.clone()),
+        Ordering::EQ | Ordering::NE => Some(negate(value.clone())),
+        _ => None,
+    }
+}
+
+/// Simplifies `c ? x : y` if `x` or `y` is a boolean literal, e.g. `c ? true : false` to `c`.
+fn simplify_choice(cond: &Expr, then: &Expr, els: &Expr) -> Option<Expr> {
+    let or = |left, right| Expr::BinaryOp(BinOp::LogOr, Box::new(left), Box::new(right));
+    let and = |left, right| Expr::BinaryOp(BinOp::LogAnd, Box::new(left), Box::new(right));
+    let cond = cond.clone();
+    match (then, els) {
+        (&Expr::Literal(Literal::Boolean(true)), &Expr::Literal(Literal::Boolean(false))) => {
+            Some(cond)
+        }
+        (&Expr::Literal(Literal::Boolean(false)), &Expr::Literal(Literal::Boolean(true))) => {
+            Some(negate(cond))
+        }
+        (&Expr::Literal(Literal::Boolean(true)), els) => Some(or(cond, els.clone())),
+        (&Expr::Literal(Literal::Boolean(false)), els) => Some(and(negate(cond), els.clone())),
+        (then, &Expr::Literal(Literal::Boolean(true))) => Some(or(negate(cond), then.clone())),
+        (then, &Expr::Literal(Literal::Boolean(false))) => Some(and(cond, then.clone())),
+        _ => None,
+    }
+}
+
+/// The common type of two values, as far as it's known without the class hierarchy.
+fn join(a: Type, b: Type) -> Type {
+    let is_int = |typ: &Type| {
+        matches!(
+            *typ,
+            Type::Byte | Type::Short | Type::Char | Type::Int | Type::Boolean
+        )
+    };
+    let is_reference = |typ: &Type| matches!(*typ, Type::Array(_) | Type::Reference(_));
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Type::Byte, Type::Short) | (Type::Short, Type::Byte) => Type::Short,
+        (ref a, ref b) if is_int(a) && is_int(b) => Type::Int,
+        (ref a, ref b) if is_reference(a) && is_reference(b) => object(),
+        (a, _) => a,
+    }
+}
+
+/// The type of the result of an arithmetic operation (binary numeric promotion).
+fn promote(left: Option<Type>, right: Option<Type>) -> Option<Type> {
+    for wide in &[Type::Double, Type::Float, Type::Long] {
+        if left.as_ref() == Some(wide) || right.as_ref() == Some(wide) {
+            return Some(wide.clone());
+        }
+    }
+    left.and(right).map(|_| Type::Int)
+}
+
+fn is_numeric(typ: &Type) -> bool {
+    matches!(
+        *typ,
+        Type::Byte | Type::Short | Type::Char | Type::Int | Type::Long | Type::Float | Type::Double
+    )
+}
+
+fn element_type(typ: &Type) -> Option<Type> {
+    match *typ {
+        Type::Array(ref element) => Some((**element).clone()),
+        _ => None,
+    }
+}
+
+/// Checks whether the block ends with a switch, otherwise its terminator is a condition.
+fn is_switch(cfg: &Cfg<Statement, Expr>, block: Label) -> bool {
+    cfg.graph
+        .edges_directed(block, Direction::Outgoing)
+        .any(|edge| edge.weight().is_case())
+}
+
+fn object() -> Type {
+    Type::Reference("java.lang.Object".to_owned())
+}
+
+fn string() -> Type {
+    Type::Reference("java.lang.String".to_owned())
+}
+
+fn throwable() -> Type {
+    Type::Reference("java.lang.Throwable".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompiler::fixtures::*;
+    use pretty::*;
+
+    /// Infers the types in a method of a single block, returning the types of its local variables
+    /// and the rewritten block, with the statements and the condition on separate lines.
+    fn infer(
+        stmts: Vec<Statement>,
+        cond: Option<Expr>,
+        return_type: Type,
+    ) -> (Vec<(Ident, Type)>, String) {
+        let mut cfg = build(vec![(stmts, None)], &[(0, 2, Edge::Branch(true))]);
+        cfg.graph[Label::new(0)].terminator = cond;
+        let metadata = Metadata::new();
+        let mut inference = Inference {
+            this: &object(),
+            return_type: &return_type,
+            metadata: &metadata,
+            types: HashMap::new(),
+        };
+        let locals = inference.infer(&cfg, &[], &[]);
+        inference.rewrite(&mut cfg);
+        let block = &cfg.graph[Label::new(0)];
+        let mut code = to_string(&block.stmts);
+        if let Some(ref cond) = block.terminator {
+            code += "\n";
+            code += &cond.pretty_with(&()).render_string(100);
+        }
+        (locals, code)
+    }
+
+    #[test]
+    fn boolean_returned() {
+        let (locals, code) = infer(
+            vec![
+                assign("local_1", int(1)),
+                Statement::Return(Some(var("local_1"))),
+            ],
+            None,
+            Type::Boolean,
+        );
+        assert_eq!(locals, vec![("local_1".to_owned(), Type::Boolean)]);
+        assert_eq!(code, "local_1 = true;\nreturn local_1;");
+    }
+
+    #[test]
+    fn boolean_compared_with_zero() {
+        let cond = Expr::BinaryOp(
+            BinOp::Cmp(Ordering::EQ),
+            Box::new(var("local_1")),
+            Box::new(int(0)),
+        );
+        let (locals, code) = infer(
+            vec![assign("local_1", int(0)), assign("local_1", int(1))],
+            Some(cond),
+            Type::Void,
+        );
+        assert_eq!(locals, vec![("local_1".to_owned(), Type::Boolean)]);
+        assert_eq!(code, "local_1 = false;\nlocal_1 = true;\n!local_1");
+    }
+
+    #[test]
+    fn zero_or_one_used_as_number() {
+        let cond = Expr::BinaryOp(
+            BinOp::Cmp(Ordering::EQ),
+            Box::new(var("local_1")),
+            Box::new(int(0)),
+        );
+        let sum = Expr::BinaryOp(BinOp::Add, Box::new(var("local_1")), Box::new(int(2)));
+        let (locals, code) = infer(
+            vec![
+                assign("local_1", int(0)),
+                assign("local_1", int(1)),
+                assign("local_2", sum),
+            ],
+            Some(cond),
+            Type::Void,
+        );
+        let expected = vec![
+            ("local_1".to_owned(), Type::Int),
+            ("local_2".to_owned(), Type::Int),
+        ];
+        assert_eq!(locals, expected);
+        assert_eq!(
+            code,
+            "local_1 = 0;\nlocal_1 = 1;\nlocal_2 = local_1 + 2;\nlocal_1 == 0"
+        );
+    }
+
+    #[test]
+    fn char_literals() {
+        let read = invoke("read", &[], Type::Char, vec![]);
+        let write = invoke("write", &[Type::Char], Type::Void, vec![var("local_1")]);
+        let (locals, code) = infer(
+            vec![
+                assign("local_1", read),
+                assign("local_1", int(97)),
+                stmt_expr(write),
+                stmt_expr(invoke("write", &[Type::Char], Type::Void, vec![int(10)])),
+            ],
+            None,
+            Type::Void,
+        );
+        assert_eq!(locals, vec![("local_1".to_owned(), Type::Char)]);
+        let expected = [
+            "local_1 = T.read();",
+            "local_1 = 'a';",
+            "T.write(local_1);",
+            "T.write('\\n');",
+        ];
+        assert_eq!(code, expected.join("\n"));
+    }
+
+    #[test]
+    fn char_literals_in_both_choices() {
+        let read = invoke("read", &[], Type::Char, vec![]);
+        let is_a = Expr::BinaryOp(
+            BinOp::Cmp(Ordering::NE),
+            Box::new(var("local_1")),
+            Box::new(int(97)),
+        );
+        let choose = |then, els| Expr::IfThenElse {
+            cond: Box::new(is_a.clone()),
+            then: Box::new(then),
+            els: Box::new(els),
+        };
+        let (locals, code) = infer(
+            vec![
+                assign("local_1", read),
+                assign("local_2", choose(var("local_1"), int(98))),
+                assign("local_3", choose(int(98), var("local_1"))),
+                stmt_expr(invoke(
+                    "write",
+                    &[Type::Int, Type::Int],
+                    Type::Void,
+                    vec![var("local_2"), var("local_3")],
+                )),
+            ],
+            None,
+            Type::Void,
+        );
+        let expected = vec![
+            ("local_1".to_owned(), Type::Char),
+            ("local_2".to_owned(), Type::Char),
+            ("local_3".to_owned(), Type::Char),
+        ];
+        assert_eq!(locals, expected);
+        let expected = [
+            "local_1 = T.read();",
+            "local_2 = local_1 != 'a' ? local_1 : 'b';",
+            "local_3 = local_1 != 'a' ? 'b' : local_1;",
+            "T.write(local_2, local_3);",
+        ];
+        assert_eq!(code, expected.join("\n"));
+    }
+}
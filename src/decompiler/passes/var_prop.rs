@@ -1,6 +1,14 @@
-//! Inlines a variable assignment if it's only used once
+//! Inlines a variable assignment if it's only used once, unless the variable has a name in the
+//! source code
+//!
+//! A value that reads fields or array elements, calls methods or creates objects is only inlined
+//! if that doesn't change the order of these effects with those of the code in between, e.g.
+//! `a = o.f; o.f = 5; return a;` stays as it is. An assignment that isn't used is removed, but a
+//! value with side effects is kept as a statement then.
 
 use decompiler::cfg::*;
+use decompiler::passes::stack_to_var::{is_stack_variable, is_unnamed_local};
+use decompiler::passes::variables::{original_name, Location, ReachingDefinitions, ON_ENTRY};
 use decompiler::types::*;
 use disassembler::types::*;
 use std::collections::{HashMap, HashSet};
@@ -11,248 +19,588 @@ pub fn var_prop(
     unit.map(propagate)
 }
 
-/// basic block and statement index
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-struct Location(usize, usize);
-
 #[derive(Clone, Debug)]
 struct Definition {
     id: Location,
-    ident: Ident,
+    var: Ident,
+    /// Whether the variable has a name in the source code, then it's kept.
+    is_named: bool,
     value: Expr,
-    relevant_defs: HashMap<Ident, HashSet<Location>>,
     uses: usize,
     non_propagatable_uses: usize,
+    /// Where the variable is (last) used.
+    used_at: Option<Location>,
 }
 
-#[derive(Debug)]
-struct DefinitionInfo {
-    definitions: HashMap<Location, Definition>,
-    relevant_on_bb_entry: Vec<HashMap<Ident, HashSet<Location>>>,
+fn propagate(mut cfg: Cfg<Statement, Expr>, _: &Metadata) -> Cfg<Statement, Expr> {
+    let reaching = ReachingDefinitions::new(&cfg);
+    let definitions = collect_definitions(&mut cfg, &reaching);
+    let mut propagatable = definitions
+        .values()
+        .filter(|def| is_propagatable(def))
+        .map(|def| def.id)
+        .collect::<HashSet<_>>();
+    // A value can't be moved past assignments to the variables it reads. Which variables these
+    // are is only known after propagating into it, so this is repeated until nothing changes:
+    loop {
+        let values = resolve_values(&definitions, &propagatable, &reaching);
+        let invalid = propagatable
+            .iter()
+            .cloned()
+            .filter(|&id| {
+                is_redefined_before_use(&definitions[&id], &values[&id], &reaching)
+                    || is_reordered(&cfg, &definitions, &propagatable, &values, &reaching, id)
+            })
+            .collect::<HashSet<_>>();
+        if invalid.is_empty() {
+            propagate_in_code(&mut cfg, &definitions, &values, &reaching);
+            return cfg;
+        }
+        // A definition may only be invalid because of an invalid one propagated into it, so
+        // these are kept until the next round:
+        let mut keep = HashSet::new();
+        for id in &invalid {
+            VariableVisitor(&mut |var: &Ident| {
+                if let Some(def_ids) = reaching.get(var, *id) {
+                    if def_ids.len() == 1 && def_ids.is_subset(&invalid) {
+                        keep.insert(*id);
+                    }
+                }
+            })
+            .visit_expr(&mut definitions[id].value.clone());
+        }
+        if keep.len() == invalid.len() {
+            keep.clear();
+        }
+        for id in invalid.difference(&keep) {
+            propagatable.remove(id);
+        }
+    }
 }
 
-fn propagate(mut cfg: Cfg<Statement, Expr>, metadata: &Metadata) -> Cfg<Statement, Expr> {
-    let info = collect_def_info(&mut cfg, &metadata);
-    println!("{:#?}", info.definitions);
-    let mut propagatable_definitions = info
-        .definitions
-        .into_iter()
-        .filter(|(_, d)| is_propagatable(&d))
-        .collect::<HashMap<_, _>>();
-    propagate_in_definitions(&mut propagatable_definitions);
-    propagate_in_code(
-        &mut cfg,
-        &propagatable_definitions,
-        &info.relevant_on_bb_entry,
-    );
-    cfg
+/// Propagates the propagatable definitions into each other, returning their resulting values.
+fn resolve_values(
+    definitions: &HashMap<Location, Definition>,
+    propagatable: &HashSet<Location>,
+    reaching: &ReachingDefinitions,
+) -> HashMap<Location, Expr> {
+    let mut values = HashMap::new();
+    for &id in propagatable {
+        resolve_definition(definitions, propagatable, reaching, id, &mut values);
+    }
+    values
 }
 
-fn propagate_in_definitions(definitions: &mut HashMap<Location, Definition>) {
-    let ids = definitions.keys().cloned().collect::<Vec<_>>();
-    for replace_id in &ids {
-        let replace_def = definitions[replace_id].clone();
-        println!("ITERATION replacing {:?}:", replace_def);
-        for def in definitions.values() {
-            println!(
-                "{}({},{}) -> {:?}",
-                def.ident, def.id.0, def.id.1, def.value
-            );
+/// Propagates the definitions into the value of the given one.
+/// The definitions it uses are resolved first, since their values have to be interpreted in
+/// their own context.
+fn resolve_definition(
+    definitions: &HashMap<Location, Definition>,
+    propagatable: &HashSet<Location>,
+    reaching: &ReachingDefinitions,
+    id: Location,
+    values: &mut HashMap<Location, Expr>,
+) {
+    if values.contains_key(&id) {
+        return;
+    }
+    let mut value = definitions[&id].value.clone();
+    // A definition that depends on itself is propagated into itself unresolved:
+    values.insert(id, value.clone());
+    let mut used = vec![];
+    VariableVisitor(&mut |var: &Ident| used.push(var.clone())).visit_expr(&mut value);
+    for var in used {
+        if let Some(possible_definitions) = reaching.get(&var, id) {
+            for def_id in possible_definitions.iter() {
+                if propagatable.contains(def_id) {
+                    resolve_definition(definitions, propagatable, reaching, *def_id, values);
+                }
+            }
         }
-        println!("");
-        for def_id in &ids {
-            if def_id == replace_id {
-                continue; // TODO: is this necessary?
+    }
+    PropagationVisitor {
+        values,
+        reaching,
+        location: id,
+    }
+    .visit_expr(&mut value);
+    values.insert(id, value);
+}
+
+/// Calls the function on each variable that is read.
+struct VariableVisitor<'a>(&'a mut dyn FnMut(&Ident));
+impl<'a> Visitor for VariableVisitor<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if let Expr::Assignable(ref assignable) = *expr {
+            if let Assignable::Variable(ref var, _) = **assignable {
+                (self.0)(var);
             }
-            let replace_def = replace_def.clone();
-            let def = &mut definitions.get_mut(def_id).unwrap();
-            PropagationVisitor {
-                defs: &Some((replace_def.id, replace_def)).into_iter().collect(),
-                relevant: &def.relevant_defs,
-            }.visit_expr(&mut def.value);
         }
+        walk_expr(self, expr);
     }
 }
 
+/// Replaces the uses of the definitions by their values and removes the definitions. The values
+/// of those that aren't used remain as statements.
 fn propagate_in_code(
     cfg: &mut Cfg<Statement, Expr>,
     definitions: &HashMap<Location, Definition>,
-    relevant_on_bb_entry: &[HashMap<Ident, HashSet<Location>>],
+    values: &HashMap<Location, Expr>,
+    reaching: &ReachingDefinitions,
 ) {
     for v in cfg.graph.node_indices() {
-        let bb_index = v.index();
-        let mut relevant = relevant_on_bb_entry[bb_index].clone();
-        {
-            let bb = cfg.graph.node_weight_mut(v).unwrap();
-            for (stmt_idx, stmt) in bb.stmts.iter_mut().enumerate() {
-                PropagationVisitor {
-                    defs: &definitions,
-                    relevant: &relevant,
-                }.visit_statement(stmt);
-                match stmt {
-                    Statement::Expr(Expr::Assign { to, op, from: _ }) => {
-                        assert!(op.is_none());
-                        match **to {
-                            Assignable::Variable(ref var, _) => {
-                                let def_id = Location(bb_index, stmt_idx);
-                                relevant.insert(var.to_owned(), Some(def_id).into_iter().collect());
-                            }
-                            _ => (),
-                        }
-                    }
-                    _ => (),
-                }
-                if definitions.contains_key(&Location(bb_index, stmt_idx)) {
+        let bb = &mut cfg.graph[v];
+        for (stmt_idx, stmt) in bb.stmts.iter_mut().enumerate() {
+            let location = Location(v.index(), stmt_idx);
+            PropagationVisitor {
+                values,
+                reaching,
+                location,
+            }
+            .visit_statement(stmt);
+            if let Some(value) = values.get(&location) {
+                *stmt = if definitions[&location].uses == 0 {
+                    stmt_expr(value.clone())
+                } else {
+                    Statement::Nop
+                };
+            }
+            // A value discarded from the stack isn't a statement on its own, e.g. `local_4;`:
+            if let Statement::Expr(ref expr) = *stmt {
+                if !has_side_effects(expr) {
                     *stmt = Statement::Nop;
                 }
             }
-            if let Some(ref mut cond) = bb.terminator {
-                PropagationVisitor {
-                    defs: &definitions,
-                    relevant: &relevant,
-                }.visit_expr(cond);
+        }
+        if let Some(ref mut cond) = bb.terminator {
+            PropagationVisitor {
+                values,
+                reaching,
+                location: Location(v.index(), bb.stmts.len()),
             }
+            .visit_expr(cond);
         }
     }
 }
 
-fn collect_def_info(cfg: &mut Cfg<Statement, Expr>, _: &Metadata) -> DefinitionInfo {
-    let mut info = DefinitionInfo {
-        definitions: HashMap::new(),
-        relevant_on_bb_entry: vec![HashMap::new(); cfg.graph.node_count()],
-    };
-    let mut changed;
-    loop {
-        changed = false;
-        for v in info.definitions.values_mut() {
-            v.non_propagatable_uses = 0;
-            v.uses = 0;
+fn collect_definitions(
+    cfg: &mut Cfg<Statement, Expr>,
+    reaching: &ReachingDefinitions,
+) -> HashMap<Location, Definition> {
+    let mut definitions = HashMap::new();
+    // The definitions are collected before their uses are counted, since a use in a loop may be
+    // reached by a definition later in the code:
+    for v in cfg.graph.node_indices() {
+        for (stmt_idx, stmt) in cfg.graph[v].stmts.iter().enumerate() {
+            if let Some((var, value)) = match_assignment(stmt) {
+                let def_id = Location(v.index(), stmt_idx);
+                definitions.insert(
+                    def_id,
+                    Definition {
+                        id: def_id,
+                        var: var.clone(),
+                        is_named: has_source_name(var),
+                        value: value.clone(),
+                        uses: 0,
+                        non_propagatable_uses: 0,
+                        used_at: None,
+                    },
+                );
+            }
         }
-        for v in cfg.graph.node_indices() {
-            let bb_index = v.index();
-            let mut relevant = info.relevant_on_bb_entry[bb_index].clone();
-            {
-                let bb = cfg.graph.node_weight_mut(v).unwrap();
-                for (stmt_idx, stmt) in bb.stmts.iter_mut().enumerate() {
-                    update_usages(stmt, &relevant, &mut info.definitions);
-                    match stmt {
-                        Statement::Expr(Expr::Assign { to, op, from }) => {
-                            assert!(op.is_none());
-                            match **to {
-                                Assignable::Variable(ref var, _) => {
-                                    let def_id = Location(bb_index, stmt_idx);
-                                    if !info.definitions.contains_key(&def_id) {
-                                        info.definitions.insert(
-                                            def_id,
-                                            Definition {
-                                                id: def_id,
-                                                ident: var.to_owned(),
-                                                value: (**from).clone(),
-                                                relevant_defs: relevant.clone(),
-                                                uses: 0,
-                                                non_propagatable_uses: 0,
-                                            },
-                                        );
-                                        changed = true;
-                                    }
-                                    info.definitions.get_mut(&def_id).unwrap().relevant_defs =
-                                        relevant.clone();
-                                    relevant
-                                        .insert(var.to_owned(), Some(def_id).into_iter().collect());
-                                }
-                                _ => (),
-                            }
-                        }
-                        _ => (),
-                    }
-                }
+    }
+    // Moving an expression to a use that is protected by different exception handlers would
+    // change which handler catches its exceptions:
+    let handlers = cfg
+        .graph
+        .node_indices()
+        .map(|v| {
+            let mut handlers = cfg
+                .graph
+                .edges(v)
+                .filter(|edge| edge.weight().is_exception())
+                .map(|edge| (edge.target(), *edge.weight()))
+                .collect::<Vec<_>>();
+            handlers.sort();
+            handlers
+        })
+        .collect::<Vec<_>>();
+    for v in cfg.graph.node_indices() {
+        let bb_index = v.index();
+        let bb = &mut cfg.graph[v];
+        let movable = |def_id: &Location| handlers[def_id.0] == handlers[bb_index];
+        for (stmt_idx, stmt) in bb.stmts.iter_mut().enumerate() {
+            UsageVisitor {
+                definitions: &mut definitions,
+                reaching,
+                location: Location(bb_index, stmt_idx),
+                movable: &movable,
+            }
+            .visit_statement(stmt);
+        }
+        if let Some(ref mut cond) = bb.terminator {
+            UsageVisitor {
+                definitions: &mut definitions,
+                reaching,
+                location: Location(bb_index, bb.stmts.len()),
+                movable: &movable,
             }
-            for target_node in cfg.graph.neighbors(v) {
-                let target_index = target_node.index();
-                let target_relevant = &mut info.relevant_on_bb_entry[target_index];
-                for (var, def_ids) in relevant.iter() {
-                    let before = target_relevant.entry(var.to_owned()).or_insert_with(|| {
-                        changed = true;
-                        def_ids.clone()
-                    });
-                    if !def_ids.is_subset(before) {
-                        changed = true;
-                        before.extend(def_ids);
+            .visit_expr(cond);
+        }
+    }
+    definitions
+}
+
+/// Counts the uses of the definitions at `location`, given whether they may be moved there.
+struct UsageVisitor<'a> {
+    definitions: &'a mut HashMap<Location, Definition>,
+    reaching: &'a ReachingDefinitions,
+    location: Location,
+    movable: &'a dyn Fn(&Location) -> bool,
+}
+
+impl<'a> Visitor for UsageVisitor<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if let Some(var) = as_variable(expr) {
+            if let Some(possible_definitions) = self.reaching.get(var, self.location) {
+                let propagatable = possible_definitions.len() <= 1;
+                for def_id in possible_definitions.iter().filter(|&&id| id != ON_ENTRY) {
+                    let def = self.definitions.get_mut(def_id).unwrap();
+                    def.uses += 1;
+                    def.used_at = Some(self.location);
+                    if !propagatable || !(self.movable)(def_id) {
+                        def.non_propagatable_uses += 1;
                     }
                 }
             }
         }
-        if !changed {
-            break;
-        }
+        walk_expr(self, expr);
     }
-    info
 }
 
-fn update_usages(
-    stmt: &mut Statement,
-    relevant_defs: &HashMap<Ident, HashSet<Location>>,
-    definitions: &mut HashMap<Location, Definition>,
-) {
-    struct UsageVisitor<'a>(
-        &'a mut HashMap<Location, Definition>,
-        &'a HashMap<Ident, HashSet<Location>>,
-    );
-    impl<'a> Visitor for UsageVisitor<'a> {
+/// Checks whether evaluating the expression calls a method, creates an object or assigns a
+/// variable, a field or an array element.
+fn has_side_effects(expr: &Expr) -> bool {
+    struct EffectVisitor(bool);
+    impl Visitor for EffectVisitor {
         fn visit_expr(&mut self, expr: &mut Expr) {
             match *expr {
-                Expr::Assignable(ref assignable) => match **assignable {
-                    Assignable::Variable(ref var, _) => {
-                        let empty = HashSet::new();
-                        let possible_definitions = self.1.get(var).unwrap_or(&empty);
-                        let propagatable = possible_definitions.len() <= 1;
-                        for def_id in possible_definitions {
-                            let def = self.0.get_mut(def_id).unwrap();
-                            def.uses += 1;
-                            if !propagatable {
-                                def.non_propagatable_uses += 1;
-                            }
-                        }
-                    }
-                    _ => (),
-                },
-                _ => (),
+                Expr::Invoke(..) | Expr::Assign { .. } | Expr::New { .. } => self.0 = true,
+                _ => walk_expr(self, expr),
             }
-            walk_expr(self, expr);
         }
     }
-    UsageVisitor(definitions, relevant_defs).visit_statement(stmt);
+    let mut visitor = EffectVisitor(false);
+    visitor.visit_expr(&mut expr.clone());
+    visitor.0
 }
 
 fn is_propagatable(def: &Definition) -> bool {
-    def.non_propagatable_uses == 0 && def.uses <= 1
+    // The value of an assignment that isn't used must be a valid statement if it's kept:
+    let is_discardable = match def.value {
+        Expr::Invoke(..) | Expr::Assign { .. } | Expr::New { .. } => true,
+        ref value => !has_side_effects(value),
+    };
+    !def.is_named
+        && def.non_propagatable_uses == 0
+        && (def.uses == 1 || def.uses == 0 && is_discardable)
+}
+
+/// Checks whether the variable was named after the local variable table of the class file.
+fn has_source_name(var: &str) -> bool {
+    let name = original_name(var);
+    !is_stack_variable(name) && !is_unnamed_local(name)
+}
+
+/// Checks whether a variable read by the value of the definition, after propagating into it, may
+/// be assigned between the definition and its use.
+fn is_redefined_before_use(def: &Definition, value: &Expr, reaching: &ReachingDefinitions) -> bool {
+    let used_at = match def.used_at {
+        Some(used_at) => used_at,
+        None => return false,
+    };
+    let mut redefined = false;
+    VariableVisitor(&mut |var: &Ident| {
+        redefined |= reaching.get(var, def.id) != reaching.get(var, used_at);
+    })
+    .visit_expr(&mut value.clone());
+    redefined
+}
+
+/// What evaluating an expression does besides computing its value, as far as the order matters.
+#[derive(Clone, Debug, Default)]
+struct Effect {
+    /// The names of the fields read and assigned.
+    reads: HashSet<String>,
+    writes: HashSet<String>,
+    reads_array: bool,
+    writes_array: bool,
+    /// Whether a method is called or an object created, which may read and assign anything.
+    calls: bool,
+}
+
+impl Effect {
+    fn is_none(&self) -> bool {
+        !self.calls
+            && !self.reads_array
+            && !self.writes_array
+            && self.reads.is_empty()
+            && self.writes.is_empty()
+    }
+
+    /// Checks whether the order of the two effects matters, i.e. one of them may assign what the
+    /// other one reads or assigns.
+    fn conflicts(&self, other: &Effect) -> bool {
+        if self.is_none() || other.is_none() {
+            return false;
+        }
+        let writes_read = |a: &Effect, b: &Effect| {
+            a.writes_array && (b.reads_array || b.writes_array)
+                || a.writes
+                    .iter()
+                    .any(|field| b.reads.contains(field) || b.writes.contains(field))
+        };
+        self.calls || other.calls || writes_read(self, other) || writes_read(other, self)
+    }
+}
+
+/// Collects the effects of a statement in the order of evaluation, up to reading the variable
+/// `until`.
+struct EffectVisitor<'a> {
+    effect: Effect,
+    until: Option<&'a str>,
+    reached: bool,
+}
+
+impl<'a> EffectVisitor<'a> {
+    fn access(&mut self, assignable: &Assignable, write: bool) {
+        if self.reached {
+            return;
+        }
+        match *assignable {
+            Assignable::Variable(..) => {}
+            Assignable::Field { ref field, .. } if write => {
+                self.effect.writes.insert(field.name.clone());
+            }
+            // The outer instance and the captured variables of inner classes never change:
+            Assignable::Field { ref field, .. }
+                if field.name.starts_with("this$") || field.name.starts_with("val$") => {}
+            Assignable::Field { ref field, .. } => {
+                self.effect.reads.insert(field.name.clone());
+            }
+            Assignable::ArrayAccess { .. } if write => self.effect.writes_array = true,
+            Assignable::ArrayAccess { .. } => self.effect.reads_array = true,
+        }
+    }
+}
+
+impl<'a> Visitor for EffectVisitor<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if self.reached {
+            return;
+        }
+        match *expr {
+            Expr::Assignable(ref mut assignable) => {
+                if let Assignable::Variable(ref var, _) = **assignable {
+                    self.reached = self.until == Some(var.as_str());
+                }
+                walk_assignable(self, assignable);
+                self.access(assignable, false);
+            }
+            Expr::Assign {
+                ref mut to,
+                op,
+                ref mut from,
+            } => {
+                walk_assignable(self, to);
+                // A compound assignment reads the old value before evaluating the operand:
+                if op.is_some() {
+                    self.access(to, false);
+                }
+                self.visit_expr(from);
+                self.access(to, true);
+            }
+            Expr::Invoke(..) | Expr::New { .. } => {
+                walk_expr(self, expr);
+                if !self.reached {
+                    self.effect.calls = true;
+                }
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/// The effects of the statement, or only of the part evaluated before reading `until`.
+fn effect(stmt: &Statement, until: Option<&str>) -> Effect {
+    let mut visitor = EffectVisitor {
+        effect: Effect::default(),
+        until,
+        reached: false,
+    };
+    visitor.visit_statement(&mut stmt.clone());
+    visitor.effect
+}
+
+/// The statement at the location, or the condition at the end of the block as a statement.
+fn statement_at(cfg: &Cfg<Statement, Expr>, Location(block, index): Location) -> Statement {
+    let block = &cfg.graph[Label::new(block)];
+    match block.stmts.get(index) {
+        Some(stmt) => stmt.clone(),
+        None => {
+            let cond = block.terminator.clone();
+            stmt_expr(cond.expect("a location after the block"))
+        }
+    }
+}
+
+/// The locations of the statements and conditions executed between `from` and `to`, if the
+/// control flow always goes straight from one to the other.
+fn locations_between(
+    cfg: &Cfg<Statement, Expr>,
+    from: Location,
+    to: Location,
+) -> Option<Vec<Location>> {
+    let mut locations = vec![];
+    let (mut block, mut start) = (Label::new(from.0), from.1 + 1);
+    for _ in 0..cfg.graph.node_count() {
+        if block.index() == to.0 && start <= to.1 {
+            locations.extend((start..to.1).map(|i| Location(to.0, i)));
+            return Some(locations);
+        }
+        if cfg.graph[block].terminator.is_some() {
+            return None;
+        }
+        let end = cfg.graph[block].stmts.len();
+        locations.extend((start..end).map(|i| Location(block.index(), i)));
+        let mut successors = cfg
+            .graph
+            .edges_directed(block, Direction::Outgoing)
+            .filter(|edge| !edge.weight().is_exception());
+        let next = match (successors.next(), successors.next()) {
+            (Some(edge), None) => edge.target(),
+            _ => return None,
+        };
+        let mut predecessors = cfg.graph.neighbors_directed(next, Direction::Incoming);
+        if predecessors.any(|predecessor| predecessor != block) {
+            return None;
+        }
+        block = next;
+        start = 0;
+    }
+    None
 }
 
+/// The locations that the value of the definition is moved to when the definitions are
+/// propagated, starting with its use, each with the variable read there.
+fn destinations<'a>(
+    definitions: &'a HashMap<Location, Definition>,
+    propagatable: &HashSet<Location>,
+    mut id: Location,
+) -> Vec<(Location, &'a str)> {
+    let mut destinations = vec![];
+    while let Some(used_at) = definitions[&id].used_at {
+        destinations.push((used_at, definitions[&id].var.as_str()));
+        if !propagatable.contains(&used_at) || destinations.len() > definitions.len() {
+            break;
+        }
+        id = used_at;
+    }
+    destinations
+}
+
+/// Checks whether propagating the definition changes the order of the effects of its value and
+/// of the code between the definition and its use. The definitions propagated into the same
+/// statement keep their order if the statement reads them in the order they were assigned, e.g.
+/// the arguments of a call.
+fn is_reordered(
+    cfg: &Cfg<Statement, Expr>,
+    definitions: &HashMap<Location, Definition>,
+    propagatable: &HashSet<Location>,
+    values: &HashMap<Location, Expr>,
+    reaching: &ReachingDefinitions,
+    id: Location,
+) -> bool {
+    let value_effect = effect(&stmt_expr(values[&id].clone()), None);
+    let used_at = match definitions[&id].used_at {
+        Some(used_at) if !value_effect.is_none() => used_at,
+        _ => return false,
+    };
+    let between = match locations_between(cfg, id, used_at) {
+        Some(between) => between,
+        None => return true,
+    };
+    let destinations = destinations(definitions, propagatable, id);
+    // The variables read by the statement, in the order of evaluation:
+    let read_order = |location: Location| {
+        let mut vars = vec![];
+        VariableVisitor(&mut |var: &Ident| vars.push(var.clone()))
+            .visit_statement(&mut statement_at(cfg, location));
+        vars
+    };
+    let is_moved_after = |other: Location| {
+        // Where the values of both definitions end up in the same statement:
+        let common = self::destinations(definitions, propagatable, other)
+            .into_iter()
+            .find_map(|(location, other_var)| {
+                destinations
+                    .iter()
+                    .find(|&&(common, _)| common == location)
+                    .map(|&(_, var)| (location, var, other_var))
+            });
+        common.is_some_and(|(location, var, other_var)| {
+            let vars = read_order(location);
+            let position = |var: &str| vars.iter().position(|read| read == var);
+            match (position(var), position(other_var)) {
+                (Some(position), Some(other_position)) => position < other_position,
+                _ => false,
+            }
+        })
+    };
+    let is_conflicting = |location: Location| {
+        if propagatable.contains(&location) && definitions[&location].uses > 0 {
+            let moved_effect = effect(&stmt_expr(values[&location].clone()), None);
+            return moved_effect.conflicts(&value_effect) && !is_moved_after(location);
+        }
+        // The value of a definition that isn't used stays where it is:
+        let stmt = match values.get(&location) {
+            Some(value) => stmt_expr(value.clone()),
+            None => {
+                let mut stmt = statement_at(cfg, location);
+                PropagationVisitor {
+                    values,
+                    reaching,
+                    location,
+                }
+                .visit_statement(&mut stmt);
+                stmt
+            }
+        };
+        effect(&stmt, None).conflicts(&value_effect)
+    };
+    let var = &definitions[&id].var;
+    between.into_iter().any(is_conflicting)
+        || effect(&statement_at(cfg, used_at), Some(var)).conflicts(&value_effect)
+}
+
+/// Replaces the variables read at `location` by the values of the definitions, where only one
+/// of them reaches it.
 struct PropagationVisitor<'a> {
-    defs: &'a HashMap<Location, Definition>,
-    relevant: &'a HashMap<Ident, HashSet<Location>>,
+    values: &'a HashMap<Location, Expr>,
+    reaching: &'a ReachingDefinitions,
+    location: Location,
 }
 impl<'a> Visitor for PropagationVisitor<'a> {
     fn visit_expr(&mut self, expr: &mut Expr) {
-        let replace = match *expr {
-            Expr::Assignable(ref assignable) => match **assignable {
-                Assignable::Variable(ref var, _) if self.relevant.contains_key(var) => {
-                    let possible_definitions = &self.relevant[var];
-                    if possible_definitions.len() == 1 {
-                        let def_id = possible_definitions.iter().next().unwrap();
-                        if let Some(def) = self.defs.get(def_id) {
-                            Some(def.value.clone())
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
+        let replace = as_variable(expr)
+            .and_then(|var| self.reaching.get(var, self.location))
+            .and_then(|possible_definitions| {
+                let mut possible_definitions = possible_definitions.iter();
+                match (possible_definitions.next(), possible_definitions.next()) {
+                    (Some(def_id), None) => self.values.get(def_id).cloned(),
+                    _ => None,
                 }
-                _ => None,
-            },
-            _ => None,
-        };
+            });
         if let Some(replace) = replace {
             *expr = replace;
         } else {
@@ -260,3 +608,76 @@ impl<'a> Visitor for PropagationVisitor<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompiler::fixtures::*;
+
+    /// Propagates the variables in a method of a single block, returning its statements.
+    fn propagate_block(stmts: Vec<Statement>) -> String {
+        let cfg = build(vec![(stmts, None)], &[(0, 2, Edge::Branch(false))]);
+        let cfg = propagate(cfg, &Metadata::new());
+        to_string(&cfg.graph[Label::new(0)].stmts)
+    }
+
+    #[test]
+    fn discarded_values() {
+        let code = propagate_block(vec![
+            assign("stack_0", var("local_4")),
+            assign("stack_1", var("exception_21")),
+            assign("stack_2", invoke("f", &[], Type::Int, vec![])),
+            stmt_expr(var("stack_0")),
+            stmt_expr(var("stack_1")),
+            stmt_expr(var("stack_2")),
+        ]);
+        assert_eq!(code, "T.f();");
+    }
+
+    #[test]
+    fn unused_values() {
+        let code = propagate_block(vec![
+            assign("stack_0", invoke("f", &[], Type::Int, vec![])),
+            assign("stack_1", var("local_2")),
+        ]);
+        assert_eq!(code, "T.f();");
+    }
+
+    #[test]
+    fn store_between_read_and_use() {
+        let store = |value| Expr::Assign {
+            to: Box::new(field("f")),
+            op: None,
+            from: Box::new(value),
+        };
+        let read = || Expr::Assignable(Box::new(field("f")));
+        let code = propagate_block(vec![
+            assign("stack_0", read()),
+            stmt_expr(store(int(5))),
+            call("g", vec![var("stack_0"), read()]),
+            assign("stack_1", read()),
+            call("g", vec![var("stack_1"), store(int(3))]),
+        ]);
+        assert_eq!(
+            code,
+            "stack_0 = T.f;\nT.f = 5;\nT.g(stack_0, T.f);\nT.g(T.f, T.f = 3);"
+        );
+    }
+
+    #[test]
+    fn calls_in_order() {
+        let next = || invoke("next", &[], Type::Int, vec![]);
+        let code = propagate_block(vec![
+            assign("stack_0", next()),
+            assign("stack_1", next()),
+            call("g", vec![var("stack_0"), var("stack_1")]),
+            assign("stack_2", next()),
+            assign("stack_3", next()),
+            call("g", vec![var("stack_3"), var("stack_2")]),
+        ]);
+        assert_eq!(
+            code,
+            "T.g(T.next(), T.next());\nstack_2 = T.next();\nT.g(T.next(), stack_2);"
+        );
+    }
+}
@@ -0,0 +1,1638 @@
+//! Splits the local variables into independent variables and declares them where they are
+//! needed.
+//!
+//! The JVM reuses the slots of the local variables (and the stack) for unrelated values, e.g. for
+//! the variables of two loops after each other, which may even have different types. All the
+//! assignments that reach a common use assign the same variable, so the assignments of a slot
+//! are grouped by the uses they reach, and each group (a web of live ranges) becomes a variable
+//! of its own. The first one keeps the name of the slot, the others are numbered like `i#1`.
+//! This happens right after converting the stack to variables, so that the types of the
+//! variables are inferred independently.
+//!
+//! After structuring, the stack variables that don't need a declaration are removed first: the
+//! copies of `this` or of variables that aren't assigned while the copies are used, and the
+//! values used once by the next statement, e.g. the value of a `return` statement that stayed on
+//! the stack while the finally code ran. Each of the remaining variables is declared in the
+//! narrowest block containing all its uses, or by the `for` loop whose initialization assigns it
+//! if it isn't used outside the loop.
+//! The assignments starting a block become the initializers of the declarations of the variables
+//! they assign first. Since `#` isn't allowed in Java identifiers, the variables that were split
+//! get their final names then: the name of the slot unless another variable of that name is in
+//! scope, and a new one otherwise. A variable in the scope of one of the same name only shares it
+//! if neither of them is live where the other one is assigned.
+
+use decompiler::cfg::*;
+use decompiler::passes::stack_to_var::is_stack_variable;
+use decompiler::types::*;
+use disassembler::types::*;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::slice;
+
+pub fn split_variables(
+    unit: CompilationUnit<Cfg<Statement, Expr>>,
+) -> CompilationUnit<Cfg<Statement, Expr>> {
+    unit.map(|mut cfg, _| {
+        split(&mut cfg);
+        cfg
+    })
+}
+
+/// The name of the variable that `var` was split from, see `split_variables`.
+pub fn original_name(var: &str) -> &str {
+    var.split('#').next().unwrap_or(var)
+}
+
+/// Checks whether the statements are the same, except for the variables that were split
+/// differently, e.g. two copies of the code of a finally clause.
+pub fn is_copy(stmt: &Statement, other: &Statement) -> bool {
+    let unsplit = |stmt: &Statement| {
+        let mut stmt = stmt.clone();
+        RenameVisitor(&mut |var: &mut Ident| *var = original_name(var).to_owned())
+            .visit_statement(&mut stmt);
+        stmt
+    };
+    stmt == other || unsplit(stmt) == unsplit(other)
+}
+
+/// The block and index of a statement assigning a variable.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Location(pub usize, pub usize);
+
+/// Stands for the values the variables have when the method is entered, e.g. the parameters.
+pub const ON_ENTRY: Location = Location(usize::MAX, 0);
+
+/// The assignments that may have assigned the current value of each variable.
+pub type Reaching = HashMap<Ident, HashSet<Location>>;
+
+fn split(cfg: &mut Cfg<Statement, Expr>) {
+    let on_entry = reaching_definitions(cfg);
+    let mut collector = WebCollector {
+        webs: HashMap::new(),
+    };
+    visit_accesses(cfg, &on_entry, &mut collector);
+    let mut webs = collector.webs;
+    for (var, webs) in &mut webs {
+        webs.name(var);
+    }
+    visit_accesses(cfg, &on_entry, &mut WebRenamer { webs });
+}
+
+/// The assignments reaching each statement of a control flow graph. Only those reaching the
+/// beginning of each block are stored, the ones reaching a statement are looked up from there.
+pub struct ReachingDefinitions {
+    on_entry: Vec<Reaching>,
+    /// The indices of the statements assigning each variable in each block, in ascending order.
+    assignments: Vec<HashMap<Ident, Vec<usize>>>,
+}
+
+impl ReachingDefinitions {
+    pub fn new(cfg: &Cfg<Statement, Expr>) -> ReachingDefinitions {
+        let mut assignments = vec![HashMap::new(); cfg.graph.node_count()];
+        for block in cfg.graph.node_indices() {
+            for (i, stmt) in cfg.graph[block].stmts.iter().enumerate() {
+                if let Some((var, _)) = match_assignment(stmt) {
+                    assignments[block.index()]
+                        .entry(var.clone())
+                        .or_insert_with(Vec::new)
+                        .push(i);
+                }
+            }
+        }
+        ReachingDefinitions {
+            on_entry: reaching_definitions(cfg),
+            assignments,
+        }
+    }
+
+    /// The assignments of the variable that reach the statement at `location`, before it's
+    /// executed. The index after the last statement of a block stands for its terminator.
+    pub fn get(&self, var: &str, location: Location) -> Option<Cow<'_, HashSet<Location>>> {
+        let Location(block, index) = location;
+        let previous = self.assignments[block].get(var).and_then(|indices| {
+            let count = indices.partition_point(|&i| i < index);
+            count.checked_sub(1).map(|last| indices[last])
+        });
+        match previous {
+            Some(i) => Some(Cow::Owned(Some(Location(block, i)).into_iter().collect())),
+            None => self.on_entry[block].get(var).map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Computes the assignments reaching the beginning of each block.
+fn reaching_definitions(cfg: &Cfg<Statement, Expr>) -> Vec<Reaching> {
+    let mut on_entry = vec![Reaching::new(); cfg.graph.node_count()];
+    let mut variables = HashMap::new();
+    for block in cfg.graph.node_indices() {
+        for stmt in &cfg.graph[block].stmts {
+            if let Some((var, _)) = match_assignment(stmt) {
+                variables.insert(var.clone(), Some(ON_ENTRY).into_iter().collect());
+            }
+        }
+    }
+    on_entry[cfg.entry_point.index()] = variables;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in cfg.graph.node_indices() {
+            let mut reaching = on_entry[block.index()].clone();
+            // An exception may be thrown anywhere in the block, so all assignments in it reach
+            // the handlers, as well as the ones on entry:
+            let mut on_exception = reaching.clone();
+            for (i, stmt) in cfg.graph[block].stmts.iter().enumerate() {
+                if let Some((var, _)) = match_assignment(stmt) {
+                    let location = Location(block.index(), i);
+                    reaching.insert(var.clone(), Some(location).into_iter().collect());
+                    on_exception
+                        .entry(var.clone())
+                        .or_default()
+                        .insert(location);
+                }
+            }
+            for edge in cfg.graph.edges(block) {
+                let reaching = if edge.weight().is_exception() {
+                    &on_exception
+                } else {
+                    &reaching
+                };
+                let target = &mut on_entry[edge.target().index()];
+                for (var, locations) in reaching {
+                    let before = target.entry(var.clone()).or_default();
+                    if !locations.is_subset(before) {
+                        before.extend(locations);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+    on_entry
+}
+
+/// Handles the reads and assignments of the variables, see `visit_accesses`.
+trait Accesses {
+    /// `var` is read where the given assignments reach.
+    fn read(&mut self, var: &mut Ident, reaching: &HashSet<Location>);
+
+    /// `var` is assigned by the statement at `location`. If the value reads the variable itself,
+    /// e.g. `i = i + 1`, `previous` holds the assignments reaching the statement.
+    fn assign(&mut self, var: &mut Ident, location: Location, previous: Option<&HashSet<Location>>);
+}
+
+/// Calls `accesses` for each read and assignment of a variable, in the order of the blocks and
+/// of their statements.
+fn visit_accesses(
+    cfg: &mut Cfg<Statement, Expr>,
+    on_entry: &[Reaching],
+    accesses: &mut dyn Accesses,
+) {
+    for block in cfg.graph.node_indices() {
+        let mut reaching = on_entry[block.index()].clone();
+        let bb = &mut cfg.graph[block];
+        for (i, stmt) in bb.stmts.iter_mut().enumerate() {
+            if let Statement::Expr(Expr::Assign {
+                ref mut to,
+                op: None,
+                ref mut from,
+            }) = *stmt
+            {
+                if let Assignable::Variable(ref mut var, _) = **to {
+                    let is_update = uses_variable(&[stmt_expr((**from).clone())], var);
+                    ReadVisitor {
+                        reaching: &reaching,
+                        accesses: &mut *accesses,
+                    }
+                    .visit_expr(from);
+                    let location = Location(block.index(), i);
+                    let previous = if is_update {
+                        reaching.get(var.as_str())
+                    } else {
+                        None
+                    };
+                    let original = var.clone();
+                    accesses.assign(var, location, previous);
+                    reaching.insert(original, Some(location).into_iter().collect());
+                    continue;
+                }
+            }
+            ReadVisitor {
+                reaching: &reaching,
+                accesses: &mut *accesses,
+            }
+            .visit_statement(stmt);
+        }
+        if let Some(ref mut cond) = bb.terminator {
+            ReadVisitor {
+                reaching: &reaching,
+                accesses: &mut *accesses,
+            }
+            .visit_expr(cond);
+        }
+    }
+}
+
+/// Calls `accesses` for each variable that is read.
+struct ReadVisitor<'a> {
+    reaching: &'a Reaching,
+    accesses: &'a mut dyn Accesses,
+}
+
+impl<'a> Visitor for ReadVisitor<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if let Expr::Assignable(ref mut assignable) = *expr {
+            if let Assignable::Variable(ref mut var, _) = **assignable {
+                if let Some(reaching) = self.reaching.get(var.as_str()) {
+                    self.accesses.read(var, reaching);
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// The assignments of a variable, partitioned into webs by a union-find structure.
+#[derive(Default)]
+struct Webs {
+    /// The assignments in the order in which they appear in the code.
+    assignments: Vec<Location>,
+    /// The assignments that reach a use.
+    used: HashSet<Location>,
+    /// The assignments reading the previous value of the variable, with one of the assignments
+    /// of that value.
+    updates: Vec<(Location, Location)>,
+    parents: HashMap<Location, Location>,
+    /// The names of the webs by the assignment representing them.
+    names: HashMap<Location, Ident>,
+}
+
+impl Webs {
+    fn find(&mut self, location: Location) -> Location {
+        let mut root = location;
+        while let Some(&parent) = self.parents.get(&root) {
+            root = parent;
+        }
+        let mut current = location;
+        while current != root {
+            current = self.parents.insert(current, root).unwrap();
+        }
+        root
+    }
+
+    fn union(&mut self, a: Location, b: Location) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents.insert(b, a);
+        }
+    }
+
+    /// Names the webs after the variable, numbering all but the first one.
+    fn name(&mut self, var: &str) {
+        // An update that isn't used anymore, e.g. a final `i++`, still belongs to the variable:
+        for (update, previous) in self.updates.clone() {
+            if !self.used.contains(&update) {
+                self.union(previous, update);
+            }
+        }
+        // The value on entry, e.g. of a parameter, keeps the name of the variable:
+        let mut locations = vec![];
+        if self.used.contains(&ON_ENTRY) {
+            locations.push(ON_ENTRY);
+        }
+        locations.extend(self.assignments.iter().cloned());
+        for location in locations {
+            let root = self.find(location);
+            if !self.names.contains_key(&root) {
+                let name = match self.names.len() {
+                    0 => var.to_owned(),
+                    i => format!("{}#{}", var, i),
+                };
+                self.names.insert(root, name);
+            }
+        }
+    }
+
+    fn name_of(&mut self, location: Location) -> Ident {
+        let root = self.find(location);
+        self.names[&root].clone()
+    }
+}
+
+/// Groups the assignments of each variable into webs.
+struct WebCollector {
+    webs: HashMap<Ident, Webs>,
+}
+
+impl Accesses for WebCollector {
+    fn read(&mut self, var: &mut Ident, reaching: &HashSet<Location>) {
+        let webs = self.webs.entry(var.clone()).or_default();
+        let mut locations = reaching.iter();
+        if let Some(&first) = locations.next() {
+            for &location in locations {
+                webs.union(first, location);
+            }
+        }
+        webs.used.extend(reaching);
+    }
+
+    fn assign(
+        &mut self,
+        var: &mut Ident,
+        location: Location,
+        previous: Option<&HashSet<Location>>,
+    ) {
+        let webs = self.webs.entry(var.clone()).or_default();
+        webs.assignments.push(location);
+        if let Some(&previous) = previous.and_then(|previous| previous.iter().next()) {
+            webs.updates.push((location, previous));
+        }
+    }
+}
+
+/// Renames the variables after the webs they belong to.
+struct WebRenamer {
+    webs: HashMap<Ident, Webs>,
+}
+
+impl Accesses for WebRenamer {
+    fn read(&mut self, var: &mut Ident, reaching: &HashSet<Location>) {
+        if let Some(&location) = reaching.iter().next() {
+            *var = self.webs.get_mut(var.as_str()).unwrap().name_of(location);
+        }
+    }
+
+    fn assign(&mut self, var: &mut Ident, location: Location, _: Option<&HashSet<Location>>) {
+        *var = self.webs.get_mut(var.as_str()).unwrap().name_of(location);
+    }
+}
+
+/// Declares the local variables of each method where they are needed, see the module
+/// documentation, and gives the variables that were split their final names.
+pub fn declare_variables(unit: &mut CompilationUnit<Block>) {
+    for declaration in &mut unit.declarations {
+        let (parameters, locals, code) = match *declaration {
+            Declaration::Method(Method {
+                ref signature,
+                ref mut locals,
+                code: Some(ref mut code),
+                ..
+            }) => (&signature.parameters, locals, code),
+            Declaration::Constructor(ref mut constructor) => (
+                &constructor.parameters,
+                &mut constructor.locals,
+                &mut constructor.code,
+            ),
+            _ => continue,
+        };
+        declare(code, parameters, locals);
+    }
+}
+
+fn declare(code: &mut Block, parameters: &[(Ident, Type)], locals: &mut [(Ident, Type)]) {
+    let mut counter = StackVariableCounter::default();
+    counter.visit_block(code);
+    StackVariableRemover {
+        counts: counter.counts,
+    }
+    .visit_block(code);
+    let mut scopes = ScopeVisitor::default();
+    scopes.visit_block(code);
+    let mut declared = parameters
+        .iter()
+        .map(|parameter| Declared {
+            var: parameter.0.clone(),
+            scope: vec![],
+            typ: Some(parameter.1.clone()),
+            placement: None,
+        })
+        .collect::<Vec<_>>();
+    for var in &scopes.used {
+        let is_declared = declared.iter().any(|declared| declared.var == *var)
+            || scopes.declared.iter().any(|declared| declared.0 == *var);
+        let typ = match locals.iter().find(|local| local.0 == *var) {
+            Some(local) if !is_declared => local.1.clone(),
+            _ => continue,
+        };
+        let mut scope = scopes.scopes[var].clone();
+        let innermost = *scope.last().unwrap();
+        let placement = if scopes.loop_inits.get(&innermost) == Some(var) {
+            Placement::Loop(innermost)
+        } else {
+            // Other variables can't be declared by a loop, but by the block containing it:
+            while scopes.loops.contains(scope.last().unwrap()) {
+                scope.pop();
+            }
+            Placement::Block(*scope.last().unwrap())
+        };
+        declared.push(Declared {
+            var: var.clone(),
+            scope,
+            typ: Some(typ),
+            placement: Some(placement),
+        });
+    }
+    declared.extend(scopes.declared.iter().map(|declared| Declared {
+        var: declared.0.clone(),
+        scope: declared.1.clone(),
+        typ: None,
+        placement: None,
+    }));
+    let mut names = scopes.used.iter().cloned().collect::<HashSet<_>>();
+    names.extend(declared.iter().map(|declared| declared.var.clone()));
+    let interference = Liveness::new(code).interference;
+    let renames = final_names(&mut declared, &names, &interference);
+    let mut declarations = DeclarationVisitor {
+        next_scope: 0,
+        blocks: HashMap::new(),
+        loops: HashMap::new(),
+    };
+    for declared in declared {
+        match (declared.placement, declared.typ) {
+            (Some(Placement::Block(block)), Some(typ)) => {
+                let decl = LocalDecl {
+                    ident: declared.var,
+                    typ,
+                    init: None,
+                };
+                declarations.blocks.entry(block).or_default().push(decl);
+            }
+            (Some(Placement::Loop(lupe)), Some(typ)) => {
+                declarations.loops.insert(lupe, typ);
+            }
+            _ => {}
+        }
+    }
+    declarations.visit_block(code);
+    if renames.is_empty() {
+        return;
+    }
+    let mut rename = |var: &mut Ident| {
+        if let Some(name) = renames.get(var) {
+            *var = name.clone();
+        }
+    };
+    RenameVisitor(&mut rename).visit_block(code);
+    for local in locals {
+        rename(&mut local.0);
+    }
+}
+
+/// Counts how often each stack variable is read or assigned.
+#[derive(Default)]
+struct StackVariableCounter {
+    counts: HashMap<Ident, usize>,
+}
+
+impl Visitor for StackVariableCounter {
+    fn visit_assignable(&mut self, assignable: &mut Assignable) {
+        if let Assignable::Variable(ref var, _) = *assignable {
+            if is_stack_variable(var) {
+                *self.counts.entry(var.clone()).or_default() += 1;
+            }
+        }
+        walk_assignable(self, assignable);
+    }
+}
+
+/// Removes the stack variables that don't need to be declared, see the module documentation.
+struct StackVariableRemover {
+    /// How often each stack variable is used in the whole method.
+    counts: HashMap<Ident, usize>,
+}
+
+impl Visitor for StackVariableRemover {
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block(self, block);
+        self.remove_in(&mut block.1);
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement(self, stmt);
+        if let Statement::Switch { ref mut cases, .. } = *stmt {
+            for case in cases {
+                self.remove_in(&mut case.stmts);
+            }
+        }
+    }
+}
+
+impl StackVariableRemover {
+    fn remove_in(&mut self, stmts: &mut Vec<Statement>) {
+        let mut i = 0;
+        while i < stmts.len() {
+            if let Some((var, assignment)) = match_conditional_assignment(&stmts[i]) {
+                stmts[i] = assignment;
+                *self.counts.get_mut(&var).unwrap() -= 1;
+            }
+            let (var, value) = match match_assignment(&stmts[i]) {
+                Some((var, value)) if is_stack_variable(var) => (var.clone(), value.clone()),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            if self.propagate_copy(stmts, i, &var, &value) || self.inline(stmts, i, &var, &value) {
+                stmts.remove(i);
+                self.counts.remove(&var);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Replaces the uses of a copy of `this` or of a variable by the original, if they all follow
+    /// the assignment `stmts[i]` of the copy, and the original isn't assigned in between.
+    fn propagate_copy(
+        &mut self,
+        stmts: &mut [Statement],
+        i: usize,
+        var: &str,
+        value: &Expr,
+    ) -> bool {
+        let original = match *value {
+            Expr::This => None,
+            Expr::Assignable(ref assignable) => match **assignable {
+                Assignable::Variable(ref original, _) if original != var => Some(original),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        let last = match (i + 1..stmts.len())
+            .rev()
+            .find(|&j| uses_variable(&stmts[j..=j], var))
+        {
+            Some(last) => last,
+            None => return false,
+        };
+        let uses = &mut stmts[i + 1..=last];
+        if original.is_some_and(|original| is_assigned(uses, original)) {
+            return false;
+        }
+        let mut substitution = Substitution {
+            var,
+            value,
+            count: 0,
+        };
+        let mut replaced = uses.to_vec();
+        for stmt in &mut replaced {
+            substitution.visit_statement(stmt);
+        }
+        if substitution.count + 1 != self.counts[var] {
+            return false;
+        }
+        if let Some(count) = original.and_then(|original| self.counts.get_mut(original)) {
+            *count += substitution.count;
+        }
+        uses.clone_from_slice(&replaced);
+        true
+    }
+
+    /// Moves the value of a stack variable into the next statement if that's its only use and the
+    /// statement evaluates it first, i.e. not in a nested block.
+    fn inline(&mut self, stmts: &mut [Statement], i: usize, var: &str, value: &Expr) -> bool {
+        if self.counts[var] != 2 {
+            return false;
+        }
+        let next = match next_stmt(stmts, i) {
+            Some(next) => next,
+            None => return false,
+        };
+        let mut substitution = Substitution {
+            var,
+            value,
+            count: 0,
+        };
+        match stmts[next] {
+            Statement::Expr(ref mut expr)
+            | Statement::Return(Some(ref mut expr))
+            | Statement::Throw(ref mut expr)
+            | Statement::If {
+                cond: ref mut expr, ..
+            }
+            | Statement::Switch { ref mut expr, .. }
+            | Statement::Synchronized(ref mut expr, _) => {
+                let mut replaced = expr.clone();
+                substitution.visit_expr(&mut replaced);
+                if substitution.count == 1 {
+                    *expr = replaced;
+                }
+            }
+            _ => {}
+        }
+        substitution.count == 1
+    }
+}
+
+/// Matches `if (cond) { var = a; } else { var = b; }` assigning a stack variable, which is
+/// turned into `var = cond ? a : b;`. Returns the variable and the new assignment.
+fn match_conditional_assignment(stmt: &Statement) -> Option<(Ident, Statement)> {
+    let (cond, then, els) = match *stmt {
+        Statement::If {
+            ref cond,
+            ref then,
+            els: Some(ref els),
+        } => (cond, then, els),
+        _ => return None,
+    };
+    let (var, then) = match_only_assignment(then)?;
+    let (other, els) = match_only_assignment(els)?;
+    if var != other || !is_stack_variable(var) {
+        return None;
+    }
+    let value = Expr::IfThenElse {
+        cond: Box::new(cond.clone()),
+        then: Box::new(then.clone()),
+        els: Box::new(els.clone()),
+    };
+    let assignment = stmt_expr(Expr::Assign {
+        to: Box::new(Assignable::Variable(var.clone(), 0)),
+        op: None,
+        from: Box::new(value),
+    });
+    Some((var.clone(), assignment))
+}
+
+/// Matches a block consisting of a single assignment to a variable.
+fn match_only_assignment(block: &Block) -> Option<(&Ident, &Expr)> {
+    let mut stmts = block.1.iter().filter(|&stmt| *stmt != Statement::Nop);
+    match (stmts.next(), stmts.next()) {
+        (Some(stmt), None) if block.0.is_empty() => match_assignment(stmt),
+        _ => None,
+    }
+}
+
+/// Checks whether the statements assign the variable.
+fn is_assigned(stmts: &[Statement], var: &str) -> bool {
+    struct AssignmentVisitor<'a>(&'a str, bool);
+    impl<'a> Visitor for AssignmentVisitor<'a> {
+        fn visit_expr(&mut self, expr: &mut Expr) {
+            if let Expr::Assign { ref to, .. } = *expr {
+                if let Assignable::Variable(ref assigned, _) = **to {
+                    self.1 |= assigned == self.0;
+                }
+            }
+            walk_expr(self, expr);
+        }
+    }
+    let mut visitor = AssignmentVisitor(var, false);
+    for stmt in stmts {
+        visitor.visit_statement(&mut stmt.clone());
+    }
+    visitor.1
+}
+
+/// Replaces the reads of a variable by a value, counting them.
+struct Substitution<'a> {
+    var: &'a str,
+    value: &'a Expr,
+    count: usize,
+}
+
+impl<'a> Visitor for Substitution<'a> {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        if as_variable(expr).is_some_and(|var| var == self.var) {
+            *expr = self.value.clone();
+            self.count += 1;
+        } else {
+            walk_expr(self, expr);
+        }
+    }
+}
+
+/// A variable that is declared in the code or as a parameter.
+struct Declared {
+    var: Ident,
+    /// The path of the scope it's declared in, see `ScopeVisitor`.
+    scope: Vec<usize>,
+    typ: Option<Type>,
+    /// Where it's declared, unless it's declared by the code already.
+    placement: Option<Placement>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Placement {
+    /// At the beginning of the block.
+    Block(usize),
+    /// By the initialization of the `for` loop.
+    Loop(usize),
+}
+
+/// Chooses the final names of the variables that were split, given the names used in the code.
+/// Returns the new names by the names of the variables.
+///
+/// A variable that is split from one in whose scope it is, with the same type, is assigned
+/// instead of being declared again, so its placement is removed. This needs them not to interfere
+/// though, since propagating values may have moved the uses of one of them past the assignments
+/// of the other.
+fn final_names(
+    declared: &mut [Declared],
+    names: &HashSet<Ident>,
+    interference: &Interference,
+) -> HashMap<Ident, Ident> {
+    // The enclosing scopes first, so that they keep the names:
+    declared.sort_by_key(|declared| declared.scope.len());
+    let mut named: Vec<(Ident, &[usize], &Option<Type>)> = vec![];
+    // The variables that get each name:
+    let mut named_vars: HashMap<Ident, Vec<&Ident>> = HashMap::new();
+    let mut renames = HashMap::new();
+    for declared in declared.iter_mut() {
+        let original = original_name(&declared.var).to_owned();
+        let (var, scope, typ) = (&declared.var, &declared.scope, &declared.typ);
+        let is_enclosing = |other: &(Ident, &[usize], &Option<Type>)| {
+            other.0 == original && scope.starts_with(other.1) && other.2.is_some() && other.2 == typ
+        };
+        let interferes = |other: &&Ident| {
+            interference
+                .get(var)
+                .is_some_and(|interfering| interfering.contains(*other))
+        };
+        let is_reusable = named.iter().any(is_enclosing)
+            && !named_vars
+                .get(&original)
+                .is_some_and(|vars| vars.iter().any(interferes));
+        let name = if declared.placement.is_some() && is_reusable {
+            declared.placement = None;
+            original
+        } else {
+            let is_free = |name: &str| {
+                !named.iter().any(|other| {
+                    other.0 == name && (other.1.starts_with(scope) || scope.starts_with(other.1))
+                })
+            };
+            let name = if is_free(&original) {
+                original.clone()
+            } else {
+                (1..)
+                    .map(|i| format!("{}_{}", original, i))
+                    .find(|name| !names.contains(name) && is_free(name))
+                    .unwrap()
+            };
+            named.push((name.clone(), scope, typ));
+            name
+        };
+        named_vars.entry(name.clone()).or_default().push(var);
+        if name != *var {
+            renames.insert(var.clone(), name);
+        }
+    }
+    renames
+}
+
+/// The variables that each variable interferes with: those that are live where it's assigned,
+/// or the other way around.
+type Interference = HashMap<Ident, HashSet<Ident>>;
+
+/// Where a `break` or `continue` statement goes to.
+struct Target {
+    label: Option<Ident>,
+    /// Whether it's a loop rather than a `switch` statement.
+    is_loop: bool,
+    /// The variables live after the statement, and at the next iteration of the loop.
+    after: HashSet<Ident>,
+    next: HashSet<Ident>,
+}
+
+/// Computes which variables interfere by going backwards through the code and collecting the
+/// variables that are live, i.e. may be read before they are assigned again.
+#[derive(Default)]
+struct Liveness {
+    interference: Interference,
+    targets: Vec<Target>,
+    /// The variables live at the handlers of the enclosing `try` statements, which an exception
+    /// may go to from anywhere.
+    handlers: HashSet<Ident>,
+    read: HashSet<Ident>,
+}
+
+impl Liveness {
+    /// Computes the interference in the code. A variable that is never read doesn't interfere,
+    /// since its assignments may be meant for a copy of code that was merged with another one and
+    /// reads it by the name of another variable, e.g. the finally code after a catch clause.
+    fn new(code: &Block) -> Liveness {
+        let mut liveness = Liveness::default();
+        liveness.block(code, HashSet::new());
+        let read = &liveness.read;
+        liveness.interference.retain(|var, interfering| {
+            interfering.retain(|other| read.contains(other));
+            read.contains(var)
+        });
+        liveness
+    }
+
+    /// Returns the variables live before the block, given those live after it.
+    fn block(&mut self, block: &Block, after: HashSet<Ident>) -> HashSet<Ident> {
+        let mut live = self.statements(&block.1, after);
+        for decl in block.0.iter().rev() {
+            live = self.declaration(decl, live);
+        }
+        live
+    }
+
+    fn statements(&mut self, stmts: &[Statement], after: HashSet<Ident>) -> HashSet<Ident> {
+        stmts
+            .iter()
+            .rev()
+            .fold(after, |live, stmt| self.statement(stmt, live))
+    }
+
+    fn declaration(&mut self, decl: &LocalDecl, after: HashSet<Ident>) -> HashSet<Ident> {
+        match decl.init {
+            Some(ref init) => {
+                let live = self.assign(&decl.ident, after, false);
+                self.expr(init, live)
+            }
+            None => after,
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement, after: HashSet<Ident>) -> HashSet<Ident> {
+        // The variables live when the condition of a loop ends it, unless it's always true:
+        let exit = |cond: &Expr, after: &HashSet<Ident>| match *cond {
+            Expr::Literal(Literal::Boolean(true)) => HashSet::new(),
+            _ => after.clone(),
+        };
+        let mut live = match *stmt {
+            Statement::Nop | Statement::Comment(_) => after,
+            Statement::Decl(ref decl) => self.declaration(decl, after),
+            Statement::Expr(ref expr)
+            | Statement::MonitorEnter(ref expr)
+            | Statement::MonitorExit(ref expr) => self.expr(expr, after),
+            Statement::Block(ref block) => self.block(block, after),
+            Statement::If {
+                ref cond,
+                ref then,
+                ref els,
+            } => {
+                let mut live = self.block(then, after.clone());
+                live.extend(match *els {
+                    Some(ref els) => self.block(els, after),
+                    None => after,
+                });
+                self.expr(cond, live)
+            }
+            Statement::While {
+                ref label,
+                ref cond,
+                ref body,
+                do_while,
+            } => {
+                // The variables live at the condition, until they don't change anymore:
+                let mut head = HashSet::new();
+                loop {
+                    let body_live = self.loop_body(label, &after, &head, body);
+                    let mut live = exit(cond, &after);
+                    live.extend(body_live.iter().cloned());
+                    let new_head = self.expr(cond, live);
+                    if new_head == head {
+                        break if do_while { body_live } else { head };
+                    }
+                    head = new_head;
+                }
+            }
+            Statement::For(ref label, ref control, ref body) => {
+                let mut head = HashSet::new();
+                match **control {
+                    ForControl::General {
+                        ref init,
+                        ref cond,
+                        ref update,
+                    } => loop {
+                        let update_live = self.expr(update, head.clone());
+                        let mut live = self.loop_body(label, &after, &update_live, body);
+                        live.extend(exit(cond, &after));
+                        let new_head = self.expr(cond, live);
+                        if new_head == head {
+                            break match *init {
+                                ForInit::Decl(ref decl) => self.declaration(decl, head),
+                                ForInit::Expr(ref init) => self.expr(init, head),
+                            };
+                        }
+                        head = new_head;
+                    },
+                    ForControl::Iteration {
+                        ref elem,
+                        ref container,
+                    } => loop {
+                        let body_live = self.loop_body(label, &after, &head, body);
+                        let mut new_head = self.assign(&elem.ident, body_live, false);
+                        new_head.extend(after.iter().cloned());
+                        if new_head == head {
+                            break self.expr(container, head);
+                        }
+                        head = new_head;
+                    },
+                }
+            }
+            Statement::Break(ref label) => self
+                .target(label, false)
+                .map_or_else(HashSet::new, |target| target.after.clone()),
+            Statement::Continue(ref label) => self
+                .target(label, true)
+                .map_or_else(HashSet::new, |target| target.next.clone()),
+            Statement::Return(None) => HashSet::new(),
+            Statement::Return(Some(ref expr)) | Statement::Throw(ref expr) => {
+                self.expr(expr, HashSet::new())
+            }
+            Statement::ThisCall(ref args) | Statement::SuperCall(ref args) => args
+                .iter()
+                .rev()
+                .fold(after, |live, arg| self.expr(arg, live)),
+            Statement::Synchronized(ref expr, ref block) => {
+                let live = self.block(block, after);
+                self.expr(expr, live)
+            }
+            Statement::Try {
+                ref resources,
+                ref block,
+                ref catches,
+                ref finally,
+            } => {
+                // An exception goes through the finally block to the enclosing handlers:
+                let outer = self.handlers.clone();
+                let finally_live = self.block(finally, outer.clone());
+                self.handlers.extend(finally_live);
+                let after = self.block(finally, after);
+                let mut handlers = self.handlers.clone();
+                for catch in catches {
+                    let live = self.block(&catch.block, after.clone());
+                    handlers.extend(self.assign(&catch.ident, live, false));
+                }
+                self.handlers = handlers;
+                let live = self.block(block, after);
+                let live = resources
+                    .iter()
+                    .rev()
+                    .fold(live, |live, resource| self.declaration(resource, live));
+                self.handlers = outer;
+                live
+            }
+            Statement::Switch {
+                ref label,
+                ref expr,
+                ref cases,
+            } => {
+                self.targets.push(Target {
+                    label: label.clone(),
+                    is_loop: false,
+                    after: after.clone(),
+                    next: HashSet::new(),
+                });
+                let has_default = cases.iter().any(|case| case.values.contains(&None));
+                let mut entry = if has_default {
+                    HashSet::new()
+                } else {
+                    after.clone()
+                };
+                // Each case falls through to the next one:
+                let mut live = after;
+                for case in cases.iter().rev() {
+                    live = self.statements(&case.stmts, live);
+                    entry.extend(live.iter().cloned());
+                }
+                self.targets.pop();
+                self.expr(expr, entry)
+            }
+        };
+        live.extend(self.handlers.iter().cloned());
+        live
+    }
+
+    /// Returns the variables live before the body of the loop, given those live after the loop
+    /// and at its next iteration.
+    fn loop_body(
+        &mut self,
+        label: &Option<Ident>,
+        after: &HashSet<Ident>,
+        next: &HashSet<Ident>,
+        body: &Block,
+    ) -> HashSet<Ident> {
+        self.targets.push(Target {
+            label: label.clone(),
+            is_loop: true,
+            after: after.clone(),
+            next: next.clone(),
+        });
+        let live = self.block(body, next.clone());
+        self.targets.pop();
+        live
+    }
+
+    /// The statement that a `break` or `continue` statement with the label goes to.
+    fn target(&self, label: &Option<Ident>, is_continue: bool) -> Option<&Target> {
+        self.targets.iter().rev().find(|target| match *label {
+            Some(_) => target.label == *label,
+            None => target.is_loop || !is_continue,
+        })
+    }
+
+    fn expr(&mut self, expr: &Expr, after: HashSet<Ident>) -> HashSet<Ident> {
+        let mut accesses = AccessVisitor::default();
+        accesses.visit_expr(&mut expr.clone());
+        let mut live = after;
+        for (var, is_assigned, is_conditional) in accesses.accesses.into_iter().rev() {
+            if is_assigned {
+                live = self.assign(&var, live, is_conditional);
+            } else {
+                self.read.insert(var.clone());
+                live.insert(var);
+            }
+        }
+        live
+    }
+
+    /// Assigns the variable, which interferes with the variables live then. A conditional
+    /// assignment may not happen, so the variable may still be live before.
+    fn assign(
+        &mut self,
+        var: &Ident,
+        after: HashSet<Ident>,
+        is_conditional: bool,
+    ) -> HashSet<Ident> {
+        let mut live = after;
+        for other in live.iter().chain(&self.handlers) {
+            if other != var {
+                let interference = &mut self.interference;
+                interference
+                    .entry(var.clone())
+                    .or_default()
+                    .insert(other.clone());
+                interference
+                    .entry(other.clone())
+                    .or_default()
+                    .insert(var.clone());
+            }
+        }
+        if !is_conditional {
+            live.remove(var);
+        }
+        live
+    }
+}
+
+/// Collects the reads and assignments of the variables in the order of evaluation, and whether
+/// they are conditional, e.g. in the second operand of `&&`.
+#[derive(Default)]
+struct AccessVisitor {
+    accesses: Vec<(Ident, bool, bool)>,
+    conditional: usize,
+}
+
+impl AccessVisitor {
+    fn conditionally(&mut self, expr: &mut Expr) {
+        self.conditional += 1;
+        self.visit_expr(expr);
+        self.conditional -= 1;
+    }
+}
+
+impl Visitor for AccessVisitor {
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        match *expr {
+            Expr::Assign {
+                ref mut to,
+                op,
+                ref mut from,
+            } => match **to {
+                // The variable is assigned after evaluating the value:
+                Assignable::Variable(ref var, _) => {
+                    if op.is_some() {
+                        self.accesses
+                            .push((var.clone(), false, self.conditional > 0));
+                    }
+                    self.visit_expr(from);
+                    self.accesses
+                        .push((var.clone(), true, self.conditional > 0));
+                }
+                ref mut to => {
+                    self.visit_assignable(to);
+                    self.visit_expr(from);
+                }
+            },
+            Expr::BinaryOp(BinOp::LogAnd, ref mut a, ref mut b)
+            | Expr::BinaryOp(BinOp::LogOr, ref mut a, ref mut b) => {
+                self.visit_expr(a);
+                self.conditionally(b);
+            }
+            Expr::IfThenElse {
+                ref mut cond,
+                ref mut then,
+                ref mut els,
+            } => {
+                self.visit_expr(cond);
+                self.conditionally(then);
+                self.conditionally(els);
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+
+    fn visit_assignable(&mut self, assignable: &mut Assignable) {
+        if let Assignable::Variable(ref var, _) = *assignable {
+            self.accesses
+                .push((var.clone(), false, self.conditional > 0));
+        }
+        walk_assignable(self, assignable);
+    }
+}
+
+/// Collects the scopes the variables are used in: the paths of the blocks and `for` loops
+/// containing all their uses, which are numbered in the order in which they are visited.
+#[derive(Default)]
+struct ScopeVisitor {
+    path: Vec<usize>,
+    next_scope: usize,
+    /// The variables in the order of their first use.
+    used: Vec<Ident>,
+    scopes: HashMap<Ident, Vec<usize>>,
+    /// The scopes that are `for` loops.
+    loops: HashSet<usize>,
+    /// The variables assigned by the initialization of the `for` loops.
+    loop_inits: HashMap<usize, Ident>,
+    /// The variables declared by the code, e.g. the elements of enhanced `for` loops, with the
+    /// scopes they are declared in.
+    declared: Vec<(Ident, Vec<usize>)>,
+}
+
+impl ScopeVisitor {
+    fn enter(&mut self) -> usize {
+        let scope = self.next_scope;
+        self.next_scope += 1;
+        self.path.push(scope);
+        scope
+    }
+}
+
+impl Visitor for ScopeVisitor {
+    fn visit_block(&mut self, block: &mut Block) {
+        self.enter();
+        for declaration in &block.0 {
+            self.declared
+                .push((declaration.ident.clone(), self.path.clone()));
+        }
+        walk_block(self, block);
+        self.path.pop();
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        match *stmt {
+            Statement::For(_, ref control, _) => {
+                let scope = self.enter();
+                self.loops.insert(scope);
+                match **control {
+                    ForControl::Iteration { ref elem, .. }
+                    | ForControl::General {
+                        init: ForInit::Decl(ref elem),
+                        ..
+                    } => self.declared.push((elem.ident.clone(), self.path.clone())),
+                    ForControl::General {
+                        init: ForInit::Expr(ref init),
+                        ..
+                    } => {
+                        if let Some((var, _)) = match_assignment(&stmt_expr(init.clone())) {
+                            self.loop_inits.insert(scope, var.clone());
+                        }
+                    }
+                }
+                walk_statement(self, stmt);
+                self.path.pop();
+            }
+            Statement::Try {
+                ref mut resources,
+                ref mut block,
+                ref mut catches,
+                ref mut finally,
+            } => {
+                // Like `walk_statement`, but the exception of a catch clause is only in scope in
+                // its block:
+                for resource in resources {
+                    self.declared
+                        .push((resource.ident.clone(), self.path.clone()));
+                    if let Some(ref mut init) = resource.init {
+                        self.visit_expr(init);
+                    }
+                }
+                self.visit_block(block);
+                for catch in catches {
+                    let mut scope = self.path.clone();
+                    scope.push(self.next_scope);
+                    self.declared.push((catch.ident.clone(), scope));
+                    self.visit_block(&mut catch.block);
+                }
+                self.visit_block(finally);
+            }
+            _ => walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_assignable(&mut self, assignable: &mut Assignable) {
+        if let Assignable::Variable(ref var, _) = *assignable {
+            if let Some(scope) = self.scopes.get_mut(var) {
+                let common = scope
+                    .iter()
+                    .zip(&self.path)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                scope.truncate(common);
+            } else {
+                self.used.push(var.clone());
+                self.scopes.insert(var.clone(), self.path.clone());
+            }
+        }
+        walk_assignable(self, assignable);
+    }
+}
+
+/// Inserts the declarations into the blocks and `for` loops, which are numbered like by
+/// `ScopeVisitor`.
+struct DeclarationVisitor {
+    next_scope: usize,
+    /// The declarations of each block, in the order of the first uses of the variables.
+    blocks: HashMap<usize, Vec<LocalDecl>>,
+    /// The types of the variables the `for` loops declare in their initialization.
+    loops: HashMap<usize, Type>,
+}
+
+impl Visitor for DeclarationVisitor {
+    fn visit_block(&mut self, block: &mut Block) {
+        let scope = self.next_scope;
+        self.next_scope += 1;
+        walk_block(self, block);
+        if let Some(declarations) = self.blocks.remove(&scope) {
+            declare_in_block(block, declarations);
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        if let Statement::For(_, ref mut control, _) = *stmt {
+            let scope = self.next_scope;
+            self.next_scope += 1;
+            if let Some(typ) = self.loops.remove(&scope) {
+                if let ForControl::General { ref mut init, .. } = **control {
+                    let decl =
+                        match *init {
+                            ForInit::Expr(ref init) => match_assignment(&stmt_expr(init.clone()))
+                                .map(|(var, value)| LocalDecl {
+                                    ident: var.clone(),
+                                    typ,
+                                    init: Some(value.clone()),
+                                }),
+                            ForInit::Decl(_) => None,
+                        };
+                    if let Some(decl) = decl {
+                        *init = ForInit::Decl(decl);
+                    }
+                }
+            }
+        }
+        walk_statement(self, stmt);
+    }
+}
+
+/// Declares the variables in the block. The assignments starting the block become the
+/// initializers of the variables they assign first, and a later assignment becomes a declaration
+/// if the variable isn't used before. The other variables are declared at the beginning of the
+/// block.
+fn declare_in_block(block: &mut Block, mut declarations: Vec<LocalDecl>) {
+    let mut i = 0;
+    while i < block.1.len() {
+        if block.1[i] == Statement::Nop {
+            i += 1;
+            continue;
+        }
+        let merged = match_assignment(&block.1[i]).and_then(|(var, value)| {
+            let j = declarations
+                .iter()
+                .position(|decl| decl.ident == *var && decl.init.is_none())?;
+            // The value can't use variables that are declared but not assigned yet:
+            let value = [stmt_expr(value.clone())];
+            if declarations
+                .iter()
+                .any(|decl| decl.init.is_none() && uses_variable(&value, &decl.ident))
+            {
+                return None;
+            }
+            match value {
+                [Statement::Expr(value)] => Some((j, value)),
+                _ => None,
+            }
+        });
+        match merged {
+            Some((j, value)) => {
+                declarations[j].init = Some(value);
+                block.1.remove(i);
+            }
+            None => break,
+        }
+    }
+    let stmts = &mut block.1;
+    declarations.retain(|decl| decl.init.is_some() || !declare_at_assignment(stmts, decl));
+    block.0.extend(declarations);
+}
+
+/// Turns the statement where the variable is first used into its declaration if it assigns the
+/// variable. Returns whether it did.
+fn declare_at_assignment(stmts: &mut [Statement], decl: &LocalDecl) -> bool {
+    let first = stmts
+        .iter()
+        .position(|stmt| uses_variable(slice::from_ref(stmt), &decl.ident));
+    let stmt = match first {
+        Some(first) => &mut stmts[first],
+        None => return false,
+    };
+    let value = match match_assignment(stmt) {
+        Some((var, value)) if *var == decl.ident => value.clone(),
+        _ => return false,
+    };
+    if uses_variable(&[stmt_expr(value.clone())], &decl.ident) {
+        return false;
+    }
+    *stmt = Statement::Decl(LocalDecl {
+        init: Some(value),
+        ..decl.clone()
+    });
+    true
+}
+
+/// Calls the function on the name of each variable, including the declared ones.
+struct RenameVisitor<'a>(&'a mut dyn FnMut(&mut Ident));
+
+impl<'a> Visitor for RenameVisitor<'a> {
+    fn visit_block(&mut self, block: &mut Block) {
+        for declaration in &mut block.0 {
+            (self.0)(&mut declaration.ident);
+        }
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        match *stmt {
+            Statement::Decl(ref mut decl) => (self.0)(&mut decl.ident),
+            Statement::For(_, ref mut control, _) => match **control {
+                ForControl::Iteration { ref mut elem, .. }
+                | ForControl::General {
+                    init: ForInit::Decl(ref mut elem),
+                    ..
+                } => (self.0)(&mut elem.ident),
+                ForControl::General { .. } => {}
+            },
+            Statement::Try {
+                ref mut resources,
+                ref mut catches,
+                ..
+            } => {
+                for resource in resources {
+                    (self.0)(&mut resource.ident);
+                }
+                for catch in catches {
+                    (self.0)(&mut catch.ident);
+                }
+            }
+            _ => {}
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_assignable(&mut self, assignable: &mut Assignable) {
+        if let Assignable::Variable(ref mut var, _) = *assignable {
+            (self.0)(var);
+        }
+        walk_assignable(self, assignable);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decompiler::fixtures::*;
+
+    /// Splits the variables, returning the statements of the blocks in order.
+    fn split_to_string(mut cfg: Cfg<Statement, Expr>) -> String {
+        split(&mut cfg);
+        let stmts = cfg
+            .graph
+            .node_indices()
+            .flat_map(|block| cfg.graph[block].stmts.clone())
+            .collect::<Vec<_>>();
+        to_string(&stmts)
+    }
+
+    #[test]
+    fn reaching_definitions_at_statements() {
+        let cfg = build(
+            vec![
+                (vec![], Some("c")),
+                (vec![assign("local_1", var("a"))], None),
+                (
+                    vec![call("f", vec![var("local_1")]), assign("local_1", var("b"))],
+                    Some("local_1"),
+                ),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 2, Edge::Branch(true)),
+                (1, 2, Edge::Branch(true)),
+                (2, 4, Edge::Branch(false)),
+                (2, 4, Edge::Branch(true)),
+            ],
+        );
+        let reaching = ReachingDefinitions::new(&cfg);
+        let get = |location| {
+            let mut locations = reaching
+                .get("local_1", location)
+                .map(|locations| locations.iter().cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            locations.sort_by_key(|&Location(block, i)| (block, i));
+            locations
+        };
+        assert_eq!(get(Location(1, 0)), [ON_ENTRY]);
+        assert_eq!(get(Location(2, 0)), [Location(1, 0), ON_ENTRY]);
+        // The terminator comes after the last statement:
+        assert_eq!(get(Location(2, 2)), [Location(2, 1)]);
+        assert_eq!(reaching.get("a", Location(2, 0)), None);
+    }
+
+    #[test]
+    fn reused_slot_with_different_types() {
+        let string = Expr::Literal(Literal::String("s".to_owned()));
+        let cfg = build(
+            vec![(
+                vec![
+                    assign("local_1", Expr::Literal(Literal::Integer(1))),
+                    call("f", vec![var("local_1")]),
+                    assign("local_1", string),
+                    call("g", vec![var("local_1")]),
+                ],
+                None,
+            )],
+            &[(0, 2, Edge::Branch(true))],
+        );
+        let expected = [
+            "local_1 = 1;",
+            "T.f(local_1);",
+            "local_1#1 = \"s\";",
+            "T.g(local_1#1);",
+        ];
+        assert_eq!(split_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn assignments_reaching_common_use() {
+        let cfg = build(
+            vec![
+                (vec![], Some("c")),
+                (vec![assign("local_1", var("a"))], None),
+                (vec![assign("local_1", var("b"))], None),
+                (vec![call("f", vec![var("local_1")])], None),
+                (vec![assign("local_1", var("d"))], None),
+                (vec![call("g", vec![var("local_1")])], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(false)),
+                (0, 2, Edge::Branch(true)),
+                (1, 3, Edge::Branch(true)),
+                (2, 3, Edge::Branch(true)),
+                (3, 4, Edge::Branch(true)),
+                (4, 5, Edge::Branch(true)),
+                (5, 7, Edge::Branch(true)),
+            ],
+        );
+        let expected = [
+            "local_1 = a;",
+            "local_1 = b;",
+            "T.f(local_1);",
+            "local_1#1 = d;",
+            "T.g(local_1#1);",
+        ];
+        assert_eq!(split_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn parameter_and_loop_update() {
+        // The parameter is only read, the counter is updated in the loop, and its final update
+        // isn't used anymore:
+        let increment = Expr::BinaryOp(
+            BinOp::Add,
+            Box::new(var("local_1")),
+            Box::new(Expr::Literal(Literal::Integer(1))),
+        );
+        let cfg = build(
+            vec![
+                (vec![assign("local_1", var("local_0"))], None),
+                (vec![], Some("c")),
+                (
+                    vec![
+                        call("f", vec![var("local_1")]),
+                        assign("local_1", increment),
+                    ],
+                    None,
+                ),
+                (vec![assign("local_1", var("local_0"))], None),
+            ],
+            &[
+                (0, 1, Edge::Branch(true)),
+                (1, 2, Edge::Branch(false)),
+                (1, 3, Edge::Branch(true)),
+                (2, 1, Edge::Branch(true)),
+                (3, 5, Edge::Branch(true)),
+            ],
+        );
+        let expected = [
+            "local_1 = local_0;",
+            "T.f(local_1);",
+            "local_1 = local_1 + 1;",
+            "local_1#1 = local_0;",
+        ];
+        assert_eq!(split_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn assignments_reaching_handler() {
+        // Both assignments in the try block may be the value when the exception is caught:
+        let cfg = build(
+            vec![
+                (
+                    vec![
+                        assign("local_1", var("a")),
+                        call("f", vec![]),
+                        assign("local_1", var("b")),
+                        call("f", vec![]),
+                    ],
+                    None,
+                ),
+                (vec![call("g", vec![var("local_1")])], None),
+                (
+                    vec![assign("local_1", var("c")), call("h", vec![var("local_1")])],
+                    None,
+                ),
+            ],
+            &[
+                (0, 1, Edge::Exception(None)),
+                (0, 2, Edge::Branch(true)),
+                (1, 4, Edge::Branch(true)),
+                (2, 4, Edge::Branch(true)),
+            ],
+        );
+        let expected = [
+            "local_1 = a;",
+            "T.f();",
+            "local_1 = b;",
+            "T.f();",
+            "T.g(local_1);",
+            "local_1#1 = c;",
+            "T.h(local_1#1);",
+        ];
+        assert_eq!(split_to_string(cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn interference_of_live_variables() {
+        let block = |stmts| Block(vec![], stmts);
+        let code = block(vec![
+            assign("local_1", var("a")),
+            call("f", vec![var("local_1")]),
+            assign("local_1#1", var("b")),
+            call("f", vec![var("local_1#1")]),
+            assign("local_2", var("a")),
+            Statement::While {
+                label: None,
+                cond: var("c"),
+                body: block(vec![
+                    call("f", vec![var("local_2")]),
+                    assign("local_2#1", var("b")),
+                    call("f", vec![var("local_2#1")]),
+                ]),
+                do_while: false,
+            },
+        ]);
+        let interference = Liveness::new(&code).interference;
+        let interferes =
+            |a: &str, b: &str| interference.get(a).is_some_and(|vars| vars.contains(b));
+        assert!(!interferes("local_1", "local_1#1"));
+        assert!(interferes("local_2", "local_2#1"));
+        assert!(interferes("local_2#1", "local_2"));
+    }
+
+    #[test]
+    fn declarations_at_first_assignments() {
+        let f = |arg| invoke("f", &[Type::Int], Type::Int, vec![arg]);
+        let block = |stmts| Block(vec![], stmts);
+        let mut code = block(vec![
+            assign("x", f(var("x"))),
+            assign("y", f(var("x"))),
+            Statement::If {
+                cond: var("c"),
+                then: block(vec![assign("z", int(1))]),
+                els: Some(block(vec![assign("z", int(2))])),
+            },
+            call("g", vec![var("y"), var("z")]),
+        ]);
+        let parameters = [("x".to_owned(), Type::Int)];
+        let mut locals = [("y".to_owned(), Type::Int), ("z".to_owned(), Type::Int)];
+        declare(&mut code, &parameters, &mut locals);
+        assert_eq!(
+            to_string(&[Statement::Block(code)]),
+            "{\n    int z;\n    x = T.f(x);\n    int y = T.f(x);\n    if (c) {\n        z = 1;\n    \
+             } else {\n        z = 2;\n    }\n    T.g(y, z);\n}"
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use decompiler::types::*;
+use disassembler::pretty::PrettyBody;
+use disassembler::types::{Literal, Type};
 use pretty::*;
 use std::cmp::Ordering;
 
@@ -57,6 +59,16 @@ where
     parens(doc)
 }
 
+/// The object whose member is accessed, in parentheses unless it binds tighter than `.`.
+/// Invocations are printed as if they had the precedence of the access itself, so that chained
+/// calls don't need parentheses.
+fn receiver(e: &Expr) -> Doc {
+    match *e {
+        Expr::Invoke(..) => e.pretty(),
+        _ => parens_if(e, Precedence::Access, false),
+    }
+}
+
 fn parens(d: Doc) -> Doc {
     doc("(") + d + ")"
 }
@@ -74,9 +86,12 @@ impl HasPrecedence for Expr {
     fn precedence(&self) -> Precedence {
         match *self {
             Expr::Assignable(ref v) => v.precedence(),
-            Expr::UnaryOp(..) => Precedence::Unary,
+            // Array creations can't be indexed without parentheses:
+            Expr::UnaryOp(..) | Expr::Cast(..) | Expr::NewArray { .. } | Expr::ArrayInit { .. } => {
+                Precedence::Unary
+            }
             Expr::BinaryOp(op, _, _) => match op {
-                BinOp::Cmp(_) => Precedence::Cmp,
+                BinOp::Cmp(_) | BinOp::FloatCmp(_) => Precedence::Cmp,
                 BinOp::Add | BinOp::Sub => Precedence::Add,
                 BinOp::Mul | BinOp::Div | BinOp::Rem => Precedence::Mul,
                 BinOp::LogAnd => Precedence::LogAnd,
@@ -88,9 +103,15 @@ impl HasPrecedence for Expr {
                 | BinOp::BitOr
                 | BinOp::BitXor => Precedence::BitOp,
             },
+            Expr::InstanceOf(..) => Precedence::Cmp,
             Expr::IfThenElse { .. } => Precedence::Ternary,
+            Expr::ArrayLength(..) => Precedence::Access,
             Expr::Invoke(..) | Expr::Assign { .. } => Precedence::Assign,
-            Expr::Literal(_) | Expr::New { .. } | Expr::This | Expr::Super => Precedence::Tightest,
+            Expr::Literal(_)
+            | Expr::New { .. }
+            | Expr::This
+            | Expr::QualifiedThis(_)
+            | Expr::Super => Precedence::Tightest,
         }
     }
 }
@@ -107,10 +128,22 @@ impl<T> PrettyWith<T> for Expr {
                 + parens_if(&**e2, self.precedence(), true))
                 .group(
             ),
-            Expr::IfThenElse { .. } => unimplemented!(),
+            Expr::IfThenElse {
+                ref cond,
+                ref then,
+                ref els,
+            } => group(
+                parens_if(&**cond, self.precedence(), true)
+                    + spaceline()
+                    + "? "
+                    + parens_if(&**then, self.precedence(), true)
+                    + spaceline()
+                    + ": "
+                    + parens_if(&**els, self.precedence(), true),
+            ),
             Expr::Invoke(ref this, ref method, ref class, ref args) => {
                 let result = if let Some(ref this) = *this {
-                    this.pretty()
+                    receiver(this)
                 } else {
                     class.0.to_owned().into()
                 };
@@ -127,8 +160,46 @@ impl<T> PrettyWith<T> for Expr {
                 let start = to.pretty() + format!(" {}=", op_string);
                 group(group(start) + spaceline() + from.pretty())
             }
-            Expr::New { .. } => unimplemented!(),
+            Expr::New {
+                ref class,
+                ref args,
+            } => group(doc("new ") + class + tupled(args.iter().map(Pretty::pretty))),
+            Expr::NewArray {
+                ref typ,
+                ref lengths,
+            } => {
+                let mut element = typ;
+                let mut dimensions = 0;
+                while let Type::Array(ref inner) = *element {
+                    element = inner;
+                    dimensions += 1;
+                }
+                let lengths = lengths
+                    .iter()
+                    .map(|length| group(doc('[') + length.pretty() + ']'));
+                let unspecified = (lengths.len()..dimensions).map(|_| doc("[]"));
+                doc("new ") + element + concat(lengths) + concat(unspecified)
+            }
+            Expr::ArrayInit {
+                ref typ,
+                ref elements,
+            } => {
+                let elements = elements.iter().map(Pretty::pretty);
+                doc("new ") + typ + enclose_sep('{', '}', doc(',') + spaceline(), elements)
+            }
+            Expr::Cast(ref typ, ref e) => {
+                let e = match **e {
+                    Expr::Invoke(..) => e.pretty(),
+                    _ => parens_if(&**e, self.precedence(), false),
+                };
+                doc("(") + typ + ") " + e
+            }
+            Expr::InstanceOf(ref e, ref typ) => {
+                parens_if(&**e, self.precedence(), false) + " instanceof " + typ
+            }
+            Expr::ArrayLength(ref e) => receiver(e) + ".length",
             Expr::This => "this".into(),
+            Expr::QualifiedThis(ref class) => doc(&class.0) + ".this",
             Expr::Super => "super".into(),
         }
     }
@@ -144,13 +215,16 @@ impl<T> PrettyWith<T> for Assignable {
                 ref field,
             } => {
                 let result = if let Some(ref this) = *this {
-                    this.pretty()
+                    receiver(this)
                 } else {
                     class.0.to_owned().into()
                 };
-                result + breakline() + format!(".{}", field.name)
+                group(result + breakline() + format!(".{}", field.name))
             }
-            Assignable::ArrayAccess { .. } => unimplemented!(),
+            Assignable::ArrayAccess {
+                ref array,
+                ref index,
+            } => receiver(array) + group(doc('[') + index.pretty() + ']'),
         }
     }
 }
@@ -160,6 +234,7 @@ impl<T> PrettyWith<T> for Statement {
         match *self {
             Statement::Nop => doc("nop;"),
             Statement::Expr(ref e) => nest(4, e.pretty() + ";"),
+            Statement::Decl(ref decl) => decl.pretty(),
             Statement::Block(ref block) => block.pretty(),
             Statement::If {
                 ref cond,
@@ -169,6 +244,7 @@ impl<T> PrettyWith<T> for Statement {
                 doc("if (") + cond.pretty() + ") " + then.pretty()
                     + els
                         .as_ref()
+                        .filter(|e| !is_empty(e))
                         .map_or_else(empty, |e| doc(" else ") + e.pretty())
             }
             Statement::While {
@@ -178,19 +254,48 @@ impl<T> PrettyWith<T> for Statement {
                 do_while,
             } => {
                 let while_part = doc("while (") + cond.pretty() + ")";
-                let while_part = if let Some(ref label) = *label {
-                    group(doc(label) + ':' + spaceline() + while_part)
+                let (header, footer) = if do_while {
+                    (doc("do"), doc(' ') + while_part + ';')
                 } else {
-                    while_part
+                    (while_part, empty())
                 };
-                let (header, footer) = if do_while {
-                    (doc("do "), while_part + ';')
+                let header = if let Some(ref label) = *label {
+                    group(doc(label) + ':' + spaceline() + header)
                 } else {
-                    (while_part + ' ', empty())
+                    header
                 };
-                header + body.pretty() + footer
+                header + ' ' + body.pretty() + footer
+            }
+            Statement::For(ref label, ref control, ref body) => {
+                let control = match **control {
+                    ForControl::Iteration {
+                        ref elem,
+                        ref container,
+                    } => doc(&elem.typ) + format!(" {} : ", elem.ident) + container.pretty(),
+                    ForControl::General {
+                        ref init,
+                        ref cond,
+                        ref update,
+                    } => {
+                        let init = match *init {
+                            ForInit::Decl(ref decl) => decl.pretty(),
+                            ForInit::Expr(ref e) => e.pretty() + ";",
+                        };
+                        init + spaceline()
+                            + cond.pretty()
+                            + ";"
+                            + spaceline()
+                            + pretty_update(update)
+                    }
+                };
+                let for_part = doc("for (") + group(nest(4, control)) + ")";
+                let for_part = if let Some(ref label) = *label {
+                    group(doc(label) + ':' + spaceline() + for_part)
+                } else {
+                    for_part
+                };
+                for_part + ' ' + body.pretty()
             }
-            Statement::For(..) => unimplemented!(),
             Statement::Break(ref label) => {
                 doc("break") + label.as_ref().map_or_else(empty, |l| doc(" ") + l) + ";"
             }
@@ -206,28 +311,112 @@ impl<T> PrettyWith<T> for Statement {
             Statement::SuperCall(ref args) => {
                 doc("super") + tupled(args.iter().map(Pretty::pretty)) + ";"
             }
-            Statement::Throw(..) => unimplemented!(),
-            Statement::Synchronized(..) => unimplemented!(),
-            Statement::Try { .. } => unimplemented!(),
+            Statement::Throw(ref e) => nest(4, doc("throw ") + e.pretty() + ";"),
+            Statement::Synchronized(ref e, ref block) => {
+                doc("synchronized (") + group(e.pretty()) + ") " + block.pretty()
+            }
+            // Left if no `synchronized` statement could be recovered, which isn't valid Java:
+            Statement::MonitorEnter(ref e) => doc("/* monitorenter(") + e.pretty() + ") */",
+            Statement::MonitorExit(ref e) => doc("/* monitorexit(") + e.pretty() + ") */",
+            Statement::Comment(ref text) => {
+                // The comment must not end early:
+                let text = text.replace("*/", "* /");
+                let lines = text
+                    .lines()
+                    .map(|line| newline() + format!(" * {}", line).trim_end());
+                doc("/*") + concat(lines) + newline() + " */"
+            }
+            Statement::Try {
+                ref resources,
+                ref block,
+                ref catches,
+                ref finally,
+            } => {
+                // `try` needs at least one catch clause or a finally clause (or resources):
+                let print_finally =
+                    !finally.1.is_empty() || (catches.is_empty() && resources.is_empty());
+                let resources = if resources.is_empty() {
+                    empty()
+                } else {
+                    let resources = resources.iter().map(|r| {
+                        let init = r
+                            .init
+                            .as_ref()
+                            .map_or_else(empty, |v| doc(" = ") + v.pretty());
+                        doc(&r.typ) + format!(" {}", r.ident) + init
+                    });
+                    doc("(") + intersperse(resources, doc(";") + spaceline()) + ") "
+                };
+                let catches = catches.iter().map(|catch| {
+                    let types = intersperse(catch.types.iter().map(doc), " | ");
+                    doc(" catch (") + types + format!(" {}) ", catch.ident) + catch.block.pretty()
+                });
+                let finally = if print_finally {
+                    doc(" finally ") + finally.pretty()
+                } else {
+                    empty()
+                };
+                doc("try ") + resources + block.pretty() + concat(catches) + finally
+            }
+            Statement::Switch {
+                ref label,
+                ref expr,
+                ref cases,
+            } => {
+                let header = doc("switch (") + expr.pretty() + ")";
+                let header = if let Some(ref label) = *label {
+                    group(doc(label) + ':' + spaceline() + header)
+                } else {
+                    header
+                };
+                let cases = cases.iter().map(|case| {
+                    let labels = case.values.iter().map(|value| match *value {
+                        Some(ref value) => doc("case ") + value.pretty() + ":",
+                        None => doc("default:"),
+                    });
+                    let stmts = without_nops(&case.stmts).map(|stmt| newline() + stmt.pretty());
+                    newline() + intersperse(labels, newline()) + nest(4, concat(stmts))
+                });
+                header + " {" + nest(4, concat(cases)) + newline() + "}"
+            }
         }
     }
 }
 
 impl<T> PrettyWith<T> for Block {
     fn pretty_with(&self, _: &T) -> Doc {
+        if is_empty(self) {
+            return doc("{}");
+        }
         let declarations = &self.0;
-        let separator = if declarations.is_empty() {
+        let mut statements = without_nops(&self.1).peekable();
+        let separator = if declarations.is_empty() || statements.peek().is_none() {
             empty()
         } else {
             newline()
         };
         let declarations = intersperse(declarations.iter().map(|d| d.pretty()), newline());
-        let statements = &self.1;
-        let statements = intersperse(statements.iter().map(|d| d.pretty()), newline());
+        let statements = intersperse(statements.map(|d| d.pretty()), newline());
         doc('{') + nest(4, newline() + declarations + separator + statements) + newline() + '}'
     }
 }
 
+/// The body of a method is the block itself.
+impl<T> PrettyBody<T> for Block {
+    fn pretty_body(&self, context: &T) -> Doc {
+        self.pretty_with(context)
+    }
+}
+
+fn is_empty(block: &Block) -> bool {
+    block.0.is_empty() && without_nops(&block.1).next().is_none()
+}
+
+/// The statements that are printed, without the no-ops left behind by the passes.
+fn without_nops(stmts: &[Statement]) -> impl Iterator<Item = &Statement> {
+    stmts.iter().filter(|stmt| **stmt != Statement::Nop)
+}
+
 impl<T> PrettyWith<T> for LocalDecl {
     fn pretty_with(&self, _: &T) -> Doc {
         let initializer = if let Some(ref value) = self.init {
@@ -238,3 +427,20 @@ impl<T> PrettyWith<T> for LocalDecl {
         doc(&self.typ) + format!(" {}", self.ident) + initializer + ';'
     }
 }
+
+/// Prints the update of a `for` loop, using `++` and `--` where possible.
+fn pretty_update(update: &Expr) -> Doc {
+    if let Expr::Assign {
+        ref to,
+        op: Some(op),
+        ref from,
+    } = *update
+    {
+        match (op, &**from) {
+            (BinOp::Add, &Expr::Literal(Literal::Integer(1))) => return to.pretty() + "++",
+            (BinOp::Sub, &Expr::Literal(Literal::Integer(1))) => return to.pretty() + "--",
+            _ => {}
+        }
+    }
+    update.pretty()
+}
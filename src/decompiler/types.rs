@@ -1,9 +1,10 @@
 use disassembler::types::*;
 use std::fmt::*;
+use std::slice;
 
 pub type Ident = String;
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub enum Expr {
     Literal(Literal),
     Assignable(Box<Assignable>),
@@ -24,7 +25,22 @@ pub enum Expr {
         class: Type,
         args: Vec<Expr>,
     },
+    /// Creates an array of type `typ`, with the given lengths of its first dimensions.
+    NewArray {
+        typ: Type,
+        lengths: Vec<Expr>,
+    },
+    /// An array of type `typ` with the given elements, e.g. `new int[]{1, 2}`.
+    ArrayInit {
+        typ: Type,
+        elements: Vec<Expr>,
+    },
+    Cast(Type, Box<Expr>),
+    InstanceOf(Box<Expr>, Type),
+    ArrayLength(Box<Expr>),
     This,
+    /// The instance of an outer class, e.g. `Outer.this`.
+    QualifiedThis(ClassRef),
     Super,
 }
 
@@ -65,6 +81,11 @@ pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &mut Statement
     match *stmt {
         Statement::Nop => (),
         Statement::Expr(ref mut expr) => visitor.visit_expr(expr),
+        Statement::Decl(LocalDecl {
+            init: Some(ref mut expr),
+            ..
+        }) => visitor.visit_expr(expr),
+        Statement::Decl(_) => (),
         Statement::Block(ref mut block) => visitor.visit_block(block),
         Statement::If {
             ref mut cond,
@@ -89,17 +110,25 @@ pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &mut Statement
                     ref mut container, ..
                 } => visitor.visit_expr(container),
                 ForControl::General {
+                    ref mut init,
                     ref mut cond,
                     ref mut update,
-                    ..
                 } => {
+                    match *init {
+                        ForInit::Decl(LocalDecl {
+                            init: Some(ref mut expr),
+                            ..
+                        })
+                        | ForInit::Expr(ref mut expr) => visitor.visit_expr(expr),
+                        ForInit::Decl(_) => (),
+                    }
                     visitor.visit_expr(cond);
                     visitor.visit_expr(update);
                 }
             }
             visitor.visit_block(body);
         }
-        Statement::Break(..) | Statement::Continue(..) => (),
+        Statement::Break(..) | Statement::Continue(..) | Statement::Comment(..) => (),
         Statement::Return(ref mut expr) => {
             expr.as_mut().map(|expr| visitor.visit_expr(expr));
         }
@@ -108,9 +137,45 @@ pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &mut Statement
                 visitor.visit_expr(expr);
             }
         }
-        Statement::Throw(..) => unimplemented!(),
-        Statement::Synchronized(..) => unimplemented!(),
-        Statement::Try { .. } => unimplemented!(),
+        Statement::Throw(ref mut expr)
+        | Statement::MonitorEnter(ref mut expr)
+        | Statement::MonitorExit(ref mut expr) => visitor.visit_expr(expr),
+        Statement::Synchronized(ref mut expr, ref mut block) => {
+            visitor.visit_expr(expr);
+            visitor.visit_block(block);
+        }
+        Statement::Try {
+            ref mut resources,
+            ref mut block,
+            ref mut catches,
+            ref mut finally,
+        } => {
+            for resource in resources {
+                if let Some(ref mut expr) = resource.init {
+                    visitor.visit_expr(expr);
+                }
+            }
+            visitor.visit_block(block);
+            for catch in catches {
+                visitor.visit_block(&mut catch.block);
+            }
+            visitor.visit_block(finally);
+        }
+        Statement::Switch {
+            ref mut expr,
+            ref mut cases,
+            ..
+        } => {
+            visitor.visit_expr(expr);
+            for case in cases {
+                for value in case.values.iter_mut().flat_map(Option::as_mut) {
+                    visitor.visit_expr(value);
+                }
+                for stmt in &mut case.stmts {
+                    visitor.visit_statement(stmt);
+                }
+            }
+        }
     }
 }
 
@@ -146,12 +211,23 @@ pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &mut Expr) {
             visitor.visit_assignable(to.as_mut());
             visitor.visit_expr(from.as_mut());
         }
-        Expr::New { ref mut args, .. } => {
+        Expr::New { ref mut args, .. }
+        | Expr::NewArray {
+            lengths: ref mut args,
+            ..
+        }
+        | Expr::ArrayInit {
+            elements: ref mut args,
+            ..
+        } => {
             for expr in args {
                 visitor.visit_expr(expr)
             }
         }
-        Expr::This => (),
+        Expr::Cast(_, ref mut expr)
+        | Expr::InstanceOf(ref mut expr, _)
+        | Expr::ArrayLength(ref mut expr) => visitor.visit_expr(expr),
+        Expr::This | Expr::QualifiedThis(_) => (),
         Expr::Super => (),
     }
 }
@@ -176,7 +252,151 @@ pub fn mk_variable(id: Ident) -> Expr {
     Expr::Assignable(Box::new(Assignable::Variable(id, 0)))
 }
 
-#[derive(Clone, Debug, Hash)]
+/// Checks whether the variable is read or assigned anywhere in the statements.
+pub fn uses_variable(stmts: &[Statement], ident: &str) -> bool {
+    count_uses(stmts, ident) > 0
+}
+
+/// Counts how often the variable is read or assigned in the statements.
+pub fn count_uses(stmts: &[Statement], ident: &str) -> usize {
+    struct UsageVisitor<'a>(&'a str, usize);
+    impl<'a> Visitor for UsageVisitor<'a> {
+        fn visit_assignable(&mut self, assignable: &mut Assignable) {
+            if let Assignable::Variable(ref var, _) = *assignable {
+                if var == self.0 {
+                    self.1 += 1;
+                }
+            }
+            walk_assignable(self, assignable);
+        }
+    }
+    let mut visitor = UsageVisitor(ident, 0);
+    for stmt in stmts {
+        visitor.visit_statement(&mut stmt.clone());
+    }
+    visitor.1
+}
+
+/// Checks whether the variable is assigned before it's read again by the statements.
+/// Statements after the enclosing block aren't taken into account.
+pub fn is_dead(stmts: &[Statement], var: &str) -> bool {
+    is_assigned_first(stmts, var) != Some(false)
+}
+
+/// Whether the statements assign the variable before reading it, or `None` if they do neither.
+fn is_assigned_first(stmts: &[Statement], var: &str) -> Option<bool> {
+    for stmt in stmts {
+        let assignment = match *stmt {
+            Statement::For(_, ref control, _) => match **control {
+                ForControl::Iteration {
+                    ref elem,
+                    ref container,
+                } if elem.ident == var => {
+                    return Some(!uses_variable(&[stmt_expr(container.clone())], var));
+                }
+                ForControl::General {
+                    init: ForInit::Expr(ref init),
+                    ..
+                } => Some(stmt_expr(init.clone())),
+                _ => None,
+            },
+            Statement::If {
+                ref cond,
+                ref then,
+                ref els,
+            } => {
+                if uses_variable(&[stmt_expr(cond.clone())], var) {
+                    return Some(false);
+                }
+                let els = els.as_ref().map_or(&[][..], |els| &els.1);
+                match (is_assigned_first(&then.1, var), is_assigned_first(els, var)) {
+                    (Some(false), _) | (_, Some(false)) => return Some(false),
+                    (Some(true), Some(true)) => return Some(true),
+                    _ => continue,
+                }
+            }
+            _ => Some(stmt.clone()),
+        };
+        let is_assigned =
+            assignment
+                .as_ref()
+                .and_then(match_assignment)
+                .is_some_and(|(assigned, value)| {
+                    assigned == var && !uses_variable(&[stmt_expr(value.clone())], var)
+                });
+        if is_assigned {
+            return Some(true);
+        }
+        if uses_variable(slice::from_ref(stmt), var) {
+            return Some(false);
+        }
+    }
+    None
+}
+
+/// Checks whether any of the statements or the statements nested in them satisfies `f`.
+pub fn any_stmt<F: Fn(&Statement) -> bool>(stmts: &[Statement], f: F) -> bool {
+    struct StatementVisitor<F>(F, bool);
+    impl<F: Fn(&Statement) -> bool> Visitor for StatementVisitor<F> {
+        fn visit_statement(&mut self, stmt: &mut Statement) {
+            self.1 |= (self.0)(stmt);
+            walk_statement(self, stmt);
+        }
+    }
+    let mut visitor = StatementVisitor(f, false);
+    for stmt in stmts {
+        visitor.visit_statement(&mut stmt.clone());
+    }
+    visitor.1
+}
+
+/// Checks whether a `break` or `continue` statement refers to the label.
+pub fn is_label_used(stmts: &[Statement], label: &str) -> bool {
+    any_stmt(stmts, |stmt| match *stmt {
+        Statement::Break(Some(ref target)) | Statement::Continue(Some(ref target)) => {
+            target == label
+        }
+        _ => false,
+    })
+}
+
+/// Matches `var = expr;`.
+pub fn match_assignment(stmt: &Statement) -> Option<(&Ident, &Expr)> {
+    match *stmt {
+        Statement::Expr(Expr::Assign {
+            ref to,
+            op: None,
+            ref from,
+        }) => match **to {
+            Assignable::Variable(ref var, _) => Some((var, from)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Matches a variable, returning its name.
+pub fn as_variable(expr: &Expr) -> Option<&Ident> {
+    match *expr {
+        Expr::Assignable(ref assignable) => match **assignable {
+            Assignable::Variable(ref var, _) => Some(var),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The index of the statement before `i`, skipping `nop`s.
+pub fn previous_stmt(stmts: &[Statement], i: usize) -> Option<usize> {
+    (0..i).rev().find(|&j| stmts[j] != Statement::Nop)
+}
+
+/// The index of the statement after `i`, skipping `nop`s.
+pub fn next_stmt(stmts: &[Statement], i: usize) -> Option<usize> {
+    (i + 1..stmts.len()).find(|&j| stmts[j] != Statement::Nop)
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub enum Assignable {
     Variable(Ident, usize),
     Field {
@@ -190,7 +410,7 @@ pub enum Assignable {
     },
 }
 
-#[derive(Copy, Clone, Debug, Hash)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
 pub enum UnOp {
     Neg,
     BitNot,
@@ -208,9 +428,12 @@ impl Display for UnOp {
     }
 }
 
-#[derive(Copy, Clone, Debug, Hash)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
 pub enum BinOp {
     Cmp(Ordering),
+    /// A comparison of floats or doubles, which is false if an operand is NaN (except for `!=`),
+    /// so it isn't negated by the opposite comparison like `Cmp`.
+    FloatCmp(Ordering),
     Add,
     Sub,
     Mul,
@@ -229,7 +452,7 @@ pub enum BinOp {
 impl Display for BinOp {
     fn fmt(&self, f: &mut Formatter) -> Result {
         let symbol = match *self {
-            BinOp::Cmp(ord) => ord.to_str(),
+            BinOp::Cmp(ord) | BinOp::FloatCmp(ord) => ord.to_str(),
             BinOp::Add => "+",
             BinOp::Sub => "-",
             BinOp::Mul => "*",
@@ -252,10 +475,12 @@ pub fn stmt_expr(e: Expr) -> Statement {
     Statement::Expr(e)
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub enum Statement {
     Nop,
     Expr(Expr),
+    /// Declares a local variable after the beginning of a block, where it's first assigned.
+    Decl(LocalDecl),
     Block(Block),
     If {
         cond: Expr,
@@ -276,12 +501,25 @@ pub enum Statement {
     SuperCall(Vec<Expr>),
     Throw(Expr),
     Synchronized(Expr, Block),
+    /// Acquires the monitor of the object. Only exists until the `synchronized` statements are
+    /// recovered.
+    MonitorEnter(Expr),
+    /// Releases the monitor of the object, see `MonitorEnter`.
+    MonitorExit(Expr),
+    /// A comment, e.g. with the unstructured code of a method whose control flow can't be
+    /// structured.
+    Comment(String),
     Try {
         resources: Vec<LocalDecl>,
         block: Block,
         catches: Vec<Catch>,
         finally: Block,
-    }, // TODO: assert, switch
+    },
+    Switch {
+        label: Option<Ident>,
+        expr: Expr,
+        cases: Vec<SwitchCase>,
+    }, // TODO: assert
 }
 
 impl Display for Statement {
@@ -290,7 +528,7 @@ impl Display for Statement {
     }
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct Block(pub Vec<LocalDecl>, pub Vec<Statement>);
 
 impl Default for Block {
@@ -299,26 +537,34 @@ impl Default for Block {
     }
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct LocalDecl {
     pub ident: Ident,
     pub typ: Type,
     pub init: Option<Expr>,
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub enum ForControl {
     Iteration {
         elem: LocalDecl,
         container: Expr,
     },
     General {
-        init: LocalDecl,
+        init: ForInit,
         cond: Expr,
         update: Expr,
     },
 }
 
+/// The initialization of a `for` loop: either a declaration of the loop variable or an
+/// expression, e.g. assigning a variable declared before.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub enum ForInit {
+    Decl(LocalDecl),
+    Expr(Expr),
+}
+
 #[derive(Clone, Debug, Hash)]
 pub struct Capsule<C> {
     pub modifiers: Vec<Modifier>,
@@ -333,9 +579,20 @@ pub enum ClassDecl<C> {
     Method(MethodDecl<C>),
 }
 
-#[derive(Clone, Debug, Hash)]
-pub enum Catch {
-    // TODO
+/// Case labels and the statements following them in a switch statement.
+/// The values are `None` for the `default` label.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub struct SwitchCase {
+    pub values: Vec<Option<Expr>>,
+    pub stmts: Vec<Statement>,
+}
+
+/// A catch clause, catching exceptions of any of the `types`.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub struct Catch {
+    pub types: Vec<Type>,
+    pub ident: Ident,
+    pub block: Block,
 }
 
 #[derive(Clone, Debug, Hash)]
@@ -1,69 +1,184 @@
 pub use classfile::parser::*;
 pub use disassembler::types::*;
 
-use byteorder::{BigEndian, ReadBytesExt};
-use std::io::{Read, Result};
+use disassembler::error::{DecodeError, DecodeErrorKind};
 
-#[derive(Debug)]
-pub struct CodeAttribute {
-    max_stack: u16,
-    max_local: u16,
-    code: Vec<u8>,
-    exception_table: Vec<ExceptionTableEntry>,
-    attributes: Vec<AttributeInfo>,
-}
-
-#[derive(Debug)]
-pub struct ExceptionTableEntry {
-    start_pc: u16,
-    end_pc: u16,
-    handler_pc: u16,
-    catch_type: u16,
-}
-
-pub fn parse_code_attribute(bytes: &[u8]) -> Result<CodeAttribute> {
-    use std::io::Cursor;
-    let mut input = Cursor::new(bytes);
-    let max_stack = input.read_u16::<BigEndian>()?;
-    let max_local = input.read_u16::<BigEndian>()?;
-    let code_length = input.read_u32::<BigEndian>()?;
-    let mut code = vec![0; code_length as usize];
-    input.read_exact(&mut code)?;
-    let exception_table_length = input.read_u16::<BigEndian>()?;
-    let mut exception_table = vec![];
-    for _ in 0..exception_table_length {
-        let start_pc = input.read_u16::<BigEndian>()?;
-        let end_pc = input.read_u16::<BigEndian>()?;
-        let handler_pc = input.read_u16::<BigEndian>()?;
-        let catch_type = input.read_u16::<BigEndian>()?;
-        exception_table.push(ExceptionTableEntry {
-            start_pc: start_pc,
-            end_pc: end_pc,
-            handler_pc: handler_pc,
-            catch_type: catch_type,
-        });
-    }
-    let attributes = parse_attributes(&mut input)?;
-    Ok(CodeAttribute {
-        max_stack: max_stack,
-        max_local: max_local,
-        code: code,
-        exception_table: exception_table,
-        attributes: attributes,
-    })
-}
-
-pub fn disassemble(code: &CodeAttribute) -> Code {
+/// Decodes the instructions of a method.
+/// In lenient mode, an instruction that can't be decoded is replaced by `Instruction::Invalid`
+/// and decoding continues at the following byte, instead of failing. Likewise an instruction
+/// whose operands refer to missing constants or constants of the wrong kind, but decoding
+/// continues after its operands.
+pub fn disassemble(
+    code: &CodeAttribute,
+    constant_pool: &ConstantPool,
+    lenient: bool,
+) -> Result<Code, DecodeError> {
     let len = code.code.len();
     let mut instructions = Vec::with_capacity(len);
     let mut bytes = code.code.iter().cloned();
     use std::iter::ExactSizeIterator;
     while let Some(opcode) = bytes.next() {
         let pc = len - bytes.len() - 1;
-        let instruction = decode_instruction(opcode, pc as u16, &mut bytes);
-        instructions.push((pc as u16, instruction));
+        let mut operands = bytes.clone();
+        match decode_instruction(opcode, pc as u16, &mut operands) {
+            Ok(instruction) => {
+                bytes = operands;
+                match check_constants(&instruction, constant_pool) {
+                    Ok(()) => instructions.push((pc as u16, instruction)),
+                    Err(_) if lenient => {
+                        instructions.push((pc as u16, Instruction::Invalid(opcode)))
+                    }
+                    Err(kind) => {
+                        return Err(DecodeError {
+                            pc: pc as u16,
+                            opcode,
+                            kind,
+                        })
+                    }
+                }
+            }
+            Err(_) if lenient => instructions.push((pc as u16, Instruction::Invalid(opcode))),
+            Err(err) => return Err(err),
+        }
     }
-    Code {
-        instructions: instructions,
+    let exception_handlers = code
+        .exception_table
+        .iter()
+        .map(|entry| ExceptionHandler {
+            start: entry.start_pc,
+            end: entry.end_pc,
+            handler: entry.handler_pc,
+            catch_type: if entry.catch_type == 0 {
+                None
+            } else {
+                Some(entry.catch_type)
+            },
+        })
+        .collect();
+    Ok(Code {
+        instructions,
+        exception_handlers,
+    })
+}
+
+/// Checks that the constant referred to by the operands of the instruction exists and is of the
+/// kind that the instruction expects.
+fn check_constants(
+    instruction: &Instruction,
+    constant_pool: &ConstantPool,
+) -> Result<(), DecodeErrorKind> {
+    let (index, is_expected): (u16, fn(&ConstantInfo) -> bool) = match *instruction {
+        Instruction::Load(RValue::ConstantRef { const_ref }) => (const_ref, |constant| {
+            matches!(
+                *constant,
+                ConstantInfo::Integer(_)
+                    | ConstantInfo::Float(_)
+                    | ConstantInfo::Long(_)
+                    | ConstantInfo::Double(_)
+                    | ConstantInfo::Class { .. }
+                    | ConstantInfo::String { .. }
+                    | ConstantInfo::MethodHandle { .. }
+                    | ConstantInfo::MethodType { .. }
+                    | ConstantInfo::Dynamic { .. }
+            )
+        }),
+        Instruction::Load(RValue::LValue(LValue::StaticField { field_ref }))
+        | Instruction::Load(RValue::LValue(LValue::InstanceField { field_ref, .. }))
+        | Instruction::Store(LValue::StaticField { field_ref })
+        | Instruction::Store(LValue::InstanceField { field_ref, .. }) => (field_ref, |constant| {
+            matches!(*constant, ConstantInfo::FieldRef { .. })
+        }),
+        Instruction::Invoke(Invoke {
+            method_index,
+            kind: InvokeKind::Dynamic,
+        }) => (method_index, |constant| {
+            matches!(*constant, ConstantInfo::InvokeDynamic { .. })
+        }),
+        Instruction::Invoke(Invoke { method_index, .. }) => (method_index, |constant| {
+            matches!(
+                *constant,
+                ConstantInfo::MethodRef { .. } | ConstantInfo::InterfaceMethodRef { .. }
+            )
+        }),
+        Instruction::ObjManip(ObjManip::New { class_ref })
+        | Instruction::ObjManip(ObjManip::ANewArray { class_ref })
+        | Instruction::ObjManip(ObjManip::MultiANewArray { class_ref, .. })
+        | Instruction::ObjManip(ObjManip::CheckCast { class_ref })
+        | Instruction::ObjManip(ObjManip::InstanceOf { class_ref }) => (class_ref, |constant| {
+            matches!(*constant, ConstantInfo::Class { .. })
+        }),
+        _ => return Ok(()),
+    };
+    match constant_pool.lookup(index) {
+        Ok(constant) if is_expected(constant) => Ok(()),
+        _ => Err(DecodeErrorKind::InvalidConstant(index)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(bytes: &[u8]) -> CodeAttribute {
+        CodeAttribute {
+            max_stack: 1,
+            max_locals: 0,
+            code: bytes.to_vec(),
+            exception_table: vec![],
+            attributes: vec![],
+        }
+    }
+
+    /// The pcs of the instructions, with the opcodes of the invalid ones.
+    fn layout(code: &Code) -> Vec<(u16, Option<u8>)> {
+        code.instructions
+            .iter()
+            .map(|&(pc, ref instruction)| match *instruction {
+                Instruction::Invalid(opcode) => (pc, Some(opcode)),
+                _ => (pc, None),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn invalid_opcodes() {
+        // `bipush 5`, an undefined opcode, `sipush` with its operand cut off by the end:
+        let code = code(&[0x10, 0x05, 0xcb, 0x11, 0x00]);
+        let constant_pool = ConstantPool { constants: vec![] };
+        let error = disassemble(&code, &constant_pool, false).unwrap_err();
+        assert_eq!(
+            error,
+            DecodeError {
+                pc: 2,
+                opcode: 0xcb,
+                kind: DecodeErrorKind::InvalidOpcode,
+            }
+        );
+        // Decoding continues at the byte after an invalid instruction, so the operand of
+        // `sipush` is decoded as `nop`:
+        let lenient = disassemble(&code, &constant_pool, true).unwrap();
+        assert_eq!(
+            layout(&lenient),
+            [(0, None), (2, Some(0xcb)), (3, Some(0x11)), (4, None)]
+        );
+    }
+
+    #[test]
+    fn missing_constants() {
+        // `getstatic #1` with an empty constant pool, `return`:
+        let code = code(&[0xb2, 0x00, 0x01, 0xb1]);
+        let constant_pool = ConstantPool { constants: vec![] };
+        let error = disassemble(&code, &constant_pool, false).unwrap_err();
+        assert_eq!(
+            error,
+            DecodeError {
+                pc: 0,
+                opcode: 0xb2,
+                kind: DecodeErrorKind::InvalidConstant(1),
+            }
+        );
+        // Decoding continues after the operands of the instruction:
+        let lenient = disassemble(&code, &constant_pool, true).unwrap();
+        assert_eq!(layout(&lenient), [(0, Some(0xb2)), (3, None)]);
     }
 }
@@ -0,0 +1,91 @@
+use classfile;
+use std::error;
+use std::fmt;
+use std::result;
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// An error encountered while turning a class file into a compilation unit.
+#[derive(Debug)]
+pub enum Error {
+    ClassFile(classfile::Error),
+    Decode { method: String, error: DecodeError },
+}
+
+/// An error encountered while decoding the bytecode of a method.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Offset of the faulty instruction into the method's code.
+    pub pc: u16,
+    pub opcode: u8,
+    pub kind: DecodeErrorKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The code ends in the middle of the instruction's operands.
+    Truncated,
+    InvalidOpcode,
+    /// The opcode following a `wide` prefix can't be widened.
+    InvalidWideOpcode(u8),
+    InvalidArrayType(u8),
+    /// An operand refers to a constant that doesn't exist or is of the wrong kind.
+    InvalidConstant(u16),
+}
+
+impl From<classfile::Error> for Error {
+    fn from(err: classfile::Error) -> Error {
+        Error::ClassFile(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ClassFile(ref err) => write!(f, "{}", err),
+            Error::Decode {
+                ref method,
+                ref error,
+            } => write!(f, "in method {}: {}", method, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::ClassFile(ref err) => Some(err),
+            Error::Decode { ref error, .. } => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "at pc {:#x}, opcode {:#04x}: {}",
+            self.pc, self.opcode, self.kind
+        )
+    }
+}
+
+impl error::Error for DecodeError {}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeErrorKind::Truncated => write!(f, "code ends within the instruction"),
+            DecodeErrorKind::InvalidOpcode => write!(f, "invalid opcode"),
+            DecodeErrorKind::InvalidWideOpcode(opcode) => {
+                write!(f, "opcode {:#04x} can't follow wide", opcode)
+            }
+            DecodeErrorKind::InvalidArrayType(atype) => {
+                write!(f, "invalid array type {} for newarray", atype)
+            }
+            DecodeErrorKind::InvalidConstant(index) => {
+                write!(f, "invalid constant #{} as operand", index)
+            }
+        }
+    }
+}
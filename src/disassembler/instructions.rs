@@ -1,9 +1,11 @@
 pub use disassembler::types::*;
 pub use std::ops::Range;
 
-#[derive(Copy, Clone, Debug)]
+use disassembler::error::{DecodeError, DecodeErrorKind};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Kind {
-    B, // byte
+    B, // byte (or boolean, for arrays)
     S, // short
     C, // char
     I, // int
@@ -13,6 +15,39 @@ pub enum Kind {
     A, // reference
 }
 
+impl Kind {
+    /// Whether values of this kind take two slots, on the stack and among the local variables.
+    pub fn is_wide(self) -> bool {
+        self == Kind::L || self == Kind::D
+    }
+
+    /// The kinds in the order in which the typed instruction families (`iload`, `lload`, ...,
+    /// `aload`) are laid out in the opcode table.
+    fn from_u8(i: u8) -> Kind {
+        use self::Kind::*;
+        match i {
+            0 => I,
+            1 => L,
+            2 => F,
+            3 => D,
+            4 => A,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The kinds of the array load and store instructions (`iaload` to `saload`).
+    fn array_from_u8(i: u8) -> Kind {
+        use self::Kind::*;
+        match i {
+            0..=4 => Kind::from_u8(i),
+            5 => B,
+            6 => C,
+            7 => S,
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Instruction {
     Nop,
@@ -23,44 +58,65 @@ pub enum Instruction {
     ObjManip(ObjManip),
     StackManage(StackManage),
     Jump(Jump),
+    Switch(Switch),
+    Subroutine(Subroutine),
     Invoke(Invoke),
     Throw,
-    Return(Option<()>),
+    Return(Option<Kind>),
     Synchronized(Synchronized),
+    /// Placeholder for an opcode that couldn't be decoded, see `disassemble`.
+    Invalid(u8),
 }
 
-pub fn decode_instruction<I>(opcode: u8, pc: u16, iter: &mut I) -> Instruction
+/// Decodes the instruction with the given opcode at `pc`, reading its operands from `iter`.
+pub fn decode_instruction<I>(opcode: u8, pc: u16, iter: &mut I) -> Result<Instruction, DecodeError>
 where
     I: Iterator<Item = u8>,
 {
     use self::Instruction::*;
-    println!("Decoding opcode 0x{:x}.", opcode);
-    match opcode {
-        0x00 => Nop,
-        0x01...0x35 | 0xb2 | 0xb4 => Load(decode_load(opcode, iter)),
-        0x36...0x56 | 0xb3 | 0xb5 => Store(decode_store(opcode, iter)),
-        0x57...0x5f => unimplemented!(), // stack management
-        0x60...0x84 => Arithm(decode_arithm(opcode, iter)), // arithmetic
-        0x85...0x93 => unimplemented!(), // type conversion
-        0x94...0x98 => unimplemented!(), // comparison (arithmetic)
-        0x99...0xab => Jump(decode_jump(opcode, pc, iter)), // control flow
-        0xac...0xb0 => Return(Some(())),
-        0xb1 => Return(None),
-        0xb6...0xba => Invoke(decode_invoke(opcode, iter)),
-        0xbb...0xbe => unimplemented!(), // object manip
-        0xbf => Throw,
-        0xc0...0xc1 => unimplemented!(), // object manip
-        0xc2...0xc3 => unimplemented!(), // monitor{enter|exit}
-        0xc4...0xc9 => unimplemented!(), // miscalleneous
-        0xca...0xff => panic!("Invalid opcode 0x{:x}", opcode),
-        _ => unreachable!(), // no other possibilities possible but rustc can't see this
-    }
+    let instruction = match opcode {
+        0x00 => Ok(Nop),
+        0x01..=0x35 | 0xb2 | 0xb4 => decode_load(opcode, iter).map(Load),
+        0x36..=0x56 | 0xb3 | 0xb5 => decode_store(opcode, iter).map(Store),
+        0x57..=0x5f => Ok(StackManage(decode_stack_manage(opcode))),
+        0x60..=0x84 => decode_arithm(opcode, iter).map(Arithm), // arithmetic
+        0x85..=0x93 => Ok(TypeConv(decode_type_conv(opcode))),
+        0x94..=0x98 => decode_arithm(opcode, iter).map(Arithm), // comparison
+        0x99..=0xa7 | 0xc6..=0xc8 => decode_jump(opcode, pc, iter).map(Jump), // control flow
+        0xa8 | 0xa9 | 0xc9 => decode_subroutine(opcode, pc, iter).map(Subroutine),
+        0xaa..=0xab => decode_switch(opcode, pc, iter).map(Switch),
+        0xac..=0xb0 => Ok(Return(Some(Kind::from_u8(opcode - 0xac)))),
+        0xb1 => Ok(Return(None)),
+        0xb6..=0xba => decode_invoke(opcode, iter).map(Invoke),
+        0xbb..=0xbe | 0xc0..=0xc1 | 0xc5 => decode_obj_manip(opcode, iter).map(ObjManip),
+        0xbf => Ok(Throw),
+        0xc2 => Ok(Synchronized(self::Synchronized::MonitorEnter)),
+        0xc3 => Ok(Synchronized(self::Synchronized::MonitorExit)),
+        0xc4 => decode_wide(iter),
+        0xca..=0xff => Err(DecodeErrorKind::InvalidOpcode),
+    };
+    instruction.map_err(|kind| DecodeError { pc, opcode, kind })
+}
+
+pub fn read_u8<I: Iterator<Item = u8>>(iter: &mut I) -> Result<u8, DecodeErrorKind> {
+    iter.next().ok_or(DecodeErrorKind::Truncated)
+}
+
+pub fn read_u16_index<I: Iterator<Item = u8>>(iter: &mut I) -> Result<u16, DecodeErrorKind> {
+    let index1 = read_u8(iter)?;
+    let index2 = read_u8(iter)?;
+    Ok((index1 as u16) << 8 | index2 as u16)
+}
+
+pub fn read_i32<I: Iterator<Item = u8>>(iter: &mut I) -> Result<i32, DecodeErrorKind> {
+    let high = read_u16_index(iter)? as u32;
+    let low = read_u16_index(iter)? as u32;
+    Ok((high << 16 | low) as i32)
 }
 
-pub fn read_u16_index<I: Iterator<Item = u8>>(iter: &mut I) -> u16 {
-    let index1 = iter.next().unwrap();
-    let index2 = iter.next().unwrap();
-    (index1 as u16) << 8 | index2 as u16
+/// Computes the absolute address of a branch target from the pc of the branch instruction.
+fn branch_target(pc: u16, offset: i32) -> u16 {
+    (pc as i32 + offset) as u16
 }
 
 #[derive(Clone, Debug)]
@@ -73,7 +129,7 @@ pub type StackVarId = isize;
 
 #[derive(Clone, Debug)]
 pub enum LValue {
-    Local(usize),
+    Local(Kind, usize),
     Stack(StackVarId),
     StaticField {
         field_ref: u16,
@@ -82,6 +138,11 @@ pub enum LValue {
         object_stack_index: StackVarId,
         field_ref: u16,
     },
+    ArrayElement {
+        kind: Kind,
+        array_stack_index: StackVarId,
+        index_stack_index: StackVarId,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -91,59 +152,141 @@ pub enum RValue {
     LValue(LValue),
 }
 
-pub fn decode_load<I: Iterator<Item = u8>>(opcode: u8, iter: &mut I) -> RValue {
-    match opcode {
-        0x02...0x08 => RValue::Constant(Literal::Integer(opcode as i32 - 0x03)),
+pub fn decode_load<I: Iterator<Item = u8>>(
+    opcode: u8,
+    iter: &mut I,
+) -> Result<RValue, DecodeErrorKind> {
+    Ok(match opcode {
+        0x01 => RValue::Constant(Literal::NullReference),
+        0x02..=0x08 => RValue::Constant(Literal::Integer(opcode as i32 - 0x03)),
+        0x09..=0x0a => RValue::Constant(Literal::Long((opcode - 0x09) as i64)),
+        0x0b..=0x0d => RValue::Constant(Literal::Float((opcode - 0x0b) as f32)),
+        0x0e..=0x0f => RValue::Constant(Literal::Double((opcode - 0x0e) as f64)),
+        0x10 => {
+            // bipush
+            let byte = read_u8(iter)? as i8;
+            RValue::Constant(Literal::Integer(byte as i32))
+        }
+        0x11 => {
+            // sipush
+            let short = read_u16_index(iter)? as i16;
+            RValue::Constant(Literal::Integer(short as i32))
+        }
         0x12 => {
-            let index = iter.next().unwrap();
+            let index = read_u8(iter)?;
             RValue::ConstantRef {
                 const_ref: index as u16,
             }
         }
-        0x1a...0x1d => RValue::LValue(LValue::Local((opcode - 0x1a) as usize)),
-        0x2a...0x2d => RValue::LValue(LValue::Local((opcode - 0x2a) as usize)),
+        0x13..=0x14 => {
+            // ldc_w, ldc2_w
+            let index = read_u16_index(iter)?;
+            RValue::ConstantRef { const_ref: index }
+        }
+        0x15..=0x19 => {
+            let index = read_u8(iter)?;
+            let kind = Kind::from_u8(opcode - 0x15);
+            RValue::LValue(LValue::Local(kind, index as usize))
+        }
+        0x1a..=0x2d => {
+            let kind = Kind::from_u8((opcode - 0x1a) / 4);
+            RValue::LValue(LValue::Local(kind, ((opcode - 0x1a) % 4) as usize))
+        }
+        0x2e..=0x35 => RValue::LValue(LValue::ArrayElement {
+            kind: Kind::array_from_u8(opcode - 0x2e),
+            array_stack_index: -2,
+            index_stack_index: -1,
+        }),
         0xb2 => {
             //getstatic
-            let index = read_u16_index(iter);
+            let index = read_u16_index(iter)?;
             RValue::LValue(LValue::StaticField { field_ref: index })
         }
         0xb4 => {
             //getfield
-            let index = read_u16_index(iter);
+            let index = read_u16_index(iter)?;
             RValue::LValue(LValue::InstanceField {
                 object_stack_index: -1,
                 field_ref: index,
             })
         }
-        _ => unimplemented!(),
-    }
+        _ => unreachable!(),
+    })
 }
 
-pub fn decode_store<I: Iterator<Item = u8>>(opcode: u8, iter: &mut I) -> LValue {
-    match opcode {
-        0x3b...0x3e => LValue::Local((opcode - 0x3b) as usize),
+pub fn decode_store<I: Iterator<Item = u8>>(
+    opcode: u8,
+    iter: &mut I,
+) -> Result<LValue, DecodeErrorKind> {
+    Ok(match opcode {
+        0x36..=0x3a => {
+            let index = read_u8(iter)?;
+            LValue::Local(Kind::from_u8(opcode - 0x36), index as usize)
+        }
+        0x3b..=0x4e => {
+            let kind = Kind::from_u8((opcode - 0x3b) / 4);
+            LValue::Local(kind, ((opcode - 0x3b) % 4) as usize)
+        }
+        0x4f..=0x56 => LValue::ArrayElement {
+            kind: Kind::array_from_u8(opcode - 0x4f),
+            array_stack_index: -3,
+            index_stack_index: -2,
+        },
         0xb3 => {
             // putstatic
-            let index = read_u16_index(iter);
+            let index = read_u16_index(iter)?;
             LValue::StaticField { field_ref: index }
         }
         0xb5 => {
             // putfield
-            let index = read_u16_index(iter);
+            let index = read_u16_index(iter)?;
             LValue::InstanceField {
                 object_stack_index: -2,
                 field_ref: index,
             }
         }
-        _ => unimplemented!(),
-    }
+        _ => unreachable!(),
+    })
+}
+
+/// Decodes the instruction following a `wide` prefix, which has a 16 bit local variable index
+/// (and, for `iinc`, a 16 bit increment).
+fn decode_wide<I: Iterator<Item = u8>>(iter: &mut I) -> Result<Instruction, DecodeErrorKind> {
+    let opcode = read_u8(iter)?;
+    let index = read_u16_index(iter)? as usize;
+    Ok(match opcode {
+        0x15..=0x19 => {
+            let kind = Kind::from_u8(opcode - 0x15);
+            Instruction::Load(RValue::LValue(LValue::Local(kind, index)))
+        }
+        0x36..=0x3a => Instruction::Store(LValue::Local(Kind::from_u8(opcode - 0x36), index)),
+        0x84 => {
+            let increase = read_u16_index(iter)? as i16;
+            Instruction::Arithm(Arithm::IncreaseLocal {
+                local_index: index as u16,
+                increase,
+            })
+        }
+        0xa9 => Instruction::Subroutine(Subroutine::Ret { local_index: index }),
+        _ => return Err(DecodeErrorKind::InvalidWideOpcode(opcode)),
+    })
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum Arithm {
-    UnaryOp(UnaryOp),
-    BinaryOp(BinaryOp),
-    IncreaseLocal { local_index: u8, increase: i8 },
+    UnaryOp(Kind, UnaryOp),
+    BinaryOp(Kind, BinaryOp),
+    IncreaseLocal {
+        local_index: u16,
+        increase: i16,
+    },
+    /// Compares two longs, floats or doubles and pushes -1, 0 or 1.
+    /// For floating point numbers, `nan_greater` says whether a NaN operand results in 1
+    /// (`fcmpg`, `dcmpg`) or -1 (`fcmpl`, `dcmpl`).
+    Compare {
+        kind: Kind,
+        nan_greater: bool,
+    },
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -166,43 +309,190 @@ pub enum BinaryOp {
     Xor,
 }
 
-pub fn decode_arithm<I: Iterator<Item = u8>>(opcode: u8, iter: &mut I) -> Arithm {
+pub fn decode_arithm<I: Iterator<Item = u8>>(
+    opcode: u8,
+    iter: &mut I,
+) -> Result<Arithm, DecodeErrorKind> {
     use self::Arithm::*;
     use self::BinaryOp::*;
     use self::UnaryOp::*;
-    match opcode {
-        0x60...0x63 => BinaryOp(Add),
-        0x64...0x67 => BinaryOp(Sub),
-        0x68...0x6b => BinaryOp(Mul),
-        0x6c...0x6e => BinaryOp(Div),
-        0x70...0x73 => BinaryOp(Rem),
-        0x74...0x77 => UnaryOp(Neg),
-        0x78...0x79 => BinaryOp(Shl),
-        0x7a...0x7b => BinaryOp(Shr),
-        0x7c...0x7d => BinaryOp(Ushr),
-        0x7e...0x7f => BinaryOp(And),
-        0x80...0x81 => BinaryOp(Or),
-        0x82...0x83 => BinaryOp(Xor),
+    Ok(match opcode {
+        0x60..=0x77 => {
+            let kind = Kind::from_u8((opcode - 0x60) % 4);
+            match (opcode - 0x60) / 4 {
+                0 => BinaryOp(kind, Add),
+                1 => BinaryOp(kind, Sub),
+                2 => BinaryOp(kind, Mul),
+                3 => BinaryOp(kind, Div),
+                4 => BinaryOp(kind, Rem),
+                5 => UnaryOp(kind, Neg),
+                _ => unreachable!(),
+            }
+        }
+        0x78..=0x83 => {
+            // Only int and long variants:
+            let kind = Kind::from_u8((opcode - 0x78) % 2);
+            let op = match (opcode - 0x78) / 2 {
+                0 => Shl,
+                1 => Shr,
+                2 => Ushr,
+                3 => And,
+                4 => Or,
+                5 => Xor,
+                _ => unreachable!(),
+            };
+            BinaryOp(kind, op)
+        }
         0x84 => {
-            let index = iter.next().unwrap();
-            let increase = iter.next().unwrap();
+            let index = read_u8(iter)?;
+            let increase = read_u8(iter)?;
             IncreaseLocal {
-                local_index: index,
-                increase: increase as i8,
+                local_index: index as u16,
+                increase: increase as i8 as i16,
             }
         }
+        0x94 => Compare {
+            kind: Kind::L,
+            nan_greater: false,
+        },
+        0x95..=0x98 => Compare {
+            kind: if opcode < 0x97 { Kind::F } else { Kind::D },
+            nan_greater: (opcode - 0x95) % 2 == 1,
+        },
         _ => unreachable!(),
-    }
+    })
 }
 
+/// Conversion of the primitive value on top of the stack.
+/// Conversions from int to byte, char and short have the `to` kinds B, C and S.
 #[derive(Copy, Clone, Debug)]
-pub enum TypeConv {}
+pub struct TypeConv {
+    pub from: Kind,
+    pub to: Kind,
+}
 
-#[derive(Copy, Clone, Debug)]
-pub enum ObjManip {}
+pub fn decode_type_conv(opcode: u8) -> TypeConv {
+    use self::Kind::*;
+    let (from, to) = match opcode {
+        0x85 => (I, L),
+        0x86 => (I, F),
+        0x87 => (I, D),
+        0x88 => (L, I),
+        0x89 => (L, F),
+        0x8a => (L, D),
+        0x8b => (F, I),
+        0x8c => (F, L),
+        0x8d => (F, D),
+        0x8e => (D, I),
+        0x8f => (D, L),
+        0x90 => (D, F),
+        0x91 => (I, B),
+        0x92 => (I, C),
+        0x93 => (I, S),
+        _ => unreachable!(),
+    };
+    TypeConv { from, to }
+}
+
+#[derive(Clone, Debug)]
+pub enum ObjManip {
+    New {
+        class_ref: u16,
+    },
+    /// Creates an array with elements of the given primitive type.
+    NewArray(Type),
+    /// Creates an array with elements of the given reference type.
+    ANewArray {
+        class_ref: u16,
+    },
+    /// `class_ref` is the array type, of which the first `dimensions` are created.
+    MultiANewArray {
+        class_ref: u16,
+        dimensions: u8,
+    },
+    ArrayLength,
+    CheckCast {
+        class_ref: u16,
+    },
+    InstanceOf {
+        class_ref: u16,
+    },
+}
+
+pub fn decode_obj_manip<I: Iterator<Item = u8>>(
+    opcode: u8,
+    iter: &mut I,
+) -> Result<ObjManip, DecodeErrorKind> {
+    Ok(match opcode {
+        0xbb => ObjManip::New {
+            class_ref: read_u16_index(iter)?,
+        },
+        0xbc => {
+            let typ = match read_u8(iter)? {
+                4 => Type::Boolean,
+                5 => Type::Char,
+                6 => Type::Float,
+                7 => Type::Double,
+                8 => Type::Byte,
+                9 => Type::Short,
+                10 => Type::Int,
+                11 => Type::Long,
+                atype => return Err(DecodeErrorKind::InvalidArrayType(atype)),
+            };
+            ObjManip::NewArray(typ)
+        }
+        0xbd => ObjManip::ANewArray {
+            class_ref: read_u16_index(iter)?,
+        },
+        0xbe => ObjManip::ArrayLength,
+        0xc0 => ObjManip::CheckCast {
+            class_ref: read_u16_index(iter)?,
+        },
+        0xc1 => ObjManip::InstanceOf {
+            class_ref: read_u16_index(iter)?,
+        },
+        0xc5 => {
+            let class_ref = read_u16_index(iter)?;
+            let dimensions = read_u8(iter)?;
+            ObjManip::MultiANewArray {
+                class_ref,
+                dimensions,
+            }
+        }
+        _ => unreachable!(),
+    })
+}
 
+/// The stack manipulation instructions.
+/// The `2` variants operate on two stack slots, i.e. on one long or double or on two other values.
 #[derive(Copy, Clone, Debug)]
-pub enum StackManage {}
+pub enum StackManage {
+    Pop,
+    Pop2,
+    Dup,
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+}
+
+pub fn decode_stack_manage(opcode: u8) -> StackManage {
+    use self::StackManage::*;
+    match opcode {
+        0x57 => Pop,
+        0x58 => Pop2,
+        0x59 => Dup,
+        0x5a => DupX1,
+        0x5b => DupX2,
+        0x5c => Dup2,
+        0x5d => Dup2X1,
+        0x5e => Dup2X2,
+        0x5f => Swap,
+        _ => unreachable!(),
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct Jump {
@@ -215,9 +505,10 @@ pub enum JumpCondition {
     CmpZero(Ordering),
     Cmp(Ordering),
     CmpRef(Ordering),
+    CmpNull(Ordering),
 }
 
-#[derive(Copy, Clone, Debug, Hash)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
 pub enum Ordering {
     EQ,
     NE,
@@ -240,22 +531,109 @@ impl Ordering {
             _ => unreachable!(),
         }
     }
+
+    /// The ordering that holds exactly if this one doesn't (for integers).
+    pub fn negate(self) -> Ordering {
+        use self::Ordering::*;
+        match self {
+            EQ => NE,
+            NE => EQ,
+            LT => GE,
+            GE => LT,
+            GT => LE,
+            LE => GT,
+        }
+    }
 }
 
-pub fn decode_jump<I: Iterator<Item = u8>>(opcode: u8, pc: u16, iter: &mut I) -> Jump {
-    let offset = read_u16_index(iter) as i16;
-    let address = (pc as i32 + offset as i32) as u16;
+pub fn decode_jump<I: Iterator<Item = u8>>(
+    opcode: u8,
+    pc: u16,
+    iter: &mut I,
+) -> Result<Jump, DecodeErrorKind> {
+    let offset = if opcode == 0xc8 {
+        // goto_w
+        read_i32(iter)?
+    } else {
+        read_u16_index(iter)? as i16 as i32
+    };
+    let address = branch_target(pc, offset);
     let condition = match opcode {
-        0x99...0x9e => Some(JumpCondition::CmpZero(Ordering::from_u8(opcode - 0x99))),
-        0x9f...0xa4 => Some(JumpCondition::Cmp(Ordering::from_u8(opcode - 0x9f))),
-        0xa5...0xa6 => Some(JumpCondition::CmpRef(Ordering::from_u8(opcode - 0x9f))),
-        0xa7 => None,
-        _ => unimplemented!(),
+        0x99..=0x9e => Some(JumpCondition::CmpZero(Ordering::from_u8(opcode - 0x99))),
+        0x9f..=0xa4 => Some(JumpCondition::Cmp(Ordering::from_u8(opcode - 0x9f))),
+        0xa5..=0xa6 => Some(JumpCondition::CmpRef(Ordering::from_u8(opcode - 0xa5))),
+        0xa7 | 0xc8 => None,
+        0xc6..=0xc7 => Some(JumpCondition::CmpNull(Ordering::from_u8(opcode - 0xc6))),
+        _ => unreachable!(),
     };
-    Jump {
+    Ok(Jump {
         address: address,
         condition: condition,
+    })
+}
+
+/// A `tableswitch` or `lookupswitch` on the int on top of the stack.
+#[derive(Clone, Debug)]
+pub struct Switch {
+    pub default: u16,
+    /// The case values with their jump addresses, sorted by value.
+    pub cases: Vec<(i32, u16)>,
+}
+
+pub fn decode_switch<I: Iterator<Item = u8>>(
+    opcode: u8,
+    pc: u16,
+    iter: &mut I,
+) -> Result<Switch, DecodeErrorKind> {
+    // The operands are aligned to a multiple of four bytes from the start of the method:
+    let padding = (4 - (pc as usize + 1) % 4) % 4;
+    for _ in 0..padding {
+        read_u8(iter)?;
+    }
+    let default = branch_target(pc, read_i32(iter)?);
+    let mut cases = vec![];
+    if opcode == 0xaa {
+        // tableswitch
+        let low = read_i32(iter)?;
+        let high = read_i32(iter)?;
+        for value in low..=high {
+            cases.push((value, branch_target(pc, read_i32(iter)?)));
+        }
+    } else {
+        // lookupswitch
+        let count = read_i32(iter)?;
+        for _ in 0..count {
+            let value = read_i32(iter)?;
+            cases.push((value, branch_target(pc, read_i32(iter)?)));
+        }
     }
+    Ok(Switch { default, cases })
+}
+
+/// The `jsr` and `ret` instructions, which are used for `finally` blocks by very old compilers.
+#[derive(Copy, Clone, Debug)]
+pub enum Subroutine {
+    Jsr { address: u16 },
+    Ret { local_index: usize },
+}
+
+pub fn decode_subroutine<I: Iterator<Item = u8>>(
+    opcode: u8,
+    pc: u16,
+    iter: &mut I,
+) -> Result<Subroutine, DecodeErrorKind> {
+    Ok(match opcode {
+        0xa8 => Subroutine::Jsr {
+            address: branch_target(pc, read_u16_index(iter)? as i16 as i32),
+        },
+        0xa9 => Subroutine::Ret {
+            local_index: read_u8(iter)? as usize,
+        },
+        0xc9 => Subroutine::Jsr {
+            address: branch_target(pc, read_i32(iter)?),
+        },
+        _ => unreachable!(),
+    })
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -269,21 +647,168 @@ pub enum InvokeKind {
     Virtual,
     Special,
     Static,
+    Interface,
+    /// `method_index` refers to an `InvokeDynamic` constant.
+    Dynamic,
 }
 
-pub fn decode_invoke<I: Iterator<Item = u8>>(opcode: u8, iter: &mut I) -> Invoke {
-    let index = read_u16_index(iter);
+pub fn decode_invoke<I: Iterator<Item = u8>>(
+    opcode: u8,
+    iter: &mut I,
+) -> Result<Invoke, DecodeErrorKind> {
+    let index = read_u16_index(iter)?;
     let kind = match opcode {
         0xb6 => InvokeKind::Virtual,
         0xb7 => InvokeKind::Special,
         0xb8 => InvokeKind::Static,
-        _ => unimplemented!(),
+        0xb9 => {
+            // The argument count and a zero byte, both redundant:
+            read_u8(iter)?;
+            read_u8(iter)?;
+            InvokeKind::Interface
+        }
+        0xba => {
+            // Two zero bytes:
+            read_u8(iter)?;
+            read_u8(iter)?;
+            InvokeKind::Dynamic
+        }
+        _ => unreachable!(),
     };
-    Invoke {
+    Ok(Invoke {
         method_index: index,
         kind: kind,
-    }
+    })
 }
 
-#[derive(Clone, Debug)]
-pub enum Synchronized {}
+#[derive(Copy, Clone, Debug)]
+pub enum Synchronized {
+    MonitorEnter,
+    MonitorExit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes the instruction at the beginning of `code`, which is at `pc` in its method.
+    /// Returns the number of bytes it takes, too.
+    fn decode(pc: u16, code: &[u8]) -> (Result<Instruction, DecodeError>, usize) {
+        let mut operands = code[1..].iter().cloned();
+        let instruction = decode_instruction(code[0], pc, &mut operands);
+        (instruction, code.len() - operands.len())
+    }
+
+    fn decode_error(pc: u16, code: &[u8]) -> DecodeError {
+        decode(pc, code).0.unwrap_err()
+    }
+
+    #[test]
+    fn wide_instructions() {
+        let (iload, len) = decode(0, &[0xc4, 0x15, 0x01, 0x2c]);
+        assert!(matches!(
+            iload,
+            Ok(Instruction::Load(RValue::LValue(LValue::Local(
+                Kind::I,
+                300
+            ))))
+        ));
+        assert_eq!(len, 4);
+        let (astore, _) = decode(0, &[0xc4, 0x3a, 0x01, 0x00]);
+        assert!(matches!(
+            astore,
+            Ok(Instruction::Store(LValue::Local(Kind::A, 256)))
+        ));
+        let (iinc, len) = decode(0, &[0xc4, 0x84, 0x01, 0x2c, 0xff, 0x38]);
+        assert!(matches!(
+            iinc,
+            Ok(Instruction::Arithm(Arithm::IncreaseLocal {
+                local_index: 300,
+                increase: -200,
+            }))
+        ));
+        assert_eq!(len, 6);
+        let (ret, _) = decode(0, &[0xc4, 0xa9, 0x01, 0x00]);
+        assert!(matches!(
+            ret,
+            Ok(Instruction::Subroutine(Subroutine::Ret {
+                local_index: 256
+            }))
+        ));
+        let error = decode_error(3, &[0xc4, 0x10, 0x00, 0x01]);
+        assert_eq!(
+            error,
+            DecodeError {
+                pc: 3,
+                opcode: 0xc4,
+                kind: DecodeErrorKind::InvalidWideOpcode(0x10),
+            }
+        );
+    }
+
+    #[test]
+    fn tableswitch_padding() {
+        // The operands start at the next multiple of four after the opcode:
+        for pc in 0..4u16 {
+            let padding = 3 - pc as usize;
+            let mut code = vec![0xaa];
+            code.extend(vec![0; padding]);
+            for operand in &[40i32, 1, 2, 20, 30] {
+                code.extend_from_slice(&operand.to_be_bytes());
+            }
+            let (switch, len) = decode(pc, &code);
+            match switch {
+                Ok(Instruction::Switch(switch)) => {
+                    assert_eq!(switch.default, pc + 40);
+                    assert_eq!(switch.cases, vec![(1, pc + 20), (2, pc + 30)]);
+                }
+                other => panic!("unexpected {:?} at {}", other, pc),
+            }
+            assert_eq!(len, 1 + padding + 20, "at {}", pc);
+        }
+    }
+
+    #[test]
+    fn lookupswitch_padding() {
+        let mut code = vec![0xab, 0, 0];
+        for operand in &[40i32, 2, -1, 20, 1000, 30] {
+            code.extend_from_slice(&operand.to_be_bytes());
+        }
+        let (switch, len) = decode(5, &code);
+        match switch {
+            Ok(Instruction::Switch(switch)) => {
+                assert_eq!(switch.default, 45);
+                assert_eq!(switch.cases, vec![(-1, 25), (1000, 35)]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(len, code.len());
+    }
+
+    #[test]
+    fn invalid_code() {
+        let error = |pc, opcode, kind| DecodeError { pc, opcode, kind };
+        // `sipush` without the second byte of its operand:
+        assert_eq!(
+            decode_error(7, &[0x11, 0x01]),
+            error(7, 0x11, DecodeErrorKind::Truncated)
+        );
+        // `tableswitch` ending in its padding:
+        assert_eq!(
+            decode_error(2, &[0xaa, 0x00]),
+            error(2, 0xaa, DecodeErrorKind::Truncated)
+        );
+        assert_eq!(
+            decode_error(1, &[0xc4, 0x15, 0x01]),
+            error(1, 0xc4, DecodeErrorKind::Truncated)
+        );
+        assert_eq!(
+            decode_error(0, &[0xcb]),
+            error(0, 0xcb, DecodeErrorKind::InvalidOpcode)
+        );
+        assert_eq!(
+            decode_error(0, &[0xbc, 3]),
+            error(0, 0xbc, DecodeErrorKind::InvalidArrayType(3))
+        );
+    }
+}
@@ -0,0 +1,1365 @@
+//! Renders a class in the layout of `javap -c -v -p`, such that the output can be diffed against
+//! the one of the JDK's disassembler.
+//!
+//! The differences are:
+//!
+//! - The first lines of javap's output (path, modification date and checksum of the class file)
+//!   are left out.
+//! - Declarations are always shown with their erased types, generic signatures are only shown
+//!   in `Signature` attributes.
+//! - Annotations, records and unknown attributes are left out.
+
+use classfile::{Error, ErrorKind, Result, Structure};
+use disassembler::transform::*;
+
+/// Renders the class file, using the code of the methods decoded in `unit`.
+/// `unit` must be the result of transforming `class_file`.
+pub fn javap(class_file: &ClassFile, unit: &CompilationUnit<Code>) -> Result<String> {
+    let mut javap = Javap {
+        class_file,
+        constant_pool: &class_file.constant_pool,
+        out: Writer::new(),
+    };
+    javap.class(unit)?;
+    Ok(javap.out.result)
+}
+
+const INDENT_WIDTH: usize = 2;
+const TAB_COLUMN: usize = 40;
+
+/// Writes lines like javap's own `LineWriter` does: spaces are only written if they are followed
+/// by something else on the same line, and `tab` aligns the following text (usually a comment) to
+/// a column that depends on the indentation.
+struct Writer {
+    result: String,
+    buffer: String,
+    indent: usize,
+    pending_spaces: usize,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer {
+            result: String::new(),
+            buffer: String::new(),
+            indent: 0,
+            pending_spaces: 0,
+        }
+    }
+
+    fn print<S: AsRef<str>>(&mut self, s: S) {
+        for c in s.as_ref().chars() {
+            match c {
+                ' ' => self.pending_spaces += 1,
+                '\n' => self.println(),
+                _ => {
+                    if self.buffer.is_empty() {
+                        self.pending_spaces += self.indent * INDENT_WIDTH;
+                    }
+                    for _ in 0..self.pending_spaces {
+                        self.buffer.push(' ');
+                    }
+                    self.pending_spaces = 0;
+                    self.buffer.push(c);
+                }
+            }
+        }
+    }
+
+    fn println(&mut self) {
+        self.pending_spaces = 0;
+        self.result.push_str(&self.buffer);
+        self.result.push('\n');
+        self.buffer.clear();
+    }
+
+    fn print_line<S: AsRef<str>>(&mut self, s: S) {
+        self.print(s);
+        self.println();
+    }
+
+    fn tab(&mut self) {
+        let column = self.indent * INDENT_WIDTH + TAB_COLUMN;
+        let length = self.buffer.chars().count();
+        self.pending_spaces += if column <= length { 1 } else { column - length };
+    }
+}
+
+const CLASS_FLAGS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "ACC_PUBLIC"),
+    (AccessFlags::FINAL, "ACC_FINAL"),
+    (AccessFlags::SUPER, "ACC_SUPER"),
+    (AccessFlags::INTERFACE, "ACC_INTERFACE"),
+    (AccessFlags::ABSTRACT, "ACC_ABSTRACT"),
+    (AccessFlags::SYNTHETIC, "ACC_SYNTHETIC"),
+    (AccessFlags::ANNOTATION, "ACC_ANNOTATION"),
+    (AccessFlags::ENUM, "ACC_ENUM"),
+    (AccessFlags::MODULE, "ACC_MODULE"),
+];
+
+const FIELD_FLAGS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "ACC_PUBLIC"),
+    (AccessFlags::PRIVATE, "ACC_PRIVATE"),
+    (AccessFlags::PROTECTED, "ACC_PROTECTED"),
+    (AccessFlags::STATIC, "ACC_STATIC"),
+    (AccessFlags::FINAL, "ACC_FINAL"),
+    (AccessFlags::VOLATILE, "ACC_VOLATILE"),
+    (AccessFlags::TRANSIENT, "ACC_TRANSIENT"),
+    (AccessFlags::SYNTHETIC, "ACC_SYNTHETIC"),
+    (AccessFlags::ENUM, "ACC_ENUM"),
+];
+
+const METHOD_FLAGS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "ACC_PUBLIC"),
+    (AccessFlags::PRIVATE, "ACC_PRIVATE"),
+    (AccessFlags::PROTECTED, "ACC_PROTECTED"),
+    (AccessFlags::STATIC, "ACC_STATIC"),
+    (AccessFlags::FINAL, "ACC_FINAL"),
+    (AccessFlags::SYNCHRONIZED, "ACC_SYNCHRONIZED"),
+    (AccessFlags::BRIDGE, "ACC_BRIDGE"),
+    (AccessFlags::VARARGS, "ACC_VARARGS"),
+    (AccessFlags::NATIVE, "ACC_NATIVE"),
+    (AccessFlags::ABSTRACT, "ACC_ABSTRACT"),
+    (AccessFlags::STRICT, "ACC_STRICT"),
+    (AccessFlags::SYNTHETIC, "ACC_SYNTHETIC"),
+];
+
+const CLASS_MODIFIERS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "public"),
+    (AccessFlags::FINAL, "final"),
+    (AccessFlags::ABSTRACT, "abstract"),
+];
+
+const INNER_CLASS_MODIFIERS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "public"),
+    (AccessFlags::PRIVATE, "private"),
+    (AccessFlags::PROTECTED, "protected"),
+    (AccessFlags::STATIC, "static"),
+    (AccessFlags::ABSTRACT, "abstract"),
+    (AccessFlags::FINAL, "final"),
+];
+
+const FIELD_MODIFIERS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "public"),
+    (AccessFlags::PRIVATE, "private"),
+    (AccessFlags::PROTECTED, "protected"),
+    (AccessFlags::STATIC, "static"),
+    (AccessFlags::FINAL, "final"),
+    (AccessFlags::VOLATILE, "volatile"),
+    (AccessFlags::TRANSIENT, "transient"),
+];
+
+const METHOD_MODIFIERS: &[(AccessFlags, &str)] = &[
+    (AccessFlags::PUBLIC, "public"),
+    (AccessFlags::PRIVATE, "private"),
+    (AccessFlags::PROTECTED, "protected"),
+    (AccessFlags::STATIC, "static"),
+    (AccessFlags::FINAL, "final"),
+    (AccessFlags::SYNCHRONIZED, "synchronized"),
+    (AccessFlags::NATIVE, "native"),
+    (AccessFlags::ABSTRACT, "abstract"),
+    (AccessFlags::STRICT, "strictfp"),
+];
+
+fn names(flags: AccessFlags, table: &[(AccessFlags, &'static str)]) -> Vec<&'static str> {
+    table
+        .iter()
+        .filter(|&&(flag, _)| flags.contains(flag))
+        .map(|&(_, name)| name)
+        .collect()
+}
+
+struct Javap<'a> {
+    class_file: &'a ClassFile,
+    constant_pool: &'a ConstantPool,
+    out: Writer,
+}
+
+impl<'a> Javap<'a> {
+    fn class(&mut self, unit: &CompilationUnit<Code>) -> Result<()> {
+        let class_file = self.class_file;
+        for attribute in &class_file.attributes {
+            if let Attribute::SourceFile(index) = *attribute {
+                let source_file = self.constant_pool.lookup_string(index)?;
+                self.out.indent += 1;
+                self.out
+                    .print_line(format!("Compiled from \"{}\"", source_file));
+                self.out.indent -= 1;
+            }
+        }
+        self.class_declaration()?;
+        self.out.indent += 1;
+        self.out
+            .print_line(format!("minor version: {}", class_file.minor_version));
+        self.out
+            .print_line(format!("major version: {}", class_file.major_version));
+        self.flags(class_file.access_flags, CLASS_FLAGS);
+        self.out
+            .print(format!("this_class: #{}", class_file.this_class));
+        self.out.tab();
+        let this_class = self.string_value(class_file.this_class)?;
+        self.out.print_line(format!("// {}", this_class));
+        self.out
+            .print(format!("super_class: #{}", class_file.super_class));
+        if class_file.super_class != 0 {
+            self.out.tab();
+            let super_class = self.string_value(class_file.super_class)?;
+            self.out.print(format!("// {}", super_class));
+        }
+        self.out.println();
+        self.out.print_line(format!(
+            "interfaces: {}, fields: {}, methods: {}, attributes: {}",
+            class_file.interfaces.len(),
+            class_file.fields.len(),
+            class_file.methods.len(),
+            class_file.attributes.len()
+        ));
+        self.out.indent -= 1;
+        self.constant_pool()?;
+
+        self.out.print_line("{");
+        self.out.indent += 1;
+        let codes = unit
+            .declarations
+            .iter()
+            .filter_map(|declaration| match *declaration {
+                Declaration::Method(ref method) => Some(method.code.as_ref()),
+                _ => None,
+            });
+        let mut first = true;
+        for field in &class_file.fields {
+            if !first {
+                self.out.println();
+            }
+            first = false;
+            self.field(field)?;
+        }
+        for (method, code) in class_file.methods.iter().zip(codes) {
+            if !first {
+                self.out.println();
+            }
+            first = false;
+            self.method(method, code)?;
+        }
+        self.out.indent -= 1;
+        self.out.print_line("}");
+        for attribute in &class_file.attributes {
+            self.attribute(attribute)?;
+        }
+        Ok(())
+    }
+
+    fn class_declaration(&mut self) -> Result<()> {
+        let class_file = self.class_file;
+        let flags = class_file.access_flags;
+        let is_interface = flags.contains(AccessFlags::INTERFACE);
+        let mut modifiers = if is_interface {
+            names(flags - AccessFlags::ABSTRACT, CLASS_MODIFIERS)
+        } else {
+            names(flags, CLASS_MODIFIERS)
+        };
+        modifiers.push(if is_interface { "interface" } else { "class" });
+        for modifier in modifiers {
+            self.out.print(format!("{} ", modifier));
+        }
+        self.out.print(self.class_name(class_file.this_class)?);
+        if !is_interface && class_file.super_class != 0 {
+            let super_class = self.class_name(class_file.super_class)?;
+            if super_class != "java.lang.Object" {
+                self.out.print(format!(" extends {}", super_class));
+            }
+        }
+        for (i, &interface) in class_file.interfaces.iter().enumerate() {
+            if i > 0 {
+                self.out.print(",");
+            } else if is_interface {
+                self.out.print(" extends ");
+            } else {
+                self.out.print(" implements ");
+            }
+            self.out.print(self.class_name(interface)?);
+        }
+        self.out.println();
+        Ok(())
+    }
+
+    fn flags(&mut self, flags: AccessFlags, table: &[(AccessFlags, &'static str)]) {
+        self.out.print(format!("flags: (0x{:04x}) ", flags.bits()));
+        self.out.print_line(names(flags, table).join(", "));
+    }
+
+    fn constant_pool(&mut self) -> Result<()> {
+        self.out.print_line("Constant pool:");
+        self.out.indent += 1;
+        let constant_pool = self.constant_pool;
+        let count = constant_pool.constants.len() + 1;
+        let width = count.to_string().len() + 1;
+        for (i, constant) in constant_pool.constants.iter().enumerate() {
+            let index = i as u16 + 1;
+            let tag = match *constant {
+                ConstantInfo::Utf8(_) => "Utf8",
+                ConstantInfo::Integer(_) => "Integer",
+                ConstantInfo::Float(_) => "Float",
+                ConstantInfo::Long(_) => "Long",
+                ConstantInfo::Double(_) => "Double",
+                ConstantInfo::Class { .. } => "Class",
+                ConstantInfo::String { .. } => "String",
+                ConstantInfo::FieldRef { .. } => "Fieldref",
+                ConstantInfo::MethodRef { .. } => "Methodref",
+                ConstantInfo::InterfaceMethodRef { .. } => "InterfaceMethodref",
+                ConstantInfo::NameAndType { .. } => "NameAndType",
+                ConstantInfo::MethodHandle { .. } => "MethodHandle",
+                ConstantInfo::MethodType { .. } => "MethodType",
+                ConstantInfo::Dynamic { .. } => "Dynamic",
+                ConstantInfo::InvokeDynamic { .. } => "InvokeDynamic",
+                ConstantInfo::Module { .. } => "Module",
+                ConstantInfo::Package { .. } => "Package",
+                ConstantInfo::Unusable => continue,
+            };
+            let index_string = format!("#{}", index);
+            self.out
+                .print(format!("{:>2$} = {:<18} ", index_string, tag, width));
+            let (references, comment_prefix) = match *constant {
+                ConstantInfo::Class { name_index }
+                | ConstantInfo::Module { name_index }
+                | ConstantInfo::Package { name_index } => (format!("#{}", name_index), "// "),
+                ConstantInfo::String { string_index } => (format!("#{}", string_index), "// "),
+                ConstantInfo::FieldRef {
+                    class_index,
+                    name_index,
+                }
+                | ConstantInfo::MethodRef {
+                    class_index,
+                    name_index,
+                }
+                | ConstantInfo::InterfaceMethodRef {
+                    class_index,
+                    name_index,
+                } => (format!("#{}.#{}", class_index, name_index), "// "),
+                ConstantInfo::NameAndType {
+                    name_index,
+                    descriptor_index,
+                } => (format!("#{}:#{}", name_index, descriptor_index), "// "),
+                ConstantInfo::MethodHandle {
+                    reference_kind,
+                    reference_index,
+                } => {
+                    let (kind, _) = reference_kind_info(reference_kind);
+                    (format!("{}:#{}", kind, reference_index), "// ")
+                }
+                // sic, javap uses two spaces here:
+                ConstantInfo::MethodType { descriptor_index } => {
+                    (format!("#{}", descriptor_index), "//  ")
+                }
+                ConstantInfo::Dynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                }
+                | ConstantInfo::InvokeDynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                } => (
+                    format!("#{}:#{}", bootstrap_method_attr_index, name_and_type_index),
+                    "// ",
+                ),
+                _ => {
+                    // Constants without references are shown without comment:
+                    let value = self.string_value(index)?;
+                    self.out.print_line(value);
+                    continue;
+                }
+            };
+            self.out.print(references);
+            self.out.tab();
+            let value = self.string_value(index)?;
+            self.out.print_line(format!("{}{}", comment_prefix, value));
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn field(&mut self, field: &FieldInfo) -> Result<()> {
+        let descriptor = self.constant_pool.lookup_string(field.descriptor_index)?;
+        let name = self.constant_pool.lookup_string(field.name_index)?;
+        for modifier in names(field.access_flags, FIELD_MODIFIERS) {
+            self.out.print(format!("{} ", modifier));
+        }
+        let typ = descriptor_to_type(descriptor)?;
+        self.out.print_line(format!("{} {};", typ, name));
+        self.out.indent += 1;
+        self.out.print_line(format!("descriptor: {}", descriptor));
+        self.flags(field.access_flags, FIELD_FLAGS);
+        for attribute in &field.attributes {
+            self.attribute(attribute)?;
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn method(&mut self, method: &MethodInfo, code: Option<&Code>) -> Result<()> {
+        let descriptor = self.constant_pool.lookup_string(method.descriptor_index)?;
+        let name = self.constant_pool.lookup_string(method.name_index)?;
+        let flags = method.access_flags;
+        let mut modifiers = names(flags, METHOD_MODIFIERS);
+        if self
+            .class_file
+            .access_flags
+            .contains(AccessFlags::INTERFACE)
+            && !flags.intersects(AccessFlags::ABSTRACT | AccessFlags::STATIC | AccessFlags::PRIVATE)
+            && name != "<clinit>"
+        {
+            modifiers.push("default");
+        }
+        for modifier in modifiers {
+            self.out.print(format!("{} ", modifier));
+        }
+        let signature = descriptor_to_signature(descriptor)?;
+        let count = signature.parameters.len();
+        let parameters = signature
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(i, (_, typ))| match *typ {
+                Type::Array(ref element)
+                    if i + 1 == count && flags.contains(AccessFlags::VARARGS) =>
+                {
+                    format!("{}...", element)
+                }
+                _ => format!("{}", typ),
+            })
+            .collect::<Vec<_>>();
+        let parameters = format!("({})", parameters.join(", "));
+        match name {
+            "<init>" => {
+                let class_name = self.class_name(self.class_file.this_class)?;
+                self.out.print(format!("{}{}", class_name, parameters));
+            }
+            "<clinit>" => self.out.print("{}"),
+            _ => self
+                .out
+                .print(format!("{} {}{}", signature.return_type, name, parameters)),
+        }
+        for attribute in &method.attributes {
+            if let Attribute::Exceptions(ref exceptions) = *attribute {
+                let exceptions = exceptions
+                    .iter()
+                    .map(|&exception| self.class_name(exception))
+                    .collect::<Result<Vec<_>>>()?;
+                self.out.print(format!(" throws {}", exceptions.join(", ")));
+            }
+        }
+        self.out.print_line(";");
+        self.out.indent += 1;
+        self.out.print_line(format!("descriptor: {}", descriptor));
+        self.flags(flags, METHOD_FLAGS);
+        for attribute in &method.attributes {
+            if let Attribute::Code(ref code_attribute) = *attribute {
+                let code = code.ok_or_else(|| {
+                    Error::new(
+                        Structure::Method(method.name_index),
+                        ErrorKind::UnexpectedConstant {
+                            index: method.name_index,
+                            expected: "a method that was disassembled",
+                        },
+                    )
+                })?;
+                let args_size = count
+                    + if flags.contains(AccessFlags::STATIC) {
+                        0
+                    } else {
+                        1
+                    };
+                self.code(code_attribute, code, args_size)?;
+            } else {
+                self.attribute(attribute)?;
+            }
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn code(
+        &mut self,
+        code_attribute: &CodeAttribute,
+        code: &Code,
+        args_size: usize,
+    ) -> Result<()> {
+        self.out.print_line("Code:");
+        self.out.indent += 1;
+        self.out.print_line(format!(
+            "stack={}, locals={}, args_size={}",
+            code_attribute.max_stack, code_attribute.max_locals, args_size
+        ));
+        let bytes = &code_attribute.code;
+        for &(pc, ref instruction) in &code.instructions {
+            let opcode = bytes[pc as usize];
+            if opcode == 0xc4 {
+                let widened = mnemonic(bytes[pc as usize + 1]);
+                self.out
+                    .print(format!("{:4}: {:<13} ", pc, format!("{}_w", widened)));
+            } else {
+                self.out
+                    .print(format!("{:4}: {:<13} ", pc, mnemonic(opcode)));
+            }
+            self.operands(opcode, pc, instruction, bytes)?;
+            self.out.println();
+        }
+        if !code_attribute.exception_table.is_empty() {
+            self.out.print_line("Exception table:");
+            self.out.indent += 1;
+            self.out.print_line(" from    to  target type");
+            for entry in &code_attribute.exception_table {
+                self.out.print(format!(
+                    " {:5} {:5} {:5}   ",
+                    entry.start_pc, entry.end_pc, entry.handler_pc
+                ));
+                if entry.catch_type == 0 {
+                    self.out.print_line("any");
+                } else {
+                    let class = self.string_value(entry.catch_type)?;
+                    self.out.print_line(format!("Class {}", class));
+                }
+            }
+            self.out.indent -= 1;
+        }
+        for attribute in &code_attribute.attributes {
+            self.attribute(attribute)?;
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn operands(
+        &mut self,
+        opcode: u8,
+        pc: u16,
+        instruction: &Instruction,
+        bytes: &[u8],
+    ) -> Result<()> {
+        match *instruction {
+            // bipush and sipush; the other constants are implied by their opcode:
+            Instruction::Load(RValue::Constant(Literal::Integer(value)))
+                if opcode == 0x10 || opcode == 0x11 =>
+            {
+                self.out.print(value.to_string())
+            }
+            Instruction::Load(RValue::ConstantRef { const_ref }) => self.constant_ref(const_ref)?,
+            Instruction::Load(RValue::LValue(ref lvalue)) | Instruction::Store(ref lvalue) => {
+                match *lvalue {
+                    LValue::Local(_, index) => match opcode {
+                        0x15..=0x19 | 0x36..=0x3a | 0xc4 => self.out.print(index.to_string()),
+                        _ => {}
+                    },
+                    LValue::StaticField { field_ref } | LValue::InstanceField { field_ref, .. } => {
+                        self.constant_ref(field_ref)?
+                    }
+                    LValue::Stack(_) | LValue::ArrayElement { .. } => {}
+                }
+            }
+            Instruction::Arithm(Arithm::IncreaseLocal {
+                local_index,
+                increase,
+            }) => self.out.print(format!("{}, {}", local_index, increase)),
+            Instruction::ObjManip(ref obj_manip) => match *obj_manip {
+                ObjManip::New { class_ref }
+                | ObjManip::ANewArray { class_ref }
+                | ObjManip::CheckCast { class_ref }
+                | ObjManip::InstanceOf { class_ref } => self.constant_ref(class_ref)?,
+                ObjManip::NewArray(ref typ) => self.out.print(format!(" {}", typ)),
+                ObjManip::MultiANewArray {
+                    class_ref,
+                    dimensions,
+                } => self.constant_ref_and_value(class_ref, dimensions)?,
+                ObjManip::ArrayLength => {}
+            },
+            Instruction::Jump(Jump { address, .. })
+            | Instruction::Subroutine(Subroutine::Jsr { address }) => {
+                self.out.print(address.to_string())
+            }
+            Instruction::Subroutine(Subroutine::Ret { local_index }) => {
+                self.out.print(local_index.to_string())
+            }
+            Instruction::Switch(ref switch) => {
+                if opcode == 0xaa {
+                    let low = switch.cases.first().map_or(0, |&(value, _)| value);
+                    let high = switch.cases.last().map_or(-1, |&(value, _)| value);
+                    self.out.print(format!("{{ // {} to {}", low, high));
+                } else {
+                    self.out.print(format!("{{ // {}", switch.cases.len()));
+                }
+                // Align with the mnemonic after the pc:
+                self.out.indent += 3;
+                for &(value, address) in &switch.cases {
+                    self.out.print(format!("\n{:12}: {}", value, address));
+                }
+                self.out
+                    .print(format!("\n     default: {}\n}}", switch.default));
+                self.out.indent -= 3;
+            }
+            Instruction::Invoke(Invoke { method_index, kind }) => match kind {
+                InvokeKind::Interface => {
+                    let count = bytes[pc as usize + 3];
+                    self.constant_ref_and_value(method_index, count)?
+                }
+                InvokeKind::Dynamic => self.constant_ref_and_value(method_index, 0)?,
+                _ => self.constant_ref(method_index)?,
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn constant_ref(&mut self, index: u16) -> Result<()> {
+        self.out.print(format!("#{}", index));
+        self.out.tab();
+        let constant = self.constant(index)?;
+        self.out.print(format!("// {}", constant));
+        Ok(())
+    }
+
+    fn constant_ref_and_value(&mut self, index: u16, value: u8) -> Result<()> {
+        self.out.print(format!("#{},  {}", index, value));
+        self.out.tab();
+        let constant = self.constant(index)?;
+        self.out.print(format!("// {}", constant));
+        Ok(())
+    }
+
+    fn attribute(&mut self, attribute: &Attribute) -> Result<()> {
+        match *attribute {
+            Attribute::Code(_) => unreachable!("code is rendered by `method`"),
+            Attribute::ConstantValue(index) => {
+                let constant = self.constant(index)?;
+                self.out.print_line(format!("ConstantValue: {}", constant));
+            }
+            Attribute::Exceptions(ref exceptions) => {
+                self.out.print_line("Exceptions:");
+                let exceptions = exceptions
+                    .iter()
+                    .map(|&exception| self.class_name(exception))
+                    .collect::<Result<Vec<_>>>()?;
+                self.out.indent += 1;
+                self.out
+                    .print_line(format!("throws {}", exceptions.join(", ")));
+                self.out.indent -= 1;
+            }
+            Attribute::SourceFile(index) => {
+                let source_file = self.constant_pool.lookup_string(index)?;
+                self.out
+                    .print_line(format!("SourceFile: \"{}\"", source_file));
+            }
+            Attribute::LineNumberTable(ref line_numbers) => {
+                self.out.print_line("LineNumberTable:");
+                self.out.indent += 1;
+                for line_number in line_numbers {
+                    self.out.print_line(format!(
+                        "line {}: {}",
+                        line_number.line_number, line_number.start_pc
+                    ));
+                }
+                self.out.indent -= 1;
+            }
+            Attribute::LocalVariableTable(ref variables) => {
+                self.out.print_line("LocalVariableTable:");
+                self.local_variables(variables)?;
+            }
+            Attribute::LocalVariableTypeTable(ref variables) => {
+                self.out.print_line("LocalVariableTypeTable:");
+                self.local_variables(variables)?;
+            }
+            Attribute::Signature(index) => {
+                self.out.print(format!("Signature: #{}", index));
+                self.out.tab();
+                let signature = self.string_value(index)?;
+                self.out.print_line(format!("// {}", signature));
+            }
+            Attribute::InnerClasses(ref inner_classes) => {
+                self.out.print_line("InnerClasses:");
+                self.out.indent += 1;
+                for inner_class in inner_classes {
+                    self.inner_class(inner_class)?;
+                }
+                self.out.indent -= 1;
+            }
+            Attribute::EnclosingMethod {
+                class_index,
+                method_index,
+            } => {
+                self.out.print(format!(
+                    "EnclosingMethod: #{}.#{}",
+                    class_index, method_index
+                ));
+                self.out.tab();
+                let class_name = self.class_name(class_index)?;
+                self.out.print(format!("// {}", class_name));
+                if method_index != 0 {
+                    let (name_index, _) = self.constant_pool.lookup_name_and_type(method_index)?;
+                    let name = self.constant_pool.lookup_string(name_index)?;
+                    self.out.print(format!(".{}", name));
+                }
+                self.out.println();
+            }
+            Attribute::BootstrapMethods(ref bootstrap_methods) => {
+                self.out.print_line("BootstrapMethods:");
+                self.out.indent += 1;
+                for (i, bootstrap_method) in bootstrap_methods.iter().enumerate() {
+                    let method_ref = bootstrap_method.bootstrap_method_ref;
+                    let method = self.string_value(method_ref)?;
+                    self.out
+                        .print_line(format!("{}: #{} {}", i, method_ref, method));
+                    self.out.indent += 1;
+                    self.out.print_line("Method arguments:");
+                    self.out.indent += 1;
+                    for &argument in &bootstrap_method.bootstrap_arguments {
+                        let value = self.string_value(argument)?;
+                        self.out.print_line(format!("#{} {}", argument, value));
+                    }
+                    self.out.indent -= 2;
+                }
+                self.out.indent -= 1;
+            }
+            Attribute::StackMapTable(ref frames) => {
+                self.out.print_line(format!(
+                    "StackMapTable: number_of_entries = {}",
+                    frames.len()
+                ));
+                self.out.indent += 1;
+                for frame in frames {
+                    self.stack_map_frame(frame)?;
+                }
+                self.out.indent -= 1;
+            }
+            Attribute::Synthetic => self.out.print_line("Synthetic: true"),
+            Attribute::Deprecated => self.out.print_line("Deprecated: true"),
+            Attribute::MethodParameters(ref parameters) => {
+                self.out.print_line("MethodParameters:");
+                self.out.indent += 1;
+                self.out.print_line(format!("{:<31}{}", "Name", "Flags"));
+                for parameter in parameters {
+                    let name = if parameter.name_index == 0 {
+                        "<no name>"
+                    } else {
+                        self.constant_pool.lookup_string(parameter.name_index)?
+                    };
+                    let flags = names(
+                        parameter.access_flags,
+                        &[
+                            (AccessFlags::FINAL, "final"),
+                            (AccessFlags::SYNTHETIC, "synthetic"),
+                            (AccessFlags::MANDATED, "mandated"),
+                        ],
+                    );
+                    self.out
+                        .print_line(format!("{:<31}{}", name, flags.join(" ")));
+                }
+                self.out.indent -= 1;
+            }
+            Attribute::NestHost(index) => {
+                let host = self.constant(index)?;
+                self.out.print_line(format!("NestHost: {}", host));
+            }
+            Attribute::NestMembers(ref classes) => {
+                self.out.print_line("NestMembers:");
+                self.class_list(classes)?;
+            }
+            Attribute::PermittedSubclasses(ref classes) => {
+                self.out.print_line("PermittedSubclasses:");
+                self.class_list(classes)?;
+            }
+            Attribute::Record(_) | Attribute::Unknown(_) => {}
+        }
+        Ok(())
+    }
+
+    fn local_variables(&mut self, variables: &[LocalVariable]) -> Result<()> {
+        self.out.indent += 1;
+        self.out.print_line("Start  Length  Slot  Name   Signature");
+        for variable in variables {
+            let name = self.string_value(variable.name_index)?;
+            let descriptor = self.string_value(variable.descriptor_index)?;
+            self.out.print_line(format!(
+                "{:5} {:7} {:5} {:>5}   {}",
+                variable.start_pc, variable.length, variable.index, name, descriptor
+            ));
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn inner_class(&mut self, inner_class: &InnerClass) -> Result<()> {
+        let flags = inner_class.inner_class_access_flags;
+        let flags = if flags.contains(AccessFlags::INTERFACE) {
+            flags - AccessFlags::ABSTRACT
+        } else {
+            flags
+        };
+        for modifier in names(flags, INNER_CLASS_MODIFIERS) {
+            self.out.print(format!("{} ", modifier));
+        }
+        if inner_class.inner_name_index != 0 {
+            self.out
+                .print(format!("#{}= ", inner_class.inner_name_index));
+        }
+        self.out
+            .print(format!("#{}", inner_class.inner_class_info_index));
+        if inner_class.outer_class_info_index != 0 {
+            self.out
+                .print(format!(" of #{}", inner_class.outer_class_info_index));
+        }
+        self.out.print(";");
+        self.out.tab();
+        self.out.print("// ");
+        if inner_class.inner_name_index != 0 {
+            let name = self
+                .constant_pool
+                .lookup_string(inner_class.inner_name_index)?;
+            self.out.print(format!("{}=", name.replace('/', ".")));
+        }
+        let inner = self.constant(inner_class.inner_class_info_index)?;
+        self.out.print(inner);
+        if inner_class.outer_class_info_index != 0 {
+            let outer = self.constant(inner_class.outer_class_info_index)?;
+            self.out.print(format!(" of {}", outer));
+        }
+        self.out.println();
+        Ok(())
+    }
+
+    fn class_list(&mut self, classes: &[u16]) -> Result<()> {
+        self.out.indent += 1;
+        for &class in classes {
+            let class = self.string_value(class)?;
+            self.out.print_line(class);
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn stack_map_frame(&mut self, frame: &StackMapFrame) -> Result<()> {
+        // The frame type isn't kept by the parser, so reconstruct it from the shortest encoding,
+        // which is what compilers use.
+        let (frame_type, name, offset_delta) = match *frame {
+            StackMapFrame::Same { offset_delta } if offset_delta < 64 => {
+                (offset_delta as u8, "same", None)
+            }
+            StackMapFrame::Same { offset_delta } => {
+                (251, "same_frame_extended", Some(offset_delta))
+            }
+            StackMapFrame::SameLocals1StackItem { offset_delta, .. } if offset_delta < 64 => {
+                (64 + offset_delta as u8, "same_locals_1_stack_item", None)
+            }
+            StackMapFrame::SameLocals1StackItem { offset_delta, .. } => (
+                247,
+                "same_locals_1_stack_item_frame_extended",
+                Some(offset_delta),
+            ),
+            StackMapFrame::Chop {
+                offset_delta,
+                chopped,
+            } => (251 - chopped, "chop", Some(offset_delta)),
+            StackMapFrame::Append {
+                offset_delta,
+                ref locals,
+            } => (251 + locals.len() as u8, "append", Some(offset_delta)),
+            StackMapFrame::Full { offset_delta, .. } => (255, "full_frame", Some(offset_delta)),
+        };
+        self.out
+            .print_line(format!("frame_type = {} /* {} */", frame_type, name));
+        self.out.indent += 1;
+        if let Some(offset_delta) = offset_delta {
+            self.out
+                .print_line(format!("offset_delta = {}", offset_delta));
+        }
+        match *frame {
+            StackMapFrame::SameLocals1StackItem { stack, .. } => {
+                let stack = self.verification_types(&[stack])?;
+                self.out.print_line(format!("stack = {}", stack));
+            }
+            StackMapFrame::Append { ref locals, .. } => {
+                let locals = self.verification_types(locals)?;
+                self.out.print_line(format!("locals = {}", locals));
+            }
+            StackMapFrame::Full {
+                ref locals,
+                ref stack,
+                ..
+            } => {
+                let locals = self.verification_types(locals)?;
+                self.out.print_line(format!("locals = {}", locals));
+                let stack = self.verification_types(stack)?;
+                self.out.print_line(format!("stack = {}", stack));
+            }
+            StackMapFrame::Same { .. } | StackMapFrame::Chop { .. } => {}
+        }
+        self.out.indent -= 1;
+        Ok(())
+    }
+
+    fn verification_types(&self, types: &[VerificationType]) -> Result<String> {
+        if types.is_empty() {
+            return Ok("[]".to_owned());
+        }
+        let types = types
+            .iter()
+            .map(|typ| {
+                Ok(match *typ {
+                    VerificationType::Top => "top".to_owned(),
+                    VerificationType::Integer => "int".to_owned(),
+                    VerificationType::Float => "float".to_owned(),
+                    VerificationType::Double => "double".to_owned(),
+                    VerificationType::Long => "long".to_owned(),
+                    VerificationType::Null => "null".to_owned(),
+                    VerificationType::UninitializedThis => "this".to_owned(),
+                    VerificationType::Object { class_index } => {
+                        format!("class {}", self.string_value(class_index)?)
+                    }
+                    VerificationType::Uninitialized { offset } => {
+                        format!("uninitialized {}", offset)
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("[ {} ]", types.join(", ")))
+    }
+
+    /// The name of a class constant in Java syntax.
+    fn class_name(&self, index: u16) -> Result<String> {
+        lookup_class_name(self.constant_pool, index)
+    }
+
+    /// A constant as it is shown in comments of instructions and some attributes, i.e. its value
+    /// prefixed by its type.
+    fn constant(&self, index: u16) -> Result<String> {
+        let constant = self.constant_pool.lookup(index)?;
+        let tag = match *constant {
+            ConstantInfo::Utf8(_) => "Utf8",
+            ConstantInfo::Integer(_) => "int",
+            ConstantInfo::Float(_) => "float",
+            ConstantInfo::Long(_) => "long",
+            ConstantInfo::Double(_) => "double",
+            ConstantInfo::Class { .. } => "class",
+            ConstantInfo::String { .. } => "String",
+            ConstantInfo::FieldRef { .. } => "Field",
+            ConstantInfo::MethodRef { .. } => "Method",
+            ConstantInfo::InterfaceMethodRef { .. } => "InterfaceMethod",
+            ConstantInfo::NameAndType { .. } => "NameAndType",
+            ConstantInfo::MethodHandle { .. } => "MethodHandle",
+            ConstantInfo::MethodType { .. } => "MethodType",
+            ConstantInfo::Dynamic { .. } => "Dynamic",
+            ConstantInfo::InvokeDynamic { .. } => "InvokeDynamic",
+            ConstantInfo::Module { .. } => "Module",
+            ConstantInfo::Package { .. } => "Package",
+            ConstantInfo::Unusable => unreachable!("rejected by lookup"),
+        };
+        // References to members of this class are shown without the class name:
+        let value = match *constant {
+            ConstantInfo::FieldRef {
+                class_index,
+                name_index,
+            }
+            | ConstantInfo::MethodRef {
+                class_index,
+                name_index,
+            }
+            | ConstantInfo::InterfaceMethodRef {
+                class_index,
+                name_index,
+            } if class_index == self.class_file.this_class => self.string_value(name_index)?,
+            _ => self.string_value(index)?,
+        };
+        Ok(format!("{} {}", tag, value))
+    }
+
+    /// The value of a constant as javap shows it.
+    fn string_value(&self, index: u16) -> Result<String> {
+        let constant_pool = self.constant_pool;
+        Ok(match *constant_pool.lookup(index)? {
+            ConstantInfo::Utf8(ref string) => escape(string),
+            ConstantInfo::Integer(int) => int.to_string(),
+            ConstantInfo::Float(float) => format!("{}f", java_float(float as f64, true)),
+            ConstantInfo::Long(long) => format!("{}l", long),
+            ConstantInfo::Double(double) => format!("{}d", java_float(double, false)),
+            ConstantInfo::Class { name_index }
+            | ConstantInfo::Module { name_index }
+            | ConstantInfo::Package { name_index } => {
+                check_name(constant_pool.lookup_string(name_index)?)
+            }
+            ConstantInfo::String {
+                string_index: index,
+            }
+            | ConstantInfo::MethodType {
+                descriptor_index: index,
+            } => self.string_value(index)?,
+            ConstantInfo::FieldRef {
+                class_index,
+                name_index,
+            }
+            | ConstantInfo::MethodRef {
+                class_index,
+                name_index,
+            }
+            | ConstantInfo::InterfaceMethodRef {
+                class_index,
+                name_index,
+            } => format!(
+                "{}.{}",
+                self.string_value(class_index)?,
+                self.string_value(name_index)?
+            ),
+            ConstantInfo::NameAndType {
+                name_index,
+                descriptor_index,
+            } => format!(
+                "{}:{}",
+                check_name(constant_pool.lookup_string(name_index)?),
+                constant_pool.lookup_string(descriptor_index)?
+            ),
+            ConstantInfo::MethodHandle {
+                reference_kind,
+                reference_index,
+            } => {
+                let (_, kind) = reference_kind_info(reference_kind);
+                format!("{} {}", kind, self.string_value(reference_index)?)
+            }
+            ConstantInfo::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            }
+            | ConstantInfo::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => format!(
+                "#{}:{}",
+                bootstrap_method_attr_index,
+                self.string_value(name_and_type_index)?
+            ),
+            ConstantInfo::Unusable => unreachable!("rejected by lookup"),
+        })
+    }
+}
+
+fn reference_kind_info(kind: ReferenceKind) -> (u8, &'static str) {
+    match kind {
+        ReferenceKind::GetField => (1, "REF_getField"),
+        ReferenceKind::GetStatic => (2, "REF_getStatic"),
+        ReferenceKind::PutField => (3, "REF_putField"),
+        ReferenceKind::PutStatic => (4, "REF_putStatic"),
+        ReferenceKind::InvokeVirtual => (5, "REF_invokeVirtual"),
+        ReferenceKind::InvokeStatic => (6, "REF_invokeStatic"),
+        ReferenceKind::InvokeSpecial => (7, "REF_invokeSpecial"),
+        ReferenceKind::NewInvokeSpecial => (8, "REF_newInvokeSpecial"),
+        ReferenceKind::InvokeInterface => (9, "REF_invokeInterface"),
+    }
+}
+
+/// Escapes a string constant like javap does.
+fn escape(string: &str) -> String {
+    let mut result = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\u{8}' => result.push_str("\\b"),
+            '\u{c}' => result.push_str("\\f"),
+            '"' => result.push_str("\\\""),
+            '\'' => result.push_str("\\'"),
+            '\\' => result.push_str("\\\\"),
+            _ if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Quotes names that aren't (slash separated) Java identifiers, like `<init>` or `[I`.
+fn check_name(name: &str) -> String {
+    let is_start = |c: char| c.is_alphabetic() || c == '$' || c == '_';
+    let mut previous = '/';
+    let is_identifier = !name.is_empty()
+        && name.chars().all(|c| {
+            let valid = if previous == '/' {
+                is_start(c)
+            } else {
+                c == '/' || is_start(c) || c.is_numeric()
+            };
+            previous = c;
+            valid
+        });
+    if is_identifier {
+        return name.to_owned();
+    }
+    let mut result = "\"".to_owned();
+    for c in name.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Formats a float or double like Java's `Float.toString` and `Double.toString`.
+fn java_float(value: f64, is_float: bool) -> String {
+    if value.is_nan() {
+        return "NaN".to_owned();
+    } else if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_owned();
+    }
+    // Print the shortest representation that reads back as the same float or double:
+    let (decimal, scientific) = if is_float {
+        (format!("{:?}", value as f32), format!("{:e}", value as f32))
+    } else {
+        (format!("{:?}", value), format!("{:e}", value))
+    };
+    let magnitude = value.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        decimal
+    } else {
+        let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+        let exponent = &exponent[1..];
+        if mantissa.contains('.') {
+            format!("{}E{}", mantissa, exponent)
+        } else {
+            format!("{}.0E{}", mantissa, exponent)
+        }
+    }
+}
+
+/// The mnemonics of the opcodes, as defined in chapter 6.5 of the JVM spec.
+fn mnemonic(opcode: u8) -> &'static str {
+    const MNEMONICS: [&str; 0xca] = [
+        "nop",
+        "aconst_null",
+        "iconst_m1",
+        "iconst_0",
+        "iconst_1",
+        "iconst_2",
+        "iconst_3",
+        "iconst_4",
+        "iconst_5",
+        "lconst_0",
+        "lconst_1",
+        "fconst_0",
+        "fconst_1",
+        "fconst_2",
+        "dconst_0",
+        "dconst_1",
+        "bipush",
+        "sipush",
+        "ldc",
+        "ldc_w",
+        "ldc2_w",
+        "iload",
+        "lload",
+        "fload",
+        "dload",
+        "aload",
+        "iload_0",
+        "iload_1",
+        "iload_2",
+        "iload_3",
+        "lload_0",
+        "lload_1",
+        "lload_2",
+        "lload_3",
+        "fload_0",
+        "fload_1",
+        "fload_2",
+        "fload_3",
+        "dload_0",
+        "dload_1",
+        "dload_2",
+        "dload_3",
+        "aload_0",
+        "aload_1",
+        "aload_2",
+        "aload_3",
+        "iaload",
+        "laload",
+        "faload",
+        "daload",
+        "aaload",
+        "baload",
+        "caload",
+        "saload",
+        "istore",
+        "lstore",
+        "fstore",
+        "dstore",
+        "astore",
+        "istore_0",
+        "istore_1",
+        "istore_2",
+        "istore_3",
+        "lstore_0",
+        "lstore_1",
+        "lstore_2",
+        "lstore_3",
+        "fstore_0",
+        "fstore_1",
+        "fstore_2",
+        "fstore_3",
+        "dstore_0",
+        "dstore_1",
+        "dstore_2",
+        "dstore_3",
+        "astore_0",
+        "astore_1",
+        "astore_2",
+        "astore_3",
+        "iastore",
+        "lastore",
+        "fastore",
+        "dastore",
+        "aastore",
+        "bastore",
+        "castore",
+        "sastore",
+        "pop",
+        "pop2",
+        "dup",
+        "dup_x1",
+        "dup_x2",
+        "dup2",
+        "dup2_x1",
+        "dup2_x2",
+        "swap",
+        "iadd",
+        "ladd",
+        "fadd",
+        "dadd",
+        "isub",
+        "lsub",
+        "fsub",
+        "dsub",
+        "imul",
+        "lmul",
+        "fmul",
+        "dmul",
+        "idiv",
+        "ldiv",
+        "fdiv",
+        "ddiv",
+        "irem",
+        "lrem",
+        "frem",
+        "drem",
+        "ineg",
+        "lneg",
+        "fneg",
+        "dneg",
+        "ishl",
+        "lshl",
+        "ishr",
+        "lshr",
+        "iushr",
+        "lushr",
+        "iand",
+        "land",
+        "ior",
+        "lor",
+        "ixor",
+        "lxor",
+        "iinc",
+        "i2l",
+        "i2f",
+        "i2d",
+        "l2i",
+        "l2f",
+        "l2d",
+        "f2i",
+        "f2l",
+        "f2d",
+        "d2i",
+        "d2l",
+        "d2f",
+        "i2b",
+        "i2c",
+        "i2s",
+        "lcmp",
+        "fcmpl",
+        "fcmpg",
+        "dcmpl",
+        "dcmpg",
+        "ifeq",
+        "ifne",
+        "iflt",
+        "ifge",
+        "ifgt",
+        "ifle",
+        "if_icmpeq",
+        "if_icmpne",
+        "if_icmplt",
+        "if_icmpge",
+        "if_icmpgt",
+        "if_icmple",
+        "if_acmpeq",
+        "if_acmpne",
+        "goto",
+        "jsr",
+        "ret",
+        "tableswitch",
+        "lookupswitch",
+        "ireturn",
+        "lreturn",
+        "freturn",
+        "dreturn",
+        "areturn",
+        "return",
+        "getstatic",
+        "putstatic",
+        "getfield",
+        "putfield",
+        "invokevirtual",
+        "invokespecial",
+        "invokestatic",
+        "invokeinterface",
+        "invokedynamic",
+        "new",
+        "newarray",
+        "anewarray",
+        "arraylength",
+        "athrow",
+        "checkcast",
+        "instanceof",
+        "monitorenter",
+        "monitorexit",
+        "wide",
+        "multianewarray",
+        "ifnull",
+        "ifnonnull",
+        "goto_w",
+        "jsr_w",
+    ];
+    match opcode {
+        0xca => "breakpoint",
+        0xfe => "impdep1",
+        0xff => "impdep2",
+        _ => MNEMONICS.get(opcode as usize).cloned().unwrap_or("invalid"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use classfile::parser::parse_class_file;
+
+    #[test]
+    fn same_as_javap() {
+        let bytes = include_bytes!("../../testdata/Disassembly.class");
+        let class_file = parse_class_file(&mut &bytes[..]).unwrap();
+        let unit = transform(&class_file, false).unwrap();
+        let actual = javap(&class_file, &unit).unwrap();
+        // The output of javap without the lines about the class file, see `Disassembly.java`:
+        let expected = include_str!("../../testdata/Disassembly.javap");
+        for (i, (actual, expected)) in actual.lines().zip(expected.lines()).enumerate() {
+            assert_eq!(actual, expected, "line {}", i + 1);
+        }
+        assert_eq!(actual.lines().count(), expected.lines().count());
+    }
+}
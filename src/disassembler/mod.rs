@@ -1,5 +1,7 @@
 pub mod disassemble;
+pub mod error;
 pub mod instructions;
+pub mod javap;
 pub mod pretty;
 pub mod transform;
 pub mod types;
@@ -4,7 +4,7 @@ use std::fmt::*;
 
 impl<C> Display for CompilationUnit<C>
 where
-    C: PrettyWith<CompilationUnit<C>>,
+    C: PrettyBody<CompilationUnit<C>>,
 {
     fn fmt(&self, f: &mut Formatter) -> Result {
         writeln!(f, "{}", self.pretty().render_string(120))
@@ -13,14 +13,62 @@ where
 
 impl<C, T> PrettyWith<T> for CompilationUnit<C>
 where
-    C: PrettyWith<CompilationUnit<C>>,
+    C: PrettyBody<CompilationUnit<C>>,
 {
     fn pretty_with(&self, _: &T) -> Doc {
-        let modifiers = self.modifiers.pretty();
-        let first = modifiers + format!(" {} {} {{", self.typ, self.name);
-        let declarations = self.declarations
-            .iter()
-            .map(|declaration| declaration.pretty_with(self));
+        let mut result = empty();
+        if !self.package().is_empty() {
+            result += doc(format!("package {};", self.package())) + newline() + newline();
+        }
+        if !self.imports.is_empty() {
+            let imports = self
+                .imports
+                .iter()
+                .map(|import| doc(format!("import {};", import)));
+            result += intersperse(imports, newline()) + newline() + newline();
+        }
+        result + self.pretty_class()
+    }
+}
+
+impl<C> CompilationUnit<C>
+where
+    C: PrettyBody<CompilationUnit<C>>,
+{
+    /// The declaration of the class, including its member classes.
+    fn pretty_class(&self) -> Doc {
+        let mut first = self.modifiers.pretty() + format!("{} {}", self.typ, self.simple_name());
+        if let Some(ref class) = self.extends {
+            first += doc(" extends ") + &class.0;
+        }
+        if !self.implements.is_empty() {
+            let keyword = match self.typ {
+                UnitType::Interface => " extends ",
+                UnitType::Class | UnitType::Enum => " implements ",
+            };
+            let interfaces = self.implements.iter().map(|class| doc(&class.0));
+            first += doc(keyword) + intersperse(interfaces, ", ");
+        }
+        let first = first + " {";
+        let mut declarations = vec![];
+        if self.typ == UnitType::Enum {
+            // The constants come first, and are separated from the other declarations by `;`:
+            let constants = self
+                .declarations
+                .iter()
+                .filter_map(|declaration| match *declaration {
+                    Declaration::EnumConstant(ref constant) => Some(constant.pretty()),
+                    _ => None,
+                });
+            declarations.push(intersperse(constants, doc(',') + newline()) + ';');
+        }
+        declarations.extend(
+            self.declarations
+                .iter()
+                .filter(|declaration| !matches!(**declaration, Declaration::EnumConstant(_)))
+                .map(|declaration| declaration.pretty_with(self)),
+        );
+        declarations.extend(self.nested.iter().map(CompilationUnit::pretty_class));
         let declarations = newline() + intersperse(declarations, newline());
         first + declarations.nest(4) + newline() + '}'
     }
@@ -45,9 +93,10 @@ impl Display for Modifier {
     }
 }
 
+/// The modifiers, each followed by a space.
 impl<T> PrettyWith<T> for Vec<Modifier> {
     fn pretty_with(&self, _: &T) -> Doc {
-        intersperse(self.iter().map(doc), ' ')
+        concat(self.iter().map(|modifier| doc(modifier) + ' '))
     }
 }
 
@@ -97,36 +146,90 @@ fn pretty_parameters(parameters: &[(String, Type)]) -> Doc {
     tupled(parameters)
 }
 
+fn pretty_throws(throws: &[ClassRef]) -> Doc {
+    if throws.is_empty() {
+        return empty();
+    }
+    let classes = throws.iter().map(|class| doc(&class.0));
+    doc(" throws ") + intersperse(classes, ", ")
+}
+
 impl Display for Signature {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(f, "{}", self.pretty_with("").render_string(None))
     }
 }
 
+impl Display for Descriptor {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            Descriptor::Type(ref typ) => write!(f, "{}", typ),
+            Descriptor::Signature(ref signature) => write!(f, "{}", signature),
+        }
+    }
+}
+
 impl<T, C> PrettyWith<CompilationUnit<T>> for Declaration<C>
 where
-    C: PrettyWith<CompilationUnit<T>>,
+    C: PrettyBody<CompilationUnit<T>>,
 {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
         match *self {
-            Declaration::Field(_) => unimplemented!(),
+            Declaration::Field(ref f) => f.pretty(),
             Declaration::Method(ref m) => m.pretty_with(unit),
             Declaration::Constructor(ref c) => c.pretty_with(unit),
+            Declaration::EnumConstant(ref c) => c.pretty(),
+        }
+    }
+}
+
+impl<T> PrettyWith<T> for EnumConstant {
+    fn pretty_with(&self, _: &T) -> Doc {
+        if self.arguments.is_empty() {
+            return doc(&self.name);
         }
+        doc(&self.name) + tupled(self.arguments.iter().map(doc))
     }
 }
 
+impl<T> PrettyWith<T> for Field {
+    fn pretty_with(&self, _: &T) -> Doc {
+        let value = self
+            .value
+            .as_ref()
+            .map_or_else(String::new, |value| format!(" = {}", value));
+        let initializer = match self.initializer {
+            Some(ref initializer) => group(nest(4, doc(" =") + spaceline() + initializer.pretty())),
+            None => empty(),
+        };
+        self.modifiers.pretty() + format!("{} {}{}", self.typ, self.name, value) + initializer + ';'
+    }
+}
+
+/// The code of a method, which is printed as its body.
+pub trait PrettyBody<T>: PrettyWith<T> {
+    /// The body including its braces.
+    fn pretty_body(&self, context: &T) -> Doc {
+        doc('{') + nest(4, newline() + self.pretty_with(context)) + newline() + '}'
+    }
+}
+
+impl<T> PrettyBody<CompilationUnit<T>> for Code {}
+
 impl<C, T> PrettyWith<CompilationUnit<T>> for Method<C>
 where
-    C: PrettyWith<CompilationUnit<T>>,
+    C: PrettyBody<CompilationUnit<T>>,
 {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
-        let mut result = self.modifiers.pretty() + ' ';
+        // The static initializer is a block in the source code rather than a method:
+        if let ("<clinit>", Some(ref code)) = (self.name.as_str(), &self.code) {
+            return doc("static ") + code.pretty_body(unit);
+        }
+        let mut result = self.modifiers.pretty();
         result += self.signature.pretty_with(&self.name);
+        result += pretty_throws(&self.throws);
         if let Some(ref code) = self.code {
-            result += " {";
-            result += nest(4, newline() + code.pretty_with(unit));
-            result += newline() + "}";
+            result += doc(' ') + code.pretty_body(unit);
         } else {
             result += ";"
         }
@@ -136,42 +239,53 @@ where
 
 impl<C, T> PrettyWith<CompilationUnit<T>> for Constructor<C>
 where
-    C: PrettyWith<CompilationUnit<T>>,
+    C: PrettyBody<CompilationUnit<T>>,
 {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
-        let mut result = self.modifiers.pretty() + ' ';
-        result += &unit.name;
+        let mut result = self.modifiers.pretty();
+        result += unit.simple_name();
         result += pretty_parameters(&self.parameters);
-        result += " {";
-        result += nest(4, newline() + self.code.pretty_with(unit));
-        result += newline() + "}";
+        result += pretty_throws(&self.throws);
+        result += doc(' ') + self.code.pretty_body(unit);
         result
     }
 }
 
 impl<T> PrettyWith<CompilationUnit<T>> for Code {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
-        let docs = self.instructions.iter().map(|&(pc, ref instruction)| {
-            doc(format!("{:#6X}: ", pc)) + instruction.pretty_with(unit)
+        let docs = self
+            .instructions
+            .iter()
+            .map(|instruction| instruction.pretty_with(unit));
+        let handlers = self.exception_handlers.iter().map(|handler| {
+            let catch_type = match handler.catch_type {
+                Some(class_ref) => unit.metadata.class_refs[&class_ref].0.clone(),
+                None => "any exception".to_owned(),
+            };
+            doc(format!(
+                "catch {} in {:#X}..{:#X} at {:#X}",
+                catch_type, handler.start, handler.end, handler.handler
+            ))
         });
-        intersperse(docs, newline())
+        intersperse(docs.chain(handlers), newline())
+    }
+}
+
+/// An instruction with its address.
+impl<T> PrettyWith<CompilationUnit<T>> for (u16, Instruction) {
+    fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
+        doc(format!("{:#6X}: ", self.0)) + self.1.pretty_with(unit)
     }
 }
 
 impl<T> PrettyWith<CompilationUnit<T>> for Instruction {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
         match *self {
-            Instruction::Nop => doc("nop"),
             Instruction::Load(ref rvalue) => doc("load ") + rvalue.pretty_with(unit),
             Instruction::Store(ref lvalue) => doc("store ") + lvalue.pretty_with(unit),
+            Instruction::ObjManip(ref obj_manip) => obj_manip.pretty_with(unit),
             Instruction::Invoke(ref invoke) => invoke.pretty_with(unit),
-            Instruction::Return(val) => doc(format!(
-                "return {}",
-                if val.is_some() { "value" } else { "void" }
-            )),
-            Instruction::Jump(ref jump) => doc(format!("{}", jump)),
-            Instruction::Arithm(ref arithm) => doc(format!("{}", arithm)),
-            _ => unimplemented!(),
+            _ => doc(format!("{}", self)),
         }
     }
 }
@@ -182,13 +296,22 @@ impl Display for Instruction {
             Instruction::Nop => write!(f, "nop"),
             Instruction::Load(ref rvalue) => write!(f, "load {}", rvalue),
             Instruction::Store(ref lvalue) => write!(f, "store {}", lvalue),
-            Instruction::Invoke(ref invoke) => write!(f, "invoke {}", invoke),
-            Instruction::Return(val) => {
-                write!(f, "return {}", if val.is_some() { "value" } else { "void" })
+            Instruction::Arithm(ref arithm) => write!(f, "{}", arithm),
+            Instruction::TypeConv(TypeConv { from, to }) => {
+                write!(f, "convert {} to {}", from, to)
             }
+            Instruction::ObjManip(ref obj_manip) => write!(f, "{}", obj_manip),
+            Instruction::StackManage(stack_manage) => write!(f, "{}", stack_manage),
             Instruction::Jump(ref jump) => write!(f, "{}", jump),
-            Instruction::Arithm(ref arithm) => write!(f, "{}", arithm),
-            _ => unimplemented!(),
+            Instruction::Switch(ref switch) => write!(f, "{}", switch),
+            Instruction::Subroutine(subroutine) => write!(f, "{}", subroutine),
+            Instruction::Invoke(ref invoke) => write!(f, "invoke {}", invoke),
+            Instruction::Throw => write!(f, "throw"),
+            Instruction::Return(Some(kind)) => write!(f, "return {}", kind),
+            Instruction::Return(None) => write!(f, "return void"),
+            Instruction::Synchronized(Synchronized::MonitorEnter) => write!(f, "monitor enter"),
+            Instruction::Synchronized(Synchronized::MonitorExit) => write!(f, "monitor exit"),
+            Instruction::Invalid(opcode) => write!(f, "invalid opcode {:#04x}", opcode),
         }
     }
 }
@@ -222,17 +345,66 @@ impl Display for Literal {
             Literal::Short(i) => write!(f, "{}", i),
             Literal::Integer(i) => write!(f, "{}", i),
             Literal::Long(i) => write!(f, "{}L", i),
-            // Literal::Float(d) => write!(f, "{}f", d),
-            // Literal::Double(d) => write!(f, "{}d", d),
-            Literal::String(ref s) => write!(f, r#""{}""#, s),
+            Literal::Float(d) => {
+                if d.is_nan() {
+                    write!(f, "Float.NaN")
+                } else if d.is_infinite() {
+                    let sign = if d > 0.0 { "POSITIVE" } else { "NEGATIVE" };
+                    write!(f, "Float.{}_INFINITY", sign)
+                } else {
+                    write!(f, "{:?}f", d)
+                }
+            }
+            Literal::Double(d) => {
+                if d.is_nan() {
+                    write!(f, "Double.NaN")
+                } else if d.is_infinite() {
+                    let sign = if d > 0.0 { "POSITIVE" } else { "NEGATIVE" };
+                    write!(f, "Double.{}_INFINITY", sign)
+                } else {
+                    write!(f, "{:?}", d)
+                }
+            }
+            Literal::Char(c) => {
+                write!(f, "'")?;
+                // A surrogate is only a character together with the other half of its pair:
+                match char::from_u32(c as u32) {
+                    Some(c) => write_escaped(f, c, '\'')?,
+                    None => write!(f, "\\u{:04x}", c)?,
+                }
+                write!(f, "'")
+            }
+            Literal::String(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    write_escaped(f, c, '"')?;
+                }
+                write!(f, "\"")
+            }
+            Literal::Class(ref typ) => write!(f, "{}.class", typ),
         }
     }
 }
 
+/// Writes the character as it appears in a literal enclosed in `quote`.
+fn write_escaped(f: &mut Formatter, c: char, quote: char) -> Result {
+    match c {
+        _ if c == quote => write!(f, "\\{}", c),
+        '\\' => write!(f, "\\\\"),
+        '\n' => write!(f, "\\n"),
+        '\r' => write!(f, "\\r"),
+        '\t' => write!(f, "\\t"),
+        '\u{8}' => write!(f, "\\b"),
+        '\u{c}' => write!(f, "\\f"),
+        _ if c.is_control() => write!(f, "\\u{:04x}", c as u32),
+        _ => write!(f, "{}", c),
+    }
+}
+
 impl Display for LValue {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match *self {
-            LValue::Local(i) => write!(f, "local_{}", i),
+            LValue::Local(kind, i) => write!(f, "local_{}: {}", i, kind),
             LValue::Stack(i) => write!(f, "stack[{}]", i),
             LValue::StaticField { field_ref } => write!(f, "static field {}", field_ref),
             LValue::InstanceField {
@@ -244,6 +416,15 @@ impl Display for LValue {
                 field_ref,
                 object_stack_index + 1
             ),
+            LValue::ArrayElement {
+                kind,
+                array_stack_index,
+                index_stack_index,
+            } => write!(
+                f,
+                "stack[{}][stack[{}]]: {}",
+                array_stack_index, index_stack_index, kind
+            ),
         }
     }
 }
@@ -251,8 +432,9 @@ impl Display for LValue {
 impl<T> PrettyWith<CompilationUnit<T>> for LValue {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
         match *self {
-            LValue::Local(i) => format!("local_{}", i),
-            LValue::Stack(i) => format!("stack[{}]", i),
+            LValue::Local(..) | LValue::Stack(_) | LValue::ArrayElement { .. } => {
+                format!("{}", self)
+            }
             LValue::StaticField { field_ref } => {
                 let field = &unit.metadata.field_refs[&field_ref];
                 let class = &unit.metadata.class_refs[&field.class_ref];
@@ -287,33 +469,43 @@ impl<T> PrettyWith<CompilationUnit<T>> for RValue {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
         match *self {
             RValue::Constant(ref constant) => format!("{}", constant),
-            RValue::ConstantRef { const_ref } => {
-                let constant = &unit.metadata.literals[&const_ref];
-                format!("{}", constant)
-            }
+            RValue::ConstantRef { const_ref } => match unit.metadata.literals.get(&const_ref) {
+                Some(constant) => format!("{}", constant),
+                // e.g. method handles, which have no literal syntax:
+                None => format!("{}", self),
+            },
             RValue::LValue(ref lvalue) => format!("{}", lvalue),
         }.into()
     }
 }
 
-impl Display for Invoke {
+impl Display for InvokeKind {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        let kind = match self.kind {
+        let kind = match *self {
             InvokeKind::Virtual => "virtual",
             InvokeKind::Special => "special",
             InvokeKind::Static => "static",
+            InvokeKind::Interface => "interface",
+            InvokeKind::Dynamic => "dynamic",
         };
-        write!(f, "{} {}", kind, self.method_index)
+        write!(f, "{}", kind)
+    }
+}
+
+impl Display for Invoke {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} {}", self.kind, self.method_index)
     }
 }
 
 impl<T> PrettyWith<CompilationUnit<T>> for Invoke {
     fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
-        let kind = match self.kind {
-            InvokeKind::Virtual => "invoke virtual",
-            InvokeKind::Special => "invoke special",
-            InvokeKind::Static => "invoke static",
-        };
+        let kind = format!("invoke {}", self.kind);
+        if let InvokeKind::Dynamic = self.kind {
+            let dynamic_ref = &unit.metadata.dynamic_refs[&self.method_index];
+            let bootstrap = format!(" (bootstrap method #{})", dynamic_ref.bootstrap_method);
+            return doc(kind) + ' ' + &dynamic_ref.name + ": " + &dynamic_ref.typ + bootstrap;
+        }
         let method_ref = &unit.metadata.method_refs[&self.method_index];
         let class = &unit.metadata.class_refs[&method_ref.class_ref].0;
         doc(kind) + ' ' + class + '.' + &method_ref.name + ": " + &method_ref.signature
@@ -337,6 +529,7 @@ impl Display for JumpCondition {
             CmpZero(ord) => write!(f, "stack[-1] {} 0", ord),
             Cmp(ord) => write!(f, "stack[-2] {} stack[-1]", ord),
             CmpRef(eq) => write!(f, "stack[-2] {} stack[-1]", eq),
+            CmpNull(eq) => write!(f, "stack[-1] {} null", eq),
         }
     }
 }
@@ -369,12 +562,23 @@ impl Display for Ordering {
 impl Display for Arithm {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match *self {
-            Arithm::UnaryOp(unary_op) => write!(f, "{}", unary_op),
-            Arithm::BinaryOp(binary_op) => write!(f, "{}", binary_op),
+            Arithm::UnaryOp(kind, unary_op) => write!(f, "{} {}", unary_op, kind),
+            Arithm::BinaryOp(kind, binary_op) => write!(f, "{} {}", binary_op, kind),
             Arithm::IncreaseLocal {
                 local_index,
                 increase,
             } => write!(f, "increase local_{} by {}", local_index, increase),
+            Arithm::Compare { kind, nan_greater } => {
+                write!(f, "compare {}", kind)?;
+                if kind != Kind::L {
+                    write!(
+                        f,
+                        " (NaN is {})",
+                        if nan_greater { "greater" } else { "less" }
+                    )?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -409,3 +613,85 @@ impl Display for BinaryOp {
         )
     }
 }
+
+impl Display for ObjManip {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            ObjManip::New { class_ref } => write!(f, "new #{}", class_ref),
+            ObjManip::NewArray(ref typ) => write!(f, "new array of {}", typ),
+            ObjManip::ANewArray { class_ref } => write!(f, "new array of #{}", class_ref),
+            ObjManip::MultiANewArray {
+                class_ref,
+                dimensions,
+            } => write!(f, "new array #{} of {} dimensions", class_ref, dimensions),
+            ObjManip::ArrayLength => write!(f, "array length"),
+            ObjManip::CheckCast { class_ref } => write!(f, "check cast to #{}", class_ref),
+            ObjManip::InstanceOf { class_ref } => write!(f, "instance of #{}", class_ref),
+        }
+    }
+}
+
+impl<T> PrettyWith<CompilationUnit<T>> for ObjManip {
+    fn pretty_with(&self, unit: &CompilationUnit<T>) -> Doc {
+        let class = |class_ref| &unit.metadata.class_refs[&class_ref].0;
+        match *self {
+            ObjManip::New { class_ref } => format!("new {}", class(class_ref)),
+            ObjManip::ANewArray { class_ref } => format!("new array of {}", class(class_ref)),
+            ObjManip::MultiANewArray {
+                class_ref,
+                dimensions,
+            } => format!(
+                "new array {} of {} dimensions",
+                class(class_ref),
+                dimensions
+            ),
+            ObjManip::CheckCast { class_ref } => format!("check cast to {}", class(class_ref)),
+            ObjManip::InstanceOf { class_ref } => format!("instance of {}", class(class_ref)),
+            ObjManip::NewArray(_) | ObjManip::ArrayLength => format!("{}", self),
+        }
+        .into()
+    }
+}
+
+impl Display for StackManage {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        use self::StackManage::*;
+        write!(
+            f,
+            "{}",
+            match *self {
+                Pop => "pop",
+                Pop2 => "pop2",
+                Dup => "dup",
+                DupX1 => "dup_x1",
+                DupX2 => "dup_x2",
+                Dup2 => "dup2",
+                Dup2X1 => "dup2_x1",
+                Dup2X2 => "dup2_x2",
+                Swap => "swap",
+            }
+        )
+    }
+}
+
+impl Display for Switch {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "switch stack[-1]:")?;
+        // The gaps of a tableswitch jump to the default address:
+        for &(value, address) in self.cases.iter().filter(|case| case.1 != self.default) {
+            write!(f, " {} => {:#X},", value, address)?;
+        }
+        write!(f, " default => {:#X}", self.default)
+    }
+}
+
+impl Display for Subroutine {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            Subroutine::Jsr { address } => write!(f, "jump to subroutine {:#X}", address),
+            Subroutine::Ret { local_index } => {
+                write!(f, "return from subroutine to local_{}", local_index)
+            }
+        }
+    }
+}
@@ -2,7 +2,13 @@ pub use classfile::parser::*;
 pub use disassembler::disassemble::*;
 pub use disassembler::types::*;
 
-pub fn transform(class_file: &ClassFile) -> CompilationUnit<Code> {
+use classfile::{Error, ErrorKind, Result, Structure};
+use disassembler::error;
+
+/// Turns a parsed class file into a compilation unit.
+/// With `lenient`, instructions that can't be decoded don't fail the whole class, see
+/// `disassemble`.
+pub fn transform(class_file: &ClassFile, lenient: bool) -> error::Result<CompilationUnit<Code>> {
     let mut unit = CompilationUnit {
         typ: if class_file.access_flags.contains(AccessFlags::INTERFACE) {
             UnitType::Interface
@@ -13,16 +19,96 @@ pub fn transform(class_file: &ClassFile) -> CompilationUnit<Code> {
         },
         modifiers: vec![],
         name: String::new(),
+        extends: None,
+        implements: vec![],
+        imports: vec![],
         declarations: vec![],
         metadata: Metadata::new(),
+        outer_class: None,
+        nested: vec![],
+        synthetic: class_file.access_flags.contains(AccessFlags::SYNTHETIC),
     };
     unit.modifiers = class_flags_to_modifiers(&class_file.access_flags);
-    process_constant_pool(&mut unit, &class_file.constant_pool);
-    unit.name = unit.metadata.class_refs[&class_file.this_class]
-        .0
-        .to_owned();
-    process_methods(&mut unit, &class_file.methods);
-    unit
+    let constant_pool = &class_file.constant_pool;
+    process_constant_pool(&mut unit, constant_pool)?;
+    for attribute in &class_file.attributes {
+        if let Attribute::BootstrapMethods(ref methods) = *attribute {
+            for method in methods {
+                unit.metadata.bootstrap_methods.push(BootstrapRef {
+                    method_ref: lookup_method_handle(constant_pool, method.bootstrap_method_ref)?,
+                    arguments: method.bootstrap_arguments.clone(),
+                });
+            }
+        }
+    }
+    unit.name = lookup_class_name(constant_pool, class_file.this_class)?;
+    for attribute in &class_file.attributes {
+        if let Attribute::InnerClasses(ref inner_classes) = *attribute {
+            // A member class is declared with the modifiers of its entry, e.g. `private static`,
+            // which can't all be flags of a class file:
+            for inner_class in inner_classes {
+                if inner_class.inner_class_info_index == class_file.this_class
+                    && inner_class.outer_class_info_index != 0
+                {
+                    let outer_class =
+                        lookup_class_name(constant_pool, inner_class.outer_class_info_index)?;
+                    unit.outer_class = Some(outer_class);
+                    unit.modifiers =
+                        class_flags_to_modifiers(&inner_class.inner_class_access_flags);
+                }
+            }
+        }
+    }
+    if unit.typ == UnitType::Enum {
+        // An enum is implicitly final, or abstract if its constants have bodies, and can't be
+        // declared like that:
+        unit.modifiers
+            .retain(|&modifier| modifier != Modifier::Final && modifier != Modifier::Abstract);
+    }
+    if class_file.super_class != 0 && unit.typ == UnitType::Class {
+        let name = lookup_class_name(constant_pool, class_file.super_class)?;
+        if name != "java.lang.Object" {
+            unit.extends = Some(ClassRef(name));
+        }
+    }
+    for &interface in &class_file.interfaces {
+        let name = lookup_class_name(constant_pool, interface)?;
+        unit.implements.push(ClassRef(name));
+    }
+    process_fields(&mut unit, constant_pool, &class_file.fields)?;
+    process_methods(&mut unit, constant_pool, &class_file.methods, lenient)?;
+    Ok(unit)
+}
+
+pub fn lookup_class_name(constant_pool: &ConstantPool, index: u16) -> Result<String> {
+    match *constant_pool.lookup(index)? {
+        ConstantInfo::Class { name_index } => {
+            Ok(constant_pool.lookup_string(name_index)?.replace('/', "."))
+        }
+        _ => Err(Error::new(
+            Structure::Constant(index),
+            ErrorKind::UnexpectedConstant {
+                index,
+                expected: "a class",
+            },
+        )),
+    }
+}
+
+/// Looks up the method that a method handle refers to, returning the index of its `MethodRef`.
+fn lookup_method_handle(constant_pool: &ConstantPool, index: u16) -> Result<u16> {
+    match *constant_pool.lookup(index)? {
+        ConstantInfo::MethodHandle {
+            reference_index, ..
+        } => Ok(reference_index),
+        _ => Err(Error::new(
+            Structure::Constant(index),
+            ErrorKind::UnexpectedConstant {
+                index,
+                expected: "a method handle",
+            },
+        )),
+    }
 }
 
 fn class_flags_to_modifiers(flags: &AccessFlags) -> Vec<Modifier> {
@@ -48,7 +134,10 @@ fn class_flags_to_modifiers(flags: &AccessFlags) -> Vec<Modifier> {
     modifiers
 }
 
-fn process_constant_pool<C>(unit: &mut CompilationUnit<C>, constant_pool: &ConstantPool) {
+fn process_constant_pool<C>(
+    unit: &mut CompilationUnit<C>,
+    constant_pool: &ConstantPool,
+) -> Result<()> {
     for (index, constant) in constant_pool.constants.iter().enumerate() {
         let index = index as u16 + 1; // plus one because of weird indexing in the JVM spec
         match *constant {
@@ -58,14 +147,28 @@ fn process_constant_pool<C>(unit: &mut CompilationUnit<C>, constant_pool: &Const
             ConstantInfo::Integer(int) => {
                 unit.metadata.literals.insert(index, Literal::Integer(int));
             }
+            ConstantInfo::Float(float) => {
+                unit.metadata.literals.insert(index, Literal::Float(float));
+            }
+            ConstantInfo::Long(long) => {
+                unit.metadata.literals.insert(index, Literal::Long(long));
+            }
+            ConstantInfo::Double(double) => {
+                unit.metadata
+                    .literals
+                    .insert(index, Literal::Double(double));
+            }
             ConstantInfo::Class { name_index } => {
-                let name = constant_pool.lookup_string(name_index);
+                let name = constant_pool.lookup_string(name_index)?;
+                // Class constants loaded with `ldc` are class literals:
+                let typ = class_name_to_type(name)?;
+                unit.metadata.literals.insert(index, Literal::Class(typ));
                 unit.metadata
                     .class_refs
                     .insert(index, ClassRef(name.replace('/', ".")));
             }
             ConstantInfo::String { string_index } => {
-                let string = constant_pool.lookup_string(string_index);
+                let string = constant_pool.lookup_string(string_index)?;
                 unit.metadata
                     .literals
                     .insert(index, Literal::String(string.to_owned()));
@@ -74,45 +177,42 @@ fn process_constant_pool<C>(unit: &mut CompilationUnit<C>, constant_pool: &Const
                 class_index,
                 name_index,
             } => {
-                let (name_index, descriptor_index) = match *constant_pool.lookup(name_index) {
-                    ConstantInfo::NameAndType {
-                        name_index,
-                        descriptor_index,
-                    } => (name_index, descriptor_index),
-                    ref c => panic!("Index doesn't point to a NameAndType but to: {:#?}", c),
-                };
-                let name = constant_pool.lookup_string(name_index).to_owned();
-                let descriptor = constant_pool.lookup_string(descriptor_index);
-                let typ = descriptor_to_type(&mut descriptor.chars());
+                // The class is only looked up when the field is used, so check it now:
+                lookup_class_name(constant_pool, class_index)?;
+                let (name_index, descriptor_index) =
+                    constant_pool.lookup_name_and_type(name_index)?;
+                let name = constant_pool.lookup_string(name_index)?.to_owned();
+                let descriptor = constant_pool.lookup_string(descriptor_index)?;
+                let typ = descriptor_to_type(descriptor)?;
                 unit.metadata.field_refs.insert(
                     index,
                     FieldRef {
                         class_ref: class_index,
-                        name: name,
-                        typ: typ,
+                        name,
+                        typ,
                     },
                 );
             }
             ConstantInfo::MethodRef {
                 class_index,
                 name_index,
+            }
+            | ConstantInfo::InterfaceMethodRef {
+                class_index,
+                name_index,
             } => {
-                let (name_index, descriptor_index) = match *constant_pool.lookup(name_index) {
-                    ConstantInfo::NameAndType {
-                        name_index,
-                        descriptor_index,
-                    } => (name_index, descriptor_index),
-                    ref c => panic!("Index doesn't point to a NameAndType but to: {:#?}", c),
-                };
-                let name = constant_pool.lookup_string(name_index).to_owned();
-                let descriptor = constant_pool.lookup_string(descriptor_index);
-                let signature = descriptor_to_signature(descriptor);
+                lookup_class_name(constant_pool, class_index)?;
+                let (name_index, descriptor_index) =
+                    constant_pool.lookup_name_and_type(name_index)?;
+                let name = constant_pool.lookup_string(name_index)?.to_owned();
+                let descriptor = constant_pool.lookup_string(descriptor_index)?;
+                let signature = descriptor_to_signature(descriptor)?;
                 unit.metadata.method_refs.insert(
                     index,
                     MethodRef {
                         class_ref: class_index,
-                        name: name,
-                        signature: signature,
+                        name,
+                        signature,
                     },
                 );
             }
@@ -120,49 +220,237 @@ fn process_constant_pool<C>(unit: &mut CompilationUnit<C>, constant_pool: &Const
                 name_index,
                 descriptor_index,
             } => {
-                let name = constant_pool.lookup_string(name_index).to_owned();
-                let descriptor_string = constant_pool.lookup_string(descriptor_index);
-                let descriptor = if descriptor_string.starts_with('(') {
-                    Descriptor::Signature(descriptor_to_signature(descriptor_string))
-                } else {
-                    Descriptor::Type(descriptor_to_type(&mut descriptor_string.chars()))
-                };
+                let name = constant_pool.lookup_string(name_index)?.to_owned();
+                let descriptor = lookup_descriptor(constant_pool, descriptor_index)?;
                 unit.metadata.name_refs.insert(
                     index,
                     NameRef {
-                        name: name,
+                        name,
                         typ: descriptor,
                     },
                 );
             }
+            ConstantInfo::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            }
+            | ConstantInfo::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => {
+                let (name_index, descriptor_index) =
+                    constant_pool.lookup_name_and_type(name_and_type_index)?;
+                let name = constant_pool.lookup_string(name_index)?.to_owned();
+                let typ = lookup_descriptor(constant_pool, descriptor_index)?;
+                unit.metadata.dynamic_refs.insert(
+                    index,
+                    DynamicRef {
+                        bootstrap_method: bootstrap_method_attr_index,
+                        name,
+                        typ,
+                    },
+                );
+            }
+            // Not needed by the disassembler (yet):
+            ConstantInfo::MethodHandle { .. }
+            | ConstantInfo::MethodType { .. }
+            | ConstantInfo::Module { .. }
+            | ConstantInfo::Package { .. }
+            | ConstantInfo::Unusable => {}
         }
     }
+    Ok(())
+}
+
+fn process_fields<C>(
+    unit: &mut CompilationUnit<C>,
+    constant_pool: &ConstantPool,
+    fields: &[FieldInfo],
+) -> Result<()> {
+    for field in fields {
+        let descriptor = constant_pool.lookup_string(field.descriptor_index)?;
+        let typ = descriptor_to_type(descriptor)?;
+        let mut value = None;
+        // The attribute is ignored for instance fields, which are assigned by the constructors:
+        if field.access_flags.contains(AccessFlags::STATIC) {
+            for attribute in &field.attributes {
+                if let Attribute::ConstantValue(index) = *attribute {
+                    value = Some(lookup_constant_value(unit, index, &typ)?);
+                }
+            }
+        }
+        unit.declarations.push(Declaration::Field(Field {
+            modifiers: field_flags_to_modifiers(&field.access_flags),
+            name: constant_pool.lookup_string(field.name_index)?.to_owned(),
+            typ,
+            value,
+            initializer: None,
+            synthetic: field.access_flags.contains(AccessFlags::SYNTHETIC),
+        }));
+    }
+    Ok(())
+}
+
+/// The constant value of a field of type `typ`. Booleans and chars are stored as ints.
+fn lookup_constant_value<C>(unit: &CompilationUnit<C>, index: u16, typ: &Type) -> Result<Literal> {
+    let value = match unit.metadata.literals.get(&index) {
+        Some(value) => value.clone(),
+        None => {
+            return Err(Error::new(
+                Structure::Constant(index),
+                ErrorKind::UnexpectedConstant {
+                    index,
+                    expected: "a constant value",
+                },
+            ))
+        }
+    };
+    Ok(match (value, typ) {
+        (Literal::Integer(value), &Type::Boolean) => Literal::Boolean(value != 0),
+        (Literal::Integer(value), &Type::Char) => Literal::Char(value as u16),
+        (value, _) => value,
+    })
+}
+
+fn field_flags_to_modifiers(flags: &AccessFlags) -> Vec<Modifier> {
+    let mut modifiers = vec![];
+    if flags.contains(AccessFlags::PUBLIC) {
+        modifiers.push(Modifier::Public);
+    }
+    if flags.contains(AccessFlags::PROTECTED) {
+        modifiers.push(Modifier::Protected);
+    }
+    if flags.contains(AccessFlags::PRIVATE) {
+        modifiers.push(Modifier::Private);
+    }
+    if flags.contains(AccessFlags::STATIC) {
+        modifiers.push(Modifier::Static);
+    }
+    if flags.contains(AccessFlags::FINAL) {
+        modifiers.push(Modifier::Final);
+    }
+    // Field specific flags:
+    if flags.contains(AccessFlags::VOLATILE) {
+        modifiers.push(Modifier::Volatile);
+    }
+    if flags.contains(AccessFlags::TRANSIENT) {
+        modifiers.push(Modifier::Transient);
+    }
+    modifiers
 }
 
-fn process_methods(unit: &mut CompilationUnit<Code>, methods: &[MethodInfo]) {
+fn process_methods(
+    unit: &mut CompilationUnit<Code>,
+    constant_pool: &ConstantPool,
+    methods: &[MethodInfo],
+    lenient: bool,
+) -> error::Result<()> {
     for method in methods {
-        let transformed = transform_method(unit, method);
+        let transformed = transform_method(constant_pool, method, lenient)?;
         unit.declarations.push(transformed);
     }
+    Ok(())
 }
 
-fn transform_method<C>(unit: &CompilationUnit<C>, method: &MethodInfo) -> Declaration<Code> {
+fn transform_method(
+    constant_pool: &ConstantPool,
+    method: &MethodInfo,
+    lenient: bool,
+) -> error::Result<Declaration<Code>> {
+    let name = constant_pool.lookup_string(method.name_index)?.to_owned();
     let mut code = None;
+    let mut throws = vec![];
+    let mut variables = vec![];
+    let mut parameter_names = vec![];
     for attribute in &method.attributes {
-        let name = unit.lookup_string(attribute.name_index);
-        if name == "Code" {
-            let code_attribute = parse_code_attribute(&attribute.info).unwrap();
-            let disassembly = disassemble(&code_attribute);
-            code = Some(disassembly);
-            break;
+        match *attribute {
+            Attribute::Code(ref code_attribute) if code.is_none() => {
+                let disassembled =
+                    disassemble(code_attribute, constant_pool, lenient).map_err(|err| {
+                        error::Error::Decode {
+                            method: name.clone(),
+                            error: err,
+                        }
+                    })?;
+                for handler in &disassembled.exception_handlers {
+                    if let Some(catch_type) = handler.catch_type {
+                        lookup_class_name(constant_pool, catch_type)?;
+                    }
+                }
+                code = Some(disassembled);
+                for attribute in &code_attribute.attributes {
+                    if let Attribute::LocalVariableTable(ref table) = *attribute {
+                        for variable in table {
+                            variables.push(transform_local_variable(constant_pool, variable)?);
+                        }
+                    }
+                }
+            }
+            Attribute::Exceptions(ref exceptions) => {
+                for &exception in exceptions {
+                    throws.push(ClassRef(lookup_class_name(constant_pool, exception)?));
+                }
+            }
+            Attribute::MethodParameters(ref parameters) => {
+                for parameter in parameters {
+                    parameter_names.push(if parameter.name_index == 0 {
+                        String::new()
+                    } else {
+                        constant_pool
+                            .lookup_string(parameter.name_index)?
+                            .to_owned()
+                    });
+                }
+            }
+            _ => {}
         }
     }
-    let signature = descriptor_to_signature(unit.lookup_string(method.descriptor_index));
-    Declaration::Method(Method {
-        modifiers: method_flags_to_modifiers(&method.access_flags),
-        name: unit.lookup_string(method.name_index).to_owned(),
-        signature: signature,
-        code: code,
+    let descriptor = constant_pool.lookup_string(method.descriptor_index)?;
+    let mut signature = descriptor_to_signature(descriptor)?;
+    let modifiers = method_flags_to_modifiers(&method.access_flags);
+    // The parameters are local variables whose scope is the whole code, so the local variable
+    // table names them even without the `MethodParameters` attribute (but not without code):
+    if parameter_names.len() != signature.parameters.len() {
+        parameter_names = vec![String::new(); signature.parameters.len()];
+    }
+    let slots = signature.parameter_slots(modifiers.contains(&Modifier::Static));
+    for ((parameter, slot), name) in signature
+        .parameters
+        .iter_mut()
+        .zip(slots)
+        .zip(parameter_names)
+    {
+        parameter.0 = variables
+            .iter()
+            .find(|variable: &&LocalVariableScope| variable.index == slot && variable.start == 0)
+            .map_or(name, |variable| variable.name.clone());
+    }
+    Ok(Declaration::Method(Method {
+        modifiers,
+        name,
+        signature,
+        throws,
+        variables,
+        locals: vec![],
+        code,
+        synthetic: method
+            .access_flags
+            .intersects(AccessFlags::SYNTHETIC | AccessFlags::BRIDGE),
+        bridge: method.access_flags.contains(AccessFlags::BRIDGE),
+    }))
+}
+
+fn transform_local_variable(
+    constant_pool: &ConstantPool,
+    variable: &LocalVariable,
+) -> Result<LocalVariableScope> {
+    let descriptor = constant_pool.lookup_string(variable.descriptor_index)?;
+    Ok(LocalVariableScope {
+        index: variable.index,
+        start: variable.start_pc,
+        end: variable.start_pc + variable.length,
+        name: constant_pool.lookup_string(variable.name_index)?.to_owned(),
+        typ: descriptor_to_type(descriptor)?,
     })
 }
 
@@ -199,30 +487,67 @@ fn method_flags_to_modifiers(flags: &AccessFlags) -> Vec<Modifier> {
     modifiers
 }
 
-fn descriptor_to_signature(descriptor: &str) -> Signature {
+fn invalid_descriptor(descriptor: &str) -> Error {
+    Error::new(
+        Structure::Descriptor,
+        ErrorKind::InvalidDescriptor(descriptor.to_owned()),
+    )
+}
+
+pub fn descriptor_to_signature(descriptor: &str) -> Result<Signature> {
     let mut chars = descriptor.chars().peekable();
     let mut params = vec![];
-    let next = chars.next().unwrap();
-    if next != '(' {
-        panic!(
-            "Expected open paren at beginning of method descriptor: {:?}",
-            descriptor
-        );
-    }
-    while *chars.peek().unwrap() != ')' {
-        params.push(("".to_owned(), descriptor_to_type(&mut chars)));
-    }
-    chars.next().unwrap();
-    let return_type = descriptor_to_type(&mut chars);
-    Signature {
+    if chars.next() != Some('(') {
+        return Err(invalid_descriptor(descriptor));
+    }
+    loop {
+        match chars.peek() {
+            Some(&')') => break,
+            Some(_) => {}
+            None => return Err(invalid_descriptor(descriptor)),
+        }
+        let typ = parse_type(&mut chars).ok_or_else(|| invalid_descriptor(descriptor))?;
+        params.push(("".to_owned(), typ));
+    }
+    chars.next();
+    let return_type = parse_type(&mut chars).ok_or_else(|| invalid_descriptor(descriptor))?;
+    if chars.next().is_some() {
+        return Err(invalid_descriptor(descriptor));
+    }
+    Ok(Signature {
         parameters: params,
-        return_type: return_type,
+        return_type,
+    })
+}
+
+fn lookup_descriptor(constant_pool: &ConstantPool, index: u16) -> Result<Descriptor> {
+    let descriptor = constant_pool.lookup_string(index)?;
+    if descriptor.starts_with('(') {
+        Ok(Descriptor::Signature(descriptor_to_signature(descriptor)?))
+    } else {
+        Ok(Descriptor::Type(descriptor_to_type(descriptor)?))
+    }
+}
+
+/// Class constants contain the binary name of a class, or the descriptor of an array type.
+fn class_name_to_type(name: &str) -> Result<Type> {
+    if name.starts_with('[') {
+        descriptor_to_type(name)
+    } else {
+        Ok(Type::Reference(name.replace('/', ".")))
     }
 }
 
-fn descriptor_to_type<I: Iterator<Item = char>>(chars: &mut I) -> Type {
-    let next = chars.next().unwrap();
-    match next {
+pub fn descriptor_to_type(descriptor: &str) -> Result<Type> {
+    let mut chars = descriptor.chars();
+    match parse_type(&mut chars) {
+        Some(typ) if chars.next().is_none() => Ok(typ),
+        _ => Err(invalid_descriptor(descriptor)),
+    }
+}
+
+fn parse_type<I: Iterator<Item = char>>(chars: &mut I) -> Option<Type> {
+    let typ = match chars.next()? {
         'B' => Type::Byte,
         'C' => Type::Char,
         'D' => Type::Double,
@@ -231,18 +556,19 @@ fn descriptor_to_type<I: Iterator<Item = char>>(chars: &mut I) -> Type {
         'J' => Type::Long,
         'L' => {
             let mut class_name = String::new();
-            for ch in chars {
-                if ch == ';' {
-                    break;
+            loop {
+                match chars.next()? {
+                    ';' => break,
+                    ch => class_name.push(ch),
                 }
-                class_name.push(ch);
             }
             Type::Reference(class_name.replace('/', "."))
         }
         'S' => Type::Short,
         'V' => Type::Void,
         'Z' => Type::Boolean,
-        '[' => Type::Array(Box::new(descriptor_to_type(chars))),
-        _ => panic!("Invalid start of type descriptor: {:?}", next),
-    }
+        '[' => Type::Array(Box::new(parse_type(chars)?)),
+        _ => return None,
+    };
+    Some(typ)
 }
@@ -1,14 +1,31 @@
 pub use classfile::parser::*;
+use decompiler::types::Expr;
 pub use disassembler::instructions::*;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
 
 #[derive(Debug)]
 pub struct CompilationUnit<C> {
     pub typ: UnitType,
     pub modifiers: Vec<Modifier>,
+    /// The fully qualified name of the class.
     pub name: String,
+    /// The super class, unless it is `java.lang.Object` or the unit is an interface or an enum.
+    pub extends: Option<ClassRef>,
+    pub implements: Vec<ClassRef>,
+    /// Fully qualified names of the imported classes.
+    pub imports: Vec<String>,
     pub metadata: Metadata,
     pub declarations: Vec<Declaration<C>>,
+    /// The class that a member class is declared in, `None` for top-level, local and anonymous
+    /// classes.
+    pub outer_class: Option<String>,
+    /// The member classes, which are printed in the body of the class.
+    pub nested: Vec<CompilationUnit<C>>,
+    /// Whether the class was generated by the compiler, e.g. to hold the switch maps of switches
+    /// on enums (see `SwitchMaps`).
+    pub synthetic: bool,
 }
 
 impl<C> CompilationUnit<C> {
@@ -16,7 +33,34 @@ impl<C> CompilationUnit<C> {
         &self.metadata.string_constants[&index]
     }
 
-    pub fn map<F, D>(mut self, mut f: F) -> CompilationUnit<D>
+    /// The package of the class, empty for the default package.
+    pub fn package(&self) -> &str {
+        self.name.rfind('.').map_or("", |i| &self.name[..i])
+    }
+
+    /// The name of the class without its package, or without its outer class for a member
+    /// class.
+    pub fn simple_name(&self) -> &str {
+        match self.outer_class {
+            Some(ref outer_class) if self.name.starts_with(&format!("{}$", outer_class)) => {
+                &self.name[outer_class.len() + 1..]
+            }
+            _ => self
+                .name
+                .rfind('.')
+                .map_or(&self.name, |i| &self.name[i + 1..]),
+        }
+    }
+
+    /// Maps the code of the methods of the class and of its member classes.
+    pub fn map<F, D>(self, mut f: F) -> CompilationUnit<D>
+    where
+        F: FnMut(C, &Metadata) -> D,
+    {
+        self.map_with(&mut f)
+    }
+
+    fn map_with<F, D>(mut self, f: &mut F) -> CompilationUnit<D>
     where
         F: FnMut(C, &Metadata) -> D,
     {
@@ -28,12 +72,23 @@ impl<C> CompilationUnit<C> {
                 .map(|d| d.map(|c| f(c, metadata)))
                 .collect::<Vec<_>>()
         };
+        let nested = self
+            .nested
+            .into_iter()
+            .map(|unit| unit.map_with(f))
+            .collect();
         CompilationUnit {
             typ: self.typ,
             modifiers: self.modifiers,
             name: self.name,
+            extends: self.extends,
+            implements: self.implements,
+            imports: self.imports,
             declarations: declarations,
             metadata: self.metadata,
+            outer_class: self.outer_class,
+            nested,
+            synthetic: self.synthetic,
         }
     }
 }
@@ -46,6 +101,8 @@ pub struct Metadata {
     pub field_refs: HashMap<u16, FieldRef>,
     pub method_refs: HashMap<u16, MethodRef>,
     pub name_refs: HashMap<u16, NameRef>,
+    pub dynamic_refs: HashMap<u16, DynamicRef>,
+    pub bootstrap_methods: Vec<BootstrapRef>,
 }
 
 impl Metadata {
@@ -54,7 +111,7 @@ impl Metadata {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum UnitType {
     Class,
     Interface,
@@ -81,6 +138,8 @@ pub enum Declaration<C> {
     Field(Field),
     Method(Method<C>),
     Constructor(Constructor<C>),
+    /// Only exists once the decompiler has recovered the constants of an enum.
+    EnumConstant(EnumConstant),
 }
 
 impl<C> Declaration<C> {
@@ -90,24 +149,41 @@ impl<C> Declaration<C> {
     {
         match self {
             Declaration::Field(f) => Declaration::Field(f),
+            Declaration::EnumConstant(c) => Declaration::EnumConstant(c),
             Declaration::Method(Method {
                 modifiers,
                 name,
                 signature,
+                throws,
+                variables,
+                locals,
                 code,
+                synthetic,
+                bridge,
             }) => Declaration::Method(Method {
                 modifiers,
                 name,
                 signature,
+                throws,
+                variables,
+                locals,
                 code: code.map(f),
+                synthetic,
+                bridge,
             }),
             Declaration::Constructor(Constructor {
                 modifiers,
                 parameters,
+                throws,
+                variables,
+                locals,
                 code,
             }) => Declaration::Constructor(Constructor {
                 modifiers,
                 parameters,
+                throws,
+                variables,
+                locals,
                 code: f(code),
             }),
         }
@@ -119,6 +195,21 @@ pub struct Field {
     pub modifiers: Vec<Modifier>,
     pub name: String,
     pub typ: Type,
+    /// The constant value of a static field, which is its initializer in the source code.
+    pub value: Option<Literal>,
+    /// The initializer of a field of an interface, which the decompiler recovers from the static
+    /// initializer, since interfaces can't have one in the source code.
+    pub initializer: Option<Expr>,
+    /// Whether the field was generated by the compiler, e.g. the one holding the outer instance
+    /// of a member class.
+    pub synthetic: bool,
+}
+
+/// A constant of an enum, created by passing the arguments to the constructor.
+#[derive(Debug)]
+pub struct EnumConstant {
+    pub name: String,
+    pub arguments: Vec<Literal>,
 }
 
 #[derive(Debug)]
@@ -126,16 +217,43 @@ pub struct Method<C> {
     pub modifiers: Vec<Modifier>,
     pub name: String,
     pub signature: Signature,
+    /// The checked exceptions declared in the `throws` clause.
+    pub throws: Vec<ClassRef>,
+    /// The local variables of the source code, if the class was compiled with debug information.
+    pub variables: Vec<LocalVariableScope>,
+    /// The local variables of the decompiled code with their types, once they are inferred.
+    pub locals: Vec<(String, Type)>,
     pub code: Option<C>,
+    /// Whether the method was generated by the compiler and isn't in the source code, e.g. a
+    /// bridge method or the body of a lambda.
+    pub synthetic: bool,
+    /// Whether the method is a bridge method, which overrides a method of a generic super type
+    /// by calling the method with the more specific types.
+    pub bridge: bool,
 }
 
 #[derive(Debug)]
 pub struct Constructor<C> {
     pub modifiers: Vec<Modifier>,
     pub parameters: Vec<(String, Type)>,
+    pub throws: Vec<ClassRef>,
+    pub variables: Vec<LocalVariableScope>,
+    pub locals: Vec<(String, Type)>,
     pub code: C,
 }
 
+/// The local variable `name` of the source code, which is held in slot `index` by the
+/// instructions from `start` up to (excluding) `end`.
+/// A slot may hold different variables in different ranges.
+#[derive(Clone, Debug)]
+pub struct LocalVariableScope {
+    pub index: u16,
+    pub start: u16,
+    pub end: u16,
+    pub name: String,
+    pub typ: Type,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq)]
 pub enum Type {
     Void,
@@ -151,43 +269,87 @@ pub enum Type {
     Reference(String),
 }
 
-#[derive(Clone, Debug, Hash)]
+impl Type {
+    /// Whether values of this type take two slots, on the stack and among the local variables.
+    pub fn is_wide(&self) -> bool {
+        *self == Type::Long || *self == Type::Double
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct Signature {
     pub parameters: Vec<(String, Type)>,
     pub return_type: Type,
 }
 
+impl Signature {
+    /// The local variable slots holding the parameters when the method is entered.
+    /// `this` is in slot 0 unless the method is static, and `long` and `double` take two slots.
+    pub fn parameter_slots(&self, is_static: bool) -> Vec<u16> {
+        let mut index = if is_static { 0 } else { 1 };
+        let mut slots = vec![];
+        for parameter in &self.parameters {
+            slots.push(index);
+            index += if parameter.1.is_wide() { 2 } else { 1 };
+        }
+        slots
+    }
+}
+
 #[derive(Clone, Debug, Hash)]
 pub enum Descriptor {
     Signature(Signature),
     Type(Type),
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Literal {
     NullReference,
     Boolean(bool),
     Byte(i8),
     Short(i16),
+    /// A UTF-16 code unit, which the class file stores as an int.
+    Char(u16),
     Integer(i32),
     Long(i64),
-    // TODO: Add these back (requires custom Hash impl):
-    // Float(f32),
-    // Double(f64),
+    Float(f32),
+    Double(f64),
     String(String),
+    /// A class literal like `String.class`.
+    Class(Type),
 }
 
-#[derive(Clone, Debug, Hash)]
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match *self {
+            Literal::NullReference => (),
+            Literal::Boolean(b) => b.hash(state),
+            Literal::Byte(i) => i.hash(state),
+            Literal::Short(i) => i.hash(state),
+            Literal::Char(c) => c.hash(state),
+            Literal::Integer(i) => i.hash(state),
+            Literal::Long(i) => i.hash(state),
+            // floats aren't `Hash`, so hash their bit patterns:
+            Literal::Float(f) => f.to_bits().hash(state),
+            Literal::Double(d) => d.to_bits().hash(state),
+            Literal::String(ref s) => s.hash(state),
+            Literal::Class(ref typ) => typ.hash(state),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct ClassRef(pub String);
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct FieldRef {
     pub class_ref: u16,
     pub name: String,
     pub typ: Type,
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct MethodRef {
     pub class_ref: u16,
     pub name: String,
@@ -200,8 +362,36 @@ pub struct NameRef {
     pub typ: Descriptor,
 }
 
+/// A call site (for `invokedynamic`) or constant whose value is computed by a bootstrap method.
+#[derive(Clone, Debug, Hash)]
+pub struct DynamicRef {
+    pub bootstrap_method: u16,
+    pub name: String,
+    pub typ: Descriptor,
+}
+
+/// A bootstrap method of the class, which links the call sites of `invokedynamic`.
+#[derive(Clone, Debug, Hash)]
+pub struct BootstrapRef {
+    /// The index of the method (a `MethodRef`) that the method handle refers to.
+    pub method_ref: u16,
+    /// The indices of the constant arguments, e.g. the recipe of a string concatenation.
+    pub arguments: Vec<u16>,
+}
+
 #[derive(Debug)]
 pub struct Code {
-    // TODO: Exception handlers
     pub instructions: Vec<(u16, Instruction)>,
+    pub exception_handlers: Vec<ExceptionHandler>,
+}
+
+/// Exceptions thrown by the instructions from `start` up to (excluding) `end` are caught by the
+/// instructions at `handler` if they are instances of `catch_type`, or regardless of their type
+/// if it is `None`.
+#[derive(Copy, Clone, Debug)]
+pub struct ExceptionHandler {
+    pub start: u16,
+    pub end: u16,
+    pub handler: u16,
+    pub catch_type: Option<u16>,
 }
@@ -0,0 +1,57 @@
+// The class file is compiled with `javac -g:none --release 8 Evaluation.java`. Running it prints
+// the results of the methods, which the decompiled code must reproduce.
+
+public class Evaluation {
+    int f;
+    static int counter;
+
+    static int next() {
+        return ++counter;
+    }
+
+    static int fieldStore(Evaluation o) {
+        int a = o.f;
+        o.f = 5;
+        return a * 10 + o.f;
+    }
+
+    static int arrayStore(int[] xs) {
+        int a = xs[0];
+        xs[0] = 7;
+        return a * 10 + xs[0];
+    }
+
+    static int calls() {
+        int a = next();
+        int b = next();
+        return b * 10 - a;
+    }
+
+    static int assignmentInOperand(Evaluation o) {
+        return o.f * 10 + (o.f = 3);
+    }
+
+    static int increments() {
+        int old = counter++;
+        counter += old;
+        return counter * 100 + old + ++counter;
+    }
+
+    static void unusedVariable() {
+        int unused = next();
+    }
+
+    public static void main(String[] args) {
+        Evaluation o = new Evaluation();
+        o.f = 1;
+        System.out.println(fieldStore(o));
+        System.out.println(arrayStore(new int[] {2}));
+        System.out.println(calls());
+        o.f = 5;
+        System.out.println(assignmentInOperand(o));
+        counter = 1;
+        System.out.println(increments());
+        unusedVariable();
+        System.out.println(counter);
+    }
+}
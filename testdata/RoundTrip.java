@@ -0,0 +1,126 @@
+// The class file is compiled with `javac -g --release 8 RoundTrip.java`.
+
+import java.util.ArrayList;
+import java.util.List;
+
+public class RoundTrip implements Comparable<RoundTrip> {
+    static final String[] NAMES = {"a", "b"};
+    static int created;
+    private final int value;
+    private final List<String> log = new ArrayList<>();
+
+    static {
+        created = NAMES.length;
+    }
+
+    public RoundTrip(int value) {
+        this.value = value;
+    }
+
+    public RoundTrip() {
+        this(7);
+    }
+
+    int sum(int[] values) {
+        int sum = 0;
+        for (int value : values) {
+            sum += value;
+        }
+        return sum;
+    }
+
+    int lengths(List<String> strings) {
+        int total = 0;
+        for (String string : strings) {
+            total += string.length();
+        }
+        return total;
+    }
+
+    int count(int limit) {
+        int n = 0;
+        for (int i = 0; i < limit; i++) {
+            if (i % 3 == 0) {
+                continue;
+            }
+            n++;
+        }
+        while (n > 100) {
+            n /= 2;
+        }
+        return n;
+    }
+
+    String describe(int code) {
+        switch (code) {
+            case 1:
+                return "one";
+            case 2:
+            case 3:
+                return "few";
+            default:
+                return "many";
+        }
+    }
+
+    char grade(boolean passed, char fallback) {
+        return passed ? 'p' : fallback;
+    }
+
+    synchronized void record(String message) {
+        log.add(message);
+    }
+
+    void guarded(Object lock) {
+        synchronized (lock) {
+            created++;
+        }
+    }
+
+    int parse(String text) {
+        try {
+            return Integer.parseInt(text);
+        } catch (NumberFormatException e) {
+            return -1;
+        } finally {
+            record(text);
+        }
+    }
+
+    long[] squares(int n) {
+        long[] squares = new long[n];
+        for (int i = 0; i < n; i++) {
+            squares[i] = (long) i * i;
+        }
+        return squares;
+    }
+
+    Exception failure(String reason) {
+        return new IllegalStateException(reason != null ? "[" + reason + "]" : "");
+    }
+
+    int value() {
+        return value;
+    }
+
+    int counted() {
+        return new Counter().next();
+    }
+
+    public int compareTo(RoundTrip other) {
+        return Integer.compare(value, other.value);
+    }
+
+    class Counter {
+        private int count;
+
+        int next() {
+            return value() + ++count;
+        }
+    }
+
+    interface Limits {
+        Integer MAX = 10;
+        String NAME = Limits.class.getName();
+    }
+}
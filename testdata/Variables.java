@@ -0,0 +1,33 @@
+// The class file is compiled with `javac -g:none --release 8 Variables.java`. Running it prints
+// the results of the methods, which the decompiled code must reproduce.
+
+public class Variables {
+    static int counter;
+
+    static int increments(int[] xs, int i) {
+        xs[i++] += 2;
+        int j = i++ + ++i;
+        return xs[--i] + j + xs[0] * 100;
+    }
+
+    static int copies(int a) {
+        int b = a;
+        a = a * 2;
+        int c = b;
+        b = a;
+        return a + b * 10 + c * 100;
+    }
+
+    static int fieldIncrements() {
+        int x = counter++;
+        counter = counter++ + x;
+        return counter;
+    }
+
+    public static void main(String[] args) {
+        System.out.println(increments(new int[] {1, 2, 3, 4, 5}, 0));
+        System.out.println(copies(3));
+        counter = 1;
+        System.out.println(fieldIncrements());
+    }
+}